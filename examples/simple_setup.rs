@@ -14,7 +14,7 @@ fn main() {
         String::from("BadPassword")).unwrap();
 
     println!("Generate a new ID");
-    let my_key = vault.new_key(); 
+    let my_key = vault.new_key().unwrap(); 
 
     println!("Setting up a simple schema");
     let test_schema = Document::new(msgpack!({
@@ -58,7 +58,7 @@ fn main() {
     println!("    Got back: {:?}", res);
 
     println!("Trying to delete the schema while it is in use");
-    let res = db.del_doc(schema_hash.clone()).unwrap();
+    let res = db.del_doc(schema_hash.clone(), &schema_permission).unwrap();
     let res = res.recv().unwrap();
     println!("    Got back: {:?}", res);
 
@@ -86,6 +86,15 @@ fn main() {
             QueryResponse::Entry(_) => {
                 println!("    Got an entry back");
             },
+            QueryResponse::EntryHash(_) => {
+                println!("    Got an entry hash back");
+            },
+            QueryResponse::RootName(_) => {
+                println!("    Got a root document name back");
+            },
+            QueryResponse::CorruptDoc(_) => {
+                println!("    Got a corrupted document's hash back");
+            },
             QueryResponse::Invalid => {
                 println!("    Invalid query");
                 break;
@@ -105,12 +114,12 @@ fn main() {
     drop(res); // Done with query response
 
     println!("Deleting a document");
-    let res = db.del_doc(doc_hash.clone()).unwrap();
+    let res = db.del_doc(doc_hash.clone(), &doc_permission).unwrap();
     let res = res.recv().unwrap();
     println!("    Got back: {:?}", res);
 
     println!("Deleting the schema");
-    let res = db.del_doc(schema_hash.clone()).unwrap();
+    let res = db.del_doc(schema_hash.clone(), &schema_permission).unwrap();
     let res = res.recv().unwrap();
     println!("    Got back: {:?}", res);
 
@@ -9,6 +9,8 @@ pub enum CryptoError {
     BadKey,
     BadFormat,
     NotInStorage,
+    NotInitialized,
+    Sealed,
     Io(io::Error),
 }
 
@@ -21,6 +23,8 @@ impl fmt::Display for CryptoError {
             CryptoError::BadLength            => write!(f, "Provided data length is invalid"),
             CryptoError::BadFormat            => write!(f, "Format of data does not match specification"),
             CryptoError::NotInStorage         => write!(f, "Provided Key/Identity/StreamKey is not in storage"),
+            CryptoError::NotInitialized       => write!(f, "Crypto library was not initialized"),
+            CryptoError::Sealed               => write!(f, "Vault is sealed"),
             CryptoError::Io(ref err)          => err.fmt(f),
         }
     }
@@ -35,6 +39,8 @@ impl Error for CryptoError {
             CryptoError::BadLength            => "invalid data length",
             CryptoError::BadFormat            => "incorrect format for data",
             CryptoError::NotInStorage         => "Key/Identity/StreamKey not in storage",
+            CryptoError::NotInitialized       => "crypto library not initialized",
+            CryptoError::Sealed               => "vault is sealed",
             CryptoError::Io(ref err)          => err.description(),
         }
     }
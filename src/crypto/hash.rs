@@ -8,6 +8,7 @@ use std::cmp::Ordering;
 
 use crypto::error::CryptoError;
 use crypto::sodium::{blake2b, Blake2BState};
+use crypto::{ensure_init, hex_encode, hex_decode};
 
 /// Crytographically secure hash of data. Can be signed by a FullKey. It is impractical to generate an 
 /// identical hash from different data.
@@ -70,6 +71,12 @@ impl fmt::Debug for Hash {
     }
 }
 
+impl fmt::Display for Hash {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(&self.to_hex_string())
+    }
+}
+
 impl hash::Hash for Hash {
     fn hash<H: hash::Hasher>(&self, state: &mut H) {
         self.version.hash(state);
@@ -80,6 +87,7 @@ impl hash::Hash for Hash {
 impl Hash {
 
     pub fn new(version: u8, data: &[u8]) -> Result<Hash, CryptoError> {
+        ensure_init()?;
         if version != 1 { return Err(CryptoError::UnsupportedVersion); }
         if data.len() > ::std::u64::MAX as usize { return Err(CryptoError::BadLength); }
         let mut hash = Hash {version, digest: [0;64]};
@@ -124,6 +132,49 @@ impl Hash {
         buf.read_exact(&mut hash.digest).map_err(CryptoError::Io)?;
         Ok(hash)
     }
+
+    /// Encode the hash, including its version, as a lowercase hex string.
+    pub fn to_hex_string(&self) -> String {
+        let mut raw = Vec::with_capacity(self.len());
+        self.encode(&mut raw);
+        hex_encode(&raw[..])
+    }
+
+    /// Decode a hash from a hex string, as produced by `to_hex_string`. Upper or lowercase hex is 
+    /// accepted. Fails with `BadFormat` if the string isn't hex, and `BadLength` if it holds more 
+    /// or less than one hash.
+    pub fn from_hex_string(s: &str) -> Result<Hash, CryptoError> {
+        Hash::from_raw(&hex_decode(s)?[..])
+    }
+
+    /// Encode the hash, including its version, as a standard base64 string. Requires the `json` 
+    /// feature.
+    #[cfg(feature = "json")]
+    pub fn to_base64(&self) -> String {
+        let mut raw = Vec::with_capacity(self.len());
+        self.encode(&mut raw);
+        base64::encode(&raw)
+    }
+
+    /// Decode a hash from a standard base64 string, as produced by `to_base64`. Fails with 
+    /// `BadFormat` if the string isn't base64, and `BadLength` if it holds more or less than one 
+    /// hash. Requires the `json` feature.
+    #[cfg(feature = "json")]
+    pub fn from_base64(s: &str) -> Result<Hash, CryptoError> {
+        let raw = base64::decode(s).map_err(|_| CryptoError::BadFormat)?;
+        Hash::from_raw(&raw[..])
+    }
+
+    /// Decode a hash that should take up the entire buffer.
+    fn from_raw(raw: &[u8]) -> Result<Hash, CryptoError> {
+        let mut buf = raw;
+        let hash = match Hash::decode(&mut buf) {
+            Err(CryptoError::Io(_)) => return Err(CryptoError::BadLength),
+            result => result?,
+        };
+        if !buf.is_empty() { return Err(CryptoError::BadLength); }
+        Ok(hash)
+    }
 }
 
 impl HashState {
@@ -171,6 +222,7 @@ mod tests {
 
     #[test]
     fn hash_vectors() {
+        ::crypto::init().unwrap();
         let file_ref = fs::File::open("test-resources/blake2b-test-vectors.json").unwrap();
         let json_ref : Value = serde_json::from_reader(file_ref).unwrap();
 
@@ -194,6 +246,7 @@ mod tests {
 
     #[test]
     fn edge_cases() {
+        ::crypto::init().unwrap();
         match Hash::new(0, &[1,2]).unwrap_err() {
             CryptoError::UnsupportedVersion => (),
             _ => panic!("New hash should always fail on version 0"),
@@ -218,4 +271,52 @@ mod tests {
         assert_eq!(h.digest(), &digest[..]);
         enc_dec(h);
     }
+
+    #[test]
+    fn strings() {
+        ::crypto::init().unwrap();
+        let empty = Hash::new_empty();
+        assert_eq!(empty.to_hex_string(), "00");
+        assert_eq!(empty.to_hex_string(), Hash::new_empty().to_hex_string());
+        assert_eq!(format!("{}", empty), "00");
+        assert_eq!(Hash::from_hex_string("00").unwrap(), empty);
+
+        let h = Hash::new(1, &hex::decode("00010203040506070809").unwrap()).unwrap();
+        let expected = "0129102511d749db3cc9b4e335fa1f5e8faca8421d558f6a3f3321d50d044a248b\
+                        a595cfc3efd3d2adc97334da732413f5cbf4751c362ba1d53862ac1e8dabeee8";
+        assert_eq!(h.to_hex_string(), expected);
+        assert_eq!(h.to_string(), expected);
+        assert_eq!(Hash::from_hex_string(expected).unwrap(), h);
+        assert_eq!(Hash::from_hex_string(&expected.to_uppercase()).unwrap(), h);
+
+        match Hash::from_hex_string("0g").unwrap_err() {
+            CryptoError::BadFormat => (),
+            e => panic!("Non-hex string should fail with BadFormat, got {:?}", e),
+        };
+        match Hash::from_hex_string(&expected[..20]).unwrap_err() {
+            CryptoError::BadLength => (),
+            e => panic!("Short string should fail with BadLength, got {:?}", e),
+        };
+        match Hash::from_hex_string("0000").unwrap_err() {
+            CryptoError::BadLength => (),
+            e => panic!("Long string should fail with BadLength, got {:?}", e),
+        };
+        match Hash::from_hex_string("02").unwrap_err() {
+            CryptoError::UnsupportedVersion => (),
+            e => panic!("Unknown version should fail with UnsupportedVersion, got {:?}", e),
+        };
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn base64_strings() {
+        ::crypto::init().unwrap();
+        let empty = Hash::new_empty();
+        assert_eq!(empty.to_base64(), "AA==");
+        assert_eq!(Hash::from_base64("AA==").unwrap(), empty);
+        let h = Hash::new(1, &hex::decode("00010203040506070809").unwrap()).unwrap();
+        assert_eq!(Hash::from_base64(&h.to_base64()).unwrap(), h);
+        assert!(Hash::from_base64("not base64!").is_err());
+        assert!(Hash::from_base64(&empty.to_base64()[..1]).is_err());
+    }
 }
@@ -5,6 +5,9 @@ use byteorder::ReadBytesExt;
 use crypto::sodium::*;
 use crypto::error::CryptoError;
 use crypto::hash::Hash;
+use crypto::stream::FullStreamKey;
+use crypto::lockbox::{self, Lockbox};
+use crypto::{hex_encode, hex_decode, LockboxType};
 
 /// A cryptographic private key, used to decrypt and sign as a particular 
 /// Identity. Requires accessing a Vault in order to use it.
@@ -31,6 +34,12 @@ impl Key {
     pub fn get_identity(&self) -> Identity {
         Identity { version: self.version, id: self.id.clone() }
     }
+
+    /// Encode the Key reference, including its version, as a lowercase hex string. This is the 
+    /// same as the hex string of its Identity, and contains no secret data.
+    pub fn to_hex_string(&self) -> String {
+        self.get_identity().to_hex_string()
+    }
 }
 
 impl Identity {
@@ -56,6 +65,36 @@ impl Identity {
         buf.read_exact(&mut id.id.0).map_err(CryptoError::Io)?;
         Ok(id)
     }
+
+    /// Encode the Identity, including its version, as a lowercase hex string.
+    pub fn to_hex_string(&self) -> String {
+        let mut raw = Vec::with_capacity(self.len());
+        self.encode(&mut raw);
+        hex_encode(&raw[..])
+    }
+
+    /// Decode an Identity from a hex string, as produced by `to_hex_string`. Upper or lowercase 
+    /// hex is accepted. Fails with `BadFormat` if the string isn't hex, and `BadLength` if it holds 
+    /// more or less than one Identity.
+    pub fn from_hex_string(s: &str) -> Result<Identity, CryptoError> {
+        let raw = hex_decode(s)?;
+        let mut buf = &raw[..];
+        let id = match Identity::decode(&mut buf) {
+            Err(CryptoError::Io(_)) => return Err(CryptoError::BadLength),
+            result => result?,
+        };
+        if !buf.is_empty() { return Err(CryptoError::BadLength); }
+        Ok(id)
+    }
+
+    /// Verify a raw Ed25519 signature over arbitrary data, as produced by `FullKey::sign_data`. 
+    /// Returns false if the signature is invalid or isn't the right length.
+    pub fn verify(&self, data: &[u8], signature: &[u8]) -> bool {
+        let mut sig: Sign = Default::default();
+        if signature.len() != sig.0.len() { return false; }
+        sig.0.copy_from_slice(signature);
+        verify_detached(&self.id, data, &sig)
+    }
 }
 
 impl fmt::Debug for Key {
@@ -64,6 +103,13 @@ impl fmt::Debug for Key {
     }
 }
 
+/// Displays as `id:` followed by the hex string, so it can't be mistaken for a Hash.
+impl fmt::Display for Identity {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "id:{}", self.to_hex_string())
+    }
+}
+
 impl fmt::Debug for Identity {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
         write!(formatter, "{} {{ ver={}, {:x?} }}", stringify!(Identity), &self.version, &self.id.0[..])
@@ -188,6 +234,15 @@ impl FullKey {
         Ok((key,id))
     }
 
+    /// Deterministically derive a child key from this key, an index, and a label.
+    pub fn derive_child(&self, index: u32, label: &str) -> Result<(FullKey, FullIdentity), CryptoError> {
+        let mut parent_seed: Seed = Default::default();
+        let mut seed: Seed = Default::default();
+        ed25519_sk_to_seed(&mut parent_seed, &self.signing);
+        derive_seed(&mut seed, &parent_seed.0, index, label);
+        FullKey::from_seed(seed)
+    }
+
     pub fn get_version(&self) -> u8 {
         self.version
     }
@@ -198,6 +253,13 @@ impl FullKey {
         pk
     }
 
+    /// Get the seed this key was generated from.
+    pub fn get_seed(&self) -> Seed {
+        let mut seed: Seed = Default::default();
+        ed25519_sk_to_seed(&mut seed, &self.signing);
+        seed
+    }
+
     pub fn get_key_ref(&self) -> Key {
         Key {
             version: self.version,
@@ -205,6 +267,37 @@ impl FullKey {
         }
     }
 
+    /// Rebuild the key from its seed and check that it produces the same Key reference. Fails 
+    /// with `BadKey` if the stored secret key doesn't match its own seed.
+    pub fn verify_ref(&self) -> Result<Key, CryptoError> {
+        if self.version != 1 { return Err(CryptoError::UnsupportedVersion); }
+        let mut seed: Seed = Default::default();
+        ed25519_sk_to_seed(&mut seed, &self.signing);
+        let (rebuilt, _) = FullKey::from_seed(seed)?;
+        let key_ref = self.get_key_ref();
+        if rebuilt.get_key_ref() == key_ref && rebuilt.signing == self.signing {
+            Ok(key_ref)
+        }
+        else {
+            Err(CryptoError::BadKey)
+        }
+    }
+
+    /// Open a Lockbox made by `FullStreamKey::wrap` for this key's Identity, returning the stream 
+    /// key inside. Fails with `DecryptFailed` if the Lockbox was made for a different Identity, 
+    /// and `BadFormat` if it doesn't hold a stream key.
+    pub fn unwrap_stream_key(&self, lock: &Lockbox) -> Result<FullStreamKey, CryptoError> {
+        if lockbox::get_key(lock) != Some(self.get_key_ref()) { return Err(CryptoError::DecryptFailed); }
+        let stream = lockbox::stream_key_from_lockbox(self, lock)?;
+        let mut data = lockbox::decrypt_lockbox(&stream, lock.clone())?;
+        let result = match data.split_first() {
+            Some((kind, mut raw)) if *kind == LockboxType::StreamKey.to_u8() => FullStreamKey::decode(&mut raw),
+            _ => Err(CryptoError::BadFormat),
+        };
+        memzero(&mut data[..]);
+        result
+    }
+
     pub fn get_identity(&self) -> Result<FullIdentity, CryptoError> {
         let mut id = FullIdentity::blank();
         id.version = 1;
@@ -225,6 +318,12 @@ impl FullKey {
         }
     }
 
+    /// Sign arbitrary data directly, without hashing it first. The result is a raw 64-byte Ed25519 
+    /// signature that can be checked with `Identity::verify`.
+    pub fn sign_data(&self, data: &[u8]) -> Vec<u8> {
+        sign_detached(&self.signing, data).0.to_vec()
+    }
+
     pub fn len(&self) -> usize {
         1 + Seed::len()
     }
@@ -426,4 +525,78 @@ mod tests {
         signature_enc_dec(sig);
     }
 
+    /// Raw signing against the test vectors in RFC 8032, section 7.1.
+    #[test]
+    fn raw_signing() {
+        init().unwrap();
+        let vectors = [
+            (
+                "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+                "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+                "",
+                "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+            ),
+            (
+                "4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb",
+                "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+                "72",
+                "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+            ),
+            (
+                "c5aa8df43f9f837bedb7442f31dcb7b166d38535076f094b85ce3a2e0b4458f7",
+                "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+                "af82",
+                "6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a",
+            ),
+        ];
+        for (seed_hex, pk_hex, msg_hex, sig_hex) in vectors.iter() {
+            let mut seed: Seed = Default::default();
+            seed.0.copy_from_slice(&hex_decode(seed_hex).unwrap());
+            let msg = hex_decode(msg_hex).unwrap();
+            let expected_sig = hex_decode(sig_hex).unwrap();
+            let (k, id) = FullKey::from_seed(seed).unwrap();
+            let id = id.get_identity_ref();
+            assert_eq!(id.id.0.to_vec(), hex_decode(pk_hex).unwrap());
+            let sig = k.sign_data(&msg);
+            assert_eq!(sig, expected_sig);
+            assert!(id.verify(&msg, &sig));
+            let mut bad_sig = sig.clone();
+            bad_sig[0] ^= 1;
+            assert!(!id.verify(&msg, &bad_sig));
+            assert!(!id.verify(b"other data", &sig));
+            assert!(!id.verify(&msg, &sig[1..]));
+        }
+    }
+
+    #[test]
+    fn hex_strings() {
+        init().unwrap();
+        let (k, id) = FullKey::new_pair().unwrap();
+        let id = id.get_identity_ref();
+        let s = id.to_hex_string();
+        assert_eq!(s.len(), 2 * id.len());
+        assert!(s.starts_with("01"));
+        assert_eq!(Identity::from_hex_string(&s).unwrap(), id);
+        assert_eq!(format!("{}", id), format!("id:{}", s));
+        assert_eq!(k.get_key_ref().to_hex_string(), s);
+
+        match Identity::from_hex_string("not hex").unwrap_err() {
+            CryptoError::BadFormat => (),
+            e => panic!("Non-hex string should fail with BadFormat, got {:?}", e),
+        };
+        match Identity::from_hex_string(&s[..s.len()-2]).unwrap_err() {
+            CryptoError::BadLength => (),
+            e => panic!("Short string should fail with BadLength, got {:?}", e),
+        };
+        match Identity::from_hex_string(&format!("{}00", s)).unwrap_err() {
+            CryptoError::BadLength => (),
+            e => panic!("Long string should fail with BadLength, got {:?}", e),
+        };
+        match Identity::from_hex_string(&format!("00{}", &s[2..])).unwrap_err() {
+            CryptoError::UnsupportedVersion => (),
+            e => panic!("Unknown version should fail with UnsupportedVersion, got {:?}", e),
+        };
+        assert!(Identity::from_hex_string("").is_err());
+    }
+
 }
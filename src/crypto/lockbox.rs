@@ -65,6 +65,14 @@ impl LockType {
 }
 
 
+/// The recipient a Lockbox was encrypted for. A Lockbox for an Identity names the Key needed to 
+/// open it, while a Lockbox for a StreamKey names the StreamKey itself.
+#[derive(Clone, PartialEq, Debug)]
+pub enum LockboxRecipient {
+    Stream(StreamKey),
+    Identity(Key),
+}
+
 /// A container for either a StreamKey, Key, or Value. Can be encrypted for a 
 /// particular Identity, or for a particular StreamKey
 #[derive(Clone, PartialEq, Debug)]
@@ -120,6 +128,23 @@ impl Lockbox {
         }
     }
 
+    /// Get the recipient the Lockbox was encrypted for, without decrypting it. Can be checked 
+    /// against a Vault to see if the Lockbox can be opened before trying.
+    pub fn recipient_kind(&self) -> LockboxRecipient {
+        match self.type_id {
+            LockType::Identity(ref id) => 
+                LockboxRecipient::Identity(crypto::key::key_from_id(self.version, id.0.clone())),
+            LockType::Stream(ref stream) => 
+                LockboxRecipient::Stream(crypto::stream::stream_from_id(self.version, stream.clone())),
+        }
+    }
+
+    /// Get the Identity the Lockbox was encrypted for, without decrypting it. Returns None if a 
+    /// StreamKey was used instead.
+    pub fn recipient_identity(&self) -> Option<Identity> {
+        self.get_id()
+    }
+
     /// Get the length of the Lockbox when it is binary-encoded
     pub fn len(&self) -> usize {
         1 + self.type_id.len() + Nonce::len() + self.ciphertext.len()
@@ -172,6 +197,10 @@ pub fn lockbox_from_stream(k: &FullStreamKey, mut message: Vec<u8>) -> Result<Lo
         memzero(&mut message[..]); // Must assume data is sensitive and zero it out before failing
         return Err(CryptoError::UnsupportedVersion);
     }
+    if let Err(e) = crypto::ensure_init() {
+        memzero(&mut message[..]);
+        return Err(e);
+    }
     let nonce = Nonce::new();
     let raw_key = k.get_key();
     let type_id = LockType::Stream(k.get_id());
@@ -194,6 +223,10 @@ pub fn lockbox_from_identity(id: &FullIdentity, mut message: Vec<u8>) -> Result<
         memzero(&mut message[..]); // Must assume data is sensitive and zero it out before failing
         return Err(CryptoError::UnsupportedVersion);
     }
+    if let Err(e) = crypto::ensure_init() {
+        memzero(&mut message[..]);
+        return Err(e);
+    }
     let nonce = Nonce::new();
     let mut esk: SecretCryptKey = Default::default();
     let mut epk: PublicCryptKey = Default::default();
@@ -283,6 +316,8 @@ mod tests {
         assert_eq!(lockbox.get_stream(), Some(stream_ref));
         assert_eq!(lockbox.get_id(), None);
         assert_eq!(get_key(&lockbox), None);
+        assert_eq!(lockbox.recipient_kind(), LockboxRecipient::Stream(stream.get_stream_ref()));
+        assert_eq!(lockbox.recipient_identity(), None);
         // Lockbox is version byte, type byte, StreamId, Nonce, encrypted data, 
         // and encryption tag 
         assert_eq!(lockbox.len(), 2 + stream.get_id().0.len() + Nonce::len() + 
@@ -309,6 +344,8 @@ mod tests {
         assert_eq!(lockbox.get_stream(), None);
         assert_eq!(lockbox.get_id(), Some(id_ref));
         assert_eq!(get_key(&lockbox), Some(key.get_key_ref()));
+        assert_eq!(lockbox.recipient_kind(), LockboxRecipient::Identity(key.get_key_ref()));
+        assert_eq!(lockbox.recipient_identity(), Some(id.get_identity_ref()));
         // Lockbox is version byte, type byte, public key, ephemeral public key, 
         // Nonce, encrypted data, and encryption tag 
         //assert_eq!(lockbox.len(), 2 + id_ref.get_id().0.len() + Nonce::len() + 
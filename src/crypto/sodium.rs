@@ -138,6 +138,30 @@ impl PasswordConfig {
         randombytes(&mut pw.salt);
         pw
     }
+    /// Use the given limits directly. Fails if they're outside the range libsodium accepts.
+    pub fn custom(ops_limit: u64, mem_limit: usize) -> Result<PasswordConfig, ()> {
+        if !PasswordConfig::limits_ok(ops_limit, mem_limit) { return Err(()); }
+        let mut pw = PasswordConfig {
+            ops_limit,
+            mem_limit,
+            alg: libsodium_sys::crypto_pwhash_ALG_DEFAULT as i32,
+            salt: [0; SALT_BYTES],
+        };
+        randombytes(&mut pw.salt);
+        Ok(pw)
+    }
+
+    /// Checks if the ops & memory limits are within the range libsodium accepts.
+    pub fn limits_ok(ops_limit: u64, mem_limit: usize) -> bool {
+        let (ops_min, ops_max, mem_min, mem_max) = unsafe {(
+            libsodium_sys::crypto_pwhash_opslimit_min() as u64,
+            libsodium_sys::crypto_pwhash_opslimit_max() as u64,
+            libsodium_sys::crypto_pwhash_memlimit_min(),
+            libsodium_sys::crypto_pwhash_memlimit_max(),
+        )};
+        (ops_limit >= ops_min) && (ops_limit <= ops_max)
+            && (mem_limit >= mem_min) && (mem_limit <= mem_max)
+    }
 
     pub fn len() -> usize {
         8+8+4+SALT_BYTES
@@ -386,6 +410,22 @@ pub fn blake2b( hash: &mut [u8; 64], data: &[u8] ) {
     }
 }
 
+/// Derives a new signing seed from secret key material, an index, and a label. The key material 
+/// is used as the key for BLAKE2b, and the big-endian index followed by the label is hashed with 
+/// it.
+pub fn derive_seed(seed: &mut Seed, key: &[u8], index: u32, label: &str) {
+    let mut data = Vec::with_capacity(4 + label.len());
+    data.extend_from_slice(&[(index >> 24) as u8, (index >> 16) as u8, (index >> 8) as u8, index as u8]);
+    data.extend_from_slice(label.as_bytes());
+    // The below will only fail if the key isn't between 16 and 64 bytes.
+    unsafe {
+        libsodium_sys::crypto_generichash_blake2b(
+            seed.0.as_mut_ptr(), seed.0.len(),
+            data.as_ptr(), data.len() as u64,
+            key.as_ptr(), key.len());
+    }
+}
+
 pub fn calc_secret(pk: &PublicCryptKey, sk: &SecretCryptKey) -> Result<SecretKey,CryptoError> {
     // This can fail with a bad key, so it must be checked
     let mut k: SecretKey = Default::default();
@@ -4,6 +4,9 @@ use std::fmt;
 
 use crypto::error::CryptoError;
 use crypto::sodium::{StreamId, SecretKey, aead_keygen, derive_id};
+use crypto::key::{FullIdentity, Identity};
+use crypto::lockbox::{self, Lockbox};
+use crypto::LockboxType;
 
 #[derive(Clone,PartialEq,Eq,Hash)]
 pub struct StreamKey {
@@ -74,6 +77,30 @@ impl FullStreamKey {
         }
     }
 
+    /// Re-derive the stream ID from the secret key and check that it produces the same StreamKey 
+    /// reference. Fails with `BadKey` if the stored ID doesn't match.
+    pub fn verify_ref(&self) -> Result<StreamKey, CryptoError> {
+        if self.version != 1 { return Err(CryptoError::UnsupportedVersion); }
+        let stream_ref = self.get_stream_ref();
+        if FullStreamKey::from_secret(self.key.clone()).get_stream_ref() == stream_ref {
+            Ok(stream_ref)
+        }
+        else {
+            Err(CryptoError::BadKey)
+        }
+    }
+
+    /// Encrypt this stream key in a Lockbox for `recipient`, without needing a Vault. The Lockbox 
+    /// is laid out the same way as one made by `Vault::encrypt_using_identity`, so it can be opened 
+    /// with `FullKey::unwrap_stream_key` or by a Vault holding the recipient's Key.
+    pub fn wrap(&self, recipient: &Identity) -> Result<Lockbox, CryptoError> {
+        let mut message = vec![LockboxType::StreamKey.to_u8()];
+        self.encode(&mut message);
+        let full_id = FullIdentity::from_identity(recipient)?;
+        let (lock, _) = lockbox::lockbox_from_identity(&full_id, message)?;
+        Ok(lock)
+    }
+
     pub fn complete(&mut self) {
         derive_id(&self.key, &mut self.id)
     }
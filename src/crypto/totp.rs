@@ -0,0 +1,184 @@
+//! Time-based one-time passwords (RFC 6238), checked in addition to the password when opening a
+//! Vault. The check is made by the Vault's API only; the code takes no part in decrypting it.
+//! libsodium doesn't provide SHA-1, which the TOTP standard (and every authenticator app) uses,
+//! so it and HMAC-SHA1 (RFC 2104) are implemented here.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crypto::sodium::memzero;
+
+/// Length of a TOTP secret, in bytes.
+pub const SECRET_LEN: usize = 20;
+/// Length of a time step, in seconds.
+const TIME_STEP: u64 = 30;
+/// How many time steps to either side of the current one will be accepted, to allow for clock
+/// drift and slow typing.
+const WINDOW: u64 = 1;
+/// Number of digits in a code.
+const DIGITS: u32 = 6;
+
+const SHA1_BLOCK_LEN: usize = 64;
+const SHA1_OUT_LEN: usize = 20;
+
+fn sha1(data: &[&[u8]]) -> [u8; SHA1_OUT_LEN] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let total_len: usize = data.iter().map(|d| d.len()).sum();
+    let mut msg = Vec::with_capacity(total_len + SHA1_BLOCK_LEN + 8);
+    for d in data.iter() {
+        msg.extend_from_slice(d);
+    }
+    msg.push(0x80);
+    while (msg.len() % SHA1_BLOCK_LEN) != (SHA1_BLOCK_LEN - 8) {
+        msg.push(0);
+    }
+    msg.extend_from_slice(&((total_len as u64) * 8).to_be_bytes());
+
+    for block in msg.chunks(SHA1_BLOCK_LEN) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i-3] ^ w[i-8] ^ w[i-14] ^ w[i-16]).rotate_left(1);
+        }
+        let (mut a, mut b, mut c, mut d, mut e) = (h[0], h[1], h[2], h[3], h[4]);
+        for i in 0..80 {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a.rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(w[i]);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        h[0] = h[0].wrapping_add(a);
+        h[1] = h[1].wrapping_add(b);
+        h[2] = h[2].wrapping_add(c);
+        h[3] = h[3].wrapping_add(d);
+        h[4] = h[4].wrapping_add(e);
+    }
+    // The message is HMAC key material when called from `hmac_sha1`
+    memzero(&mut msg[..]);
+
+    let mut out = [0u8; SHA1_OUT_LEN];
+    for (chunk, word) in out.chunks_mut(4).zip(h.iter()) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    out
+}
+
+fn hmac_sha1(key: &[u8], msg: &[u8]) -> [u8; SHA1_OUT_LEN] {
+    let mut block = [0u8; SHA1_BLOCK_LEN];
+    if key.len() > SHA1_BLOCK_LEN {
+        block[..SHA1_OUT_LEN].copy_from_slice(&sha1(&[key]));
+    }
+    else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut ipad = [0x36u8; SHA1_BLOCK_LEN];
+    let mut opad = [0x5cu8; SHA1_BLOCK_LEN];
+    for i in 0..SHA1_BLOCK_LEN {
+        ipad[i] ^= block[i];
+        opad[i] ^= block[i];
+    }
+    let mut inner = sha1(&[&ipad, msg]);
+    let mac = sha1(&[&opad, &inner]);
+    memzero(&mut inner);
+    memzero(&mut block);
+    memzero(&mut ipad);
+    memzero(&mut opad);
+    mac
+}
+
+/// A TOTP secret, zeroed out when dropped.
+pub struct Secret(pub [u8; SECRET_LEN]);
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        memzero(&mut self.0);
+    }
+}
+
+/// Compute the code for a given time step (RFC 4226, section 5.3).
+fn code_at_step(secret: &[u8], step: u64) -> u32 {
+    let mac = hmac_sha1(secret, &step.to_be_bytes());
+    let offset = (mac[SHA1_OUT_LEN - 1] & 0x0F) as usize;
+    let bin_code = u32::from_be_bytes([mac[offset], mac[offset+1], mac[offset+2], mac[offset+3]])
+        & 0x7FFF_FFFF;
+    bin_code % 10u32.pow(DIGITS)
+}
+
+/// Compute the code for a given Unix time, in seconds.
+pub fn code_at_time(secret: &[u8], time: u64) -> u32 {
+    code_at_step(secret, time / TIME_STEP)
+}
+
+/// Check a code against a given Unix time, in seconds, accepting codes from one time step to
+/// either side.
+pub fn verify_at_time(secret: &[u8], code: u32, time: u64) -> bool {
+    let step = time / TIME_STEP;
+    // Check every step in the window instead of stopping at the first match, so the time taken
+    // doesn't say which step matched.
+    (step.saturating_sub(WINDOW)..=(step + WINDOW))
+        .fold(false, |found, step| (code_at_step(secret, step) == code) | found)
+}
+
+/// Check a code against the current system time. Fails if the system time is before the Unix
+/// epoch.
+pub fn verify_now(secret: &[u8], code: u32) -> bool {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(now) => verify_at_time(secret, code, now.as_secs()),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sha1_vectors() {
+        assert_eq!(sha1(&[b""]).to_vec(),
+            vec![0xda,0x39,0xa3,0xee,0x5e,0x6b,0x4b,0x0d,0x32,0x55,0xbf,0xef,0x95,0x60,0x18,0x90,0xaf,0xd8,0x07,0x09]);
+        assert_eq!(sha1(&[b"ab", b"c"]).to_vec(),
+            vec![0xa9,0x99,0x3e,0x36,0x47,0x06,0x81,0x6a,0xba,0x3e,0x25,0x71,0x78,0x50,0xc2,0x6c,0x9c,0xd0,0xd8,0x9d]);
+        // RFC 2202, test case 1
+        assert_eq!(hmac_sha1(&[0x0b; 20], b"Hi There").to_vec(),
+            vec![0xb6,0x17,0x31,0x86,0x55,0x05,0x72,0x64,0xe2,0x8b,0xc0,0xb6,0xfb,0x37,0x8c,0x8e,0xf1,0x46,0xbe,0x00]);
+        // RFC 2202, test case 6: key longer than the block size
+        assert_eq!(hmac_sha1(&[0xaa; 80], b"Test Using Larger Than Block-Size Key - Hash Key First").to_vec(),
+            vec![0xaa,0x4a,0xe5,0xe1,0x52,0x72,0xd0,0x0e,0x95,0x70,0x56,0x37,0xce,0x8a,0x3b,0x55,0xed,0x40,0x21,0x12]);
+    }
+
+    #[test]
+    fn totp_vectors() {
+        // RFC 6238, appendix B, using the SHA-1 secret. The RFC lists 8-digit codes; these are
+        // the last 6 digits.
+        let secret = b"12345678901234567890";
+        let vectors = [
+            (59u64, 287082u32),
+            (1111111109, 81804),
+            (1111111111, 50471),
+            (1234567890, 5924),
+            (2000000000, 279037),
+            (20000000000, 353130),
+        ];
+        for (time, code) in vectors.iter() {
+            assert_eq!(code_at_time(secret, *time), *code, "Wrong code for time {}", time);
+            assert!(verify_at_time(secret, *code, *time));
+            assert!(verify_at_time(secret, *code, *time + TIME_STEP));
+            assert!(verify_at_time(secret, *code, *time - TIME_STEP));
+            assert!(!verify_at_time(secret, *code, *time + 3 * TIME_STEP));
+        }
+        assert!(!verify_at_time(secret, 287083, 59));
+    }
+}
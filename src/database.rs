@@ -70,29 +70,85 @@
 
 
 
-use std::collections::HashMap;
-use crossbeam_channel::{TrySendError, TryRecvError, RecvError, Sender, Receiver, unbounded, bounded, Select};
+use std::collections::{HashMap, BTreeMap};
+use crossbeam_channel::{TryRecvError, RecvError, Sender, Receiver, unbounded, bounded, Select};
 use std::path::Path;
+use std::io;
+#[cfg(feature = "json")]
+use std::io::{Read, Write};
 
-use super::{Schema, Permission, Query, Hash, Document, Entry};
+use super::{Schema, Permission, Query, Hash, Document, Entry, Value, DbError};
+use schema::Checklist;
+use string_index::StringFieldIndex;
 use document;
+use entry;
+use decode;
+use encode;
+#[cfg(feature = "json")]
+use serde_json;
+
+/// Maximum levels of nested objects allowed in documents and entries, unless their schema sets a 
+/// lower limit.
+const MAX_OBJECT_DEPTH: usize = 64;
+
+/// Maximum number of referenced documents that will be checked when adding a single entry.
+const MAX_ENTRY_DOC_CHECKS: usize = 1000;
+
+/// Name of the RocksDB column family holding the `reference` store.
+const REFERENCE_CF: &str = "reference";
+
+/// Number of documents processed by a reindex each time through the database loop.
+const REINDEX_BATCH: usize = 256;
+
+/// Top-level document field that makes a document a root document. Its value is the document's 
+/// name in `root`, which must be unique.
+pub const ROOT_NAME_FIELD: &str = "$root_name";
+
+/// Raw copy of every document (hash, raw document) and entry (document hash, field, raw entry) 
+/// in the database.
+type DbSnapshot = (Vec<(Hash, Vec<u8>, Permission, u32)>, Vec<(Hash, String, Vec<u8>, u32)>);
 
 /// Types of changes that can be made to the database.
 enum ChangeRequest {
     /// Add a document to the databse.
     AddDoc((Document, Permission, u32)),
-    /// Remove a document from the database by hash.
-    DelDoc(Hash),
+    /// Add a document to the database, failing if it is already there.
+    AddDocStrict((Document, Permission, u32)),
+    /// Remove a document from the database by hash, if the permission allows it.
+    DelDoc((Hash, Permission)),
+    /// Replace a document with a copy that has some top-level fields overwritten, if the 
+    /// permission allows it. Fails if the patched document doesn't have the expected hash.
+    ApplyPatch((Hash, BTreeMap<String, Value>, Hash, Permission)),
     /// Add an entry to the database.
     AddEntry((Entry, u32)),
     /// Remove an entry from the database by hash of document & entry.
     DelEntry((Hash, Hash)),
+    /// Remove an entry from the database by its hash alone.
+    DelEntryByHash(Hash),
+    /// Remove every entry with the given field from a document.
+    DelEntriesByField((Hash, String)),
     /// Remove documents/entries that match the query given.
     DelQuery(Query),
     /// Set time-to-live for a given document.
     SetTtlDoc((Hash, u32)),
     /// Set time-to-live for a given entry, by hash of document & entry.
     SetTtlEntry((Hash, Hash, u32)),
+    /// Send a copy of all documents and entries out on the provided channel.
+    Export(Sender<DbSnapshot>),
+    /// Add a set of documents and entries, adding documents only after their schema have been 
+    /// added.
+    Import((Vec<(Document, Permission, u32)>, Vec<(Entry, u32)>)),
+    /// Change how documents using older or newer versions of a schema are handled.
+    SetSchemaVersionPolicy(SchemaVersionPolicy),
+    /// Rebuild all schema tracking from the stored documents.
+    Reindex,
+    /// Start indexing documents by the value of a string field.
+    IndexStringField(String),
+    /// Send the number of document bytes counted against a permission's quota out on the provided 
+    /// channel.
+    QuotaUsage((Permission, Sender<u64>)),
+    /// Remove every document with a TTL of 0 that has no entries.
+    CleanupTemp,
 }
 
 /// Result of any changes requested of the database.
@@ -100,6 +156,8 @@ enum ChangeRequest {
 pub enum ChangeResult {
     /// Change made successfuly.
     Ok,
+    /// Change made successfully, affecting the given number of documents or entries.
+    OkCount(u64),
     /// Failed for unknown reasons, likely due to underlying database
     Failed,
     /// Couldn't operate on a document because it doesn't exist. Also returned if adding an entry 
@@ -109,6 +167,9 @@ pub enum ChangeResult {
     NoSuchEntry,
     /// Couldn't delete a schema document because it is in use.
     SchemaInUse,
+    /// Couldn't delete a document because other documents, or entries that require it, still 
+    /// reference it.
+    StillReferenced,
     /// Couldn't delete a certificate because it is in use.
     CertInUse,
     /// Document/Entry failed schema check when being added.
@@ -119,6 +180,32 @@ pub enum ChangeResult {
     NotValidSchema,
     /// Query didn't match schema of the document(s) being operated on.
     InvalidQuery,
+    /// Document's schema is a different version than the newest schema with the same name in the 
+    /// database, and the database's `SchemaVersionPolicy` doesn't allow it.
+    SchemaVersionMismatch,
+    /// Document was already in the database when added with `add_doc_strict`.
+    AlreadyExists,
+    /// Adding the document would put more bytes under its permission than its quota allows.
+    QuotaExceeded,
+    /// The document's root name is already used by a different root document.
+    RootNameTaken,
+    /// The permission used doesn't allow adding or removing documents.
+    PermissionDenied,
+    /// A patched document didn't have the hash the patch was prepared for.
+    HashMismatch,
+}
+
+/// How the database handles a document whose schema has the same name as another schema in the 
+/// database, but a different version. The newest version of each named schema is tracked as 
+/// schemas are used by documents.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SchemaVersionPolicy {
+    /// The document's schema must be the newest known version.
+    StrictMatch,
+    /// The document must also pass validation by the newest known version of the schema.
+    AllowCompatible,
+    /// Schema versions are ignored.
+    AnyVersion,
 }
 
 /// Control operations on the database. For housekeeping and starting/stopping the database.
@@ -130,6 +217,38 @@ enum DbControl {
 struct QueryRequest {
     pub query:  Query,
     pub permission: Permission,
+    pub kind: QueryKind,
+}
+
+/// What a query request is looking for.
+enum QueryKind {
+    /// Documents matching the query.
+    Docs,
+    /// A single entry, by hash. The query itself is ignored.
+    EntryByHash(Hash),
+    /// Entries with the given field, from every document using the given schema. The query 
+    /// itself is ignored.
+    Entries((String, Hash)),
+    /// The schema document used by a document, by hash. The query itself is ignored.
+    SchemaForDoc(Hash),
+    /// Documents with the given indexed string field set to the given value. The query itself is 
+    /// ignored.
+    StringIndex((String, String)),
+    /// Documents using the given schema, whose given field is set to the given encoded value. The 
+    /// query itself is ignored.
+    FieldValue((Hash, String, Vec<u8>)),
+    /// Hash & field of every entry attached to the given document. The query itself is ignored.
+    EntryHashes(Hash),
+    /// Entries attached to the given document, optionally only those with the given field. The 
+    /// query itself is ignored.
+    DocEntries((Hash, Option<String>)),
+    /// The root document with the given name. The query itself is ignored.
+    RootDoc(String),
+    /// Names of every root document. The query itself is ignored.
+    RootNames,
+    /// Hashes of every stored document that no longer matches its hash. The query itself is 
+    /// ignored.
+    CorruptDocs,
 }
 
 /// Possible responses to a query.
@@ -138,6 +257,12 @@ pub enum QueryResponse {
     Doc((Document, i32)),
     /// An entry matching the query, along with the relative effort spent retrieving it.
     Entry((Entry, i32)),
+    /// The hash and field of an entry, without the entry itself.
+    EntryHash((Hash, String)),
+    /// The name of a root document.
+    RootName(String),
+    /// The hash of a stored document whose contents no longer match it.
+    CorruptDoc(Hash),
     /// Query has been exhausted. Only occurs for queries made to retrive a set list of documents. 
     /// The query channel is closed after this.
     DoneForever,
@@ -164,7 +289,7 @@ impl Db {
         let (control_in, control_out) = unbounded();
         let (change_in, change_out) = unbounded();
         let (query_in, query_out) = unbounded();
-        let db = rocksdb::DB::open_default(path).map_err(|e| e.into_string())?;
+        let db = open_rocks_db(path).map_err(|e| e.into_string())?;
         let handle = std::thread::spawn(move || db_loop(db, control_out, change_out, query_out));
         Ok(Db {
             handle,
@@ -182,6 +307,17 @@ impl Db {
         Ok(ChangeWait { chan: result_out })
     }
 
+    /// Submit a query request to the database. Returns a `QueryWait` that holds up to `capacity` 
+    /// responses at a time, or an error if `capacity` is 0.
+    fn make_query(&self, query: Query, perm: &Permission, kind: QueryKind, capacity: usize) -> Result<QueryWait, ()> {
+        if capacity == 0 { return Err(()); }
+        let (result_in, result_out) = bounded(capacity);
+        let (done, quit) = bounded(0); // Channel to drop when the query maker is done
+        let request = QueryRequest { query, permission: perm.clone(), kind };
+        self.query_in.send((request, result_in, quit)).map_err(|_e| ())?;
+        Ok(QueryWait { chan: result_out, done })
+    }
+
     /// Close out the database and safely exit. Blocks until the thread finishes. Returns an error 
     /// if the thread panicked or the control handle was already closed.
     pub fn close(self) -> Result<(), ()> {
@@ -197,10 +333,34 @@ impl Db {
         self.make_change(ChangeRequest::AddDoc((doc, perm.clone(), ttl)))
     }
 
+    /// Add a document to the database, like `add_doc`. The `ChangeWait` will return 
+    /// `AlreadyExists` if the document is already in the database, instead of succeeding.
+    pub fn add_doc_strict(&self, doc: Document, perm: &Permission, ttl: u32) -> Result<ChangeWait, ()> {
+        self.make_change(ChangeRequest::AddDocStrict((doc, perm.clone(), ttl)))
+    }
+
     /// Remove a document from the database, based on its hash. Returns a `ChangeWait` if request 
-    /// is successfully made.
-    pub fn del_doc(&self, hash: Hash) -> Result<ChangeWait, ()> {
-        self.make_change(ChangeRequest::DelDoc(hash))
+    /// is successfully made. The `ChangeWait` will return `PermissionDenied` if `perm` doesn't 
+    /// allow writes, and `StillReferenced` if the document is a root document or is linked to by 
+    /// another document or entry.
+    pub fn del_doc(&self, hash: Hash, perm: &Permission) -> Result<ChangeWait, ()> {
+        self.make_change(ChangeRequest::DelDoc((hash, perm.clone())))
+    }
+
+    /// Update top-level fields of a document without resending the whole thing. The document is 
+    /// decoded, each field in `patch` is set (overwriting any existing value), and the result is 
+    /// re-encoded, checked against its schema, and stored with the original's permissions and 
+    /// time-to-live, replacing the original. Signatures and entries on the original are not 
+    /// carried over, as they are tied to its hash.
+    ///
+    /// `expected_hash` is the hash of the patched document, as computed by the caller from their 
+    /// copy of the original. The `ChangeWait` will return `HashMismatch` if the patched document 
+    /// has a different hash, in which case nothing is changed. As with `del_doc`, it will return 
+    /// `PermissionDenied` if `perm` doesn't allow writes.
+    pub fn apply_patch(&self, doc_hash: Hash, patch: BTreeMap<String, Value>, expected_hash: Hash, perm: &Permission)
+        -> Result<ChangeWait, ()>
+    {
+        self.make_change(ChangeRequest::ApplyPatch((doc_hash, patch, expected_hash, perm.clone())))
     }
 
     /// Add an entry into the database. Returns a `ChangeWait` if request is successfully made.
@@ -216,6 +376,19 @@ impl Db {
         self.make_change(ChangeRequest::DelEntry((doc_hash, entry_hash)))
     }
 
+    /// Remove an entry from the database using only its hash. Returns a `ChangeWait` if request is 
+    /// successfully made. The `ChangeWait` will return `NoSuchEntry` if no entry has the hash.
+    pub fn del_entry_by_hash(&self, entry_hash: Hash) -> Result<ChangeWait, ()> {
+        self.make_change(ChangeRequest::DelEntryByHash(entry_hash))
+    }
+
+    /// Remove every entry with a given field from a document. Returns a `ChangeWait` if request 
+    /// is successfully made. The `ChangeWait` will return `NoSuchDoc` if the document isn't in the 
+    /// database, and `Ok` otherwise, even if no entries were removed.
+    pub fn del_entries_by_field(&self, doc_hash: Hash, field: String) -> Result<ChangeWait, ()> {
+        self.make_change(ChangeRequest::DelEntriesByField((doc_hash, field)))
+    }
+
     /// Delete documents and/or entries that match a given query.
     pub fn del_query(&self, query: Query) -> Result<ChangeWait, ()> {
         self.make_change(ChangeRequest::DelQuery(query))
@@ -231,13 +404,222 @@ impl Db {
         self.make_change(ChangeRequest::SetTtlEntry((doc, entry, ttl)))
     }
 
+    /// Remove every document with a time-to-live of 0 that has no entries, such as scratch 
+    /// documents that were never deleted. Schema documents still used by other documents are kept. 
+    /// The `ChangeWait` will return `OkCount` with the number of documents removed.
+    pub fn cleanup_temp_docs(&self) -> Result<ChangeWait, ()> {
+        self.make_change(ChangeRequest::CleanupTemp)
+    }
+
+    /// Set how documents using a different version of a schema than the newest one known are 
+    /// handled. The default is `SchemaVersionPolicy::AnyVersion`.
+    pub fn set_schema_version_policy(&self, policy: SchemaVersionPolicy) -> Result<ChangeWait, ()> {
+        self.make_change(ChangeRequest::SetSchemaVersionPolicy(policy))
+    }
+
+    /// Rebuild the database's schema tracking, reference counters, root document names, string 
+    /// field index, quota usage, and entry lookup from the stored documents and entries. Queries 
+    /// continue to be answered while the rebuild runs. If documents or entries are added or 
+    /// removed, or a string field is indexed, before the rebuild finishes, it starts over. The 
+    /// `ChangeWait` will return `Ok` once the rebuild is complete.
+    pub fn reindex(&self) -> Result<ChangeWait, ()> {
+        self.make_change(ChangeRequest::Reindex)
+    }
+
+    /// Start indexing documents by the value of the top-level string field `field`. All documents 
+    /// already in the database are indexed before the `ChangeWait` returns `Ok`. Documents where 
+    /// the field is missing or isn't a string are left out of the index. See 
+    /// [`query_string_index`](#method.query_string_index).
+    pub fn index_string_field(&self, field: &str) -> Result<ChangeWait, ()> {
+        self.make_change(ChangeRequest::IndexStringField(field.to_string()))
+    }
+
+    /// Get the number of bytes of documents counted against the quota of a permission. Documents 
+    /// count against the quota of every permission with the same sharing settings; the quota 
+    /// itself is ignored when matching them.
+    pub fn get_quota_usage(&self, perm: &Permission) -> Result<CountWait, ()> {
+        let (count_in, count_out) = bounded(1);
+        self.make_change(ChangeRequest::QuotaUsage((perm.clone(), count_in)))?;
+        Ok(CountWait { chan: count_out })
+    }
+
+    /// Write every document and entry in the database out as JSON, for human-readable backups. 
+    /// The output is an object with a `documents` array and an `entries` array. Documents are 
+    /// written as `{"hash": <hex>, "raw": <base64>, "schema": <hex> or null, "perm": <object>, 
+    /// "ttl": <integer>}`, and entries as `{"doc": <hex>, "field": <string>, "raw": <base64>, 
+    /// "ttl": <integer>}`. The permission object has a boolean for each of its settings, and 
+    /// `quota` as an integer or null. Blocks until the database has been copied out, and returns a 
+    /// `ChangeWait` that will be `Failed` if writing failed.
+    #[cfg(feature = "json")]
+    pub fn export_json<W: Write>(&self, w: &mut W) -> Result<ChangeWait, ()> {
+        let (snapshot_in, snapshot_out) = bounded(1);
+        let result = self.make_change(ChangeRequest::Export(snapshot_in))?;
+        let (docs, entries) = snapshot_out.recv().map_err(|_e| ())?;
+        result.recv().map_err(|_e| ())?;
+
+        let docs = docs.iter().map(|(hash, raw, perm, ttl)| {
+            let schema = match document::extract_schema_hash(&raw[..]) {
+                Ok(Some(schema)) => serde_json::Value::from(schema.to_hex_string()),
+                _ => serde_json::Value::Null,
+            };
+            let mut obj = serde_json::Map::new();
+            obj.insert("hash".to_string(), serde_json::Value::from(hash.to_hex_string()));
+            obj.insert("raw".to_string(), serde_json::Value::from(base64::encode(raw)));
+            obj.insert("schema".to_string(), schema);
+            obj.insert("perm".to_string(), perm_to_json(perm));
+            obj.insert("ttl".to_string(), serde_json::Value::from(*ttl));
+            serde_json::Value::Object(obj)
+        }).collect::<Vec<serde_json::Value>>();
+        let entries = entries.iter().map(|(doc, field, raw, ttl)| {
+            let mut obj = serde_json::Map::new();
+            obj.insert("doc".to_string(), serde_json::Value::from(doc.to_hex_string()));
+            obj.insert("field".to_string(), serde_json::Value::from(field.as_str()));
+            obj.insert("raw".to_string(), serde_json::Value::from(base64::encode(raw)));
+            obj.insert("ttl".to_string(), serde_json::Value::from(*ttl));
+            serde_json::Value::Object(obj)
+        }).collect::<Vec<serde_json::Value>>();
+        let mut backup = serde_json::Map::new();
+        backup.insert("documents".to_string(), serde_json::Value::Array(docs));
+        backup.insert("entries".to_string(), serde_json::Value::Array(entries));
+
+        let (result_in, result_out) = bounded(1);
+        if serde_json::to_writer(w, &serde_json::Value::Object(backup)).is_ok() {
+            result_in.send(ChangeResult::Ok).map_err(|_e| ())?;
+        }
+        else {
+            result_in.send(ChangeResult::Failed).map_err(|_e| ())?;
+        }
+        Ok(ChangeWait { chan: result_out })
+    }
+
+    /// Read in a JSON backup made by `export_json` and add every document and entry in it to the 
+    /// database, with the permission and time to live each had. Backups without these get 
+    /// `Permission::new()` and no time to live. Fails if the JSON isn't in the expected format, or 
+    /// if any document or entry fails to decode. Documents are added only once the schema they 
+    /// reference has been added, and the `ChangeWait` will return `SchemaNotFound` if a schema is 
+    /// missing from both the backup and the database.
+    #[cfg(feature = "json")]
+    pub fn import_json<R: Read>(&self, r: &mut R) -> Result<ChangeWait, ()> {
+        let backup: serde_json::Value = serde_json::from_reader(r).map_err(|_e| ())?;
+        let docs = backup.get("documents").and_then(|v| v.as_array()).ok_or(())?;
+        let entries = backup.get("entries").and_then(|v| v.as_array()).ok_or(())?;
+
+        let mut doc_list = Vec::with_capacity(docs.len());
+        for doc in docs.iter() {
+            let hash = doc.get("hash").and_then(|v| v.as_str()).ok_or(())?;
+            let hash = Hash::from_hex_string(hash).map_err(|_e| ())?;
+            let raw = doc.get("raw").and_then(|v| v.as_str()).ok_or(())?;
+            let raw = base64::decode(raw).map_err(|_e| ())?;
+            let doc_len = decode::verify_value(&mut &raw[..]).map_err(|_e| ())?;
+            let perm = match doc.get("perm") {
+                Some(perm) => perm_from_json(perm)?,
+                None => Permission::new(),
+            };
+            let ttl = ttl_from_json(doc)?;
+            doc_list.push((document::from_raw(&hash, raw, doc_len).map_err(|_e| ())?, perm, ttl));
+        }
+
+        let mut entry_list = Vec::with_capacity(entries.len());
+        for entry in entries.iter() {
+            let doc_hash = entry.get("doc").and_then(|v| v.as_str()).ok_or(())?;
+            let doc_hash = Hash::from_hex_string(doc_hash).map_err(|_e| ())?;
+            let field = entry.get("field").and_then(|v| v.as_str()).ok_or(())?;
+            let raw = entry.get("raw").and_then(|v| v.as_str()).ok_or(())?;
+            let raw = base64::decode(raw).map_err(|_e| ())?;
+            let entry_len = decode::verify_value(&mut &raw[..]).map_err(|_e| ())?;
+            // The backup doesn't carry entry hashes, so recalculate it from the parts
+            let hash = entry::compute_hash(&doc_hash, field, &raw[..]);
+            let ttl = ttl_from_json(entry)?;
+            entry_list.push((entry::from_raw(&hash, doc_hash, field, raw, entry_len).map_err(|_e| ())?, ttl));
+        }
+
+        self.make_change(ChangeRequest::Import((doc_list, entry_list)))
+    }
+
     pub fn query(&self, query: Query, perm: &Permission, capacity: usize) -> Result<QueryWait, ()> {
-        if capacity == 0 { return Err(()); }
-        let (result_in, result_out) = bounded(capacity);
-        let (done, quit) = bounded(0); // Channel to drop when the query maker is done
-        let request = QueryRequest { query: query, permission: perm.clone() };
-        self.query_in.send((request, result_in, quit)).map_err(|_e| ())?;
-        Ok(QueryWait { chan: result_out, done: done })
+        self.make_query(query, perm, QueryKind::Docs, capacity)
+    }
+
+    /// Retrieve an entry using only its hash. The `QueryWait` will return the entry, followed by 
+    /// `DoneForever`. If the entry isn't in the database, only `DoneForever` is returned.
+    pub fn get_entry_by_hash(&self, entry_hash: Hash) -> Result<QueryWait, ()> {
+        self.make_query(Query::new(), &Permission::new(), QueryKind::EntryByHash(entry_hash), 2)
+    }
+
+    /// Retrieve the hash and field of every entry attached to a document, without the entries 
+    /// themselves. The `QueryWait` returns an `EntryHash` for each entry, followed by 
+    /// `DoneForever`. Only entries present when the request is handled are returned.
+    pub fn entry_hashes(&self, doc_hash: Hash, perm: &Permission) -> Result<QueryWait, ()> {
+        self.make_query(Query::new(), perm, QueryKind::EntryHashes(doc_hash), 2)
+    }
+
+    /// Retrieve the entries attached to a document, optionally only those with the field 
+    /// `entry_type`. The `QueryWait` returns each entry found, followed by `DoneForever`. Only 
+    /// entries present when the request is handled are returned.
+    pub fn iter_entries(&self, doc_hash: Hash, entry_type: Option<String>, perm: &Permission, capacity: usize)
+        -> Result<QueryWait, ()>
+    {
+        self.make_query(Query::new(), perm, QueryKind::DocEntries((doc_hash, entry_type)), capacity)
+    }
+
+    /// Retrieve the root document registered under `name`. The `QueryWait` returns the document, 
+    /// followed by `DoneForever`. If there is no root document with that name, only 
+    /// `DoneForever` is returned.
+    pub fn get_root_doc(&self, name: String) -> Result<QueryWait, ()> {
+        self.make_query(Query::new(), &Permission::new(), QueryKind::RootDoc(name), 2)
+    }
+
+    /// Retrieve the names of every root document. The `QueryWait` returns a `RootName` for each 
+    /// name, followed by `DoneForever`. Only names present when the request is handled are 
+    /// returned.
+    pub fn list_root_names(&self) -> Result<QueryWait, ()> {
+        self.make_query(Query::new(), &Permission::new(), QueryKind::RootNames, 2)
+    }
+
+    /// Check every stored document against its hash. The `QueryWait` returns a `CorruptDoc` for 
+    /// each document whose stored bytes no longer match its hash, followed by `DoneForever`. 
+    /// Corrupted documents are never returned by other queries.
+    pub fn integrity_check(&self) -> Result<QueryWait, ()> {
+        self.make_query(Query::new(), &Permission::new(), QueryKind::CorruptDocs, 2)
+    }
+
+    /// Retrieve the schema document used by a document in the database. The `QueryWait` returns 
+    /// the schema document, followed by `DoneForever`. If the document isn't in the database or 
+    /// doesn't use a schema, only `DoneForever` is returned.
+    pub fn get_schema_for_doc(&self, hash: Hash) -> Result<QueryWait, ()> {
+        self.make_query(Query::new(), &Permission::new(), QueryKind::SchemaForDoc(hash), 2)
+    }
+
+    /// Retrieve every entry with the field `entry_type` from all documents that use the schema 
+    /// `schema_hash`. The `QueryWait` returns each entry found, followed by `DoneForever`. Only 
+    /// entries present when the request is handled are returned.
+    pub fn query_entries(&self, entry_type: String, schema_hash: Hash, perm: &Permission, capacity: usize)
+        -> Result<QueryWait, ()>
+    {
+        self.make_query(Query::new(), perm, QueryKind::Entries((entry_type, schema_hash)), capacity)
+    }
+
+    /// Retrieve every document with the indexed string field `field` set to `value`. The 
+    /// `QueryWait` returns each document found, followed by `DoneForever`. Only documents present 
+    /// when the request is handled are returned. If `field` isn't being indexed, only 
+    /// `DoneForever` is returned.
+    pub fn query_string_index(&self, field: &str, value: &str, perm: &Permission, capacity: usize)
+        -> Result<QueryWait, ()>
+    {
+        self.make_query(Query::new(), perm, QueryKind::StringIndex((field.to_string(), value.to_string())), capacity)
+    }
+
+    /// Retrieve every document using the schema `schema` that has the top-level field `field` set 
+    /// to `value`. The `QueryWait` returns each document found, followed by `DoneForever`. Only 
+    /// documents present when the request is handled are returned. Every document using the 
+    /// schema is checked, so prefer [`query_string_index`](#method.query_string_index) for 
+    /// frequent lookups on string fields.
+    pub fn query_by_field(&self, schema: Hash, field: String, value: Value, perm: &Permission, capacity: usize)
+        -> Result<QueryWait, ()>
+    {
+        let mut raw_value = Vec::new();
+        encode::write_value(&mut raw_value, &value);
+        self.make_query(Query::new(), perm, QueryKind::FieldValue((schema, field, raw_value)), capacity)
     }
 
 }
@@ -254,6 +636,21 @@ impl ChangeWait {
         self.chan.recv()
     }
 
+    /// Block until the database change request completes, like `recv`, but report a document or 
+    /// entry that failed its schema as `DbError::Schema`, and a dead database process as 
+    /// `DbError::Io`. Any other result is returned as-is.
+    pub fn wait(self) -> Result<ChangeResult, DbError> {
+        let result = self.chan.recv()?;
+        let reason = match result {
+            ChangeResult::FailedSchemaCheck => "failed validation against its schema",
+            ChangeResult::SchemaNotFound => "schema isn't in the database",
+            ChangeResult::NotValidSchema => "schema couldn't be read",
+            ChangeResult::SchemaVersionMismatch => "schema version isn't allowed by the version policy",
+            _ => return Ok(result),
+        };
+        Err(DbError::schema_error(io::Error::new(io::ErrorKind::InvalidData, reason)))
+    }
+
     /// Check to see if the change request has completed. Errors if channel is not ready, or if the 
     /// channel disconnected.
     pub fn try_recv(&self) -> Result<ChangeResult, TryRecvError> {
@@ -261,6 +658,25 @@ impl ChangeWait {
     }
 }
 
+/// A channel that receives a count from the database.
+pub struct CountWait {
+    chan: Receiver<u64>
+}
+
+impl CountWait {
+    /// Block until the count is ready. Errors if the count has been received already, or if the 
+    /// database process died.
+    pub fn recv(self) -> Result<u64, RecvError> {
+        self.chan.recv()
+    }
+
+    /// Check to see if the count is ready. Errors if channel is not ready, or if the channel 
+    /// disconnected.
+    pub fn try_recv(&self) -> Result<u64, TryRecvError> {
+        self.chan.try_recv()
+    }
+}
+
 pub struct QueryWait {
     chan: Receiver<QueryResponse>,
     done: Sender<()>
@@ -282,43 +698,151 @@ impl QueryWait {
     }
 }
 
+/// Open the RocksDB database backing a `Db`, creating it and its column families if needed.
+fn open_rocks_db<P: AsRef<Path>>(path: P) -> Result<rocksdb::DB, rocksdb::Error> {
+    let mut opts = rocksdb::Options::default();
+    opts.create_if_missing(true);
+    opts.create_missing_column_families(true);
+    rocksdb::DB::open_cf(&opts, path, &[REFERENCE_CF])
+}
+
+/// Reference counters for a document, as described for the `reference` store in the module 
+/// documentation.
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+struct References {
+    /// References from `root`.
+    root: u32,
+    /// References from documents, and from entries that require the document to satisfy a schema.
+    strong: u32,
+    /// All other references from entries.
+    weak: u32,
+}
+
+impl References {
+    /// Length of the encoded counters.
+    const LEN: usize = 12;
+
+    /// Checks if nothing references the document, in which case it can be removed automatically.
+    fn is_unreferenced(&self) -> bool {
+        (self.root == 0) && (self.strong == 0) && (self.weak == 0)
+    }
+
+    /// Encode the root, strong, and weak counters, in that order, as big-endian u32s.
+    fn to_bytes(&self) -> [u8; References::LEN] {
+        let mut raw = [0u8; References::LEN];
+        raw[0..4].copy_from_slice(&self.root.to_be_bytes());
+        raw[4..8].copy_from_slice(&self.strong.to_be_bytes());
+        raw[8..12].copy_from_slice(&self.weak.to_be_bytes());
+        raw
+    }
+
+    /// Decode counters written by `to_bytes`. Fails if `raw` isn't exactly 12 bytes long.
+    fn from_bytes(raw: &[u8]) -> Option<References> {
+        if raw.len() != References::LEN { return None; }
+        let read = |i: usize| {
+            let mut bytes = [0u8; 4];
+            bytes.copy_from_slice(&raw[i..(i + 4)]);
+            u32::from_be_bytes(bytes)
+        };
+        Some(References { root: read(0), strong: read(4), weak: read(8) })
+    }
+}
+
 struct InternalDb {
     /// The core database
     rocks_db: rocksdb::DB,
-    /// The document database
-    doc_db: HashMap<Hash,(usize, Vec<u8>,Permission,u32)>,
+    /// Index of documents by the value of selected string fields.
+    string_index: StringFieldIndex,
+    /// Hash of each root document, keyed by its unique name.
+    root_docs: HashMap<String, Hash>,
+    /// The document database. Holds the document length, raw document, permissions, time-to-live, 
+    /// and the hash of the document's schema.
+    doc_db: HashMap<Hash,(usize, Vec<u8>,Permission,u32,Option<Hash>)>,
     /// The database of entries
     entry_db: HashMap<Hash, Vec<(String,Vec<u8>,u32)>>,
+    /// Hash of each entry in the database, mapped to the hash of its parent document.
+    entry_hashes: HashMap<Hash, Hash>,
     /// Tracking how many places a given schema is used.
     schema_tracking: HashMap<Hash, usize>,
+    /// Newest version of each named schema, along with its hash.
+    schema_versions: HashMap<String, (u32, Hash)>,
+    /// How to handle documents that don't use the newest version of a schema.
+    version_policy: SchemaVersionPolicy,
+    /// Total size in bytes of the documents held under each set of sharing settings, for 
+    /// enforcing quotas. Keyed by the permission with its quota cleared.
+    quota_tracker: HashMap<Permission, u64>,
+    /// Reindex operation in progress, if any.
+    reindex: Option<Reindex>,
+}
+
+/// State of a partially completed reindex. Everything derived from the stored documents and 
+/// entries is rebuilt in new maps, which replace the current ones once every document has been 
+/// processed.
+struct Reindex {
+    /// Documents still to be processed.
+    remaining: Vec<Hash>,
+    schema_tracking: HashMap<Hash, usize>,
+    schema_versions: HashMap<String, (u32, Hash)>,
+    references: HashMap<Hash, References>,
+    root_docs: HashMap<String, Hash>,
+    /// Starts out indexing the same fields as the current index.
+    string_index: StringFieldIndex,
+    quota_tracker: HashMap<Permission, u64>,
+    entry_hashes: HashMap<Hash, Hash>,
+    /// Requesters waiting for the reindex to complete.
+    waiting: Vec<Sender<ChangeResult>>,
 }
 
 impl InternalDb {
     fn new(rocks_db: rocksdb::DB) -> InternalDb {
-        InternalDb {
+        let mut db = InternalDb {
             rocks_db,
+            string_index: StringFieldIndex::new(),
+            root_docs: HashMap::new(),
             doc_db: HashMap::new(),
             entry_db: HashMap::new(),
+            entry_hashes: HashMap::new(),
             schema_tracking: HashMap::new(),
-        }
+            schema_versions: HashMap::new(),
+            version_policy: SchemaVersionPolicy::AnyVersion,
+            quota_tracker: HashMap::new(),
+            reindex: None,
+        };
+        // Documents are only held in memory, so any counters left from an earlier run are stale
+        db.reset_references();
+        db
     }
 
     fn make_change(&mut self, change: ChangeRequest) -> ChangeResult {
         match change {
             ChangeRequest::AddDoc((doc, perm, ttl)) => {
+                if !perm.allows_write() { return ChangeResult::PermissionDenied; }
                 let hash = doc.hash();
                 if !self.doc_db.contains_key(&hash) {
+                    if let Some(quota) = perm.quota {
+                        if self.quota_usage(&perm) + (doc.size_bytes() as u64) > quota {
+                            return ChangeResult::QuotaExceeded;
+                        }
+                    }
                     let doc_len = doc.doc_len();
                     let doc = doc.to_vec();
+                    let root_name = root_name(&doc[..]);
+                    if let Some(ref name) = root_name {
+                        if self.root_docs.contains_key(name) { return ChangeResult::RootNameTaken; }
+                    }
                     // extract_schema_hash verifies the document is a msgpack object & gets the schema.
-                    let result = match document::extract_schema_hash(&doc[..]) {
-                        Ok(Some(schema_hash)) => {
-                            match self.doc_db.get(&schema_hash) {
-                                Some((_,schema,_,_)) => {
+                    let schema_hash = document::extract_schema_hash(&doc[..]);
+                    let result = match schema_hash {
+                        Ok(Some(ref schema_hash)) => {
+                            match self.doc_db.get(schema_hash) {
+                                Some((_,schema,_,_,_)) => {
                                     // Get the schema and verify the document
-                                    let schema_result = Schema::from_raw(&mut &schema[..]);
+                                    let schema_result = read_schema(&schema[..]);
                                     if let Ok(verifier) = schema_result {
                                         if let Ok(_) = verifier.validate_doc(&mut &doc[..]) {
+                                            if !self.version_ok(schema_hash, &verifier, &doc[..]) {
+                                                return ChangeResult::SchemaVersionMismatch;
+                                            }
                                             // Increment the schema tracking count
                                             self.schema_tracking.entry(schema_hash.clone())
                                                 .and_modify(|v| *v += 1)
@@ -344,7 +868,16 @@ impl InternalDb {
                         Err(_) => ChangeResult::FailedSchemaCheck, // Failed because it wasn't even a proper document
                     };
                     if result == ChangeResult::Ok {
-                        self.doc_db.insert(hash, (doc_len, doc, perm, ttl));
+                        *self.quota_tracker.entry(quota_key(&perm)).or_insert(0) += doc.len() as u64;
+                        self.string_index.add_doc(&hash, &doc[..]);
+                        for linked in value_hashes(&doc[..doc_len]) {
+                            self.update_references(&linked, |r| r.strong += 1);
+                        }
+                        if let Some(name) = root_name {
+                            self.root_docs.insert(name, hash.clone());
+                            self.update_references(&hash, |r| r.root += 1);
+                        }
+                        self.doc_db.insert(hash, (doc_len, doc, perm, ttl, schema_hash.unwrap_or(None)));
                     }
                     result
                 }
@@ -352,11 +885,27 @@ impl InternalDb {
                     ChangeResult::Ok
                 }
             },
-            ChangeRequest::DelDoc(hash) => {
+            ChangeRequest::AddDocStrict((doc, perm, ttl)) => {
+                if !perm.allows_write() {
+                    ChangeResult::PermissionDenied
+                }
+                else if self.doc_db.contains_key(&doc.hash()) {
+                    ChangeResult::AlreadyExists
+                }
+                else {
+                    self.make_change(ChangeRequest::AddDoc((doc, perm, ttl)))
+                }
+            },
+            ChangeRequest::DelDoc((hash, perm)) => {
+                // Schemas in use are strongly referenced too, but are reported as `SchemaInUse`
+                let still_referenced = !self.references(&hash).is_unreferenced()
+                    && self.schema_tracking.get(&hash).map_or(true, |count| *count == 0);
                 let result = match self.doc_db.get(&hash) {
-                    Some((_,doc,_,_)) => {
-                        if let Ok(Some(schema_hash)) = document::extract_schema_hash(&doc[..]) {
-                            self.schema_tracking.entry(schema_hash)
+                    Some(_) if !perm.allows_write() => ChangeResult::PermissionDenied,
+                    Some(_) if still_referenced => ChangeResult::StillReferenced,
+                    Some((_,_,_,_,schema_hash)) => {
+                        if let Some(schema_hash) = schema_hash {
+                            self.schema_tracking.entry(schema_hash.clone())
                                 .and_modify(|v| *v -= 1);
                         };
                         match self.schema_tracking.get(&hash) {
@@ -374,33 +923,97 @@ impl InternalDb {
                     None => ChangeResult::NoSuchDoc,
                 };
                 if result == ChangeResult::Ok {
-                    self.doc_db.remove(&hash);
-                    self.entry_db.remove(&hash);
+                    // Entries are released first, while the schema they were checked against can 
+                    // still be found.
+                    if let Some(entries) = self.entry_db.remove(&hash) {
+                        for (field, entry, _) in entries.iter() {
+                            self.entry_hashes.remove(&entry::compute_hash(&hash, field, &entry[..]));
+                            self.release_entry(&hash, field, &entry[..]);
+                        }
+                    }
+                    if let Some((doc_len, doc, perm, _, _)) = self.doc_db.remove(&hash) {
+                        if let Some(bytes) = self.quota_tracker.get_mut(&quota_key(&perm)) {
+                            *bytes -= doc.len() as u64;
+                        }
+                        self.string_index.remove_doc(&hash, &doc[..]);
+                        for linked in value_hashes(&doc[..doc_len]) {
+                            self.update_references(&linked, |r| r.strong = r.strong.saturating_sub(1));
+                        }
+                        if let Some(name) = root_name(&doc[..]) {
+                            self.root_docs.remove(&name);
+                        }
+                    }
+                }
+                result
+            },
+            ChangeRequest::ApplyPatch((doc_hash, patch, expected_hash, caller_perm)) => {
+                let (doc, perm, ttl) = match self.doc_db.get(&doc_hash) {
+                    Some(_) if !caller_perm.allows_write() => return ChangeResult::PermissionDenied,
+                    Some((doc_len, doc, perm, ttl, _)) => {
+                        match decode::read_value(&mut &doc[..*doc_len]) {
+                            Ok(Value::Object(doc)) => (doc, perm.clone(), *ttl),
+                            _ => return ChangeResult::Failed,
+                        }
+                    },
+                    None => return ChangeResult::NoSuchDoc,
+                };
+                if self.schema_tracking.get(&doc_hash).map_or(false, |count| *count > 0) {
+                    return ChangeResult::SchemaInUse;
+                }
+                // The document's own root reference passes to the patched document
+                let refs = self.references(&doc_hash);
+                if (refs.strong > 0) || (refs.weak > 0) { return ChangeResult::StillReferenced; }
+                let mut doc = doc;
+                doc.extend(patch.into_iter());
+                let patched = match Document::new(Value::Object(doc)) {
+                    Ok(patched) => patched,
+                    Err(_) => return ChangeResult::Failed,
+                };
+                let patched_hash = patched.hash();
+                if patched_hash != expected_hash { return ChangeResult::HashMismatch; }
+                if patched_hash == doc_hash { return ChangeResult::Ok; }
+
+                // The patched document may keep the original's root name, so release it while 
+                // adding the patched document, and restore it if that fails.
+                let old_root = self.doc_db.get(&doc_hash)
+                    .and_then(|(_, doc, _, _, _)| root_name(&doc[..]));
+                if let Some(ref name) = old_root { self.root_docs.remove(name); }
+                let result = self.make_change(ChangeRequest::AddDoc((patched, perm.clone(), ttl)));
+                if result != ChangeResult::Ok {
+                    if let Some(name) = old_root { self.root_docs.insert(name, doc_hash); }
+                    return result;
                 }
+                let new_root = self.doc_db.get(&patched_hash)
+                    .and_then(|(_, doc, _, _, _)| root_name(&doc[..]));
+                if let Some(name) = old_root.clone() {
+                    self.root_docs.insert(name, doc_hash.clone());
+                    self.update_references(&doc_hash, |r| r.root = r.root.saturating_sub(1));
+                }
+                let result = self.make_change(ChangeRequest::DelDoc((doc_hash.clone(), caller_perm)));
+                if (result != ChangeResult::Ok) && old_root.is_some() {
+                    self.update_references(&doc_hash, |r| r.root += 1);
+                }
+                if let Some(name) = new_root { self.root_docs.insert(name, patched_hash); }
                 result
             },
             ChangeRequest::AddEntry((entry, ttl)) => {
+                let entry_hash = entry.hash();
+                if let Some((doc_hash, index)) = self.find_entry(&entry_hash) {
+                    self.entry_db.get_mut(&doc_hash).unwrap()[index].2 = ttl;
+                    return ChangeResult::Ok;
+                }
                 let (doc_hash, field, entry) = entry.to_parts();
-                let result = self.doc_db.get(&doc_hash);
-                if result.is_none() { return ChangeResult::NoSuchDoc; }
-                let (_,doc,_,_) = result.unwrap();
-                if let Some(schema_hash) = document::extract_schema_hash(&doc[..])
-                    .expect(&format!("Corrupted Database: Document wasn't a valid document: {:X?}", doc_hash))
-                {
-                    let (_,schema,_,_) = self.doc_db
-                        .get(&schema_hash)
-                        .expect(&format!("Corrupted Database: Document's schema is missing: {:X?}", schema_hash));
-                    let schema = Schema::from_raw(&mut &schema[..])
-                        .expect(&format!("Corrupted Database: Schema that was added can't be read: {:X?}", schema_hash));
-
+                if !self.doc_db.contains_key(&doc_hash) { return ChangeResult::NoSuchDoc; }
+                if let Some(schema) = self.get_schema_for_doc(&doc_hash) {
                     // Validate against retrieved schema
-                    let checklist = schema.validate_entry(&field, &mut &entry[..]);
+                    let checklist = schema.validate_entry_with_checklist(&field, &mut &entry[..],
+                        Checklist::with_limit(MAX_ENTRY_DOC_CHECKS));
                     if checklist.is_err() { return ChangeResult::FailedSchemaCheck; }
                     let checklist = checklist.unwrap();
 
                     // Go through all items in the checklist created by the schema
                     if checklist.iter().all(|(hash, list)| {
-                        if let Some((_,doc,_,_)) = self.doc_db.get(&hash) {
+                        if let Some((_,doc,_,_,_)) = self.doc_db.get(&hash) {
                             list.iter().all(|index| schema.validate_checklist_item(*index, &mut &doc[..]).is_ok())
                         }
                         else {
@@ -408,9 +1021,8 @@ impl InternalDb {
                         }
                     })
                     {
-                        self.entry_db.entry(doc_hash)
-                            .or_insert(Vec::with_capacity(1)) 
-                            .push((field, entry, ttl));
+                        let required: Vec<Hash> = checklist.iter().map(|(hash, _)| hash.clone()).collect();
+                        self.store_entry(doc_hash, entry_hash, field, entry, ttl, &required);
                         ChangeResult::Ok
                     }
                     else {
@@ -418,127 +1030,745 @@ impl InternalDb {
                     }
                 }
                 else {
-                    self.entry_db.entry(doc_hash)
-                        .or_insert(Vec::with_capacity(1)) 
-                        .push((field, entry, ttl));
+                    self.store_entry(doc_hash, entry_hash, field, entry, ttl, &[]);
                     ChangeResult::Ok
                 }
             }
             ChangeRequest::DelEntry(_)      => ChangeResult::Failed,
+            ChangeRequest::DelEntryByHash(entry_hash) => {
+                match self.find_entry(&entry_hash) {
+                    Some((doc_hash, index)) => {
+                        let (now_empty, (field, entry, _)) = {
+                            let entries = self.entry_db.get_mut(&doc_hash).unwrap();
+                            let removed = entries.remove(index);
+                            (entries.is_empty(), removed)
+                        };
+                        if now_empty { self.entry_db.remove(&doc_hash); }
+                        self.entry_hashes.remove(&entry_hash);
+                        self.release_entry(&doc_hash, &field, &entry[..]);
+                        ChangeResult::Ok
+                    },
+                    None => ChangeResult::NoSuchEntry,
+                }
+            },
+            ChangeRequest::DelEntriesByField((doc_hash, field)) => {
+                if !self.doc_db.contains_key(&doc_hash) {
+                    return ChangeResult::NoSuchDoc;
+                }
+                let mut removed = Vec::new();
+                let now_empty = match self.entry_db.get_mut(&doc_hash) {
+                    Some(entries) => {
+                        let entry_hashes = &mut self.entry_hashes;
+                        entries.retain(|(entry_field, entry, _)| {
+                            if *entry_field == field {
+                                entry_hashes.remove(&entry::compute_hash(&doc_hash, entry_field, &entry[..]));
+                                removed.push(entry.clone());
+                                false
+                            }
+                            else {
+                                true
+                            }
+                        });
+                        entries.is_empty()
+                    },
+                    None => false,
+                };
+                if now_empty { self.entry_db.remove(&doc_hash); }
+                for entry in removed.iter() {
+                    self.release_entry(&doc_hash, &field, &entry[..]);
+                }
+                ChangeResult::Ok
+            },
+            ChangeRequest::CleanupTemp => {
+                // Removing a document can free up the documents it referenced, so keep going until 
+                // nothing more can be removed. Referenced documents, including schemas in use, are 
+                // skipped up front, as a failed `DelDoc` still releases the schema the deleted 
+                // document used.
+                let mut count = 0;
+                loop {
+                    let candidates: Vec<(Hash, Permission)> = self.doc_db.iter()
+                        .filter(|(hash, (_, _, _, ttl, _))| {
+                            (*ttl == 0) && !self.entry_db.contains_key(hash)
+                                && self.schema_tracking.get(hash).map_or(true, |count| *count == 0)
+                                && self.references(hash).is_unreferenced()
+                        })
+                        .map(|(hash, (_, _, perm, _, _))| (hash.clone(), perm.clone()))
+                        .collect();
+                    let removed = candidates.into_iter()
+                        .filter(|(hash, perm)| {
+                            self.make_change(ChangeRequest::DelDoc((hash.clone(), perm.clone()))) == ChangeResult::Ok
+                        })
+                        .count() as u64;
+                    if removed == 0 { break; }
+                    count += removed;
+                }
+                ChangeResult::OkCount(count)
+            },
             ChangeRequest::DelQuery(_)      => ChangeResult::Failed,
             ChangeRequest::SetTtlDoc(_)     => ChangeResult::Failed,
             ChangeRequest::SetTtlEntry(_)   => ChangeResult::Failed,
+            ChangeRequest::Export(chan) => {
+                let docs = self.doc_db.iter()
+                    .map(|(hash, (_,doc,perm,ttl,_))| (hash.clone(), doc.clone(), perm.clone(), *ttl))
+                    .collect();
+                let entries = self.entry_db.iter()
+                    .flat_map(|(hash, list)| {
+                        list.iter().map(move |(field, entry, ttl)| (hash.clone(), field.clone(), entry.clone(), *ttl))
+                    })
+                    .collect();
+                match chan.send((docs, entries)) {
+                    Ok(_) => ChangeResult::Ok,
+                    Err(_) => ChangeResult::Failed,
+                }
+            },
+            ChangeRequest::Import((mut docs, entries)) => {
+                // Keep making passes over the documents, adding any whose schema is present, 
+                // until none are left or no more can be added.
+                while docs.len() > 0 {
+                    let mut pending = Vec::with_capacity(docs.len());
+                    let start_len = docs.len();
+                    for (doc, perm, ttl) in docs.drain(..) {
+                        let ready = {
+                            let value = doc.get_value();
+                            match value.as_obj().and_then(|obj| obj.get("")).and_then(|v| v.as_hash()) {
+                                Some(schema_hash) => self.doc_db.contains_key(schema_hash),
+                                None => true,
+                            }
+                        };
+                        if ready {
+                            let result = self.make_change(ChangeRequest::AddDoc((doc, perm, ttl)));
+                            if result != ChangeResult::Ok { return result; }
+                        }
+                        else {
+                            pending.push((doc, perm, ttl));
+                        }
+                    }
+                    if pending.len() == start_len { return ChangeResult::SchemaNotFound; }
+                    docs = pending;
+                }
+                for (entry, ttl) in entries.into_iter() {
+                    let result = self.make_change(ChangeRequest::AddEntry((entry, ttl)));
+                    if result != ChangeResult::Ok { return result; }
+                }
+                ChangeResult::Ok
+            },
+            ChangeRequest::SetSchemaVersionPolicy(policy) => {
+                self.version_policy = policy;
+                ChangeResult::Ok
+            },
+            ChangeRequest::Reindex => {
+                self.start_reindex(None);
+                while !self.reindex_step(usize::max_value()) { }
+                ChangeResult::Ok
+            },
+            ChangeRequest::QuotaUsage((perm, chan)) => {
+                match chan.send(self.quota_usage(&perm)) {
+                    Ok(_) => ChangeResult::Ok,
+                    Err(_) => ChangeResult::Failed,
+                }
+            },
+            ChangeRequest::IndexStringField(field) => {
+                if self.string_index.add_field(&field) {
+                    for (hash, doc) in self.doc_db.iter() {
+                        self.string_index.add(hash, &doc.1[..], &field);
+                    }
+                }
+                ChangeResult::Ok
+            },
         }
     }
 
-    /// Retrieve a document. If decoding the document fails, return nothing & assume it is 
-    /// corrupted.
-    fn get_doc(&self, hash: &Hash, _perm: &Permission) -> Option<Document> {
-        match self.doc_db.get(hash) {
-            Some(storage) => {
-                let doc = storage.1.clone();
-                match super::document::from_raw(hash, doc, storage.0) {
-                    Ok(doc) => Some(doc),
-                    Err(_) => None,
+    /// Begin rebuilding schema tracking, reference counters, and the other indexes from the stored 
+    /// documents and entries, discarding any partial reindex already in progress. The optional 
+    /// channel is sent the result once the reindex finishes, along with those of any reindex that 
+    /// was restarted.
+    fn start_reindex(&mut self, resp: Option<Sender<ChangeResult>>) {
+        let mut waiting = match self.reindex.take() {
+            Some(reindex) => reindex.waiting,
+            None => Vec::new(),
+        };
+        waiting.extend(resp);
+        let mut string_index = StringFieldIndex::new();
+        for field in self.string_index.fields() {
+            string_index.add_field(field);
+        }
+        self.reindex = Some(Reindex {
+            remaining: self.doc_db.keys().cloned().collect(),
+            schema_tracking: HashMap::new(),
+            schema_versions: HashMap::new(),
+            references: HashMap::new(),
+            root_docs: HashMap::new(),
+            string_index,
+            quota_tracker: HashMap::new(),
+            entry_hashes: HashMap::new(),
+            waiting,
+        });
+    }
+
+    /// Process up to `count` documents for the reindex in progress. Returns true if there is no 
+    /// reindex in progress, or if this step finished it.
+    fn reindex_step(&mut self, count: usize) -> bool {
+        let mut reindex = match self.reindex.take() {
+            Some(reindex) => reindex,
+            None => return true,
+        };
+        for _ in 0..count {
+            let hash = match reindex.remaining.pop() {
+                Some(hash) => hash,
+                None => break,
+            };
+            if let Some((doc_len, doc, perm, _, _)) = self.doc_db.get(&hash) {
+                *reindex.quota_tracker.entry(quota_key(perm)).or_insert(0) += doc.len() as u64;
+                reindex.string_index.add_doc(&hash, &doc[..]);
+                for linked in value_hashes(&doc[..*doc_len]) {
+                    reindex.references.entry(linked).or_default().strong += 1;
                 }
+                if let Some(name) = root_name(&doc[..]) {
+                    reindex.root_docs.insert(name, hash.clone());
+                    reindex.references.entry(hash.clone()).or_default().root += 1;
+                }
+            }
+            if let Some(entries) = self.entry_db.get(&hash) {
+                for (field, entry, _) in entries.iter() {
+                    reindex.entry_hashes.insert(entry::compute_hash(&hash, field, &entry[..]), hash.clone());
+                    let required = self.entry_required(&hash, field, &entry[..]);
+                    for linked in value_hashes(&entry[..]) {
+                        let refs = reindex.references.entry(linked.clone()).or_default();
+                        if required.contains(&linked) { refs.strong += 1; } else { refs.weak += 1; }
+                    }
+                }
+            }
+            let schema_hash = self.doc_db.get(&hash).and_then(|(_,_,_,_,schema_hash)| schema_hash.clone());
+            if let Some(schema_hash) = schema_hash {
+                let count = reindex.schema_tracking.entry(schema_hash.clone()).or_insert(0);
+                *count += 1;
+                // Record the schema's version the first time it's seen
+                if *count == 1 {
+                    let schema = self.doc_db.get(&schema_hash)
+                        .and_then(|(_,schema,_,_,_)| Schema::from_raw(&mut &schema[..]).ok())
+                        .filter(|schema| !schema.name().is_empty());
+                    if let Some(schema) = schema {
+                        let newest = reindex.schema_versions.entry(schema.name().to_string())
+                            .or_insert((schema.version(), schema_hash.clone()));
+                        if schema.version() > newest.0 {
+                            *newest = (schema.version(), schema_hash);
+                        }
+                    }
+                }
+            }
+        }
+        if reindex.remaining.is_empty() {
+            self.schema_tracking = reindex.schema_tracking;
+            self.schema_versions = reindex.schema_versions;
+            self.root_docs = reindex.root_docs;
+            self.string_index = reindex.string_index;
+            self.quota_tracker = reindex.quota_tracker;
+            self.entry_hashes = reindex.entry_hashes;
+            self.reset_references();
+            for (hash, refs) in reindex.references.iter() {
+                self.store_references(hash, refs);
+            }
+            for resp in reindex.waiting.drain(..) {
+                resp.send(ChangeResult::Ok).unwrap_or(());
+            }
+            true
+        }
+        else {
+            self.reindex = Some(reindex);
+            false
+        }
+    }
+
+    /// Record the schema's version and check that a document validated against it is acceptable 
+    /// under the database's schema version policy. Unnamed schemas aren't versions of each other, 
+    /// so they aren't tracked and always pass.
+    fn version_ok(&mut self, schema_hash: &Hash, schema: &Schema, doc: &[u8]) -> bool {
+        if schema.name().is_empty() { return true; }
+        let newest = self.schema_versions.entry(schema.name().to_string())
+            .or_insert((schema.version(), schema_hash.clone()));
+        if schema.version() > newest.0 {
+            *newest = (schema.version(), schema_hash.clone());
+        }
+        if newest.0 == schema.version() { return true; }
+        match self.version_policy {
+            SchemaVersionPolicy::AnyVersion => true,
+            SchemaVersionPolicy::StrictMatch => false,
+            SchemaVersionPolicy::AllowCompatible => {
+                let newest = &newest.1;
+                self.doc_db.get(newest)
+                    .and_then(|(_,schema,_,_,_)| read_schema(&schema[..]).ok())
+                    .map_or(false, |schema| schema.validate_doc(&mut &doc[..]).is_ok())
             },
-            None => None,
         }
     }
-}
 
-struct OpenQuery {
-    root: Hash,
-    perm: Permission,
-    channel: Sender<QueryResponse>,
-    quit: Receiver<()>,
-    root_in_db: bool,
-    root_sent: bool,
-    active: bool,
-}
+    /// Get the number of document bytes counted against a permission's quota.
+    fn quota_usage(&self, perm: &Permission) -> u64 {
+        self.quota_tracker.get(&quota_key(perm)).cloned().unwrap_or(0)
+    }
 
-impl OpenQuery {
-    fn new(root: Hash, perm: Permission, channel: Sender<QueryResponse>, quit: Receiver<()>) -> OpenQuery {
-        OpenQuery {
-            root,
-            perm,
-            channel,
-            quit, 
-            root_in_db: true,
-            root_sent: false,
-            active: true,
+    /// Get the hash of the schema used by a stored document. Returns `None` if the document isn't 
+    /// in the database or doesn't use a schema.
+    fn schema_hash_for_doc(&self, doc_hash: &Hash) -> Option<Hash> {
+        self.doc_db.get(doc_hash).and_then(|(_,_,_,_,schema_hash)| schema_hash.clone())
+    }
+
+    /// Retrieve and read the schema used by a stored document. Returns `None` if the document 
+    /// isn't in the database or doesn't use a schema. Panics if the schema is missing or can't be 
+    /// read, as a schema is checked when added and can't be removed while documents use it.
+    fn get_schema_for_doc(&self, doc_hash: &Hash) -> Option<Schema> {
+        let schema_hash = self.schema_hash_for_doc(doc_hash)?;
+        let (_,schema,_,_,_) = self.doc_db
+            .get(&schema_hash)
+            .expect(&format!("Corrupted Database: Document's schema is missing: {:X?}", schema_hash));
+        let schema = read_schema(&schema[..])
+            .expect(&format!("Corrupted Database: Schema that was added can't be read: {:X?}", schema_hash));
+        Some(schema)
+    }
+
+    /// Add an entry that has passed validation to the database. `required` holds the hashes the 
+    /// document's schema required the entry to link to; these are strong references, and any 
+    /// other hashes in the entry are weak references.
+    fn store_entry(&mut self, doc_hash: Hash, entry_hash: Hash, field: String, entry: Vec<u8>, ttl: u32,
+        required: &[Hash])
+    {
+        for linked in value_hashes(&entry[..]) {
+            if required.contains(&linked) {
+                self.update_references(&linked, |r| r.strong += 1);
+            }
+            else {
+                self.update_references(&linked, |r| r.weak += 1);
+            }
         }
+        self.entry_db.entry(doc_hash.clone())
+            .or_insert(Vec::with_capacity(1))
+            .push((field, entry, ttl));
+        self.entry_hashes.insert(entry_hash, doc_hash);
     }
 
-    fn channel_full(&self) -> bool {
-        self.channel.is_full()
+    /// Release the references held by an entry being removed. Must be called while the entry's 
+    /// document is still in the database, so the same hashes are found to be required as when 
+    /// the entry was stored.
+    fn release_entry(&mut self, doc_hash: &Hash, field: &str, entry: &[u8]) {
+        let required = self.entry_required(doc_hash, field, entry);
+        for linked in value_hashes(entry) {
+            if required.contains(&linked) {
+                self.update_references(&linked, |r| r.strong = r.strong.saturating_sub(1));
+            }
+            else {
+                self.update_references(&linked, |r| r.weak = r.weak.saturating_sub(1));
+            }
+        }
     }
 
-    fn get_in_db(&self) -> bool {
-        self.root_in_db
+    /// Get the hashes a stored entry is required to link to by its document's schema.
+    fn entry_required(&self, doc_hash: &Hash, field: &str, entry: &[u8]) -> Vec<Hash> {
+        match self.get_schema_for_doc(doc_hash) {
+            Some(schema) => schema.validate_entry_with_checklist(field, &mut &entry[..],
+                Checklist::with_limit(MAX_ENTRY_DOC_CHECKS))
+                .map(|checklist| checklist.iter().map(|(hash, _)| hash.clone()).collect())
+                .unwrap_or_default(),
+            None => Vec::new(),
+        }
     }
 
-    fn set_in_db(&mut self, in_db: bool) {
-        self.root_in_db = in_db;
+    /// Get the reference counters of a document from the `reference` store. Documents without a 
+    /// record have no references.
+    fn references(&self, hash: &Hash) -> References {
+        let cf = self.rocks_db.cf_handle(REFERENCE_CF)
+            .expect("Database wasn't opened with the reference column family");
+        let mut key = Vec::new();
+        hash.encode(&mut key);
+        match self.rocks_db.get_cf(cf, &key).expect("Database failed to read reference counters") {
+            Some(raw) => References::from_bytes(&raw)
+                .expect(&format!("Corrupted Database: Reference counters are malformed: {:X?}", hash)),
+            None => References::default(),
+        }
     }
 
-    fn get_root(&self) -> &Hash {
-        &self.root
+    /// Update the reference counters of a document, dropping its record once nothing references 
+    /// it.
+    fn update_references<F: FnOnce(&mut References)>(&mut self, hash: &Hash, f: F) {
+        let mut refs = self.references(hash);
+        f(&mut refs);
+        self.store_references(hash, &refs);
     }
 
-    fn is_root_sent(&self) -> bool {
-        self.root_sent
+    /// Write the reference counters of a document to the `reference` store, or remove its record 
+    /// if nothing references it.
+    fn store_references(&self, hash: &Hash, refs: &References) {
+        let cf = self.rocks_db.cf_handle(REFERENCE_CF)
+            .expect("Database wasn't opened with the reference column family");
+        let mut key = Vec::new();
+        hash.encode(&mut key);
+        if refs.is_unreferenced() {
+            self.rocks_db.delete_cf(cf, &key).expect("Database failed to remove reference counters");
+        }
+        else {
+            self.rocks_db.put_cf(cf, &key, &refs.to_bytes()[..]).expect("Database failed to write reference counters");
+        }
     }
 
-    fn root_sent(&mut self) {
-        self.root_sent = true;
+    /// Remove every record from the `reference` store.
+    fn reset_references(&mut self) {
+        self.rocks_db.drop_cf(REFERENCE_CF).expect("Database failed to drop reference counters");
+        self.rocks_db.create_cf(REFERENCE_CF, &rocksdb::Options::default())
+            .expect("Database failed to recreate reference counters");
     }
 
-    fn get_perm(&self) -> &Permission {
-        &self.perm
+    /// Locate an entry by its hash. Returns the parent document's hash and the entry's position in 
+    /// the document's list of entries.
+    fn find_entry(&self, entry_hash: &Hash) -> Option<(Hash, usize)> {
+        let doc_hash = self.entry_hashes.get(entry_hash)?;
+        self.entry_db.get(doc_hash)?
+            .iter()
+            .position(|(field, entry, _)| &entry::compute_hash(doc_hash, field, &entry[..]) == entry_hash)
+            .map(|index| (doc_hash.clone(), index))
     }
 
-    fn finish(&mut self) {
-        self.active = false;
+    /// Get the hashes of all entries with a given field, from documents using a given schema.
+    fn find_entries(&self, field: &str, schema_hash: &Hash) -> Vec<Hash> {
+        self.doc_db.iter()
+            .filter(|(_, (_,_,_,_,doc_schema))| doc_schema.as_ref() == Some(schema_hash))
+            .filter_map(|(doc_hash, _)| self.entry_db.get(doc_hash).map(|list| (doc_hash, list)))
+            .flat_map(|(doc_hash, list)| {
+                list.iter()
+                    .filter(|(entry_field, _, _)| entry_field == field)
+                    .map(move |(entry_field, entry, _)| entry::compute_hash(doc_hash, entry_field, &entry[..]))
+            })
+            .collect()
     }
 
-    fn try_send(&self, response: QueryResponse) -> Result<(), TrySendError<QueryResponse>> {
-        self.channel.try_send(response)
+    /// Get the hashes of all entries attached to a document, optionally only those with a given 
+    /// field.
+    fn find_doc_entries(&self, doc_hash: &Hash, field: Option<&str>) -> Vec<Hash> {
+        self.entry_db.get(doc_hash)
+            .map(|list| {
+                list.iter()
+                    .filter(|(entry_field, _, _)| field.map_or(true, |field| entry_field == field))
+                    .map(|(entry_field, entry, _)| entry::compute_hash(doc_hash, entry_field, &entry[..]))
+                    .collect()
+            })
+            .unwrap_or_else(Vec::new)
     }
 
-    /// Returns true if the query cannot return anything yet
-    fn not_ready(&self) -> bool {
-        !self.active || self.channel_full() || !self.get_in_db()
+    /// Get the hash and field of every entry attached to a document.
+    fn entry_headers(&self, doc_hash: &Hash) -> Vec<(Hash, String)> {
+        self.entry_db.get(doc_hash)
+            .map(|list| {
+                list.iter()
+                    .map(|(field, entry, _)| (entry::compute_hash(doc_hash, field, &entry[..]), field.clone()))
+                    .collect()
+            })
+            .unwrap_or_else(Vec::new)
     }
 
-    /// Run the query on the database. This will push either nothing to the response channel, or a 
-    /// single response. It never pushes multiple responses in a single call.
-    fn run(&mut self, db: &InternalDb) {
-        // Check to see if query is still open, and halt if it isn't
-        self.active = match self.quit.try_recv() {
-            Err(e) => {
-                !e.is_disconnected()
-            }
-            _ => true
-        };
-        if !self.active { return; }
+    /// Find all documents using a given schema that have a field set to a given encoded value. 
+    /// Encoding is canonical, so the raw bytes can be compared directly.
+    fn find_docs_by_field(&self, schema_hash: &Hash, field: &str, raw_value: &[u8]) -> Vec<Hash> {
+        self.doc_db.iter()
+            .filter(|(_, (_,_,_,_,doc_schema))| doc_schema.as_ref() == Some(schema_hash))
+            .filter(|(_, (_,doc,_,_,_))| {
+                match decode::try_extract_field(&doc[..], field) {
+                    Ok(Some(raw)) => raw == raw_value,
+                    _ => false,
+                }
+            })
+            .map(|(doc_hash, _)| doc_hash.clone())
+            .collect()
+    }
 
-        if self.is_root_sent() {
-            if let Ok(()) = self.try_send(QueryResponse::DoneForever) {
-                self.finish();
-            }
+    /// Retrieve an entry by its hash. If decoding the entry fails, return nothing & assume it is 
+    /// corrupted.
+    fn get_entry(&self, entry_hash: &Hash) -> Option<Entry> {
+        let (doc_hash, index) = self.find_entry(entry_hash)?;
+        let (field, entry, _) = &self.entry_db.get(&doc_hash)?[index];
+        let entry_len = decode::verify_value(&mut &entry[..]).ok()?;
+        entry::from_raw(entry_hash, doc_hash, field, entry.clone(), entry_len).ok()
+    }
+
+    /// Retrieve a document. If decoding the document fails, including when its bytes no longer 
+    /// match its hash, return nothing & assume it is corrupted.
+    fn get_doc(&self, hash: &Hash, _perm: &Permission) -> Option<Document> {
+        match self.doc_db.get(hash) {
+            Some(storage) => {
+                let doc = storage.1.clone();
+                super::document::from_raw(hash, doc, storage.0).ok()
+            },
+            None => None,
         }
-        match db.get_doc(self.get_root(), self.get_perm()) {
-            Some(doc) => {
-                if let Ok(()) = self.try_send(QueryResponse::Doc((doc, 0))) {
-                    self.root_sent();
-                };
+    }
+
+    /// Work out what a query will send, as of now. Queries for documents by hash get one open 
+    /// query per document, which waits for the document if it isn't in the database yet.
+    fn open_query(&self, query: &Query, kind: QueryKind) -> Vec<OpenQueryKind> {
+        let kind = match kind {
+            QueryKind::Docs => {
+                return query.root_iter()
+                    .map(|root| OpenQueryKind::Root { hash: root.clone(), in_db: true, sent: false })
+                    .collect();
+            },
+            QueryKind::EntryByHash(entry_hash) => OpenQueryKind::Entries(vec![entry_hash]),
+            QueryKind::Entries((field, schema_hash)) => {
+                OpenQueryKind::Entries(self.find_entries(&field, &schema_hash))
+            },
+            QueryKind::SchemaForDoc(doc_hash) => {
+                match self.schema_hash_for_doc(&doc_hash) {
+                    Some(hash) => OpenQueryKind::Root { hash, in_db: true, sent: false },
+                    None => OpenQueryKind::Docs(Vec::new()), // Nothing to send, so finish right away
+                }
+            },
+            QueryKind::StringIndex((field, value)) => {
+                OpenQueryKind::Docs(self.string_index.query(&field, &value).collect())
+            },
+            QueryKind::FieldValue((schema_hash, field, raw_value)) => {
+                OpenQueryKind::Docs(self.find_docs_by_field(&schema_hash, &field, &raw_value[..]))
+            },
+            QueryKind::EntryHashes(doc_hash) => OpenQueryKind::EntryHeaders(self.entry_headers(&doc_hash)),
+            QueryKind::DocEntries((doc_hash, field)) => {
+                OpenQueryKind::Entries(self.find_doc_entries(&doc_hash, field.as_ref().map(|f| f.as_str())))
+            },
+            QueryKind::RootDoc(name) => {
+                OpenQueryKind::Docs(self.root_docs.get(&name).cloned().into_iter().collect())
+            },
+            QueryKind::RootNames => {
+                let mut names: Vec<String> = self.root_docs.keys().cloned().collect();
+                names.sort_unstable();
+                OpenQueryKind::RootNames(names)
             },
-            None => { self.set_in_db(false); }
+            QueryKind::CorruptDocs => OpenQueryKind::CorruptDocs(self.verify_all_hashes()),
         };
+        vec![kind]
+    }
+
+    /// Hashes of every stored document whose bytes don't match its hash, in sorted order.
+    fn verify_all_hashes(&self) -> Vec<Hash> {
+        let mut corrupted: Vec<Hash> = self.doc_db.iter()
+            .filter(|(hash, (_, doc, _, _, _))| {
+                Hash::new(hash.get_version(), &doc[..]).map_or(true, |computed| computed != **hash)
+            })
+            .map(|(hash, _)| hash.clone())
+            .collect();
+        corrupted.sort_unstable();
+        corrupted
+    }
+}
+
+/// Key for a permission in the quota tracker. Documents count against their sharing settings, 
+/// regardless of the quota or write access they were added with.
+fn quota_key(perm: &Permission) -> Permission {
+    perm.clone().quota(None).write(true)
+}
+
+/// Every hash in a raw msgpack value, including those nested in arrays and objects. Hashes 
+/// appearing more than once are listed each time.
+fn value_hashes(raw: &[u8]) -> Vec<Hash> {
+    fn collect(value: &Value, hashes: &mut Vec<Hash>) {
+        match value {
+            Value::Hash(hash) => hashes.push(hash.clone()),
+            Value::Array(values) => values.iter().for_each(|v| collect(v, hashes)),
+            Value::Object(fields) => fields.values().for_each(|v| collect(v, hashes)),
+            _ => (),
+        }
+    }
+    let mut hashes = Vec::new();
+    if let Ok(value) = decode::read_value(&mut &raw[..]) {
+        collect(&value, &mut hashes);
+    }
+    hashes
+}
+
+/// Get the root name of a raw document, if it has one.
+fn root_name(doc: &[u8]) -> Option<String> {
+    match decode::try_extract_field(doc, ROOT_NAME_FIELD) {
+        Ok(Some(mut field)) => decode::read_string(&mut field).ok(),
+        _ => None,
+    }
+}
+
+/// Read a schema for validating documents and entries, applying the database's object depth limit.
+fn read_schema(raw: &[u8]) -> io::Result<Schema> {
+    let mut schema = Schema::from_raw(&mut &raw[..])?;
+    schema.set_max_depth(MAX_OBJECT_DEPTH);
+    Ok(schema)
+}
+
+/// Write a permission out as a JSON object for `Db::export_json`.
+#[cfg(feature = "json")]
+fn perm_to_json(perm: &Permission) -> serde_json::Value {
+    let mut obj = serde_json::Map::new();
+    obj.insert("advertise".to_string(), serde_json::Value::from(perm.advertise));
+    obj.insert("machine_local".to_string(), serde_json::Value::from(perm.machine_local));
+    obj.insert("direct".to_string(), serde_json::Value::from(perm.direct));
+    obj.insert("local_net".to_string(), serde_json::Value::from(perm.local_net));
+    obj.insert("global".to_string(), serde_json::Value::from(perm.global));
+    obj.insert("anonymous".to_string(), serde_json::Value::from(perm.anonymous));
+    obj.insert("quota".to_string(), perm.quota.map_or(serde_json::Value::Null, serde_json::Value::from));
+    obj.insert("write".to_string(), serde_json::Value::from(perm.write));
+    serde_json::Value::Object(obj)
+}
+
+/// Read a permission written by `perm_to_json`. Fails if any setting is missing or has the wrong 
+/// type.
+#[cfg(feature = "json")]
+fn perm_from_json(value: &serde_json::Value) -> Result<Permission, ()> {
+    let flag = |name: &str| value.get(name).and_then(|v| v.as_bool()).ok_or(());
+    let quota = match value.get("quota").ok_or(())? {
+        serde_json::Value::Null => None,
+        quota => Some(quota.as_u64().ok_or(())?),
+    };
+    Ok(Permission {
+        advertise: flag("advertise")?,
+        machine_local: flag("machine_local")?,
+        direct: flag("direct")?,
+        local_net: flag("local_net")?,
+        global: flag("global")?,
+        anonymous: flag("anonymous")?,
+        quota,
+        write: flag("write")?,
+    })
+}
+
+/// Read the `ttl` of a document or entry in a JSON backup, which is 0 if it isn't present.
+#[cfg(feature = "json")]
+fn ttl_from_json(value: &serde_json::Value) -> Result<u32, ()> {
+    match value.get("ttl") {
+        Some(ttl) => ttl.as_u64().filter(|ttl| *ttl <= u32::max_value() as u64).map(|ttl| ttl as u32).ok_or(()),
+        None => Ok(0),
+    }
+}
+
+/// What an open query still has to send. Lists are sent from the end, so they're stored in 
+/// reverse order.
+enum OpenQueryKind {
+    /// A single document, which may not be in the database yet. `in_db` is cleared while it's 
+    /// missing, and set again once it's added.
+    Root { hash: Hash, in_db: bool, sent: bool },
+    /// A set list of documents.
+    Docs(Vec<Hash>),
+    /// A set list of entries.
+    Entries(Vec<Hash>),
+    /// Entry hashes & fields, for queries that only return entry headers.
+    EntryHeaders(Vec<(Hash, String)>),
+    /// Root document names.
+    RootNames(Vec<String>),
+    /// Hashes of corrupted documents, for integrity checks.
+    CorruptDocs(Vec<Hash>),
+}
+
+struct OpenQuery {
+    kind: OpenQueryKind,
+    perm: Permission,
+    channel: Sender<QueryResponse>,
+    quit: Receiver<()>,
+    active: bool,
+}
+
+impl OpenQuery {
+    fn new(mut kind: OpenQueryKind, perm: Permission, channel: Sender<QueryResponse>, quit: Receiver<()>) -> OpenQuery {
+        match kind {
+            OpenQueryKind::Root { .. } => (),
+            OpenQueryKind::Docs(ref mut list) => list.reverse(),
+            OpenQueryKind::Entries(ref mut list) => list.reverse(),
+            OpenQueryKind::EntryHeaders(ref mut list) => list.reverse(),
+            OpenQueryKind::RootNames(ref mut list) => list.reverse(),
+            OpenQueryKind::CorruptDocs(ref mut list) => list.reverse(),
+        }
+        OpenQuery {
+            kind,
+            perm,
+            channel,
+            quit,
+            active: true,
+        }
+    }
+
+    /// Mark the query as ready to run again if it's waiting on the document that was just added.
+    fn doc_added(&mut self, added: &Hash) {
+        if let OpenQueryKind::Root { ref hash, ref mut in_db, .. } = self.kind {
+            if hash == added { *in_db = true; }
+        }
+    }
+
+    /// Returns true if the query cannot return anything yet
+    fn not_ready(&self) -> bool {
+        let in_db = match self.kind {
+            OpenQueryKind::Root { in_db, .. } => in_db,
+            _ => true,
+        };
+        !self.active || self.channel.is_full() || !in_db
+    }
+
+    /// Run the query on the database. This will push either nothing to the response channel, or a 
+    /// single response. It never pushes multiple responses in a single call.
+    fn run(&mut self, db: &InternalDb) {
+        // Check to see if query is still open, and halt if it isn't
+        self.active = match self.quit.try_recv() {
+            Err(e) => {
+                !e.is_disconnected()
+            }
+            _ => true
+        };
+        if !self.active { return; }
+
+        let channel = &self.channel;
+        let perm = &self.perm;
+        let finished = match self.kind {
+            OpenQueryKind::Root { ref hash, ref mut in_db, ref mut sent } => {
+                if *sent {
+                    channel.try_send(QueryResponse::DoneForever).is_ok()
+                }
+                else {
+                    match db.get_doc(hash, perm) {
+                        Some(doc) => {
+                            *sent = channel.try_send(QueryResponse::Doc((doc, 0))).is_ok();
+                        },
+                        None => { *in_db = false; }
+                    }
+                    false
+                }
+            },
+            // Skip over any documents or entries that were removed since the query was made
+            OpenQueryKind::Docs(ref mut docs) => {
+                send_next(channel, docs, |hash| db.get_doc(hash, perm).map(|doc| QueryResponse::Doc((doc, 0))))
+            },
+            OpenQueryKind::Entries(ref mut entries) => {
+                send_next(channel, entries, |hash| db.get_entry(hash).map(|entry| QueryResponse::Entry((entry, 0))))
+            },
+            OpenQueryKind::EntryHeaders(ref mut headers) => {
+                send_next(channel, headers, |header| Some(QueryResponse::EntryHash(header.clone())))
+            },
+            OpenQueryKind::RootNames(ref mut names) => {
+                send_next(channel, names, |name| Some(QueryResponse::RootName(name.clone())))
+            },
+            OpenQueryKind::CorruptDocs(ref mut hashes) => {
+                send_next(channel, hashes, |hash| Some(QueryResponse::CorruptDoc(hash.clone())))
+            },
+        };
+        if finished { self.active = false; }
     }
 }
 
+/// Send the response for the last item in `list`, removing it once sent. Items that `respond` 
+/// returns nothing for are skipped. Once the list is empty, `DoneForever` is sent instead. Returns 
+/// true if `DoneForever` was sent.
+fn send_next<T, F>(channel: &Sender<QueryResponse>, list: &mut Vec<T>, respond: F) -> bool
+    where F: Fn(&T) -> Option<QueryResponse>
+{
+    while let Some(response) = list.last().map(&respond) {
+        match response {
+            Some(response) => {
+                if let Ok(()) = channel.try_send(response) {
+                    list.pop();
+                }
+                return false;
+            },
+            None => { list.pop(); },
+        }
+    }
+    channel.try_send(QueryResponse::DoneForever).is_ok()
+}
+
 /// The primary event loop for the database. The actual database handles change requests, queries, 
 /// and management in this loop, which should be running in its own thread. It will block until one 
 /// of the following conditions are met:
@@ -585,29 +1815,50 @@ fn db_loop(
                 },
                 i if i == index_change => {
                     if let Ok((cmd, resp)) = oper.recv(&change) {
-                        // Get document hash if document is being added
-                        let (add_doc, hash) = if let ChangeRequest::AddDoc((ref doc,_,_)) = cmd {
-                            (true, doc.hash())
+                        if let ChangeRequest::Reindex = cmd {
+                            // Reindexing is done a batch at a time further down, so queries 
+                            // continue to be answered. The response is sent once it finishes.
+                            db.start_reindex(Some(resp));
                         }
                         else {
-                            (false, Hash::new_empty())
-                        };
-                        // Make change to database
-                        let result = db.make_change(cmd);
-                        // Check for open queries on this document & update as appropriate
-                        if (result == ChangeResult::Ok) && add_doc {
-                            for query in open_queries.iter_mut() {
-                                if *query.get_root() == hash { query.set_in_db(true); }
+                            // Get document hash if document is being added
+                            let (add_doc, hash) = match cmd {
+                                ChangeRequest::AddDoc((ref doc,_,_)) | ChangeRequest::AddDocStrict((ref doc,_,_)) => {
+                                    (true, doc.hash())
+                                },
+                                _ => (false, Hash::new_empty()),
+                            };
+                            let changes_docs = match cmd {
+                                ChangeRequest::AddDoc(_) | ChangeRequest::AddDocStrict(_)
+                                    | ChangeRequest::DelDoc(_) | ChangeRequest::ApplyPatch(_)
+                                    | ChangeRequest::Import(_) | ChangeRequest::CleanupTemp
+                                    | ChangeRequest::AddEntry(_) | ChangeRequest::DelEntryByHash(_)
+                                    | ChangeRequest::DelEntriesByField(_)
+                                    | ChangeRequest::IndexStringField(_) => true,
+                                _ => false,
+                            };
+                            // Make change to database
+                            let result = db.make_change(cmd);
+                            // Check for open queries on this document & update as appropriate
+                            if (result == ChangeResult::Ok) && add_doc {
+                                for query in open_queries.iter_mut() {
+                                    query.doc_added(&hash);
+                                }
                             }
+                            // A partial reindex is out of date once documents, entries, or indexed 
+                            // fields change, so start over
+                            if changes_docs && db.reindex.is_some() {
+                                db.start_reindex(None);
+                            }
+                            // Send the response. If nothing is at the other end, we don't care.
+                            resp.send(result).unwrap_or(());
                         }
-                        // Send the response. If nothing is at the other end, we don't care.
-                        resp.send(result).unwrap_or(());
                     }
                 },
                 i if i == index_query => {
-                    if let Ok((query, resp, quit)) = oper.recv(&query_inbox) {
-                        for root in query.query.root_iter() {
-                            open_queries.push(OpenQuery::new(root.clone(), query.permission.clone(), resp.clone(), quit.clone()));
+                    if let Ok((request, resp, quit)) = oper.recv(&query_inbox) {
+                        for kind in db.open_query(&request.query, request.kind) {
+                            open_queries.push(OpenQuery::new(kind, request.permission.clone(), resp.clone(), quit.clone()));
                         }
                     }
                 }
@@ -624,6 +1875,11 @@ fn db_loop(
         // Drop completed queries
         open_queries.retain(|query| query.active);
 
+        // Continue any reindex in progress
+        if !db.reindex_step(REINDEX_BATCH) {
+            active = true;
+        }
+
         if done {
             break;
         }
@@ -636,3 +1892,1179 @@ fn db_loop(
 }
 
 
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+    use super::*;
+    use crypto;
+    use value::Value;
+
+    #[test]
+    fn schema_version_policy() {
+        crypto::init().unwrap();
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Db::new(db_dir.path()).unwrap();
+        let perm = Permission::new();
+
+        let schema_v1 = Document::new(msgpack!({
+            "name": "Versioned schema",
+            "version": 1,
+            "req": {
+                "title": { "type": "Str" }
+            }
+        })).unwrap();
+        let schema_v2 = Document::new(msgpack!({
+            "name": "Versioned schema",
+            "version": 2,
+            "req": {
+                "title": { "type": "Str" }
+            },
+            "opt": {
+                "index": { "type": "Int" }
+            }
+        })).unwrap();
+        let hash_v1 = schema_v1.hash();
+        let hash_v2 = schema_v2.hash();
+        assert_eq!(db.add_doc(schema_v1, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        assert_eq!(db.add_doc(schema_v2, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+
+        // Using version 2 makes it the newest known version
+        let doc = Document::new(msgpack!({
+            "": hash_v2.clone(),
+            "title": "Version 2",
+            "index": 2
+        })).unwrap();
+        assert_eq!(db.add_doc(doc, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+
+        let old_doc = |title: &str| Document::new(msgpack!({
+            "": hash_v1.clone(),
+            "title": title
+        })).unwrap();
+
+        assert_eq!(db.set_schema_version_policy(SchemaVersionPolicy::StrictMatch).unwrap().recv().unwrap(), ChangeResult::Ok);
+        assert_eq!(db.add_doc(old_doc("Strict"), &perm, 0).unwrap().recv().unwrap(), ChangeResult::SchemaVersionMismatch);
+
+        // Version 1 documents are still valid under version 2, which only adds an optional field
+        assert_eq!(db.set_schema_version_policy(SchemaVersionPolicy::AllowCompatible).unwrap().recv().unwrap(), ChangeResult::Ok);
+        assert_eq!(db.add_doc(old_doc("Compatible"), &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+
+        assert_eq!(db.set_schema_version_policy(SchemaVersionPolicy::AnyVersion).unwrap().recv().unwrap(), ChangeResult::Ok);
+        assert_eq!(db.add_doc(old_doc("Any"), &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn schema_errors_reach_caller() {
+        crypto::init().unwrap();
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Db::new(db_dir.path()).unwrap();
+        let perm = Permission::new();
+
+        let schema = Document::new(msgpack!({
+            "name": "Person",
+            "req": {
+                "age": { "type": "Int", "min": 0 }
+            }
+        })).unwrap();
+        let schema_hash = schema.hash();
+        assert_eq!(db.add_doc(schema, &perm, 0).unwrap().wait().unwrap(), ChangeResult::Ok);
+        let person = |age: i64| Document::new(msgpack!({ "": schema_hash.clone(), "age": age })).unwrap();
+        assert_eq!(db.add_doc(person(3), &perm, 0).unwrap().wait().unwrap(), ChangeResult::Ok);
+        match db.add_doc(person(-3), &perm, 0).unwrap().wait() {
+            Err(DbError::Schema(_)) => (),
+            other => panic!("Expected a schema error, got {:?}", other),
+        }
+
+        // Other failures are still reported as change results
+        let read_only = Permission::read_only();
+        assert_eq!(db.add_doc(person(4), &read_only, 0).unwrap().wait().unwrap(), ChangeResult::PermissionDenied);
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn unnamed_schema_versions() {
+        crypto::init().unwrap();
+        let db_dir = tempfile::tempdir().unwrap();
+        let mut db = InternalDb::new(open_rocks_db(db_dir.path()).unwrap());
+        let perm = Permission::new();
+        db.version_policy = SchemaVersionPolicy::StrictMatch;
+
+        // Unrelated schemas without names don't count as versions of each other
+        let schema_v1 = Document::new(msgpack!({
+            "version": 1,
+            "req": { "title": { "type": "Str" } }
+        })).unwrap();
+        let schema_v2 = Document::new(msgpack!({
+            "version": 2,
+            "req": { "index": { "type": "Int" } }
+        })).unwrap();
+        let hash_v1 = schema_v1.hash();
+        let hash_v2 = schema_v2.hash();
+        assert_eq!(db.make_change(ChangeRequest::AddDoc((schema_v1, perm.clone(), 0))), ChangeResult::Ok);
+        assert_eq!(db.make_change(ChangeRequest::AddDoc((schema_v2, perm.clone(), 0))), ChangeResult::Ok);
+        let doc = Document::new(msgpack!({ "": hash_v2.clone(), "index": 2 })).unwrap();
+        assert_eq!(db.make_change(ChangeRequest::AddDoc((doc, perm.clone(), 0))), ChangeResult::Ok);
+        let doc = Document::new(msgpack!({ "": hash_v1.clone(), "title": "Version 1" })).unwrap();
+        assert_eq!(db.make_change(ChangeRequest::AddDoc((doc, perm.clone(), 0))), ChangeResult::Ok);
+        assert!(db.schema_versions.get("").is_none());
+
+        assert_eq!(db.make_change(ChangeRequest::Reindex), ChangeResult::Ok);
+        assert!(db.schema_versions.is_empty());
+    }
+
+    #[test]
+    fn quota() {
+        crypto::init().unwrap();
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Db::new(db_dir.path()).unwrap();
+        let doc = |i: i32| Document::new(msgpack!({ "index": i })).unwrap();
+        // Every test document encodes to the same size
+        let size = doc(0).size_bytes() as u64;
+        let perm = Permission::new().quota(Some(5 * size));
+
+        for i in 0..5 {
+            assert_eq!(db.add_doc(doc(i), &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        }
+        assert_eq!(db.add_doc(doc(5), &perm, 0).unwrap().recv().unwrap(), ChangeResult::QuotaExceeded);
+        assert_eq!(db.get_quota_usage(&perm).unwrap().recv().unwrap(), 5 * size);
+        // Re-adding a document that's already present doesn't count again
+        assert_eq!(db.add_doc(doc(0), &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        // Other sharing settings are tracked separately
+        let other = Permission::new().global(true).quota(Some(size));
+        assert_eq!(db.add_doc(doc(6), &other, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        assert_eq!(db.get_quota_usage(&other).unwrap().recv().unwrap(), size);
+        // A document bigger than the space left is rejected
+        let big = Permission::new().global(true).anonymous(true).quota(Some(size - 1));
+        assert_eq!(db.add_doc(doc(8), &big, 0).unwrap().recv().unwrap(), ChangeResult::QuotaExceeded);
+        // Deleting a document frees up space under its quota
+        assert_eq!(db.del_doc(doc(0).hash(), &perm).unwrap().recv().unwrap(), ChangeResult::Ok);
+        assert_eq!(db.get_quota_usage(&Permission::new()).unwrap().recv().unwrap(), 4 * size);
+        assert_eq!(db.add_doc(doc(5), &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        assert_eq!(db.add_doc(doc(7), &perm, 0).unwrap().recv().unwrap(), ChangeResult::QuotaExceeded);
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn add_doc_strict() {
+        crypto::init().unwrap();
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Db::new(db_dir.path()).unwrap();
+        let perm = Permission::new();
+        let doc = || Document::new(msgpack!({ "title": "Only once" })).unwrap();
+
+        assert_eq!(db.add_doc_strict(doc(), &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        assert_eq!(db.add_doc_strict(doc(), &perm, 0).unwrap().recv().unwrap(), ChangeResult::AlreadyExists);
+        // The permissive version still accepts duplicates
+        assert_eq!(db.add_doc(doc(), &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        // Deleting the document lets it be added strictly again
+        assert_eq!(db.del_doc(doc().hash(), &perm).unwrap().recv().unwrap(), ChangeResult::Ok);
+        assert_eq!(db.add_doc_strict(doc(), &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn permission_denied() {
+        crypto::init().unwrap();
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Db::new(db_dir.path()).unwrap();
+        let full = Permission::full_access();
+        let read_only = Permission::read_only();
+        let doc = |i: u32| Document::new(msgpack!({ "index": i })).unwrap();
+
+        assert_eq!(db.add_doc(doc(0), &full, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        assert_eq!(db.del_doc(doc(0).hash(), &read_only).unwrap().recv().unwrap(), ChangeResult::PermissionDenied);
+        assert_eq!(db.add_doc(doc(1), &read_only, 0).unwrap().recv().unwrap(), ChangeResult::PermissionDenied);
+        assert_eq!(db.add_doc_strict(doc(1), &read_only, 0).unwrap().recv().unwrap(), ChangeResult::PermissionDenied);
+        // Documents that don't exist are reported as such, regardless of permission
+        assert_eq!(db.del_doc(doc(1).hash(), &read_only).unwrap().recv().unwrap(), ChangeResult::NoSuchDoc);
+        // Write access doesn't affect quota tracking
+        assert_eq!(db.get_quota_usage(&read_only).unwrap().recv().unwrap(), doc(0).size_bytes() as u64);
+        assert_eq!(db.del_doc(doc(0).hash(), &full).unwrap().recv().unwrap(), ChangeResult::Ok);
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn entry_doc_check_limit() {
+        crypto::init().unwrap();
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Db::new(db_dir.path()).unwrap();
+        let perm = Permission::new();
+
+        let schema = Document::new(msgpack!({
+            "name": "Reference schema",
+            "entries": {
+                "refs": {
+                    "type": "Array",
+                    "extra_items": { "type": "Hash", "link": { "type": "Obj", "unknown_ok": true } }
+                }
+            }
+        })).unwrap();
+        let schema_hash = schema.hash();
+        assert_eq!(db.add_doc(schema, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        let doc = Document::new(msgpack!({ "": schema_hash.clone() })).unwrap();
+        let doc_hash = doc.hash();
+        assert_eq!(db.add_doc(doc, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        let target = Document::new(msgpack!({ "target": true })).unwrap();
+        let target_hash = target.hash();
+        assert_eq!(db.add_doc(target, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+
+        let refs = |n: usize| {
+            let refs: Vec<Value> = (0..n).map(|_| Value::from(target_hash.clone())).collect();
+            Entry::new(doc_hash.clone(), String::from("refs"), Value::from(refs)).unwrap()
+        };
+        assert_eq!(db.add_entry(refs(MAX_ENTRY_DOC_CHECKS), 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        assert_eq!(db.add_entry(refs(MAX_ENTRY_DOC_CHECKS+1), 0).unwrap().recv().unwrap(), ChangeResult::FailedSchemaCheck);
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn reindex() {
+        crypto::init().unwrap();
+        let db_dir = tempfile::tempdir().unwrap();
+        let mut db = InternalDb::new(open_rocks_db(db_dir.path()).unwrap());
+
+        let schema = Document::new(msgpack!({
+            "name": "Test schema",
+            "req": {
+                "index": { "type": "Int" }
+            }
+        })).unwrap();
+        let schema_hash = schema.hash();
+        assert_eq!(db.make_change(ChangeRequest::AddDoc((schema, Permission::new(), 0))), ChangeResult::Ok);
+        for i in 0..10 {
+            let doc = Document::new(msgpack!({
+                "": schema_hash.clone(),
+                "index": i
+            })).unwrap();
+            assert_eq!(db.make_change(ChangeRequest::AddDoc((doc, Permission::new(), 0))), ChangeResult::Ok);
+        }
+        assert_eq!(db.schema_tracking.get(&schema_hash), Some(&10));
+
+        // Corrupt the tracking, then rebuild it a few documents at a time
+        db.schema_tracking.insert(schema_hash.clone(), 3);
+        db.schema_tracking.insert(Hash::new_empty(), 5);
+        db.schema_versions.clear();
+        db.update_references(&schema_hash, |r| r.strong = 2);
+        db.update_references(&Hash::new_empty(), |r| r.weak = 1);
+        db.start_reindex(None);
+        assert!(!db.reindex_step(4));
+        assert!(db.reindex.is_some());
+        assert_eq!(db.schema_tracking.get(&schema_hash), Some(&3));
+        db.start_reindex(None); // Restarting drops the partial progress
+        assert!(!db.reindex_step(4));
+        assert!(!db.reindex_step(4));
+        assert!(db.reindex_step(4));
+        assert!(db.reindex.is_none());
+        assert_eq!(db.schema_tracking.get(&schema_hash), Some(&10));
+        assert_eq!(db.schema_tracking.get(&Hash::new_empty()), None);
+        assert_eq!(db.schema_versions.get("Test schema"), Some(&(0, schema_hash.clone())));
+        assert_eq!(db.references(&schema_hash), References { root: 0, strong: 10, weak: 0 });
+        assert!(db.references(&Hash::new_empty()).is_unreferenced());
+
+        // Synchronous reindex through make_change
+        db.schema_tracking.clear();
+        assert_eq!(db.make_change(ChangeRequest::Reindex), ChangeResult::Ok);
+        assert_eq!(db.schema_tracking.get(&schema_hash), Some(&10));
+    }
+
+    #[test]
+    fn reindex_rebuilds_indexes() {
+        crypto::init().unwrap();
+        let db_dir = tempfile::tempdir().unwrap();
+        let mut db = InternalDb::new(open_rocks_db(db_dir.path()).unwrap());
+        let perm = Permission::new();
+        let add = |db: &mut InternalDb, doc: Document| {
+            assert_eq!(db.make_change(ChangeRequest::AddDoc((doc, perm.clone(), 0))), ChangeResult::Ok);
+        };
+        assert_eq!(db.make_change(ChangeRequest::IndexStringField(String::from("title"))), ChangeResult::Ok);
+
+        let schema = Document::new(msgpack!({
+            "name": "Linking schema",
+            "entries": {
+                "required": { "type": "Hash", "link": { "type": "Obj", "unknown_ok": true } },
+                "other": { "type": "Hash" }
+            }
+        })).unwrap();
+        let schema_hash = schema.hash();
+        add(&mut db, schema);
+        let target = Document::new(msgpack!({ "$root_name": "target", "title": "Target" })).unwrap();
+        let target_hash = target.hash();
+        add(&mut db, target);
+        let holder = Document::new(msgpack!({ "": schema_hash.clone(), "title": "Holder" })).unwrap();
+        let holder_hash = holder.hash();
+        add(&mut db, holder);
+        let linker = Document::new(msgpack!({ "link": target_hash.clone(), "title": "Linker" })).unwrap();
+        let linker_hash = linker.hash();
+        add(&mut db, linker);
+        let entry = |field: &str| Entry::new(holder_hash.clone(), String::from(field), Value::from(target_hash.clone())).unwrap();
+        let other_hash = entry("other").hash();
+        assert_eq!(db.make_change(ChangeRequest::AddEntry((entry("required"), 0))), ChangeResult::Ok);
+        assert_eq!(db.make_change(ChangeRequest::AddEntry((entry("other"), 0))), ChangeResult::Ok);
+
+        // Removed documents and entries shouldn't come back
+        let temp = Document::new(msgpack!({ "$root_name": "temp", "title": "Target" })).unwrap();
+        let temp_hash = temp.hash();
+        add(&mut db, temp);
+        assert_eq!(db.make_change(ChangeRequest::DelEntryByHash(other_hash)), ChangeResult::Ok);
+        assert_eq!(db.make_change(ChangeRequest::DelDoc((linker_hash.clone(), perm.clone()))), ChangeResult::Ok);
+        let mut patch = BTreeMap::new();
+        patch.insert(String::from(ROOT_NAME_FIELD), Value::Null);
+        let unnamed = Document::new(msgpack!({ "$root_name": null, "title": "Target" })).unwrap();
+        let unnamed_hash = unnamed.hash();
+        assert_eq!(db.make_change(ChangeRequest::ApplyPatch((temp_hash, patch, unnamed_hash.clone(), perm.clone()))),
+            ChangeResult::Ok);
+        assert_eq!(db.make_change(ChangeRequest::DelDoc((unnamed_hash, perm.clone()))), ChangeResult::Ok);
+
+        let titled = |db: &InternalDb, title: &str| {
+            let mut docs: Vec<Hash> = db.string_index.query("title", title).collect();
+            docs.sort_unstable();
+            docs
+        };
+        let root_docs = db.root_docs.clone();
+        let quota_tracker = db.quota_tracker.clone();
+        let entry_hashes = db.entry_hashes.clone();
+        let hashes = [schema_hash.clone(), target_hash.clone(), holder_hash.clone(), linker_hash.clone()];
+        let references: Vec<References> = hashes.iter().map(|hash| db.references(hash)).collect();
+        assert_eq!(references[1], References { root: 1, strong: 1, weak: 0 });
+        assert_eq!(titled(&db, "Target"), vec![target_hash.clone()]);
+
+        // Corrupt everything, then rebuild
+        db.root_docs.insert(String::from("bogus"), linker_hash.clone());
+        db.root_docs.remove("target");
+        let raw_target = db.doc_db[&target_hash].1.clone();
+        db.string_index.remove_doc(&target_hash, &raw_target[..]);
+        db.string_index.add_doc(&linker_hash, &raw_target[..]);
+        db.quota_tracker.clear();
+        db.entry_hashes.insert(Hash::new_empty(), holder_hash.clone());
+        db.update_references(&target_hash, |r| r.weak += 3);
+        db.update_references(&linker_hash, |r| r.strong += 1);
+        assert_eq!(db.make_change(ChangeRequest::Reindex), ChangeResult::Ok);
+
+        assert_eq!(db.root_docs, root_docs);
+        assert!(db.quota_tracker == quota_tracker);
+        assert_eq!(db.entry_hashes, entry_hashes);
+        assert_eq!(hashes.iter().map(|hash| db.references(hash)).collect::<Vec<References>>(), references);
+        assert_eq!(titled(&db, "Target"), vec![target_hash]);
+        assert!(titled(&db, "Linker").is_empty());
+        assert_eq!(titled(&db, "Holder"), vec![holder_hash]);
+    }
+
+    #[test]
+    fn entry_by_hash() {
+        crypto::init().unwrap();
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Db::new(db_dir.path()).unwrap();
+        let perm = Permission::new();
+
+        let doc = Document::new(msgpack!({ "title": "Entry holder" })).unwrap();
+        let doc_hash = doc.hash();
+        assert_eq!(db.add_doc(doc, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        let entry = Entry::new(doc_hash.clone(), String::from("note"), msgpack!("A note")).unwrap();
+        let entry_hash = entry.compute_hash();
+        assert_eq!(entry_hash, entry.hash());
+        assert_eq!(db.add_entry(entry.clone(), 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        // Adding it again doesn't duplicate it
+        assert_eq!(db.add_entry(entry, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+
+        let query = db.get_entry_by_hash(entry_hash.clone()).unwrap();
+        match query.recv().unwrap() {
+            QueryResponse::Entry((entry, _)) => {
+                assert_eq!(entry.hash(), entry_hash);
+                assert_eq!(entry.doc_hash(), &doc_hash);
+                assert_eq!(entry.field(), "note");
+                assert_eq!(entry.value().as_str(), Some("A note"));
+            },
+            _ => panic!("Entry wasn't retrieved by its hash"),
+        }
+        match query.recv().unwrap() {
+            QueryResponse::DoneForever => (),
+            _ => panic!("Entry lookup didn't finish"),
+        }
+
+        assert_eq!(db.del_entry_by_hash(entry_hash.clone()).unwrap().recv().unwrap(), ChangeResult::Ok);
+        assert_eq!(db.del_entry_by_hash(entry_hash.clone()).unwrap().recv().unwrap(), ChangeResult::NoSuchEntry);
+        let query = db.get_entry_by_hash(entry_hash).unwrap();
+        match query.recv().unwrap() {
+            QueryResponse::DoneForever => (),
+            _ => panic!("Deleted entry was still retrieved"),
+        }
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn entry_hashes() {
+        crypto::init().unwrap();
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Db::new(db_dir.path()).unwrap();
+        let perm = Permission::new();
+
+        let doc = Document::new(msgpack!({ "title": "Entry holder" })).unwrap();
+        let doc_hash = doc.hash();
+        assert_eq!(db.add_doc(doc, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        let mut expected = Vec::new();
+        for (i, field) in ["note", "note", "like"].iter().enumerate() {
+            let entry = Entry::new(doc_hash.clone(), field.to_string(), msgpack!(i as i32)).unwrap();
+            expected.push((entry.hash(), field.to_string()));
+            assert_eq!(db.add_entry(entry, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        }
+
+        let headers = |hash: &Hash| {
+            drain_query(db.entry_hashes(hash.clone(), &perm).unwrap(), |response| match response {
+                QueryResponse::EntryHash(header) => Some(header),
+                _ => None,
+            })
+        };
+        let mut found = headers(&doc_hash);
+        found.sort();
+        found.dedup();
+        expected.sort();
+        assert_eq!(found.len(), 3);
+        assert_eq!(found, expected);
+
+        let missing = Document::new(msgpack!({ "title": "Not added" })).unwrap().hash();
+        assert!(headers(&missing).is_empty());
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn del_entries_by_field() {
+        crypto::init().unwrap();
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Db::new(db_dir.path()).unwrap();
+        let perm = Permission::new();
+
+        let doc = Document::new(msgpack!({ "title": "Entry holder" })).unwrap();
+        let doc_hash = doc.hash();
+        assert_eq!(db.add_doc(doc, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        let mut notes = Vec::new();
+        let mut likes = Vec::new();
+        for i in 0..3 {
+            let note = Entry::new(doc_hash.clone(), String::from("note"), msgpack!(i)).unwrap();
+            let like = Entry::new(doc_hash.clone(), String::from("like"), msgpack!(i)).unwrap();
+            notes.push(note.hash());
+            likes.push(like.hash());
+            assert_eq!(db.add_entry(note, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+            assert_eq!(db.add_entry(like, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        }
+        let found = |hash: &Hash| {
+            match db.get_entry_by_hash(hash.clone()).unwrap().recv().unwrap() {
+                QueryResponse::Entry(_) => true,
+                _ => false,
+            }
+        };
+
+        assert_eq!(db.del_entries_by_field(doc_hash.clone(), String::from("note")).unwrap().recv().unwrap(), ChangeResult::Ok);
+        assert!(notes.iter().all(|h| !found(h)));
+        assert!(likes.iter().all(|h| found(h)));
+        // Nothing left to remove is still Ok
+        assert_eq!(db.del_entries_by_field(doc_hash.clone(), String::from("note")).unwrap().recv().unwrap(), ChangeResult::Ok);
+        assert_eq!(db.del_entries_by_field(doc_hash.clone(), String::from("like")).unwrap().recv().unwrap(), ChangeResult::Ok);
+        assert!(likes.iter().all(|h| !found(h)));
+        assert_eq!(db.del_entries_by_field(doc_hash.clone(), String::from("like")).unwrap().recv().unwrap(), ChangeResult::Ok);
+
+        let missing = Document::new(msgpack!({ "title": "Not added" })).unwrap().hash();
+        assert_eq!(db.del_entries_by_field(missing, String::from("note")).unwrap().recv().unwrap(), ChangeResult::NoSuchDoc);
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn cleanup_temp_docs() {
+        crypto::init().unwrap();
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Db::new(db_dir.path()).unwrap();
+        let perm = Permission::new();
+
+        let mut temp = Vec::new();
+        for i in 0..5 {
+            let doc = Document::new(msgpack!({ "scratch": i })).unwrap();
+            temp.push(doc.hash());
+            assert_eq!(db.add_doc(doc, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        }
+        let kept = Document::new(msgpack!({ "title": "Not temporary" })).unwrap();
+        let kept_hash = kept.hash();
+        assert_eq!(db.add_doc(kept, &perm, 60).unwrap().recv().unwrap(), ChangeResult::Ok);
+        let entry = Entry::new(temp[2].clone(), String::from("note"), msgpack!(1)).unwrap();
+        let entry_hash = entry.hash();
+        assert_eq!(db.add_entry(entry, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        let kept_entry = Entry::new(kept_hash.clone(), String::from("note"), msgpack!(2)).unwrap();
+        let kept_entry_hash = kept_entry.hash();
+        assert_eq!(db.add_entry(kept_entry, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+
+        // Removing entries of a field nothing uses only fails if the document is gone
+        let found = |hash: &Hash| {
+            db.del_entries_by_field(hash.clone(), String::from("unused")).unwrap().recv().unwrap() != ChangeResult::NoSuchDoc
+        };
+        let entry_found = |hash: &Hash| {
+            match db.get_entry_by_hash(hash.clone()).unwrap().recv().unwrap() {
+                QueryResponse::Entry(_) => true,
+                _ => false,
+            }
+        };
+
+        assert_eq!(db.cleanup_temp_docs().unwrap().recv().unwrap(), ChangeResult::OkCount(4));
+        for (i, hash) in temp.iter().enumerate() {
+            assert_eq!(found(hash), i == 2);
+        }
+        assert!(found(&kept_hash));
+        assert!(entry_found(&entry_hash));
+        assert!(entry_found(&kept_entry_hash));
+        assert_eq!(db.cleanup_temp_docs().unwrap().recv().unwrap(), ChangeResult::OkCount(0));
+
+        // A schema is only removed once nothing uses it
+        let schema_doc = |name: &str| Document::new(msgpack!({
+            "name": name,
+            "req": { "scratch": { "type": "Int" } }
+        })).unwrap();
+        let used = schema_doc("Used schema");
+        let used_hash = used.hash();
+        let freed = schema_doc("Freed schema");
+        let freed_hash = freed.hash();
+        assert_eq!(db.add_doc(used, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        assert_eq!(db.add_doc(freed, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        let user = Document::new(msgpack!({ "": used_hash.clone(), "scratch": 1 })).unwrap();
+        let temp_user = Document::new(msgpack!({ "": freed_hash.clone(), "scratch": 2 })).unwrap();
+        let temp_user_hash = temp_user.hash();
+        assert_eq!(db.add_doc(user, &perm, 60).unwrap().recv().unwrap(), ChangeResult::Ok);
+        assert_eq!(db.add_doc(temp_user, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        assert_eq!(db.cleanup_temp_docs().unwrap().recv().unwrap(), ChangeResult::OkCount(2));
+        assert!(found(&used_hash));
+        assert!(!found(&freed_hash));
+        assert!(!found(&temp_user_hash));
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn schema_for_doc() {
+        crypto::init().unwrap();
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Db::new(db_dir.path()).unwrap();
+        let perm = Permission::new();
+
+        let schema = Document::new(msgpack!({
+            "name": "Test schema",
+            "req": {
+                "index": { "type": "Int" }
+            }
+        })).unwrap();
+        let schema_hash = schema.hash();
+        assert_eq!(db.add_doc(schema, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        let doc = Document::new(msgpack!({ "": schema_hash.clone(), "index": 1 })).unwrap();
+        let doc_hash = doc.hash();
+        assert_eq!(db.add_doc(doc, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+
+        let query = db.get_schema_for_doc(doc_hash).unwrap();
+        match query.recv().unwrap() {
+            QueryResponse::Doc((doc, _)) => assert_eq!(doc.hash(), schema_hash),
+            _ => panic!("Schema document wasn't returned"),
+        }
+        match query.recv().unwrap() {
+            QueryResponse::DoneForever => (),
+            _ => panic!("Schema lookup didn't finish"),
+        }
+
+        // The schema document itself has no schema
+        let query = db.get_schema_for_doc(schema_hash).unwrap();
+        match query.recv().unwrap() {
+            QueryResponse::DoneForever => (),
+            _ => panic!("Document without a schema returned something"),
+        }
+        let query = db.get_schema_for_doc(Hash::new_empty()).unwrap();
+        match query.recv().unwrap() {
+            QueryResponse::DoneForever => (),
+            _ => panic!("Missing document returned something"),
+        }
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn query_entries() {
+        crypto::init().unwrap();
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Db::new(db_dir.path()).unwrap();
+        let perm = Permission::new();
+
+        let schema = Document::new(msgpack!({
+            "name": "Entry schema",
+            "req": {
+                "index": { "type": "Int" }
+            },
+            "entries": {
+                "note": { "type": "Str" },
+                "tag": { "type": "Int" }
+            }
+        })).unwrap();
+        let schema_hash = schema.hash();
+        assert_eq!(db.add_doc(schema, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        let mut expected = Vec::new();
+        for i in 0..5 {
+            let doc = Document::new(msgpack!({ "": schema_hash.clone(), "index": i })).unwrap();
+            let doc_hash = doc.hash();
+            assert_eq!(db.add_doc(doc, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+            for j in 0..3 {
+                let entry = Entry::new(doc_hash.clone(), String::from("note"), msgpack!(format!("{}-{}", i, j))).unwrap();
+                expected.push(entry.hash());
+                assert_eq!(db.add_entry(entry, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+            }
+            let entry = Entry::new(doc_hash.clone(), String::from("tag"), msgpack!(i)).unwrap();
+            assert_eq!(db.add_entry(entry, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        }
+        // Entries on documents without the schema aren't returned
+        let doc = Document::new(msgpack!({ "index": 5 })).unwrap();
+        let doc_hash = doc.hash();
+        assert_eq!(db.add_doc(doc, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        let entry = Entry::new(doc_hash, String::from("note"), msgpack!("Unrelated")).unwrap();
+        assert_eq!(db.add_entry(entry, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+
+        let query = db.query_entries(String::from("note"), schema_hash.clone(), &perm, 2).unwrap();
+        let mut found = Vec::new();
+        loop {
+            match query.recv().unwrap() {
+                QueryResponse::Entry((entry, _)) => {
+                    assert_eq!(entry.field(), "note");
+                    found.push(entry.hash());
+                },
+                QueryResponse::DoneForever => break,
+                _ => panic!("Unexpected response to entry query"),
+            }
+        }
+        assert_eq!(found.len(), 15);
+        assert!(expected.iter().all(|hash| found.contains(hash)));
+
+        let query = db.query_entries(String::from("missing"), schema_hash, &perm, 2).unwrap();
+        match query.recv().unwrap() {
+            QueryResponse::DoneForever => (),
+            _ => panic!("Query for a field without entries returned something"),
+        }
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn iter_entries() {
+        crypto::init().unwrap();
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Db::new(db_dir.path()).unwrap();
+        let perm = Permission::new();
+
+        let doc = Document::new(msgpack!({ "title": "Discussion" })).unwrap();
+        let doc_hash = doc.hash();
+        assert_eq!(db.add_doc(doc, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        let mut comments = Vec::new();
+        for i in 0..5 {
+            let entry = Entry::new(doc_hash.clone(), String::from("comment"), msgpack!(format!("Comment {}", i))).unwrap();
+            comments.push(entry.hash());
+            assert_eq!(db.add_entry(entry, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        }
+        for i in 0..3 {
+            let entry = Entry::new(doc_hash.clone(), String::from("vote"), msgpack!(i)).unwrap();
+            assert_eq!(db.add_entry(entry, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        }
+
+        let entries = |hash: &Hash, entry_type: Option<&str>| {
+            let query = db.iter_entries(hash.clone(), entry_type.map(String::from), &perm, 2).unwrap();
+            let mut found = Vec::new();
+            loop {
+                match query.recv().unwrap() {
+                    QueryResponse::Entry((entry, _)) => found.push(entry),
+                    QueryResponse::DoneForever => break,
+                    _ => panic!("Unexpected response to entry iteration"),
+                }
+            }
+            found
+        };
+        let found = entries(&doc_hash, Some("comment"));
+        assert_eq!(found.len(), 5);
+        assert!(found.iter().all(|entry| entry.field() == "comment"));
+        assert!(comments.iter().all(|hash| found.iter().any(|entry| &entry.hash() == hash)));
+        assert_eq!(entries(&doc_hash, Some("vote")).len(), 3);
+        assert_eq!(entries(&doc_hash, None).len(), 8);
+        assert!(entries(&doc_hash, Some("missing")).is_empty());
+
+        let missing = Document::new(msgpack!({ "title": "Not added" })).unwrap().hash();
+        assert!(entries(&missing, None).is_empty());
+        assert!(db.iter_entries(doc_hash, None, &perm, 0).is_err());
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn root_docs() {
+        crypto::init().unwrap();
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Db::new(db_dir.path()).unwrap();
+        let perm = Permission::new();
+
+        let root_doc = |name: &str| query_doc_hashes(db.get_root_doc(String::from(name)).unwrap());
+        let root_names = || {
+            drain_query(db.list_root_names().unwrap(), |response| match response {
+                QueryResponse::RootName(name) => Some(name),
+                _ => None,
+            })
+        };
+
+        let settings = Document::new(msgpack!({ "$root_name": "settings", "theme": "dark" })).unwrap();
+        let settings_hash = settings.hash();
+        let contacts = Document::new(msgpack!({ "$root_name": "contacts", "list": [] })).unwrap();
+        let plain = Document::new(msgpack!({ "title": "Not a root document" })).unwrap();
+        assert_eq!(db.add_doc(settings, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        assert_eq!(db.add_doc(contacts, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        assert_eq!(db.add_doc(plain, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        assert_eq!(root_doc("settings"), vec![settings_hash.clone()]);
+        assert!(root_doc("missing").is_empty());
+        assert_eq!(root_names(), vec![String::from("contacts"), String::from("settings")]);
+
+        // Root names must be unique
+        let other = Document::new(msgpack!({ "$root_name": "settings", "theme": "light" })).unwrap();
+        assert_eq!(db.add_doc(other, &perm, 0).unwrap().recv().unwrap(), ChangeResult::RootNameTaken);
+        assert_eq!(root_doc("settings"), vec![settings_hash.clone()]);
+
+        // Root documents are held by their root reference, but patching the name away frees it
+        assert_eq!(db.del_doc(settings_hash.clone(), &perm).unwrap().recv().unwrap(), ChangeResult::StillReferenced);
+        let mut patch = BTreeMap::new();
+        patch.insert(String::from(ROOT_NAME_FIELD), Value::Null);
+        let unnamed = Document::new(msgpack!({ "$root_name": null, "theme": "dark" })).unwrap();
+        assert_eq!(db.apply_patch(settings_hash.clone(), patch, unnamed.hash(), &perm).unwrap().recv().unwrap(),
+            ChangeResult::Ok);
+        assert_eq!(db.del_doc(settings_hash, &perm).unwrap().recv().unwrap(), ChangeResult::NoSuchDoc);
+        assert!(root_doc("settings").is_empty());
+        assert_eq!(root_names(), vec![String::from("contacts")]);
+        let other = Document::new(msgpack!({ "$root_name": "settings", "theme": "light" })).unwrap();
+        assert_eq!(db.add_doc(other, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn apply_patch() {
+        crypto::init().unwrap();
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Db::new(db_dir.path()).unwrap();
+        let perm = Permission::new();
+
+        let schema = Document::new(msgpack!({
+            "name": "Patch schema",
+            "req": {
+                "count": { "type": "Int" },
+                "title": { "type": "Str" }
+            },
+            "opt": {
+                "tags": { "type": "Array", "extra_items": { "type": "Str" } }
+            }
+        })).unwrap();
+        let schema_hash = schema.hash();
+        assert_eq!(db.add_doc(schema, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        let doc = |count: i64| Document::new(msgpack!({
+            "": schema_hash.clone(),
+            "count": count,
+            "tags": ["a", "b"],
+            "title": "Patched"
+        })).unwrap();
+        let patch = |value: Value| {
+            let mut patch = BTreeMap::new();
+            patch.insert(String::from("count"), value);
+            patch
+        };
+        let original = doc(1).hash();
+        assert_eq!(db.add_doc(doc(1), &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+
+        // The patched document must match the expected hash, and pass the schema
+        assert_eq!(db.apply_patch(original.clone(), patch(Value::from(2)), doc(3).hash(), &perm).unwrap().recv().unwrap(),
+            ChangeResult::HashMismatch);
+        assert_eq!(db.apply_patch(original.clone(), patch(Value::from("two")), doc(2).hash(), &perm).unwrap().recv().unwrap(),
+            ChangeResult::HashMismatch);
+        let bad = Document::new(msgpack!({ "": schema_hash.clone(), "count": "two", "tags": ["a", "b"], "title": "Patched" })).unwrap();
+        assert_eq!(db.apply_patch(original.clone(), patch(Value::from("two")), bad.hash(), &perm).unwrap().recv().unwrap(),
+            ChangeResult::FailedSchemaCheck);
+        assert_eq!(db.add_doc_strict(doc(1), &perm, 0).unwrap().recv().unwrap(), ChangeResult::AlreadyExists);
+
+        // A caller that can't write can't patch
+        let read_only = Permission::read_only();
+        assert_eq!(db.apply_patch(original.clone(), patch(Value::from(2)), doc(2).hash(), &read_only).unwrap().recv().unwrap(),
+            ChangeResult::PermissionDenied);
+        assert_eq!(db.add_doc_strict(doc(1), &perm, 0).unwrap().recv().unwrap(), ChangeResult::AlreadyExists);
+
+        // A successful patch replaces the original
+        assert_eq!(db.apply_patch(original.clone(), patch(Value::from(2)), doc(2).hash(), &perm).unwrap().recv().unwrap(),
+            ChangeResult::Ok);
+        assert_eq!(db.add_doc_strict(doc(2), &perm, 0).unwrap().recv().unwrap(), ChangeResult::AlreadyExists);
+        assert_eq!(db.del_doc(original.clone(), &perm).unwrap().recv().unwrap(), ChangeResult::NoSuchDoc);
+        assert_eq!(db.apply_patch(original, patch(Value::from(3)), doc(3).hash(), &perm).unwrap().recv().unwrap(),
+            ChangeResult::NoSuchDoc);
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn reference_counts() {
+        crypto::init().unwrap();
+        let db_dir = tempfile::tempdir().unwrap();
+        let mut db = InternalDb::new(open_rocks_db(db_dir.path()).unwrap());
+        let perm = Permission::new();
+        let refs = |db: &InternalDb, hash: &Hash| db.references(hash);
+        let counts = |root, strong, weak| References { root, strong, weak };
+
+        let target = Document::new(msgpack!({ "$root_name": "target" })).unwrap();
+        let target_hash = target.hash();
+        assert_eq!(db.make_change(ChangeRequest::AddDoc((target, perm.clone(), 0))), ChangeResult::Ok);
+        assert_eq!(refs(&db, &target_hash), counts(1, 0, 0));
+
+        // Entries that a schema requires to link to the target hold strong references, and other 
+        // entries hold weak ones
+        let schema = Document::new(msgpack!({
+            "name": "Linking schema",
+            "entries": {
+                "required": { "type": "Hash", "link": { "type": "Obj", "unknown_ok": true } },
+                "other": { "type": "Hash" }
+            }
+        })).unwrap();
+        let schema_hash = schema.hash();
+        assert_eq!(db.make_change(ChangeRequest::AddDoc((schema, perm.clone(), 0))), ChangeResult::Ok);
+        let holder = Document::new(msgpack!({ "": schema_hash.clone() })).unwrap();
+        let holder_hash = holder.hash();
+        assert_eq!(db.make_change(ChangeRequest::AddDoc((holder, perm.clone(), 0))), ChangeResult::Ok);
+        assert_eq!(refs(&db, &schema_hash), counts(0, 1, 0));
+        let required = Entry::new(holder_hash.clone(), String::from("required"), Value::from(target_hash.clone())).unwrap();
+        let required_hash = required.hash();
+        let other = Entry::new(holder_hash.clone(), String::from("other"), Value::from(target_hash.clone())).unwrap();
+        assert_eq!(db.make_change(ChangeRequest::AddEntry((required, 0))), ChangeResult::Ok);
+        assert_eq!(db.make_change(ChangeRequest::AddEntry((other, 0))), ChangeResult::Ok);
+        assert_eq!(refs(&db, &target_hash), counts(1, 1, 1));
+
+        // Documents linking to the target hold strong references
+        let linker = Document::new(msgpack!({ "links": [target_hash.clone(), target_hash.clone()] })).unwrap();
+        let linker_hash = linker.hash();
+        assert_eq!(db.make_change(ChangeRequest::AddDoc((linker, perm.clone(), 0))), ChangeResult::Ok);
+        assert_eq!(refs(&db, &target_hash), counts(1, 3, 1));
+
+        // Strongly referenced documents can't be deleted
+        assert_eq!(db.make_change(ChangeRequest::DelDoc((target_hash.clone(), perm.clone()))), ChangeResult::StillReferenced);
+        assert!(db.doc_db.contains_key(&target_hash));
+
+        // The counters survive encoding
+        let encoded = refs(&db, &target_hash).to_bytes();
+        assert_eq!(encoded, [0, 0, 0, 1, 0, 0, 0, 3, 0, 0, 0, 1]);
+        assert_eq!(References::from_bytes(&encoded[..]), Some(counts(1, 3, 1)));
+        assert_eq!(References::from_bytes(&encoded[1..]), None);
+
+        // Automatic cleanup leaves referenced documents alone
+        assert_eq!(db.make_change(ChangeRequest::CleanupTemp), ChangeResult::OkCount(1));
+        assert!(db.doc_db.contains_key(&target_hash));
+        assert!(!db.doc_db.contains_key(&linker_hash));
+        assert_eq!(refs(&db, &target_hash), counts(1, 1, 1));
+
+        // Removing entries releases their references
+        assert_eq!(db.make_change(ChangeRequest::DelEntryByHash(required_hash)), ChangeResult::Ok);
+        assert_eq!(refs(&db, &target_hash), counts(1, 0, 1));
+        assert_eq!(db.make_change(ChangeRequest::DelDoc((holder_hash.clone(), perm.clone()))), ChangeResult::Ok);
+        assert_eq!(refs(&db, &target_hash), counts(1, 0, 0));
+        assert_eq!(refs(&db, &schema_hash), counts(0, 0, 0));
+
+        // Only the root reference is left, and it still blocks deletion
+        assert_eq!(db.make_change(ChangeRequest::CleanupTemp), ChangeResult::OkCount(1));
+        assert!(db.doc_db.contains_key(&target_hash));
+        assert!(!db.doc_db.contains_key(&schema_hash));
+        assert_eq!(db.make_change(ChangeRequest::DelDoc((target_hash.clone(), perm.clone()))), ChangeResult::StillReferenced);
+        assert!(db.doc_db.contains_key(&target_hash));
+    }
+
+    #[test]
+    fn references_block_deletion() {
+        crypto::init().unwrap();
+        let db_dir = tempfile::tempdir().unwrap();
+        let mut db = InternalDb::new(open_rocks_db(db_dir.path()).unwrap());
+        let perm = Permission::new();
+        let add = |db: &mut InternalDb, doc: Document| {
+            assert_eq!(db.make_change(ChangeRequest::AddDoc((doc, perm.clone(), 0))), ChangeResult::Ok);
+        };
+        let del = |db: &mut InternalDb, hash: &Hash| db.make_change(ChangeRequest::DelDoc((hash.clone(), perm.clone())));
+
+        // Root reference
+        let root = Document::new(msgpack!({ "$root_name": "root" })).unwrap();
+        let root_hash = root.hash();
+        add(&mut db, root);
+        assert_eq!(db.references(&root_hash), References { root: 1, strong: 0, weak: 0 });
+        assert_eq!(del(&mut db, &root_hash), ChangeResult::StillReferenced);
+        let mut patch = BTreeMap::new();
+        patch.insert(String::from(ROOT_NAME_FIELD), Value::Null);
+        let unnamed = Document::new(msgpack!({ "$root_name": null })).unwrap();
+        let unnamed_hash = unnamed.hash();
+        assert_eq!(db.make_change(ChangeRequest::ApplyPatch((root_hash.clone(), patch, unnamed_hash.clone(), perm.clone()))),
+            ChangeResult::Ok);
+        assert!(db.references(&root_hash).is_unreferenced());
+        assert!(db.root_docs.is_empty());
+        assert_eq!(del(&mut db, &unnamed_hash), ChangeResult::Ok);
+
+        // Strong reference
+        let target = Document::new(msgpack!({ "target": "strong" })).unwrap();
+        let target_hash = target.hash();
+        let linker = Document::new(msgpack!({ "link": target_hash.clone() })).unwrap();
+        let linker_hash = linker.hash();
+        add(&mut db, target);
+        add(&mut db, linker);
+        assert_eq!(db.references(&target_hash), References { root: 0, strong: 1, weak: 0 });
+        assert_eq!(del(&mut db, &target_hash), ChangeResult::StillReferenced);
+        assert_eq!(del(&mut db, &linker_hash), ChangeResult::Ok);
+        assert_eq!(del(&mut db, &target_hash), ChangeResult::Ok);
+
+        // Weak reference
+        let target = Document::new(msgpack!({ "target": "weak" })).unwrap();
+        let target_hash = target.hash();
+        let holder = Document::new(msgpack!({ "holder": true })).unwrap();
+        let holder_hash = holder.hash();
+        add(&mut db, target);
+        add(&mut db, holder);
+        let entry = Entry::new(holder_hash.clone(), String::from("link"), Value::from(target_hash.clone())).unwrap();
+        let entry_hash = entry.hash();
+        assert_eq!(db.make_change(ChangeRequest::AddEntry((entry, 0))), ChangeResult::Ok);
+        assert_eq!(db.references(&target_hash), References { root: 0, strong: 0, weak: 1 });
+        assert_eq!(del(&mut db, &target_hash), ChangeResult::StillReferenced);
+        assert_eq!(db.make_change(ChangeRequest::DelEntryByHash(entry_hash)), ChangeResult::Ok);
+        assert_eq!(del(&mut db, &target_hash), ChangeResult::Ok);
+    }
+
+    #[test]
+    fn reference_counts_reset() {
+        crypto::init().unwrap();
+        let db_dir = tempfile::tempdir().unwrap();
+        let perm = Permission::new();
+        let target = || Document::new(msgpack!({ "target": true })).unwrap();
+        let target_hash = target().hash();
+        let linker = Document::new(msgpack!({ "link": target_hash.clone() })).unwrap();
+        {
+            let mut db = InternalDb::new(open_rocks_db(db_dir.path()).unwrap());
+            assert_eq!(db.make_change(ChangeRequest::AddDoc((target(), perm.clone(), 0))), ChangeResult::Ok);
+            assert_eq!(db.make_change(ChangeRequest::AddDoc((linker, perm.clone(), 0))), ChangeResult::Ok);
+            assert_eq!(db.references(&target_hash), References { root: 0, strong: 1, weak: 0 });
+        }
+
+        // The documents holding the references weren't kept, so their counters are dropped
+        let mut db = InternalDb::new(open_rocks_db(db_dir.path()).unwrap());
+        assert!(db.references(&target_hash).is_unreferenced());
+        assert_eq!(db.make_change(ChangeRequest::AddDoc((target(), perm.clone(), 0))), ChangeResult::Ok);
+        assert_eq!(db.make_change(ChangeRequest::DelDoc((target_hash, perm))), ChangeResult::Ok);
+    }
+
+    #[test]
+    fn integrity_check() {
+        crypto::init().unwrap();
+        let db_dir = tempfile::tempdir().unwrap();
+        let mut db = InternalDb::new(open_rocks_db(db_dir.path()).unwrap());
+        let perm = Permission::new();
+
+        let mut hashes = Vec::new();
+        for i in 0..4 {
+            let doc = Document::new(msgpack!({ "index": i })).unwrap();
+            hashes.push(doc.hash());
+            assert_eq!(db.make_change(ChangeRequest::AddDoc((doc, perm.clone(), 0))), ChangeResult::Ok);
+        }
+        assert!(db.verify_all_hashes().is_empty());
+        assert!(db.get_doc(&hashes[1], &perm).is_some());
+
+        // Corrupt the stored bytes of two documents
+        for hash in hashes[1..3].iter() {
+            let doc = &mut db.doc_db.get_mut(hash).unwrap().1;
+            let last = doc.len() - 1;
+            doc[last] ^= 0x01;
+        }
+        let mut corrupted = hashes[1..3].to_vec();
+        corrupted.sort_unstable();
+        assert_eq!(db.verify_all_hashes(), corrupted);
+        assert!(db.get_doc(&hashes[0], &perm).is_some());
+        assert!(db.get_doc(&hashes[1], &perm).is_none());
+        assert!(db.get_doc(&hashes[2], &perm).is_none());
+
+        // An intact database streams no hashes back
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Db::new(db_dir.path()).unwrap();
+        let doc = Document::new(msgpack!({ "title": "Intact" })).unwrap();
+        assert_eq!(db.add_doc(doc, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        let query = db.integrity_check().unwrap();
+        match query.recv().unwrap() {
+            QueryResponse::DoneForever => (),
+            _ => panic!("Intact database reported a corrupted document"),
+        }
+        db.close().unwrap();
+    }
+
+    /// Collect every response to a query until it's done. Panics on any response that `pick` 
+    /// returns `None` for.
+    fn drain_query<T, F: Fn(QueryResponse) -> Option<T>>(query: QueryWait, pick: F) -> Vec<T> {
+        let mut found = Vec::new();
+        loop {
+            match query.recv().unwrap() {
+                QueryResponse::DoneForever => break,
+                response => found.push(pick(response).expect("Unexpected query response")),
+            }
+        }
+        found
+    }
+
+    /// Hashes of the documents a query returns.
+    fn query_doc_hashes(query: QueryWait) -> Vec<Hash> {
+        drain_query(query, |response| match response {
+            QueryResponse::Doc((doc, _)) => Some(doc.hash()),
+            _ => None,
+        })
+    }
+
+    fn string_index_docs(db: &Db, field: &str, value: &str, perm: &Permission) -> Vec<Hash> {
+        query_doc_hashes(db.query_string_index(field, value, perm, 2).unwrap())
+    }
+
+    #[test]
+    fn string_index() {
+        crypto::init().unwrap();
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Db::new(db_dir.path()).unwrap();
+        let perm = Permission::new();
+        let names = ["alice", "bob"];
+
+        // Documents added before the field is indexed are picked up when indexing starts
+        let mut alice = Vec::new();
+        for i in 0..10 {
+            let doc = Document::new(msgpack!({ "index": i, "name": names[i % 2] })).unwrap();
+            if i % 2 == 0 { alice.push(doc.hash()); }
+            assert_eq!(db.add_doc(doc, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+            if i == 4 {
+                assert_eq!(db.index_string_field("name").unwrap().recv().unwrap(), ChangeResult::Ok);
+            }
+        }
+
+        let found = string_index_docs(&db, "name", "alice", &perm);
+        assert_eq!(found.len(), 5);
+        assert!(alice.iter().all(|hash| found.contains(hash)));
+        assert_eq!(string_index_docs(&db, "name", "bob", &perm).len(), 5);
+        assert_eq!(string_index_docs(&db, "name", "carol", &perm).len(), 0);
+        assert_eq!(string_index_docs(&db, "index", "alice", &perm).len(), 0);
+
+        // Deleted documents drop out of the index
+        assert_eq!(db.del_doc(alice[0].clone(), &perm).unwrap().recv().unwrap(), ChangeResult::Ok);
+        let found = string_index_docs(&db, "name", "alice", &perm);
+        assert_eq!(found.len(), 4);
+        assert!(!found.contains(&alice[0]));
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn query_by_field() {
+        crypto::init().unwrap();
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Db::new(db_dir.path()).unwrap();
+        let perm = Permission::new();
+
+        let schema = Document::new(msgpack!({
+            "name": "Field schema",
+            "req": {
+                "color": { "type": "Str" },
+                "index": { "type": "Int" }
+            }
+        })).unwrap();
+        let schema_hash = schema.hash();
+        assert_eq!(db.add_doc(schema, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        let mut red = Vec::new();
+        for i in 0..10 {
+            let color = if i % 2 == 0 { "red" } else { "blue" };
+            let doc = Document::new(msgpack!({ "": schema_hash.clone(), "color": color, "index": i })).unwrap();
+            if i % 2 == 0 { red.push(doc.hash()); }
+            assert_eq!(db.add_doc(doc, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        }
+        // Documents without the schema aren't matched
+        let other = Document::new(msgpack!({ "color": "red", "index": 0 })).unwrap();
+        assert_eq!(db.add_doc(other, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+
+        let find = |field: &str, value: Value| {
+            query_doc_hashes(db.query_by_field(schema_hash.clone(), field.to_string(), value, &perm, 2).unwrap())
+        };
+        let found = find("color", msgpack!("red"));
+        assert_eq!(found.len(), 5);
+        assert!(red.iter().all(|hash| found.contains(hash)));
+        assert_eq!(find("index", msgpack!(3)).len(), 1);
+        assert_eq!(find("color", msgpack!("green")).len(), 0);
+        assert_eq!(find("shape", msgpack!("red")).len(), 0);
+        assert!(db.query_by_field(schema_hash.clone(), String::from("color"), msgpack!("red"), &perm, 0).is_err());
+        db.close().unwrap();
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_round_trip() {
+        crypto::init().unwrap();
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Db::new(db_dir.path()).unwrap();
+        let perm = Permission::new();
+
+        let schema = Document::new(msgpack!({
+            "name": "Test schema",
+            "req": {
+                "title": { "type": "Str" },
+                "index": { "type": "Int" }
+            },
+            "entries": {
+                "note": { "type": "Str" }
+            }
+        })).unwrap();
+        let schema_hash = schema.hash();
+        assert_eq!(db.add_doc(schema, &perm, 0).unwrap().recv().unwrap(), ChangeResult::Ok);
+        let mut doc_hashes = vec![schema_hash.clone()];
+        for i in 0..10 {
+            let doc = Document::new(msgpack!({
+                "": schema_hash.clone(),
+                "title": "Test document",
+                "index": i
+            })).unwrap();
+            let doc_hash = doc.hash();
+            let doc_perm = Permission::new().advertise(i % 2 == 0).quota(Some(1 << 20));
+            assert_eq!(db.add_doc(doc, &doc_perm, i * 1000).unwrap().recv().unwrap(), ChangeResult::Ok);
+            let entry = Entry::new(doc_hash.clone(), String::from("note"), msgpack!("A note")).unwrap();
+            assert_eq!(db.add_entry(entry, i * 100).unwrap().recv().unwrap(), ChangeResult::Ok);
+            doc_hashes.push(doc_hash);
+        }
+
+        let mut backup = Vec::new();
+        assert_eq!(db.export_json(&mut backup).unwrap().recv().unwrap(), ChangeResult::Ok);
+        db.close().unwrap();
+
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Db::new(db_dir.path()).unwrap();
+        assert_eq!(db.import_json(&mut &backup[..]).unwrap().recv().unwrap(), ChangeResult::Ok);
+        for hash in doc_hashes.iter() {
+            let mut query = Query::new();
+            query.add_root(hash);
+            let query = db.query(query, &perm, 1).unwrap();
+            match query.recv().unwrap() {
+                QueryResponse::Doc((doc, _)) => assert_eq!(&doc.hash(), hash),
+                _ => panic!("Document wasn't restored from the JSON backup"),
+            }
+        }
+
+        let mut restored = Vec::new();
+        assert_eq!(db.export_json(&mut restored).unwrap().recv().unwrap(), ChangeResult::Ok);
+        let backup: serde_json::Value = serde_json::from_slice(&backup[..]).unwrap();
+        let restored: serde_json::Value = serde_json::from_slice(&restored[..]).unwrap();
+        assert_eq!(backup["documents"].as_array().unwrap().len(), 11);
+        assert_eq!(restored["documents"].as_array().unwrap().len(), 11);
+        assert_eq!(restored["entries"].as_array().unwrap().len(), 10);
+
+        // Permissions and time to live come back as they were
+        let sorted = |backup: &serde_json::Value, list: &str, key: &str| {
+            let mut list = backup[list].as_array().unwrap().clone();
+            list.sort_by(|a, b| a[key].as_str().cmp(&b[key].as_str()));
+            list
+        };
+        assert_eq!(sorted(&backup, "documents", "hash"), sorted(&restored, "documents", "hash"));
+        assert_eq!(sorted(&backup, "entries", "doc"), sorted(&restored, "entries", "doc"));
+        let docs = sorted(&restored, "documents", "hash");
+        assert!(docs.iter().any(|doc| doc["perm"]["advertise"] == true && doc["ttl"] != 0));
+        assert!(docs.iter().all(|doc| doc["perm"]["quota"] == (1 << 20) || doc["schema"].is_null()));
+        db.close().unwrap();
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_perm() {
+        let perm = Permission::new().direct(true).anonymous(true).write(false).quota(Some(500));
+        assert!(perm_from_json(&perm_to_json(&perm)) == Ok(perm));
+        let perm = Permission::new();
+        assert!(perm_from_json(&perm_to_json(&perm)) == Ok(perm));
+        let mut missing = perm_to_json(&Permission::new());
+        missing.as_object_mut().unwrap().remove("global");
+        assert!(perm_from_json(&missing).is_err());
+    }
+}
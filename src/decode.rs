@@ -3,6 +3,8 @@ use std::io::Error;
 use std::io::ErrorKind::{InvalidData, UnexpectedEof};
 use std::collections::BTreeMap;
 use std::cmp::Ordering;
+use std::fmt;
+use std::error;
 
 use byteorder::{ReadBytesExt, BigEndian};
 use num_traits::NumCast;
@@ -10,13 +12,34 @@ use num_traits::NumCast;
 use super::{Value, Integer, ValueRef, Hash, Identity, Lockbox, Timestamp};
 use Marker;
 use MarkerType;
+use schema::{Validator, Checklist};
+
+/// Error payload for data that is valid MessagePack, but not in condense-db canonical form.
+#[derive(Debug)]
+struct NonCanonical(String);
+
+impl fmt::Display for NonCanonical {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl error::Error for NonCanonical {}
+
+fn is_non_canonical(e: &io::Error) -> bool {
+    e.get_ref().map_or(false, |inner| inner.is::<NonCanonical>())
+}
 
 fn not_shortest() -> io::Error {
-    Error::new(InvalidData, "Not shortest possible encoding")
+    Error::new(InvalidData, NonCanonical(String::from("Not shortest possible encoding")))
 }
 
 fn not_negative() -> io::Error {
-    Error::new(InvalidData, "Positive value used in Int type")
+    Error::new(InvalidData, NonCanonical(String::from("Positive value used in Int type")))
+}
+
+fn not_ordered(detail: &str) -> io::Error {
+    Error::new(InvalidData, NonCanonical(format!("Object fields not in lexicographic order{}", detail)))
 }
 
 /// Decode a MessagePack value. Decoding will fail if the value isn't in 
@@ -85,6 +108,22 @@ pub fn read_value_ref<'a>(buf: &mut &'a [u8]) -> io::Result<ValueRef<'a>> {
     })
 }
 
+/// Decode a MessagePack value that must pass a schema validator. `types` is the list of validators 
+/// that `validator` may refer to. The value is decoded in the same pass that validates it, so a 
+/// value failing the validator is rejected at the first violation, without decoding the rest of 
+/// it. Hash validators' checks against linked documents are not made.
+pub fn read_value_with_schema<'a>(buf: &mut &'a [u8], validator: &Validator, types: &[Validator])
+    -> io::Result<ValueRef<'a>>
+{
+    let mut checklist = Checklist::new();
+    let start = *buf;
+    match validator.validate_value("", buf, types, 0, &mut checklist, true)? {
+        Some(value) => Ok(value),
+        // Only possible if the validator passed without reading anything
+        None => read_value_ref(&mut &start[..]),
+    }
+}
+
 /// Verify a MessagePack value and return the number of bytes in it. Fails if the value isn't in 
 /// condense-db canonical form. That is:
 /// - All types are encoded in as few bytes as possible
@@ -117,13 +156,55 @@ pub fn verify_value(buf: &mut &[u8]) -> io::Result<usize> {
     Ok(length - buf.len())
 }
 
+/// Check if the value at the start of the buffer is in condense-db canonical form (see 
+/// [`verify_value`](fn.verify_value.html)). Returns `Ok(false)` if it is otherwise valid 
+/// MessagePack, but uses a longer encoding than necessary or has object fields out of order. Fails 
+/// if it isn't valid MessagePack at all, e.g. if it is truncated, has invalid UTF-8, or has 
+/// repeated object fields. Checking stops at the first non-canonical encoding found, so data 
+/// after that point is not checked.
+pub fn is_canonical_msgpack(buf: &[u8]) -> io::Result<bool> {
+    match verify_value(&mut &buf[..]) {
+        Ok(_) => Ok(true),
+        Err(ref e) if is_non_canonical(e) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Find a field in an encoded object and return the raw encoded value for it, without reading 
+/// the rest of the object. Fields are expected in canonical lexicographic order, so scanning stops 
+/// once the field's position has been passed. Returns `None` if the field isn't present. Fails if 
+/// the buffer doesn't start with an object, or if any value read before the field isn't in 
+/// canonical form.
+pub fn try_extract_field<'a>(buf: &'a [u8], key: &str) -> io::Result<Option<&'a [u8]>> {
+    let mut buf = buf;
+    let len = if let MarkerType::Object(len) = read_marker(&mut buf)? {
+        len
+    }
+    else {
+        return Err(Error::new(InvalidData, "Expected an object"));
+    };
+    for _ in 0..len {
+        let field = read_str(&mut buf)?;
+        match field.cmp(key) {
+            Ordering::Less => { verify_value(&mut buf)?; },
+            Ordering::Equal => {
+                let start = buf;
+                let value_len = verify_value(&mut buf)?;
+                return Ok(Some(&start[..value_len]));
+            },
+            Ordering::Greater => { return Ok(None); },
+        }
+    }
+    Ok(None)
+}
+
 pub fn read_null(buf: &mut &[u8]) -> io::Result<()> {
     let marker = read_marker(buf)?;
     if let MarkerType::Null = marker {
         Ok(())
     }
     else {
-        Err(Error::new(InvalidData, format!("Expected null value, got {:?}", marker)))
+        Err(Error::new(InvalidData, format!("Expected Null, got {}", marker.type_name())))
     }
 }
 
@@ -133,7 +214,7 @@ pub fn read_bool(buf: &mut &[u8]) -> io::Result<bool> {
         Ok(v)
     }
     else {
-        Err(Error::new(InvalidData, format!("Expected boolean, got {:?}", marker)))
+        Err(Error::new(InvalidData, format!("Expected Boolean, got {}", marker.type_name())))
     }
 }
 
@@ -143,7 +224,7 @@ pub fn read_integer(buf: &mut &[u8]) -> io::Result<Integer> {
     match marker {
         MarkerType::PosInt((len, v)) => read_pos_int(buf, len, v),
         MarkerType::NegInt((len, v)) => read_neg_int(buf, len, v),
-        _ => Err(Error::new(InvalidData, format!("Expected Integer, got {:?}", marker))),
+        _ => Err(Error::new(InvalidData, format!("Expected Integer, got {}", marker.type_name()))),
     }
 }
 
@@ -225,7 +306,7 @@ pub fn read_str<'a>(buf: &mut &'a [u8]) -> io::Result<&'a str> {
         read_raw_str(buf, len)
     }
     else {
-        Err(Error::new(InvalidData, format!("Expected a string, got {:?}", marker)))
+        Err(Error::new(InvalidData, format!("Expected String, got {}", marker.type_name())))
     }
 }
 
@@ -241,7 +322,7 @@ pub fn read_f32(buf: &mut &[u8]) -> io::Result<f32> {
         buf.read_f32::<BigEndian>()
     }
     else {
-        Err(Error::new(InvalidData, format!("Expected f32, got {:?}", marker)))
+        Err(Error::new(InvalidData, format!("Expected F32, got {}", marker.type_name())))
     }
 }
 
@@ -252,7 +333,7 @@ pub fn read_f64(buf: &mut &[u8]) -> io::Result<f64> {
         buf.read_f64::<BigEndian>()
     }
     else {
-        Err(Error::new(InvalidData, format!("Expected f64, got {:?}", marker)))
+        Err(Error::new(InvalidData, format!("Expected F64, got {}", marker.type_name())))
     }
 }
 
@@ -263,7 +344,7 @@ pub fn read_bin<'a>(buf: &mut &'a [u8]) -> io::Result<&'a [u8]> {
         read_raw_bin(buf, len)
     }
     else {
-        Err(Error::new(InvalidData, format!("Expected binary data, got {:?}", marker)))
+        Err(Error::new(InvalidData, format!("Expected Binary, got {}", marker.type_name())))
     }
 }
 
@@ -283,7 +364,26 @@ pub fn read_array_ref<'a>(buf: &mut &'a [u8]) -> io::Result<Vec<ValueRef<'a>>> {
         Ok(v)
     }
     else {
-        Err(Error::new(InvalidData, format!("Expected array, got {:?}", marker)))
+        Err(Error::new(InvalidData, format!("Expected Array, got {}", marker.type_name())))
+    }
+}
+
+/// Attempt to read an array, returning the raw bytes of each item without parsing them. Each 
+/// slice holds exactly one complete, verified msgpack value.
+pub fn read_array_items_raw<'a>(buf: &mut &'a [u8]) -> io::Result<Vec<&'a [u8]>> {
+    let marker = read_marker(buf)?;
+    if let MarkerType::Array(len) = marker {
+        // Every item takes at least one byte, so don't trust a length longer than the buffer
+        let mut v = Vec::with_capacity(len.min(buf.len()));
+        for _i in 0..len {
+            let start: &'a [u8] = buf;
+            let item_len = verify_value(buf)?;
+            v.push(&start[..item_len]);
+        }
+        Ok(v)
+    }
+    else {
+        Err(Error::new(InvalidData, format!("Expected Array, got {}", marker.type_name())))
     }
 }
 
@@ -298,7 +398,7 @@ pub fn read_array(buf: &mut &[u8]) -> io::Result<Vec<Value>> {
         Ok(v)
     }
     else {
-        Err(Error::new(InvalidData, format!("Expected array, got {:?}", marker)))
+        Err(Error::new(InvalidData, format!("Expected Array, got {}", marker.type_name())))
     }
 }
 
@@ -309,7 +409,28 @@ pub fn read_object_ref<'a>(buf: &mut &'a [u8]) -> io::Result<BTreeMap<&'a str, V
         read_to_map_ref(buf, len)
     }
     else {
-        Err(Error::new(InvalidData, format!("Expected object, got {:?}", marker)))
+        Err(Error::new(InvalidData, format!("Expected Object, got {}", marker.type_name())))
+    }
+}
+
+/// Attempt to read an object, returning each field along with the raw bytes of its value, without 
+/// parsing the values. Each slice holds exactly one complete, verified msgpack value, and fields 
+/// are checked for canonical ordering.
+pub fn read_object_ref_raw<'a>(buf: &mut &'a [u8]) -> io::Result<Vec<(&'a str, &'a [u8])>> {
+    let marker = read_marker(buf)?;
+    if let MarkerType::Object(len) = marker {
+        // Every field takes at least two bytes, so don't trust a length longer than the buffer
+        let mut v = Vec::with_capacity(len.min(buf.len() / 2));
+        object_iterate(buf, len, |field, buf| {
+            let start: &'a [u8] = buf;
+            let value_len = verify_value(buf)?;
+            v.push((field, &start[..value_len]));
+            Ok(())
+        })?;
+        Ok(v)
+    }
+    else {
+        Err(Error::new(InvalidData, format!("Expected Object, got {}", marker.type_name())))
     }
 }
 
@@ -320,7 +441,7 @@ pub fn read_object(buf: &mut &[u8]) -> io::Result<BTreeMap<String, Value>> {
         read_to_map(buf, len)
     }
     else {
-        Err(Error::new(InvalidData, format!("Expected object, got {:?}", marker)))
+        Err(Error::new(InvalidData, format!("Expected Object, got {}", marker.type_name())))
     }
 }
 
@@ -331,7 +452,7 @@ pub fn read_hash(buf: &mut &[u8]) -> io::Result<Hash> {
         read_raw_hash(buf, len)
     }
     else {
-        Err(Error::new(InvalidData, format!("Expected hash, got {:?}", marker)))
+        Err(Error::new(InvalidData, format!("Expected Hash, got {}", marker.type_name())))
     }
 }
 
@@ -342,7 +463,7 @@ pub fn read_id(buf: &mut &[u8]) -> io::Result<Identity> {
         read_raw_id(buf, len)
     }
     else {
-        Err(Error::new(InvalidData, format!("Expected Identity, got {:?}", marker)))
+        Err(Error::new(InvalidData, format!("Expected Identity, got {}", marker.type_name())))
     }
 }
 
@@ -353,7 +474,7 @@ pub fn read_lockbox(buf: &mut &[u8]) -> io::Result<Lockbox> {
         read_raw_lockbox(buf, len)
     }
     else {
-        Err(Error::new(InvalidData, format!("Expected Lockbox, got {:?}", marker)))
+        Err(Error::new(InvalidData, format!("Expected Lockbox, got {}", marker.type_name())))
     }
 }
 
@@ -364,7 +485,7 @@ pub fn read_time(buf: &mut &[u8]) -> io::Result<Timestamp> {
         read_raw_time(buf, len)
     }
     else {
-        Err(Error::new(InvalidData, format!("Expected Timestamp, got {:?}", marker)))
+        Err(Error::new(InvalidData, format!("Expected Timestamp, got {}", marker.type_name())))
     }
 }
 
@@ -499,6 +620,18 @@ pub fn read_raw_str<'a>(buf: &mut &'a [u8], len: usize) -> io::Result<&'a str> {
     }
 }
 
+/// Step through every field/value pair in an object, failing before reading anything if the object 
+/// claims to have more than `max_fields` fields.
+pub fn object_iterate_with_limit<'a, F>(buf: &mut &'a [u8], len: usize, max_fields: usize, f: F) -> io::Result<()>
+    where F: FnMut(&'a str, &mut &'a [u8]) -> io::Result<()>
+{
+    if len > max_fields {
+        return Err(Error::new(InvalidData,
+            format!("Object has {} fields, more than the limit of {}", len, max_fields)));
+    }
+    object_iterate(buf, len, f)
+}
+
 /// Step through every field/value pair in an object
 pub fn object_iterate<'a, F>(buf: &mut &'a [u8], len: usize, mut f: F) -> io::Result<()>
     where F: FnMut(&'a str, &mut &'a [u8]) -> io::Result<()>
@@ -518,8 +651,7 @@ pub fn object_iterate<'a, F>(buf: &mut &'a [u8], len: usize, mut f: F) -> io::Re
                 return Err(Error::new(InvalidData, format!("Found object with non-unique field \"{}\"", field)));
             },
             Ordering::Greater => {
-                return Err(Error::new(InvalidData,
-                    format!("Object fields not in lexicographic order. Last = '{}', Current = '{}'", old_field, field)));
+                return Err(not_ordered(&format!(". Last = '{}', Current = '{}'", old_field, field)));
             },
         }
         old_field = field;
@@ -560,6 +692,44 @@ pub fn verify_map(buf: &mut &[u8], len: usize) -> io::Result<usize> {
     Ok(length - buf.len())
 }
 
+/// Verify that an object's fields and values are all in canonical form, without parsing out any
+/// values. Keys are compared as raw bytes, which for valid UTF-8 gives the same lexicographic
+/// order as comparing the strings. Returns the number of bytes the object's fields occupied.
+pub fn verify_object_fields(buf: &mut &[u8], expected_len: usize) -> io::Result<usize> {
+    let length = buf.len();
+    let mut old_field: &[u8] = &[];
+    for i in 0..expected_len {
+        let field = match read_marker(buf)? {
+            MarkerType::String(len) => {
+                if buf.len() < len {
+                    return Err(Error::new(UnexpectedEof, "String length larger than amount of data"));
+                }
+                let (field, rem) = buf.split_at(len);
+                *buf = rem;
+                field
+            },
+            marker => return Err(Error::new(InvalidData, format!("Expected String, got {}", marker.type_name()))),
+        };
+        if std::str::from_utf8(field).is_err() {
+            return Err(Error::new(InvalidData, "String decoded is not valid UTF-8"));
+        }
+        if i > 0 {
+            match old_field.cmp(field) {
+                Ordering::Less => (),
+                Ordering::Equal => {
+                    return Err(Error::new(InvalidData, "Found object with non-unique field"));
+                },
+                Ordering::Greater => {
+                    return Err(not_ordered(""));
+                },
+            }
+        }
+        verify_value(buf)?;
+        old_field = field;
+    }
+    Ok(length - buf.len())
+}
+
 
 /// Read raw Timestamp out from a buffer
 pub fn read_raw_time(buf: &mut &[u8], len: usize) -> io::Result<Timestamp> {
@@ -747,3 +917,190 @@ pub fn read_marker(buf: &mut &[u8]) -> io::Result<MarkerType> {
 
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encode;
+
+    #[test]
+    fn extract_field() {
+        let mut obj = BTreeMap::new();
+        for i in 0..20 {
+            obj.insert(format!("field{:02}", i), Value::from(i));
+        }
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &Value::Object(obj));
+
+        for (key, expected) in [("field00", 0), ("field10", 10), ("field19", 19)].iter() {
+            let mut field = try_extract_field(&raw[..], key).unwrap().unwrap();
+            assert_eq!(read_integer(&mut field).unwrap().as_u64(), Some(*expected));
+            assert!(field.is_empty());
+        }
+        assert_eq!(try_extract_field(&raw[..], "").unwrap(), None);
+        assert_eq!(try_extract_field(&raw[..], "field05a").unwrap(), None);
+        assert_eq!(try_extract_field(&raw[..], "zzz").unwrap(), None);
+
+        raw.clear();
+        encode::write_value(&mut raw, &Value::from(vec![Value::from(1)]));
+        assert!(try_extract_field(&raw[..], "").is_err());
+    }
+
+    #[test]
+    fn object_field_limit() {
+        // Object header claiming 10000 fields, followed by only one
+        let mut raw = vec![0xde, 0x27, 0x10];
+        encode::write_value(&mut raw, &Value::from("a"));
+        encode::write_value(&mut raw, &Value::from(1));
+        let mut buf = &raw[..];
+        let len = match read_marker(&mut buf).unwrap() {
+            MarkerType::Object(len) => len,
+            _ => panic!("Expected an object marker"),
+        };
+        assert_eq!(len, 10000);
+        let mut calls = 0;
+        let mut limited = buf;
+        let err = object_iterate_with_limit(&mut limited, len, 1000, |_, _| { calls += 1; Ok(()) }).unwrap_err();
+        assert_eq!(err.kind(), InvalidData);
+        assert_eq!(calls, 0);
+        assert_eq!(limited.len(), buf.len());
+
+        let mut buf = &raw[3..];
+        object_iterate_with_limit(&mut buf, 1, 1, |field, raw| {
+            assert_eq!(field, "a");
+            read_integer(raw).map(|_| ())
+        }).unwrap();
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn array_items_raw() {
+        let array = msgpack!([1, "two", [3, 4], { "five": 5 }, null]);
+        let items = array.as_array().unwrap();
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &array);
+        raw.push(0xc3); // Trailing data that isn't part of the array
+
+        let mut buf = &raw[..];
+        let raw_items = read_array_items_raw(&mut buf).unwrap();
+        assert_eq!(buf, &[0xc3]);
+        assert_eq!(raw_items.len(), items.len());
+        for (raw_item, item) in raw_items.iter().zip(items.iter()) {
+            let mut expected = Vec::new();
+            encode::write_value(&mut expected, item);
+            assert_eq!(*raw_item, &expected[..]);
+            let mut item_buf = *raw_item;
+            let decoded = read_value(&mut item_buf).unwrap();
+            assert!(item_buf.is_empty());
+            let mut reencoded = Vec::new();
+            encode::write_value(&mut reencoded, &decoded);
+            assert_eq!(reencoded, expected);
+        }
+
+        // Truncated arrays and non-arrays fail
+        assert!(read_array_items_raw(&mut &raw[..raw.len()-3]).is_err());
+        assert!(read_array_items_raw(&mut &[0xc3][..]).is_err());
+    }
+
+    #[test]
+    fn object_ref_raw() {
+        let mut obj = BTreeMap::new();
+        for i in 0..20 {
+            obj.insert(format!("field{:02}", i), Value::from(vec![Value::from(i), Value::from("x")]));
+        }
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &Value::Object(obj));
+        raw.push(0xc3); // Trailing data that isn't part of the object
+
+        let mut buf = &raw[..];
+        let fields = read_object_ref_raw(&mut buf).unwrap();
+        assert_eq!(buf, &[0xc3]);
+        assert_eq!(fields.len(), 20);
+        assert!(fields.iter().enumerate().all(|(i, (field, _))| *field == format!("field{:02}", i)));
+        // Only the one field needed gets parsed
+        let (_, mut value) = fields.iter().find(|(field, _)| *field == "field10").cloned().unwrap();
+        let value = read_array_ref(&mut value).unwrap();
+        assert_eq!(value[0].as_u64(), Some(10));
+        assert_eq!(value[1].as_str(), Some("x"));
+
+        // Small objects use the fixmap marker, and the map ref reader agrees with the raw one
+        let small = msgpack!({ "a": 1, "b": { "c": null } });
+        raw.clear();
+        encode::write_value(&mut raw, &small);
+        assert_eq!(raw[0], 0x82);
+        let fields = read_object_ref_raw(&mut &raw[..]).unwrap();
+        let map = read_object_ref(&mut &raw[..]).unwrap();
+        assert_eq!(fields.iter().map(|(field, _)| *field).collect::<Vec<_>>(),
+            map.keys().cloned().collect::<Vec<_>>());
+        assert_eq!(fields[1].1, &[0x81, 0xa1, b'c', 0xc0][..]);
+
+        // Out of order fields, truncated objects, and non-objects fail
+        assert!(read_object_ref_raw(&mut &[0x82, 0xa1, b'b', 0x01, 0xa1, b'a', 0x02][..]).is_err());
+        assert!(read_object_ref_raw(&mut &raw[..raw.len()-1]).is_err());
+        assert!(read_object_ref_raw(&mut &[0xc3][..]).is_err());
+    }
+
+    #[test]
+    fn canonical_check() {
+        // 5, as a positive fixint and as a uint8
+        assert!(is_canonical_msgpack(&[0x05]).unwrap());
+        assert!(!is_canonical_msgpack(&[0xcc, 0x05]).unwrap());
+        // -1, as a negative fixint and as an int8 positive value
+        assert!(is_canonical_msgpack(&[0xff]).unwrap());
+        assert!(!is_canonical_msgpack(&[0xd0, 0x05]).unwrap());
+        // Object with fields out of order
+        assert!(is_canonical_msgpack(&[0x82, 0xa1, b'a', 0x01, 0xa1, b'b', 0x02]).unwrap());
+        assert!(!is_canonical_msgpack(&[0x82, 0xa1, b'b', 0x01, 0xa1, b'a', 0x02]).unwrap());
+
+        // Truncated data, invalid UTF-8, and repeated fields aren't valid at all
+        assert!(is_canonical_msgpack(&[0xcd, 0x01]).is_err());
+        assert!(is_canonical_msgpack(&[0xa2, 0xc3, 0x28]).is_err());
+        assert!(is_canonical_msgpack(&[0x82, 0xa1, b'a', 0x01, 0xa1, b'a', 0x02]).is_err());
+        let err = verify_value(&mut &[0xcc, 0x05][..]).unwrap_err();
+        assert_eq!(err.to_string(), "Not shortest possible encoding");
+    }
+
+    #[test]
+    fn object_fields() {
+        let mut obj = BTreeMap::new();
+        obj.insert(String::from("a"), Value::from(1));
+        obj.insert(String::from("b"), Value::from("two"));
+        obj.insert(String::from("c"), Value::from(vec![Value::from(3)]));
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &Value::Object(obj));
+        // Skip the map marker; only the fields are verified
+        let fields = &raw[1..];
+
+        // Valid
+        let mut buf = fields;
+        assert_eq!(verify_object_fields(&mut buf, 3).unwrap(), fields.len());
+        assert!(buf.is_empty());
+        assert_eq!(verify_map(&mut &fields[..], 3).unwrap(), fields.len());
+
+        // Truncated
+        for cut in 1..fields.len() {
+            assert!(verify_object_fields(&mut &fields[..cut], 3).is_err(), "Passed when cut to {} bytes", cut);
+        }
+
+        // Length mismatch: too many fields runs out of data, and too few leaves the rest unread
+        assert_eq!(verify_object_fields(&mut &fields[..], 4).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        let mut buf = fields;
+        let used = verify_object_fields(&mut buf, 2).unwrap();
+        assert_eq!(used + buf.len(), fields.len());
+        assert_eq!(read_str(&mut buf).unwrap(), "c");
+
+        // Out of order or repeated fields
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &Value::from("b"));
+        encode::write_value(&mut raw, &Value::from(1));
+        encode::write_value(&mut raw, &Value::from("a"));
+        encode::write_value(&mut raw, &Value::from(2));
+        assert!(verify_object_fields(&mut &raw[..], 2).is_err());
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &Value::from("a"));
+        encode::write_value(&mut raw, &Value::from(1));
+        encode::write_value(&mut raw, &Value::from("a"));
+        encode::write_value(&mut raw, &Value::from(2));
+        assert!(verify_object_fields(&mut &raw[..], 2).is_err());
+    }
+}
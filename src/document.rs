@@ -1,15 +1,15 @@
 use std::io;
 use std::io::ErrorKind::InvalidData;
 
-use MarkerType;
 use super::{Hash, Value, ValueRef};
 use super::crypto::{HashState, Vault, Key, Identity, CryptoError};
 use decode;
 use crypto;
+use marker::MarkerType;
 
 /// A Condense-db document. Guaranteed to hold a raw msgpack object with validated signatures. 
 /// Schema validation is not guaranteed unless it has come from the database.
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct Document {
     hash_state: HashState,
     doc_hash: Hash,
@@ -61,6 +61,12 @@ impl Document {
         self.doc.len()
     }
 
+    /// Get the number of bytes the document takes up when fully encoded, including signatures. 
+    /// This is the same as `len`, and is the length `to_vec` would return.
+    pub fn size_bytes(&self) -> usize {
+        self.len()
+    }
+
     /// Get the length of the encoded value only (without signatures).
     pub fn doc_len(&self) -> usize {
         self.doc_len
@@ -77,35 +83,75 @@ impl Document {
         self.hash_state.get_hash()
     }
 
+    /// Recompute the hash from the document's encoded bytes, including signatures, and check that 
+    /// it matches `hash`. Returns false if the bytes have been corrupted.
+    pub fn verify_hash(&self) -> bool {
+        let hash = self.hash();
+        match Hash::new(hash.get_version(), &self.doc[..]) {
+            Ok(computed) => computed == hash,
+            Err(_) => false,
+        }
+    }
+
     /// Retrieve the value stored inside the document as a `ValueRef`. This value has the same 
     /// lifetime as the Document; it can be converted to a `Value` if it needs to outlast the 
     /// Document.
     pub fn get_value(&self) -> ValueRef {
         super::decode::read_value_ref(&mut &self.doc[..]).unwrap()
     }
+
+    /// Decode the document's object into a new `Value`, leaving out any signatures.
+    pub fn to_value(&self) -> io::Result<Value> {
+        decode::read_value(&mut &self.doc[..self.doc_len])
+    }
+
+    /// Iterate over the names of the document's fields, in their stored lexicographic order, 
+    /// without decoding any of the values. Includes the empty schema field, if present.
+    pub fn field_names<'a>(&'a self) -> impl Iterator<Item=&'a str> + 'a {
+        let mut buf = &self.doc[..self.doc_len];
+        let len = match decode::read_marker(&mut buf) {
+            Ok(MarkerType::Object(len)) => len,
+            _ => 0,
+        };
+        (0..len).scan(buf, |buf, _| {
+            let field = decode::read_str(buf).ok()?;
+            decode::verify_value(buf).ok()?;
+            Some(field)
+        })
+    }
+
+    /// Checks if the document has a field with the given name, without decoding any of the values.
+    pub fn has_field(&self, name: &str) -> bool {
+        decode::try_extract_field(&self.doc[..self.doc_len], name).ok().map_or(false, |f| f.is_some())
+    }
+}
+
+/// Create a new document from a `Value` that uses a schema. Fails if the value isn't an Object, 
+/// or if its empty field ("") doesn't contain the Hash of a schema. For documents without a 
+/// schema, use `Document::new`.
+pub fn from_value(v: Value) -> io::Result<Document> {
+    match v.as_obj() {
+        Some(obj) => {
+            if obj.get("").and_then(|v| v.as_hash()).is_none() {
+                return Err(io::Error::new(InvalidData, "Value doesn't have a schema Hash in its empty field"));
+            }
+        },
+        None => return Err(io::Error::new(InvalidData, "Value isn't an Object")),
+    }
+    Document::new(v).map_err(|_e| io::Error::new(InvalidData, "Value isn't an Object"))
 }
 
 // Finds the schema hash for a raw, encoded document. Fails if raw data isn't an object, or if 
 // the empty field ("") doesn't contain a Hash. If there is no empty field, `None` is returned.
 pub fn extract_schema_hash(buf: &[u8]) -> io::Result<Option<Hash>> {
-    let mut buf: &[u8] = buf;
-    // Get the object tag & number of field/value pairs it has
-    let obj_len = if let MarkerType::Object(len) = decode::read_marker(&mut buf)? {
-        len
-    }
-    else {
-        return Err(io::Error::new(InvalidData, "Raw document isn't a msgpack object"));
-    };
-    if obj_len == 0 { return Ok(None); }
-
-    // Get the first field - should be the empty string if there is a schema used.
-    let field = decode::read_str(&mut buf)?;
-    if field.len() > 0 {
-        return Ok(None);
-    }
-    decode::read_hash(&mut buf)
-        .map(|v| Some(v))
-        .map_err(|_e| io::Error::new(InvalidData, "Empty string field doesn't have a Hash as its value"))
+    match decode::try_extract_field(buf, "")? {
+        Some(mut field) => {
+            decode::read_hash(&mut field)
+                .map(|v| Some(v))
+                .map_err(|_e| io::Error::new(InvalidData, "Empty string field doesn't have a Hash as its value"))
+        },
+        None => Ok(None),
+    }
 }
 
 /// Convert from a raw vector straight into a document. This should *only* be called by the 
@@ -175,14 +221,81 @@ pub fn from_raw_parts(
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encode;
 
+    #[test]
+    fn value_round_trip() {
+        crypto::init().unwrap();
+        let schema_hash = Hash::new(1, &[1,2,3]).unwrap();
+        let v = msgpack!({
+            "": schema_hash,
+            "title": "A document",
+            "list": [1, 2, 3]
+        });
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &v);
+        let doc = from_value(v).unwrap();
+        assert_eq!(doc.hash(), Hash::new(1, &raw[..]).unwrap());
 
+        let v = doc.to_value().unwrap();
+        let mut raw_new = Vec::new();
+        encode::write_value(&mut raw_new, &v);
+        assert_eq!(raw, raw_new);
 
+        assert!(from_value(msgpack!("not an object")).is_err());
+        assert!(from_value(msgpack!([1, 2, 3])).is_err());
+        assert!(from_value(msgpack!({ "title": "No schema" })).is_err());
+        assert!(from_value(msgpack!({ "": "Not a hash" })).is_err());
+    }
 
+    #[test]
+    fn size_bytes() {
+        crypto::init().unwrap();
+        let mut vault = Vault::new_from_password(crypto::PasswordLevel::Interactive, String::from("test")).unwrap();
+        let key = vault.new_key().unwrap();
+        let mut doc = Document::new(msgpack!({ "title": "A document", "list": [1, 2, 3] })).unwrap();
+        assert_eq!(doc.size_bytes(), doc.doc_len());
+        assert_eq!(doc.size_bytes(), doc.clone().to_vec().len());
+        doc.sign(&vault, &key).unwrap();
+        assert!(doc.size_bytes() > doc.doc_len());
+        assert_eq!(doc.size_bytes(), doc.clone().to_vec().len());
+    }
 
+    #[test]
+    fn field_names() {
+        crypto::init().unwrap();
+        let mut obj = ::std::collections::BTreeMap::new();
+        for name in ["kiwi", "apple", "fig", "banana", "lime", "cherry", "date", "grape", "elder", "honeydew"].iter() {
+            obj.insert(name.to_string(), Value::from(name.len() as u64));
+        }
+        let doc = Document::new(Value::Object(obj)).unwrap();
+        let names: Vec<&str> = doc.field_names().collect();
+        assert_eq!(names, vec!["apple", "banana", "cherry", "date", "elder", "fig", "grape", "honeydew", "kiwi", "lime"]);
+        assert!(names.iter().all(|name| doc.has_field(name)));
+        assert!(!doc.has_field("mango"));
+        assert!(!doc.has_field(""));
+        assert!(!doc.has_field("app"));
 
+        // The schema field is included, and signatures aren't read as fields
+        let mut vault = Vault::new_from_password(crypto::PasswordLevel::Interactive, String::from("test")).unwrap();
+        let key = vault.new_key().unwrap();
+        let mut doc = from_value(msgpack!({ "": Hash::new(1, &[1,2,3]).unwrap(), "title": "Signed" })).unwrap();
+        doc.sign(&vault, &key).unwrap();
+        assert_eq!(doc.field_names().collect::<Vec<_>>(), vec!["", "title"]);
+        assert!(doc.has_field(""));
+        assert!(Document::new(msgpack!({})).unwrap().field_names().next().is_none());
+    }
 
-
-
-
-
+    #[test]
+    fn verify_hash() {
+        crypto::init().unwrap();
+        let mut doc = Document::new(msgpack!({ "title": "A document" })).unwrap();
+        assert!(doc.verify_hash());
+        let last = doc.doc.len() - 1;
+        doc.doc[last] ^= 0x01;
+        assert!(!doc.verify_hash());
+    }
+}
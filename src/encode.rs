@@ -1,4 +1,8 @@
-use super::Value;
+use std::io;
+use std::io::Error;
+use std::io::ErrorKind::InvalidData;
+
+use super::{Value, ValueRef, Integer, Timestamp, Hash, Identity, Lockbox};
 use Marker;
 use integer;
 use ExtType;
@@ -140,50 +144,128 @@ pub fn write_value(buf: &mut Vec<u8>, val: &Value) {
             }
         },
 
-        Value::Hash(ref val) => {
-            write_ext_marker(buf, val.len() as u32);
-            buf.push(ExtType::Hash.into());
-            val.encode(buf);
-        }
+        Value::Hash(ref val) => write_hash(buf, val),
 
-        Value::Identity(ref val) => {
-            write_ext_marker(buf, val.len() as u32);
-            buf.push(ExtType::Identity.into());
-            val.encode(buf);
-        },
+        Value::Identity(ref val) => write_identity(buf, val),
 
-        Value::Lockbox(ref val) => {
-            write_ext_marker(buf, val.len() as u32);
-            buf.push(ExtType::Lockbox.into());
-            val.encode(buf);
-        },
+        Value::Lockbox(ref val) => write_lockbox(buf, val),
 
-        Value::Timestamp(val) => {
-            let sec = val.timestamp();
-            let nano = val.timestamp_subsec_nanos();
+        Value::Timestamp(val) => write_timestamp(buf, val),
+    }
+}
 
-            if (sec < (std::u32::MAX as i64)) && (sec >= 0) && (nano == 0) {
-                // 32-bit unsigned seconds
-                buf.push(Marker::FixExt4.into());
-                buf.push(ExtType::Timestamp.into());
-                buf.extend_from_slice(&(sec as u32).to_be_bytes());
-            }
-            else if (sec >= 0) && (sec < ((1i64 << 34)-1)) && (nano < ((1u32 << 30)-1)) {
-                // 64-bit unsigned seconds with nanoseconds
-                buf.push(Marker::FixExt8.into());
-                buf.push(ExtType::Timestamp.into());
-                let data: u64 = (sec as u64) | ((nano as u64) << 34);
-                buf.extend_from_slice(&data.to_be_bytes());
-            }
-            else {
-                // 96-bit signed seconds with nanoseconds
-                buf.push(Marker::Ext8.into());
-                buf.push(12u8);
-                buf.push(ExtType::Timestamp.into());
-                buf.extend_from_slice(&nano.to_be_bytes());
-                buf.extend_from_slice(&sec.to_be_bytes());
-            }
+/// Write a `Hash` out to a Vector as a MessagePack extension.
+pub fn write_hash(buf: &mut Vec<u8>, h: &Hash) {
+    write_ext_marker(buf, h.len() as u32);
+    buf.push(ExtType::Hash.into());
+    h.encode(buf);
+}
+
+/// Write an `Identity` out to a Vector as a MessagePack extension.
+pub fn write_identity(buf: &mut Vec<u8>, id: &Identity) {
+    write_ext_marker(buf, id.len() as u32);
+    buf.push(ExtType::Identity.into());
+    id.encode(buf);
+}
+
+/// Write a `Lockbox` out to a Vector as a MessagePack extension.
+pub fn write_lockbox(buf: &mut Vec<u8>, lock: &Lockbox) {
+    write_ext_marker(buf, lock.len() as u32);
+    buf.push(ExtType::Lockbox.into());
+    lock.encode(buf);
+}
+
+/// Write a `Timestamp` out to a Vector as a MessagePack timestamp extension, using the shortest 
+/// format that holds it: 4 bytes of seconds if there are no nanoseconds, 8 bytes with 34 bits of 
+/// seconds and 30 of nanoseconds, or 12 bytes with 64-bit signed seconds otherwise. Seconds equal 
+/// to the maximum of the 4- and 8-byte formats use the next larger one.
+pub fn write_timestamp(buf: &mut Vec<u8>, t: Timestamp) {
+    let sec = t.timestamp();
+    let nano = t.timestamp_subsec_nanos();
+
+    if fits_timestamp32(sec, nano) {
+        // 32-bit unsigned seconds
+        buf.push(Marker::FixExt4.into());
+        buf.push(ExtType::Timestamp.into());
+        buf.extend_from_slice(&(sec as u32).to_be_bytes());
+    }
+    else if fits_timestamp64(sec, nano) {
+        // 64-bit unsigned seconds with nanoseconds
+        buf.push(Marker::FixExt8.into());
+        buf.push(ExtType::Timestamp.into());
+        let data: u64 = (sec as u64) | ((nano as u64) << 34);
+        buf.extend_from_slice(&data.to_be_bytes());
+    }
+    else {
+        // 96-bit signed seconds with nanoseconds
+        buf.push(Marker::Ext8.into());
+        buf.push(12u8);
+        buf.push(ExtType::Timestamp.into());
+        buf.extend_from_slice(&nano.to_be_bytes());
+        buf.extend_from_slice(&sec.to_be_bytes());
+    }
+}
+
+/// Number of bytes `write_timestamp` will write for a `Timestamp`, including the marker and type.
+pub fn timestamp_encoded_len(t: Timestamp) -> usize {
+    let sec = t.timestamp();
+    let nano = t.timestamp_subsec_nanos();
+    if fits_timestamp32(sec, nano) {
+        6
+    }
+    else if fits_timestamp64(sec, nano) {
+        10
+    }
+    else {
+        15
+    }
+}
+
+fn fits_timestamp32(sec: i64, nano: u32) -> bool {
+    (sec < (std::u32::MAX as i64)) && (sec >= 0) && (nano == 0)
+}
+
+fn fits_timestamp64(sec: i64, nano: u32) -> bool {
+    (sec >= 0) && (sec < ((1i64 << 34)-1)) && (nano < ((1u32 << 30)-1))
+}
+
+/// Write the MessagePack value out to a Vector in condense-db canonical form, failing instead of 
+/// writing invalid output if any string, binary data, object, or array has 2^32 or more elements.
+///
+/// Everything else about canonical form is already guaranteed by `Value`: objects are `BTreeMap`s, 
+/// so their keys are unique (the last inserted value wins) and iterate in lexicographic order; 
+/// strings are always valid UTF-8; and integers are always written in their shortest form.
+pub fn write_value_canonical(buf: &mut Vec<u8>, val: &Value) -> io::Result<()> {
+    check_lengths(val)?;
+    write_value(buf, val);
+    Ok(())
+}
+
+/// Check that every length in a value fits in the 32 bits MessagePack allows.
+fn check_lengths(val: &Value) -> io::Result<()> {
+    let check = |len: usize, what: &str| {
+        if len > (std::u32::MAX as usize) {
+            Err(Error::new(InvalidData, format!("{} is too large to encode", what)))
+        }
+        else {
+            Ok(())
         }
+    };
+    match *val {
+        Value::String(ref v) => check(v.len(), "String"),
+        Value::Binary(ref v) => check(v.len(), "Binary data"),
+        Value::Array(ref v) => {
+            check(v.len(), "Array")?;
+            v.iter().try_for_each(check_lengths)
+        },
+        Value::Object(ref v) => {
+            check(v.len(), "Object")?;
+            v.iter().try_for_each(|(key, content)| {
+                check(key.len(), "Object key")?;
+                check_lengths(content)
+            })
+        },
+        _ => Ok(()),
     }
 }
 
@@ -228,9 +310,143 @@ fn write_ext_marker(buf: &mut Vec<u8>, len: u32) {
     };
 }
 
+/// Compute the number of bytes `write_value` would produce for a value, without encoding it.
+pub fn size_of_value(val: &Value) -> usize {
+    match *val {
+        Value::Null => 1,
+        Value::Boolean(_) => 1,
+        Value::Integer(ref val) => size_of_integer(val),
+        Value::String(ref val) => size_of_string(val.len()),
+        Value::F32(_) => 5,
+        Value::F64(_) => 9,
+        Value::Binary(ref val) => size_of_binary(val.len()),
+        Value::Array(ref val) => {
+            val.iter().fold(size_of_collection_marker(val.len()), |acc, item| acc + size_of_value(item))
+        },
+        Value::Object(ref val) => {
+            val.iter().fold(size_of_collection_marker(val.len()), |acc, (key, content)| {
+                acc + size_of_string(key.len()) + size_of_value(content)
+            })
+        },
+        Value::Hash(ref val) => size_of_ext(val.len()),
+        Value::Identity(ref val) => size_of_ext(val.len()),
+        Value::Lockbox(ref val) => size_of_ext(val.len()),
+        Value::Timestamp(ref val) => timestamp_encoded_len(*val),
+    }
+}
+
+/// Compute the number of bytes a value would take up when encoded, without encoding it.
+pub fn size_of_value_ref(val: &ValueRef) -> usize {
+    match *val {
+        ValueRef::Null => 1,
+        ValueRef::Boolean(_) => 1,
+        ValueRef::Integer(ref val) => size_of_integer(val),
+        ValueRef::String(val) => size_of_string(val.len()),
+        ValueRef::F32(_) => 5,
+        ValueRef::F64(_) => 9,
+        ValueRef::Binary(val) => size_of_binary(val.len()),
+        ValueRef::Array(ref val) => {
+            val.iter().fold(size_of_collection_marker(val.len()), |acc, item| acc + size_of_value_ref(item))
+        },
+        ValueRef::Object(ref val) => {
+            val.iter().fold(size_of_collection_marker(val.len()), |acc, (key, content)| {
+                acc + size_of_string(key.len()) + size_of_value_ref(content)
+            })
+        },
+        ValueRef::Hash(ref val) => size_of_ext(val.len()),
+        ValueRef::Identity(ref val) => size_of_ext(val.len()),
+        ValueRef::Lockbox(ref val) => size_of_ext(val.len()),
+        ValueRef::Timestamp(ref val) => timestamp_encoded_len(*val),
+    }
+}
+
+fn size_of_integer(val: &Integer) -> usize {
+    match integer::get_int_internal(val) {
+        integer::IntPriv::PosInt(u) => {
+            if u <= 127 { 1 }
+            else if u <= std::u8::MAX as u64 { 2 }
+            else if u <= std::u16::MAX as u64 { 3 }
+            else if u <= std::u32::MAX as u64 { 5 }
+            else { 9 }
+        },
+        integer::IntPriv::NegInt(u) => {
+            if u >= -32 { 1 }
+            else if u >= -128 { 2 }
+            else if u >= std::i16::MIN as i64 { 3 }
+            else if u >= std::i32::MIN as i64 { 5 }
+            else { 9 }
+        },
+    }
+}
+
+fn size_of_string(len: usize) -> usize {
+    len + if len <= 31 { 1 }
+        else if len <= std::u8::MAX as usize { 2 }
+        else if len <= std::u16::MAX as usize { 3 }
+        else { 5 }
+}
+
+fn size_of_binary(len: usize) -> usize {
+    len + if len <= std::u8::MAX as usize { 2 }
+        else if len <= std::u16::MAX as usize { 3 }
+        else { 5 }
+}
+
+fn size_of_collection_marker(len: usize) -> usize {
+    if len <= 15 { 1 }
+    else if len <= std::u16::MAX as usize { 3 }
+    else { 5 }
+}
+
+/// Size of an ext type holding `len` bytes of data, including the marker and the type byte. Mirrors 
+/// `write_ext_marker`.
+fn size_of_ext(len: usize) -> usize {
+    len + match len {
+        1 | 2 | 4 | 8 | 16 => 2,
+        len if len < (std::u8::MAX as usize) => 3,
+        len if len < (std::u16::MAX as usize) => 4,
+        _ => 6,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::BTreeMap;
+    use crypto::Hash;
+
+    #[test]
+    fn encode_canonical() {
+        // Repeated keys collapse to the last value written, in sorted order
+        let mut map = BTreeMap::new();
+        map.insert(String::from("b"), Value::from(1));
+        map.insert(String::from("a"), Value::from(2));
+        map.insert(String::from("b"), Value::from(3));
+        // Integers built from wider or signed types still use the shortest encoding
+        map.insert(String::from("c"), Value::Array(vec![
+            Value::from(5i64),
+            Value::from(300u64),
+            Value::from(-1i64),
+            Value::from(Integer::from(1u64 << 40) + (-(1i64 << 40))),
+        ]));
+        let val = Value::Object(map);
+        let mut v = Vec::new();
+        write_value_canonical(&mut v, &val).unwrap();
+        assert_eq!(v, vec![
+            0x83,
+            0xa1, b'a', 2,
+            0xa1, b'b', 3,
+            0xa1, b'c', 0x94, 5, 0xcd, 1, 44, 0xff, 0,
+        ]);
+        // The output passes the decoder's canonical form checks and matches `write_value`
+        let mut plain = Vec::new();
+        write_value(&mut plain, &val);
+        assert_eq!(v, plain);
+        let decoded = ::decode::read_value(&mut &v[..]).unwrap();
+        let mut v2 = Vec::new();
+        write_value_canonical(&mut v2, &decoded).unwrap();
+        assert_eq!(v, v2);
+    }
 
     #[test]
     fn encode_null_and_bool() {
@@ -342,12 +558,102 @@ mod tests {
         assert_eq!(v, comp);
     }
 
-}
-
-
+    fn check_size(val: &Value) {
+        let mut v = Vec::new();
+        write_value(&mut v, val);
+        assert_eq!(size_of_value(val), v.len(), "Wrong size for {:?}", val);
+        assert_eq!(size_of_value_ref(&val.as_ref()), v.len(), "Wrong size for {:?}", val);
+    }
 
+    #[test]
+    fn value_sizes() {
+        check_size(&Value::Null);
+        check_size(&Value::Boolean(true));
+        for i in [0i64, 127, -1, -32, -33, -128, -129, -32768, -32769, -2147483649i64, std::i64::MIN].iter() {
+            check_size(&Value::from(*i));
+        }
+        for i in [128u64, 255, 256, 65535, 65536, 4294967295u64, 4294967296u64, std::u64::MAX].iter() {
+            check_size(&Value::from(*i));
+        }
+        for len in [0usize, 31, 32, 255, 256, 65535, 65536].iter() {
+            check_size(&Value::from("a".repeat(*len)));
+            check_size(&Value::from(vec![0u8; *len]));
+            check_size(&Value::Array((0..*len).map(|_| Value::Null).collect()));
+            check_size(&Value::Object((0..*len).map(|i| (i.to_string(), Value::Null)).collect::<BTreeMap<String, Value>>()));
+        }
+        check_size(&Value::F32(1.0));
+        check_size(&Value::F64(1.0));
+        check_size(&Value::from(Hash::new_empty()));
+        ::crypto::init().unwrap();
+        check_size(&Value::from(Hash::new(1, &[1,2,3]).unwrap()));
+        check_size(&Value::from(Timestamp::from_sec(1)));
+        check_size(&Value::from(Timestamp::from_raw(1, 1).unwrap()));
+        check_size(&Value::from(Timestamp::from_raw(-1, 1).unwrap()));
 
+        // Deeply nested structures
+        let mut nested = msgpack!({ "base": [1, "two", 3.0, { "four": vec![4u8; 300] }] });
+        for i in 0..20 {
+            nested = msgpack!({ "level": i, "inner": nested, "list": [i, -i, "x"] });
+        }
+        check_size(&nested);
+    }
 
+    #[test]
+    fn timestamp_formats() {
+        let check = |sec: i64, nano: u32, len: usize| {
+            let t = Timestamp::from_raw(sec, nano).unwrap();
+            let mut buf = Vec::new();
+            write_timestamp(&mut buf, t);
+            assert_eq!(buf.len(), len, "Wrong length for {}s {}ns", sec, nano);
+            assert_eq!(timestamp_encoded_len(t), len);
+            let mut value_buf = Vec::new();
+            write_value(&mut value_buf, &Value::from(t));
+            assert_eq!(buf, value_buf);
+            assert_eq!(::decode::read_time(&mut &buf[..]).unwrap(), t);
+        };
+        // 4-byte seconds
+        check(0, 0, 6);
+        check(std::u32::MAX as i64 - 1, 0, 6);
+        // 8-byte seconds & nanoseconds
+        check(std::u32::MAX as i64, 0, 10);
+        check(0, 1, 10);
+        check(1, 999_999_999, 10);
+        check((1i64 << 34) - 2, 999_999_999, 10);
+        // 12-byte signed seconds & nanoseconds
+        check((1i64 << 34) - 1, 0, 15);
+        check(-1, 0, 15);
+        check(-1, 1, 15);
+        check(std::i64::MIN, 0, 15);
+        check(std::i64::MAX, 999_999_999, 15);
+    }
 
+    #[test]
+    fn ext_types() {
+        use crypto::{self, Vault, PasswordLevel, LockboxContent};
+        crypto::init().unwrap();
+        let mut vault = Vault::new_from_password(PasswordLevel::Interactive, String::from("password")).unwrap();
+        let id = vault.new_key().unwrap().get_identity();
+        let stream = vault.new_stream().unwrap();
+        let lock = vault.encrypt_using_stream(LockboxContent::Data(vec![1, 2, 3]), &stream).unwrap();
+        let hash = Hash::new(1, b"test").unwrap();
 
+        let mut buf = Vec::new();
+        let mut value_buf = Vec::new();
+        write_hash(&mut buf, &hash);
+        write_value(&mut value_buf, &Value::from(hash.clone()));
+        write_hash(&mut buf, &Hash::new_empty());
+        write_value(&mut value_buf, &Value::from(Hash::new_empty()));
+        write_identity(&mut buf, &id);
+        write_value(&mut value_buf, &Value::from(id.clone()));
+        write_lockbox(&mut buf, &lock);
+        write_value(&mut value_buf, &Value::from(lock.clone()));
+        assert_eq!(buf, value_buf);
 
+        let mut buf = &buf[..];
+        assert_eq!(::decode::read_hash(&mut buf).unwrap(), hash);
+        assert_eq!(::decode::read_hash(&mut buf).unwrap(), Hash::new_empty());
+        assert_eq!(::decode::read_id(&mut buf).unwrap(), id);
+        assert_eq!(::decode::read_lockbox(&mut buf).unwrap(), lock);
+        assert!(buf.is_empty());
+    }
+}
@@ -66,11 +66,22 @@ impl Entry {
         self.entry.len()
     }
 
+    /// Get the number of bytes the entry (minus document hash & field name) takes up when fully 
+    /// encoded, including signatures. This is the same as `len`, and is the length of `raw_bytes`.
+    pub fn size_bytes(&self) -> usize {
+        self.len()
+    }
+
     /// Get the length of the encoded value only (without signatures).
     pub fn entry_len(&self) -> usize {
         self.entry_len
     }
 
+    /// Get the fully encoded entry (minus document hash & field name), including signatures.
+    pub fn raw_bytes(&self) -> &[u8] {
+        &self.entry[..]
+    }
+
     /// Turn the entry into its component parts.
     pub fn to_parts(self) -> (Hash, String, Vec<u8>) {
         (self.doc, self.field, self.entry)
@@ -82,6 +93,12 @@ impl Entry {
         self.hash_state.get_hash()
     }
 
+    /// Calculate the Hash of the Entry from its parent document hash, field, and raw bytes. This 
+    /// is the same as `hash`, but doesn't rely on the internal hash state.
+    pub fn compute_hash(&self) -> Hash {
+        compute_hash(&self.doc, &self.field, &self.entry[..])
+    }
+
     /// Get the Hash of the parent document for the Entry.
     pub fn doc_hash(&self) -> &Hash {
         &self.doc
@@ -101,6 +118,18 @@ impl Entry {
 
 }
 
+/// Calculate the Hash of an entry from its parent document hash, field, and raw bytes, as returned 
+/// by `Entry::to_parts`.
+pub fn compute_hash(doc_hash: &Hash, field: &str, raw: &[u8]) -> Hash {
+    let mut hash_state = HashState::new(1).unwrap(); // Shouldn't fail if version == 1
+    let mut temp_vec = Vec::new();
+    super::encode::write_value(&mut temp_vec, &Value::from(doc_hash.clone()));
+    super::encode::write_value(&mut temp_vec, &Value::from(field));
+    hash_state.update(&temp_vec[..]);
+    hash_state.update(raw);
+    hash_state.get_hash()
+}
+
 pub fn from_raw(
     hash: &Hash,
     doc_hash: Hash,
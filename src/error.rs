@@ -1,4 +1,5 @@
 use super::crypto::CryptoError;
+use crossbeam_channel::RecvError;
 use std::{fmt, io};
 use std::error::Error;
 
@@ -6,6 +7,21 @@ use std::error::Error;
 pub enum DbError {
     Io(io::Error),
     Crypto(CryptoError),
+    /// A document or entry failed validation against its schema.
+    Schema(io::Error),
+}
+
+impl DbError {
+    /// Wrap a schema validation failure. Schema validation reports errors as `io::Error`, so this 
+    /// must be used instead of `From`, which treats them as plain I/O errors.
+    pub fn schema_error(err: io::Error) -> DbError {
+        DbError::Schema(err)
+    }
+
+    /// Wrap an error from the crypto module.
+    pub fn crypto_error(err: CryptoError) -> DbError {
+        DbError::Crypto(err)
+    }
 }
 
 impl fmt::Display for DbError {
@@ -13,6 +29,7 @@ impl fmt::Display for DbError {
         match *self {
             DbError::Crypto(ref err) => err.fmt(f),
             DbError::Io(ref err)     => err.fmt(f),
+            DbError::Schema(ref err) => write!(f, "Failed schema check: {}", err),
         }
     }
 }
@@ -22,6 +39,7 @@ impl Error for DbError {
         match *self {
             DbError::Crypto(ref err) => err.description(),
             DbError::Io(ref err)     => err.description(),
+            DbError::Schema(_)       => "failed schema check",
         }
     }
 }
@@ -37,3 +55,51 @@ impl From<CryptoError> for DbError {
         DbError::Crypto(err)
     }
 }
+
+/// The database process stopped before responding.
+impl From<RecvError> for DbError {
+    fn from(err: RecvError) -> DbError {
+        DbError::Io(io::Error::new(io::ErrorKind::BrokenPipe, err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encode;
+    use schema::Schema;
+    use value::Value;
+
+    #[test]
+    fn schema_errors() {
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &msgpack!({
+            "name": "person",
+            "req": { "age": { "type": "Int", "min": 0 } }
+        }));
+        let schema = Schema::from_raw(&mut &raw[..]).unwrap();
+        let check = |doc: Value| -> Result<(), DbError> {
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &doc);
+            schema.validate_doc(&mut &raw[..]).map_err(DbError::schema_error)
+        };
+        check(msgpack!({ "age": 3 })).unwrap();
+        match check(msgpack!({ "age": -3 })) {
+            Err(DbError::Schema(_)) => (),
+            other => panic!("Expected a schema error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn crypto_errors() {
+        let crypto = || -> Result<(), DbError> { Err(CryptoError::BadKey)? };
+        match crypto() {
+            Err(DbError::Crypto(CryptoError::BadKey)) => (),
+            other => panic!("Expected a crypto error, got {:?}", other),
+        }
+        match DbError::crypto_error(CryptoError::DecryptFailed) {
+            DbError::Crypto(CryptoError::DecryptFailed) => (),
+            other => panic!("Expected a crypto error, got {:?}", other),
+        }
+    }
+}
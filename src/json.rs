@@ -0,0 +1,223 @@
+//! Conversion between `Value` and JSON text, for prototyping documents and schema without writing
+//! out MessagePack directly.
+//!
+//! Types that JSON can't represent directly are written as objects with a single tagged field:
+//!
+//! - `{"$f32": <number>}`: 32-bit floating point number
+//! - `{"$bin": <hex>}`: Binary data
+//! - `{"$hash": <hex>}`: Hash
+//! - `{"$ident": <hex>}`: Identity
+//! - `{"$lock": <hex>}`: Lockbox
+//! - `{"$time": [<seconds>, <nanoseconds>]}`: Timestamp
+//!
+//! Any JSON object with exactly one field named by one of these tags is read as the tagged type.
+//! Other JSON numbers are read as an Integer if they have no fractional part or exponent, and as
+//! an F64 otherwise.
+
+use serde_json;
+use serde_json::Value as Json;
+use hex;
+
+use super::{Value, Hash, Identity, Lockbox, Timestamp};
+
+/// Convert a JSON string into a `Value`.
+pub fn value_from_json_str(json: &str) -> Result<Value, String> {
+    let json: Json = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    from_json(&json)
+}
+
+/// Convert a `Value` into a JSON string. Floating point values that JSON can't represent (NaN and
+/// the infinities) are written as `null`.
+pub fn value_to_json_str(v: &Value) -> String {
+    to_json(v).to_string()
+}
+
+fn tagged(tag: &str, v: Json) -> Json {
+    let mut obj = serde_json::Map::new();
+    obj.insert(tag.to_string(), v);
+    Json::Object(obj)
+}
+
+fn to_json(v: &Value) -> Json {
+    match *v {
+        Value::Null => Json::Null,
+        Value::Boolean(v) => Json::Bool(v),
+        Value::Integer(ref v) => {
+            match (v.as_u64(), v.as_i64()) {
+                (Some(v), _) => Json::from(v),
+                (None, Some(v)) => Json::from(v),
+                (None, None) => Json::Null,
+            }
+        },
+        Value::String(ref v) => Json::from(v.as_str()),
+        Value::F32(v) => tagged("$f32", Json::from(v as f64)),
+        Value::F64(v) => Json::from(v),
+        Value::Binary(ref v) => tagged("$bin", Json::from(hex::encode(v))),
+        Value::Array(ref v) => Json::Array(v.iter().map(to_json).collect()),
+        Value::Object(ref v) => {
+            Json::Object(v.iter().map(|(field, v)| (field.clone(), to_json(v))).collect())
+        },
+        Value::Hash(ref v) => {
+            let mut raw = Vec::with_capacity(v.len());
+            v.encode(&mut raw);
+            tagged("$hash", Json::from(hex::encode(raw)))
+        },
+        Value::Identity(ref v) => {
+            let mut raw = Vec::with_capacity(v.len());
+            v.encode(&mut raw);
+            tagged("$ident", Json::from(hex::encode(raw)))
+        },
+        Value::Lockbox(ref v) => {
+            let mut raw = Vec::with_capacity(v.len());
+            v.encode(&mut raw);
+            tagged("$lock", Json::from(hex::encode(raw)))
+        },
+        Value::Timestamp(ref v) => tagged("$time", Json::Array(vec![Json::from(v.sec), Json::from(v.nano)])),
+    }
+}
+
+fn from_json(json: &Json) -> Result<Value, String> {
+    Ok(match *json {
+        Json::Null => Value::Null,
+        Json::Bool(v) => Value::Boolean(v),
+        Json::Number(ref v) => {
+            if let Some(v) = v.as_u64() {
+                Value::from(v)
+            }
+            else if let Some(v) = v.as_i64() {
+                Value::from(v)
+            }
+            else {
+                Value::F64(v.as_f64().ok_or_else(|| format!("Number {} can't be represented", v))?)
+            }
+        },
+        Json::String(ref v) => Value::String(v.clone()),
+        Json::Array(ref v) => Value::Array(v.iter().map(from_json).collect::<Result<Vec<Value>, String>>()?),
+        Json::Object(ref v) => {
+            if v.len() == 1 {
+                if let Some((tag, v)) = v.iter().next() {
+                    if let Some(v) = from_tagged(tag, v) {
+                        return v;
+                    }
+                }
+            }
+            let mut obj = ::std::collections::BTreeMap::new();
+            for (field, v) in v.iter() {
+                obj.insert(field.clone(), from_json(v)?);
+            }
+            Value::Object(obj)
+        },
+    })
+}
+
+/// Read a tagged object's contents. Returns None if the tag isn't recognized.
+fn from_tagged(tag: &str, v: &Json) -> Option<Result<Value, String>> {
+    let hex_content = || {
+        v.as_str()
+            .ok_or_else(|| format!("`{}` field didn't contain a string", tag))
+            .and_then(|v| hex::decode(v).map_err(|_e| format!("`{}` field didn't contain hex", tag)))
+    };
+    let all_read = |buf: &[u8]| {
+        if buf.len() == 0 { Ok(()) } else { Err(format!("`{}` field had extra bytes", tag)) }
+    };
+    Some(match tag {
+        "$f32" => v.as_f64()
+            .map(|v| Value::F32(v as f32))
+            .ok_or_else(|| String::from("`$f32` field didn't contain a number")),
+        "$bin" => hex_content().map(Value::Binary),
+        "$hash" => hex_content().and_then(|raw| {
+            let mut buf = &raw[..];
+            let hash = Hash::decode(&mut buf).map_err(|e| e.to_string())?;
+            all_read(buf)?;
+            Ok(Value::Hash(hash))
+        }),
+        "$ident" => hex_content().and_then(|raw| {
+            let mut buf = &raw[..];
+            let id = Identity::decode(&mut buf).map_err(|e| e.to_string())?;
+            all_read(buf)?;
+            Ok(Value::Identity(id))
+        }),
+        "$lock" => hex_content().and_then(|raw| {
+            let mut buf = &raw[..];
+            let lock = Lockbox::decode(raw.len(), &mut buf).map_err(|e| e.to_string())?;
+            all_read(buf)?;
+            Ok(Value::Lockbox(lock))
+        }),
+        "$time" => {
+            match v.as_array().map(|v| &v[..]) {
+                Some([sec, nano]) => {
+                    sec.as_i64()
+                        .and_then(|sec| nano.as_u64().map(|nano| (sec, nano)))
+                        .and_then(|(sec, nano)| if nano > (u32::max_value() as u64) { None } else { Some((sec, nano as u32)) })
+                        .and_then(|(sec, nano)| Timestamp::from_raw(sec, nano))
+                        .map(Value::Timestamp)
+                        .ok_or_else(|| String::from("`$time` field didn't contain a valid timestamp"))
+                },
+                _ => Err(String::from("`$time` field didn't contain a [seconds, nanoseconds] array")),
+            }
+        },
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encode;
+    use crypto;
+
+    fn round_trip(v: &Value) {
+        let json = value_to_json_str(v);
+        let v_new = value_from_json_str(&json).unwrap();
+        let mut raw = Vec::new();
+        let mut raw_new = Vec::new();
+        encode::write_value(&mut raw, v);
+        encode::write_value(&mut raw_new, &v_new);
+        assert_eq!(raw, raw_new, "Value didn't survive JSON round trip: {}", json);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        crypto::init().unwrap();
+        let hash = Hash::new(1, &[1,2,3]).unwrap();
+        let id = Identity::decode(&mut &[1u8; 33][..]).unwrap();
+        round_trip(&Value::Null);
+        round_trip(&Value::from(true));
+        round_trip(&Value::from(0u8));
+        round_trip(&Value::from(u64::max_value()));
+        round_trip(&Value::from(i64::min_value()));
+        round_trip(&Value::from("string"));
+        round_trip(&Value::from(1.5f32));
+        round_trip(&Value::from(1.0f64));
+        round_trip(&Value::from(-1.0e100f64));
+        round_trip(&Value::from(vec![0u8, 1, 2, 255]));
+        round_trip(&Value::from(Hash::new_empty()));
+        round_trip(&Value::from(hash.clone()));
+        round_trip(&Value::from(id.clone()));
+        round_trip(&Value::from(Timestamp::from_raw(-1, 999_999_999).unwrap()));
+        round_trip(&msgpack!({
+            "array": [1, -1, "a", 2.5, null, false],
+            "hash": hash,
+            "id": id,
+            "obj": { "time": Timestamp::from_sec(1), "bin": vec![1u8, 2] },
+        }));
+    }
+
+    #[test]
+    fn json_parse() {
+        let v = value_from_json_str(r#"{"a": 1, "b": [1.5, "x"], "c": {"$bin": "00ff"}, "d": {"$other": 1}}"#).unwrap();
+        let mut raw = Vec::new();
+        let mut raw_expected = Vec::new();
+        encode::write_value(&mut raw, &v);
+        encode::write_value(&mut raw_expected, &msgpack!({
+            "a": 1,
+            "b": [1.5, "x"],
+            "c": vec![0u8, 255],
+            "d": { "$other": 1 }
+        }));
+        assert_eq!(raw, raw_expected);
+        assert!(value_from_json_str(r#"{"$hash": "zz"}"#).is_err());
+        assert!(value_from_json_str(r#"{"$time": [1]}"#).is_err());
+        assert!(value_from_json_str("[1,").is_err());
+    }
+}
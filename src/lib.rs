@@ -1,10 +1,12 @@
 #![allow(dead_code)]
 #![recursion_limit="500"]
 
-#[cfg(test)]
+#[cfg(any(test, feature = "json"))]
 extern crate serde_json;
-#[cfg(test)]
+#[cfg(any(test, feature = "json"))]
 extern crate hex;
+#[cfg(feature = "json")]
+extern crate base64;
 #[cfg(test)]
 extern crate rand;
 #[cfg(test)]
@@ -19,6 +21,7 @@ extern crate libsodium_sys;
 extern crate libc;
 extern crate regex;
 extern crate ieee754;
+extern crate unicode_segmentation;
 extern crate rocksdb;
 //use std::io::Write;
 
@@ -37,6 +40,7 @@ mod entry;
 mod schema;
 mod permission;
 mod query;
+mod string_index;
 // Uncomment this in like a week (say 05/10). The feature this uses should be stable by then.
 //mod str_char; 
 
@@ -44,10 +48,12 @@ pub mod database;
 pub mod crypto;
 pub mod encode;
 pub mod decode;
+#[cfg(feature = "json")]
+pub mod json;
 
 use marker::{Marker, ExtType, MarkerType};
 
-pub use self::schema::Schema;
+pub use self::schema::{Schema, ValidationContext};
 pub use self::crypto::{Hash, Identity, Lockbox, CryptoError};
 pub use self::index::Index;
 //pub use self::index_ref::IndexRef;
@@ -60,4 +66,6 @@ pub use self::entry::Entry;
 pub use self::database::{Db, QueryResponse};
 pub use self::permission::Permission;
 pub use self::query::Query;
+#[cfg(feature = "json")]
+pub use self::json::{value_from_json_str, value_to_json_str};
 
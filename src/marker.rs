@@ -218,6 +218,25 @@ impl MarkerType {
             _ => None,
         }
     }
+
+    /// Name of the type of value this marker starts, matching `Value::type_name`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            MarkerType::Null => "Null",
+            MarkerType::Boolean(_) => "Boolean",
+            MarkerType::NegInt(_) | MarkerType::PosInt(_) => "Integer",
+            MarkerType::String(_) => "String",
+            MarkerType::F32 => "F32",
+            MarkerType::F64 => "F64",
+            MarkerType::Binary(_) => "Binary",
+            MarkerType::Array(_) => "Array",
+            MarkerType::Object(_) => "Object",
+            MarkerType::Hash(_) => "Hash",
+            MarkerType::Identity(_) => "Identity",
+            MarkerType::Lockbox(_) => "Lockbox",
+            MarkerType::Timestamp(_) => "Timestamp",
+        }
+    }
 }
 
 
@@ -1,5 +1,5 @@
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct Permission {
     pub advertise: bool,
     pub machine_local: bool,
@@ -7,6 +7,8 @@ pub struct Permission {
     pub local_net: bool,
     pub global: bool,
     pub anonymous: bool,
+    pub quota: Option<u64>,
+    pub write: bool,
 }
 
 impl Default for Permission {
@@ -24,9 +26,21 @@ impl Permission {
             local_net: false,
             global: false,
             anonymous: false,
+            quota: None,
+            write: true,
         }
     }
 
+    /// A permission that can't add or remove documents. Sharing is disabled, as with `new`.
+    pub fn read_only() -> Permission {
+        Permission::new().write(false)
+    }
+
+    /// A permission that can add and remove documents. Sharing is disabled, as with `new`.
+    pub fn full_access() -> Permission {
+        Permission::new().write(true)
+    }
+
     /// Whether to advertise a document or not. This is ignored for entries and queries.
     pub fn advertise(mut self, yes: bool) -> Self {
         self.advertise = yes;
@@ -75,5 +89,27 @@ impl Permission {
         self.anonymous = yes;
         self
     }
+
+    /// Whether documents can be added or removed using this permission. Adding or removing a 
+    /// document without it fails with `ChangeResult::PermissionDenied`. Defaults to true.
+    pub fn write(mut self, yes: bool) -> Self {
+        self.write = yes;
+        self
+    }
+
+    /// Checks if documents can be added or removed using this permission.
+    pub fn allows_write(&self) -> bool {
+        self.write
+    }
+
+    /// Maximum total size, in bytes, of the documents the database will hold with these sharing 
+    /// settings. Documents are counted by their full encoded size, including signatures. Adding a 
+    /// document past the limit fails with `ChangeResult::QuotaExceeded`. Documents count against 
+    /// every permission with the same sharing settings, regardless of the quota each was added 
+    /// with. This is ignored for entries and queries.
+    pub fn quota(mut self, max_bytes: Option<u64>) -> Self {
+        self.quota = max_bytes;
+        self
+    }
 }
 
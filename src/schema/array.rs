@@ -1,450 +1,970 @@
-use std::io;
-use std::io::Error;
-use std::io::ErrorKind::InvalidData;
-use std::collections::{HashMap, HashSet};
-
-use decode::*;
-use super::*;
-use marker::MarkerType;
-
-#[derive(Clone, Debug)]
-pub struct ValidArray {
-    /// Raw msgpack to compare against
-    in_vec: Vec<Box<[u8]>>,
-    nin_vec: Vec<Box<[u8]>>,
-    min_len: usize,
-    max_len: usize,
-    items: Vec<usize>,
-    extra_items: Option<usize>,
-    contains: Vec<usize>,
-    unique: bool,
-    query: bool,
-    array: bool,
-    contains_ok: bool,
-}
-
-/// Array type validator
-impl ValidArray {
-    pub fn new(is_query: bool) -> ValidArray {
-        ValidArray {
-            in_vec: Vec::with_capacity(0),
-            nin_vec: Vec::with_capacity(0),
-            min_len: usize::min_value(),
-            max_len: usize::max_value(),
-            items: Vec::with_capacity(0),
-            extra_items: None,
-            contains: Vec::with_capacity(0),
-            unique: false,
-            query: is_query,
-            array: is_query,
-            contains_ok: is_query,
-        }
-    }
-
-    pub fn from_const(constant: Box<[u8]>, is_query: bool) -> ValidArray {
-        let mut v = ValidArray::new(is_query);
-        let mut in_vec = Vec::with_capacity(1);
-        in_vec.push(constant);
-        v.in_vec = in_vec;
-        v
-    }
-
-    /// Update the validator. Returns `Ok(true)` if everything is read out Ok, `Ok(false)` if we 
-    /// don't recognize the field type or value, and `Err` if we recognize the field but fail to 
-    /// parse the expected contents. The updated `raw` slice reference is only accurate if 
-    /// `Ok(true)` was returned.
-    pub fn update(&mut self, field: &str, raw: &mut &[u8], is_query: bool, types: &mut Vec<Validator>, type_names: &mut HashMap<String,usize>)
-        -> io::Result<bool>
-    {
-        // Note about this match: because fields are lexicographically ordered, the items in this 
-        // match statement are either executed sequentially or are skipped.
-        match field {
-            "array" => {
-                self.array = read_bool(raw)?;
-                Ok(true)
-            }
-            "contains" => {
-                if let MarkerType::Array(len) = read_marker(raw)? {
-                    for _ in 0..len {
-                        let v = Validator::read_validator(raw, is_query, types, type_names)?;
-                        self.contains.push(v);
-                    }
-                    Ok(true)
-                }
-                else {
-                    Err(Error::new(InvalidData, "Array `contains` isn't a valid array of validators"))
-                }
-            },
-            "contains_ok" => {
-                self.contains_ok = read_bool(raw)?;
-                Ok(true)
-            },
-            "default" => {
-                if let MarkerType::Array(len) = read_marker(raw)? {
-                    for _ in 0..len {
-                        verify_value(raw)?;
-                    }
-                    Ok(true)
-                }
-                else {
-                    Err(Error::new(InvalidData, "Array `default` isn't a valid array"))
-                }
-            },
-            "extra_items" => {
-                self.extra_items = Some(Validator::read_validator(raw, is_query, types, type_names)?);
-                Ok(true)
-            },
-            "in" => {
-                if let MarkerType::Array(len) = read_marker(raw)? {
-                    // Push without reserving - otherwise recursive reserving is possible and 
-                    // can lead to an exponential amount of memory reservation.
-                    for _ in 0..len {
-                        let v = if let MarkerType::Array(len) = read_marker(raw)? {
-                            get_raw_array(raw, len)?
-                        }
-                        else {
-                            return Err(Error::new(InvalidData, "Array validator expected array of arrays for `in` field"));
-                        };
-                        self.in_vec.push(v);
-                    };
-                    self.in_vec.sort_unstable();
-                    self.in_vec.dedup();
-                }
-                else {
-                    return Err(Error::new(InvalidData, "Array validator expected array of arrays for `in` field"));
-                }
-                Ok(true)
-            },
-            "items" => {
-                if let MarkerType::Array(len) = read_marker(raw)? {
-                    for _ in 0..len {
-                        let v = Validator::read_validator(raw, is_query, types, type_names)?;
-                        self.items.push(v);
-                    }
-                    Ok(true)
-                }
-                else {
-                    Err(Error::new(InvalidData, "Array `items` isn't a valid array of validators"))
-                }
-            },
-            "max_len" => {
-                if let Some(len) = read_integer(raw)?.as_u64() {
-                    self.max_len = len as usize;
-                    Ok(true)
-                }
-                else {
-                    Ok(false)
-                }
-            },
-            "min_len" => {
-                if let Some(len) = read_integer(raw)?.as_u64() {
-                    self.min_len = len as usize;
-                    Ok(self.max_len >= self.min_len)
-                }
-                else {
-                    Ok(false)
-                }
-            },
-            "nin" => {
-                if let MarkerType::Array(len) = read_marker(raw)? {
-                    // Push without reserving - otherwise recursive reserving is possible and 
-                    // can lead to an exponential amount of memory reservation.
-                    for _ in 0..len {
-                        let v = if let MarkerType::Array(len) = read_marker(raw)? {
-                            get_raw_array(raw, len)?
-                        }
-                        else {
-                            return Err(Error::new(InvalidData, "Array validator expected array of arrays for `in` field"));
-                        };
-                        self.nin_vec.push(v);
-                    };
-                    self.nin_vec.sort_unstable();
-                    self.nin_vec.dedup();
-                }
-                else {
-                    return Err(Error::new(InvalidData, "Array validator expected array of arrays for `in` field"));
-                }
-                Ok(true)
-            },
-            "query" => {
-                self.query = read_bool(raw)?;
-                Ok(true)
-            },
-            "unique" => {
-                self.query = read_bool(raw)?;
-                Ok(true)
-            },
-            "type" => if "Array" == read_str(raw)? { Ok(true) } else { Err(Error::new(InvalidData, "Type doesn't match Array")) },
-            _ => Err(Error::new(InvalidData, "Unknown fields not allowed in Array validator")),
-        }
-    }
-
-    /// Final check on the validator. Returns true if at least one value can (probably) still pass the 
-    /// validator. We do not check the `in` and `nin` against all validation parts
-    pub fn finalize(&mut self) -> bool {
-        if self.in_vec.len() > 0 {
-            let mut in_vec: Vec<Box<[u8]>> = Vec::with_capacity(self.in_vec.len());
-            let mut nin_index = 0;
-            for val in self.in_vec.iter() {
-                while let Some(nin) = self.nin_vec.get(nin_index) {
-                    if nin < val { nin_index += 1; } else { break; }
-                }
-                if let Some(nin) = self.nin_vec.get(nin_index) {
-                    if nin == val { continue; }
-                }
-                in_vec.push(val.clone());
-            }
-            in_vec.shrink_to_fit();
-            self.in_vec = in_vec;
-            self.nin_vec = Vec::with_capacity(0);
-            (self.in_vec.len() > 0) && (self.min_len <= self.max_len)
-        }
-        else {
-            self.nin_vec.shrink_to_fit();
-            self.min_len <= self.max_len
-        }
-    }
-
-    /// Validates that the next value is a Hash that meets the validator requirements. Fails if the 
-    /// requirements are not met. If it passes, the optional returned Hash indicates that an 
-    /// additional document (referenced by the Hash) needs to be checked.
-    pub fn validate(&self,
-                    field: &str,
-                    doc: &mut &[u8],
-                    types: &Vec<Validator>,
-                    list: &mut Checklist,
-                    ) -> io::Result<()>
-    {
-        let num_items = match read_marker(doc)? {
-            MarkerType::Array(len) => len,
-            _ => return Err(Error::new(InvalidData, format!("Array for field \"{}\"not found", field))),
-        };
-        if num_items == 0 && self.min_len == 0 && self.items.len() == 0 && self.contains.len() == 0 {
-            return Ok(());
-        }
-
-        let array_start = doc.clone();
-
-        // Size checks
-        if num_items < self.min_len {
-            return Err(Error::new(InvalidData,
-                format!("Field {} contains array with {} items, less than minimum of {}", field, num_items, self.min_len)));
-        }
-        if num_items > self.max_len {
-            return Err(Error::new(InvalidData,
-                format!("Field {} contains array with {} items, greater than maximum of {}", field, num_items, self.max_len)));
-        }
-
-        // Setup for iterating over array
-        let mut unique_set: HashSet<&[u8]> = if self.unique {
-            HashSet::with_capacity(num_items)
-        }
-        else {
-            HashSet::with_capacity(0)
-        };
-        let mut contain_set: Vec<bool> = vec![false; self.contains.len()];
-
-        // Run through the whole array
-        for i in 0..num_items {
-            // Validate as appropriate
-            let item_start = doc.clone();
-            if let Some(v_index) = self.items.get(i) {
-                if let Err(e) = types[*v_index].validate(field, doc, types, *v_index, list) {
-                    return Err(e);
-                }
-            }
-            else if let Some(v_index) = self.extra_items {
-                if let Err(e) = types[v_index].validate(field, doc, types, v_index, list) {
-                    return Err(e);
-                }
-            }
-            else {
-                verify_value(doc)?;
-            }
-            let (item, _) = item_start.split_at(item_start.len()-doc.len());
-
-            // Check for uniqueness
-            if self.unique {
-                if !unique_set.insert(item) {
-                    return Err(Error::new(InvalidData,
-                        format!("Field {} contains a repeated item at index {}", field, i)));
-                }
-            }
-            // Check to see if any `contains` requirements are met
-            contain_set.iter_mut()
-                .zip(self.contains.iter())
-                .filter(|(checked,_)| !**checked)
-                .for_each(|(checked,contains_item)| {
-                    if let Ok(()) = types[*contains_item].validate(field, &mut item.clone(), types, *contains_item, list) {
-                        *checked = true;
-                    }
-                });
-        }
-
-        let (array, _) = array_start.split_at(array_start.len()-doc.len());
-        if contain_set.contains(&false) {
-            Err(Error::new(InvalidData,
-                format!("Field {} does not satisfy all `contains` requirements", field)))
-        }
-        else if self.nin_vec.binary_search_by(|probe| (**probe).cmp(array)).is_ok() {
-            Err(Error::new(InvalidData,
-                format!("Field \"{}\" contains array on `nin` list", field)))
-        }
-        else if (self.in_vec.len() > 0) && self.in_vec.binary_search_by(|probe| (**probe).cmp(array)).is_err() {
-            Err(Error::new(InvalidData,
-                format!("Field \"{}\" contains array not on `in` list", field)))
-        }
-        else {
-            Ok(())
-        }
-    }
-
-    /// Intersection of Array with other Validators. Returns Err only if `query` is true and the 
-    /// other validator contains non-allowed query parameters.
-    pub fn intersect(&self,
-                 other: &Validator,
-                 query: bool,
-                 builder: &mut ValidBuilder
-                 )
-        -> Result<Validator, ()>
-    {
-        let builder_len = builder.len();
-        if query && !self.query && !self.array && !self.contains_ok { return Err(()); }
-        match other {
-            Validator::Array(other) => {
-                if query && (
-                    (!self.query &&
-                     ((other.max_len < usize::max_value()) || (other.min_len > usize::min_value())
-                      || other.unique || !other.in_vec.is_empty() || !other.nin_vec.is_empty()))
-                    || (!self.array && 
-                        (!other.items.is_empty() || other.extra_items.is_some()))
-                    || (!self.contains_ok && !other.contains.is_empty()))
-                {
-                    Err(())
-                }
-                else {
-                    // Get intersection of `in` vectors
-                    let in_vec = if (self.in_vec.len() > 0) && (other.in_vec.len() > 0) {
-                        sorted_intersection(&self.in_vec[..], &other.in_vec[..], |a,b| a.cmp(b))
-                    }
-                    else if self.in_vec.len() > 0 {
-                        self.in_vec.clone()
-                    }
-                    else {
-                        other.in_vec.clone()
-                    };
-
-                    // Get intersection of items
-                    let items_len = self.items.len().max(other.items.len());
-                    let mut items = Vec::with_capacity(items_len);
-                    for i in 0..items_len {
-                        let self_index = if let Some(index) = self.items.get(i) {
-                            *index
-                        }
-                        else if let Some(index) = self.extra_items {
-                            index
-                        }
-                        else {
-                            1
-                        };
-                        let other_index = if let Some(index) = other.items.get(i) {
-                            *index
-                        }
-                        else if let Some(index) = other.extra_items {
-                            index
-                        }
-                        else {
-                            1
-                        };
-
-                        items.push(builder.intersect(query, self_index, other_index)?);
-                    }
-
-                    // Get extra items
-                    let extra_items = if let (Some(self_extra), Some(other_extra)) = (self.extra_items,other.extra_items) {
-                        Some(builder.intersect(query, self_extra, other_extra)?)
-                    }
-                    else if let Some(extra_items) = self.extra_items {
-                        Some(builder.intersect(query, extra_items, 1)?)
-                    }
-                    else if let Some(extra_items) = other.extra_items {
-                        Some(builder.intersect(query, 1, extra_items)?)
-                    }
-                    else {
-                        None
-                    };
-
-                    // Check that this isn't an invalid validator before proceeding
-                    if items.contains(&0) {
-                        builder.undo_to(builder_len);
-                        return Ok(Validator::Invalid);
-                    }
-
-                    let mut contains: Vec<usize> = Vec::with_capacity(self.contains.len() + other.contains.len());
-                    contains.extend(self.contains.iter()
-                        .map(|x| builder.intersect(query, *x, 1).unwrap()));
-                    contains.extend(other.contains.iter()
-                        .map(|x| builder.intersect(query, 1, *x).unwrap()));
-
-                    // Create new Validator
-                    let mut new_validator = ValidArray {
-                        in_vec: in_vec,
-                        nin_vec: sorted_union(&self.nin_vec[..], &other.nin_vec[..], |a,b| a.cmp(b)),
-                        min_len: self.min_len.max(other.min_len),
-                        max_len: self.max_len.min(other.max_len),
-                        items: items,
-                        extra_items: extra_items,
-                        contains: contains,
-                        unique: self.unique || other.unique,
-                        query: self.query && other.query,
-                        array: self.array && other.array,
-                        contains_ok: self.contains_ok && other.contains_ok,
-                    };
-                    if new_validator.in_vec.len() == 0 && (self.in_vec.len()+other.in_vec.len() > 0) {
-                        builder.undo_to(builder_len);
-                        return Ok(Validator::Invalid);
-                    }
-                    let valid = new_validator.finalize();
-                    if !valid {
-                        builder.undo_to(builder_len);
-                        Ok(Validator::Invalid)
-                    }
-                    else {
-                        Ok(Validator::Array(new_validator))
-                    }
-                }
-            },
-            Validator::Valid => {
-                // Get intersection of items
-                let mut v = self.clone();
-                let mut items = Vec::with_capacity(self.items.len());
-                items.extend(self.items.iter()
-                    .map(|x| builder.intersect(query, *x, 1).unwrap()));
-                v.items = items;
-
-                if let Some(extra) = self.extra_items {
-                    v.extra_items = Some(builder.intersect(query, extra, 1).unwrap());
-                }
-
-                let mut contains: Vec<usize> = Vec::with_capacity(self.contains.len());
-                contains.extend(self.contains.iter()
-                    .map(|x| builder.intersect(query, *x, 1).unwrap()));
-                v.contains = contains;
-                Ok(Validator::Array(v))
-            }
-            _ => Ok(Validator::Invalid),
-        }
-    }
-}
-
-pub fn get_raw_array(raw: &mut &[u8], len: usize) -> io::Result<Box<[u8]>> {
-    let start = raw.clone();
-    for _ in 0..len {
-        verify_value(raw)?;
-    }
-    let (array, _) = start.split_at(start.len()-raw.len());
-    Ok(array.to_vec().into_boxed_slice())
-}
-
-
-
+use std::io;
+use std::io::Error;
+use std::io::ErrorKind::InvalidData;
+use std::collections::{HashMap, HashSet};
+
+use decode::*;
+use super::*;
+use marker::MarkerType;
+use value::{Value, ValueRef};
+
+/// Maximum number of `items` validators allowed when `any_order` is set
+const MAX_ANY_ORDER_ITEMS: usize = 16;
+
+#[derive(Clone, Debug)]
+pub struct ValidArray {
+    /// Raw msgpack to compare against
+    in_vec: Vec<Box<[u8]>>,
+    nin_vec: Vec<Box<[u8]>>,
+    min_len: usize,
+    max_len: usize,
+    items: Vec<usize>,
+    extra_items: Option<usize>,
+    contains: Vec<usize>,
+    /// Labels for `contains` validators, used when reporting which requirements weren't met
+    contains_labels: Vec<Option<String>>,
+    unique: bool,
+    /// Array must have exactly as many values as `items`
+    strict_tuple: bool,
+    /// Values may meet `items` in any order, instead of position by position
+    any_order: bool,
+    query: bool,
+    array: bool,
+    contains_ok: bool,
+}
+
+/// Array type validator
+impl ValidArray {
+    pub fn new(is_query: bool) -> ValidArray {
+        ValidArray {
+            in_vec: Vec::with_capacity(0),
+            nin_vec: Vec::with_capacity(0),
+            min_len: usize::min_value(),
+            max_len: usize::max_value(),
+            items: Vec::with_capacity(0),
+            extra_items: None,
+            contains: Vec::with_capacity(0),
+            contains_labels: Vec::with_capacity(0),
+            unique: false,
+            strict_tuple: false,
+            any_order: false,
+            query: is_query,
+            array: is_query,
+            contains_ok: is_query,
+        }
+    }
+
+    pub fn from_const(constant: Box<[u8]>, is_query: bool) -> ValidArray {
+        let mut v = ValidArray::new(is_query);
+        let mut in_vec = Vec::with_capacity(1);
+        in_vec.push(constant);
+        v.in_vec = in_vec;
+        v
+    }
+
+    /// Human-readable name of this validator type, for use in error messages.
+    pub fn type_name() -> &'static str {
+        "Array"
+    }
+
+    /// Update the validator. Returns `Ok(true)` if everything is read out Ok, `Ok(false)` if we 
+    /// don't recognize the field type or value, and `Err` if we recognize the field but fail to 
+    /// parse the expected contents. The updated `raw` slice reference is only accurate if 
+    /// `Ok(true)` was returned.
+    pub fn update(&mut self, field: &str, raw: &mut &[u8], is_query: bool, types: &mut Vec<Validator>, type_names: &mut HashMap<String,usize>)
+        -> io::Result<bool>
+    {
+        // Note about this match: because fields are lexicographically ordered, the items in this 
+        // match statement are either executed sequentially or are skipped.
+        match field {
+            "any_order" => {
+                self.any_order = read_bool(raw)?;
+                Ok(true)
+            },
+            "array" => {
+                self.array = read_bool(raw)?;
+                Ok(true)
+            }
+            "contains" => {
+                if let MarkerType::Array(len) = read_marker(raw)? {
+                    if len > MAX_SCHEMA_FIELDS {
+                        return Err(Error::new(InvalidData, format!("{} `contains` has too many validators", Self::type_name())));
+                    }
+                    for _ in 0..len {
+                        let v = Validator::read_validator(raw, is_query, types, type_names)?;
+                        self.contains.push(v);
+                    }
+                    Ok(true)
+                }
+                else {
+                    Err(Error::new(InvalidData, format!("{} `contains` isn't a valid array of validators", Self::type_name())))
+                }
+            },
+            "contains_labels" => {
+                if let MarkerType::Array(len) = read_marker(raw)? {
+                    if len > self.contains.len() {
+                        return Err(Error::new(InvalidData, format!("{} `contains_labels` has more labels than `contains` has validators", Self::type_name())));
+                    }
+                    for _ in 0..len {
+                        let label = if let MarkerType::Null = read_marker(&mut raw.clone())? {
+                            read_marker(raw)?;
+                            None
+                        }
+                        else {
+                            Some(read_string(raw)?)
+                        };
+                        self.contains_labels.push(label);
+                    }
+                    Ok(true)
+                }
+                else {
+                    Err(Error::new(InvalidData, format!("{} `contains_labels` isn't a valid array of strings", Self::type_name())))
+                }
+            },
+            "contains_ok" => {
+                self.contains_ok = read_bool(raw)?;
+                Ok(true)
+            },
+            "default" => {
+                if let MarkerType::Array(len) = read_marker(raw)? {
+                    for _ in 0..len {
+                        verify_value(raw)?;
+                    }
+                    Ok(true)
+                }
+                else {
+                    Err(Error::new(InvalidData, format!("{} `default` isn't a valid array", Self::type_name())))
+                }
+            },
+            "extra_items" => {
+                self.extra_items = Some(Validator::read_validator(raw, is_query, types, type_names)?);
+                Ok(true)
+            },
+            "in" => {
+                if let MarkerType::Array(len) = read_marker(raw)? {
+                    // Push without reserving - otherwise recursive reserving is possible and 
+                    // can lead to an exponential amount of memory reservation.
+                    for _ in 0..len {
+                        let v = if let MarkerType::Array(len) = read_marker(raw)? {
+                            get_raw_array(raw, len)?
+                        }
+                        else {
+                            return Err(Error::new(InvalidData, format!("{} validator expected array of arrays for `in` field", Self::type_name())));
+                        };
+                        self.in_vec.push(v);
+                    };
+                    self.in_vec.sort_unstable();
+                    self.in_vec.dedup();
+                }
+                else {
+                    return Err(Error::new(InvalidData, format!("{} validator expected array of arrays for `in` field", Self::type_name())));
+                }
+                Ok(true)
+            },
+            "items" => {
+                if let MarkerType::Array(len) = read_marker(raw)? {
+                    if len > MAX_SCHEMA_FIELDS {
+                        return Err(Error::new(InvalidData, format!("{} `items` has too many validators", Self::type_name())));
+                    }
+                    for _ in 0..len {
+                        let v = Validator::read_validator(raw, is_query, types, type_names)?;
+                        self.items.push(v);
+                    }
+                    Ok(true)
+                }
+                else {
+                    Err(Error::new(InvalidData, format!("{} `items` isn't a valid array of validators", Self::type_name())))
+                }
+            },
+            "max_len" => {
+                if let Some(len) = read_integer(raw)?.as_u64() {
+                    self.max_len = len as usize;
+                    Ok(true)
+                }
+                else {
+                    Ok(false)
+                }
+            },
+            "min_len" => {
+                if let Some(len) = read_integer(raw)?.as_u64() {
+                    self.min_len = len as usize;
+                    Ok(self.max_len >= self.min_len)
+                }
+                else {
+                    Ok(false)
+                }
+            },
+            "nin" => {
+                if let MarkerType::Array(len) = read_marker(raw)? {
+                    // Push without reserving - otherwise recursive reserving is possible and 
+                    // can lead to an exponential amount of memory reservation.
+                    for _ in 0..len {
+                        let v = if let MarkerType::Array(len) = read_marker(raw)? {
+                            get_raw_array(raw, len)?
+                        }
+                        else {
+                            return Err(Error::new(InvalidData, format!("{} validator expected array of arrays for `in` field", Self::type_name())));
+                        };
+                        self.nin_vec.push(v);
+                    };
+                    self.nin_vec.sort_unstable();
+                    self.nin_vec.dedup();
+                }
+                else {
+                    return Err(Error::new(InvalidData, format!("{} validator expected array of arrays for `in` field", Self::type_name())));
+                }
+                Ok(true)
+            },
+            "query" => {
+                self.query = read_bool(raw)?;
+                Ok(true)
+            },
+            "strict_tuple" => {
+                self.strict_tuple = read_bool(raw)?;
+                Ok(true)
+            },
+            "unique" => {
+                self.query = read_bool(raw)?;
+                Ok(true)
+            },
+            "type" => if "Array" == read_str(raw)? { Ok(true) } else { Err(Error::new(InvalidData, "Type doesn't match Array")) },
+            _ => Err(Error::new(InvalidData, format!("Unknown fields not allowed in {} validator", Self::type_name()))),
+        }
+    }
+
+    /// Final check on the validator. Returns true if at least one value can (probably) still pass the 
+    /// validator. We do not check the `in` and `nin` against all validation parts
+    pub fn finalize(&mut self) -> bool {
+        if self.any_order && (self.items.len() > MAX_ANY_ORDER_ITEMS) {
+            return false;
+        }
+        if self.strict_tuple {
+            if (self.min_len > self.items.len()) || (self.max_len < self.items.len()) {
+                return false;
+            }
+            self.min_len = self.items.len();
+            self.max_len = self.items.len();
+            self.extra_items = None;
+        }
+        if self.in_vec.len() > 0 {
+            let mut in_vec: Vec<Box<[u8]>> = Vec::with_capacity(self.in_vec.len());
+            let mut nin_index = 0;
+            for val in self.in_vec.iter() {
+                while let Some(nin) = self.nin_vec.get(nin_index) {
+                    if nin < val { nin_index += 1; } else { break; }
+                }
+                if let Some(nin) = self.nin_vec.get(nin_index) {
+                    if nin == val { continue; }
+                }
+                in_vec.push(val.clone());
+            }
+            in_vec.shrink_to_fit();
+            self.in_vec = in_vec;
+            self.nin_vec = Vec::with_capacity(0);
+            (self.in_vec.len() > 0) && (self.min_len <= self.max_len)
+        }
+        else {
+            self.nin_vec.shrink_to_fit();
+            self.min_len <= self.max_len
+        }
+    }
+
+    /// Generate a minimal example value that passes this validator: the first allowed array, or 
+    /// an example for each of `items` and `contains`, padded out to `min_len` with examples of 
+    /// `extra_items`.
+    pub fn example_value(&self, types: &[Validator], depth: usize) -> Option<Value> {
+        if let Some(v) = self.in_vec.first() {
+            return read_value(&mut &v[..]).ok();
+        }
+        let mut value = Vec::new();
+        for v_index in self.items.iter().chain(self.contains.iter()) {
+            value.push(types[*v_index].example(types, depth)?);
+        }
+        while value.len() < self.min_len {
+            match self.extra_items {
+                Some(v_index) => value.push(types[v_index].example(types, depth)?),
+                None => value.push(Value::Null),
+            }
+        }
+        Some(Value::from(value))
+    }
+
+    /// Validates that the next value is a Hash that meets the validator requirements. Fails if the 
+    /// requirements are not met. If it passes, the optional returned Hash indicates that an 
+    /// additional document (referenced by the Hash) needs to be checked.
+    pub fn validate(&self,
+                    field: &str,
+                    doc: &mut &[u8],
+                    types: &[Validator],
+                    list: &mut Checklist,
+                    ) -> io::Result<()>
+    {
+        self.validate_value(field, doc, types, list, false).and(Ok(()))
+    }
+
+    /// Validates the next value like `validate`, and also decodes it if `decode` is set. Each item 
+    /// is decoded as it is validated.
+    pub fn validate_value<'a>(&self,
+                              field: &str,
+                              doc: &mut &'a [u8],
+                              types: &[Validator],
+                              list: &mut Checklist,
+                              decode: bool,
+                              ) -> io::Result<Option<ValueRef<'a>>>
+    {
+        let num_items = match read_marker(doc)? {
+            MarkerType::Array(len) => len,
+            _ => return Err(Error::new(InvalidData, format!("Array for field \"{}\"not found", field))),
+        };
+        if num_items == 0 && self.min_len == 0 && self.items.len() == 0 && self.contains.len() == 0 {
+            return Ok(if decode { Some(ValueRef::Array(Vec::new())) } else { None });
+        }
+
+        let array_start = doc.clone();
+
+        // Size checks
+        if num_items < self.min_len {
+            return Err(Error::new(InvalidData,
+                format!("Field {} contains array with {} items, less than minimum of {}", field, num_items, self.min_len)));
+        }
+        if num_items > self.max_len {
+            return Err(Error::new(InvalidData,
+                format!("Field {} contains array with {} items, greater than maximum of {}", field, num_items, self.max_len)));
+        }
+
+        // Setup for iterating over array
+        let mut unique_set: HashSet<&[u8]> = if self.unique {
+            HashSet::with_capacity(num_items)
+        }
+        else {
+            HashSet::with_capacity(0)
+        };
+        let mut contain_set: Vec<bool> = vec![false; self.contains.len()];
+        let any_order = self.any_order && !self.items.is_empty();
+        let mut any_order_set: Vec<(u16, bool)> = Vec::new();
+        let mut any_order_values: Vec<&[u8]> = Vec::new();
+        let mut decoded = Vec::new();
+
+        // Run through the whole array
+        for i in 0..num_items {
+            // Validate as appropriate. `any_order` items are decoded once their validators are known.
+            let item_start = doc.clone();
+            let value = if any_order {
+                verify_value(doc)?;
+                None
+            }
+            else if let Some(v_index) = self.items.get(i).cloned().or(self.extra_items) {
+                match types[v_index].validate_value(field, doc, types, v_index, list, decode) {
+                    Ok(value) => value,
+                    Err(e) => {
+                        track_path(|| format!("[{}]", i));
+                        return Err(e);
+                    },
+                }
+            }
+            else if decode {
+                Some(read_value_ref(doc)?)
+            }
+            else {
+                verify_value(doc)?;
+                None
+            };
+            decoded.extend(value);
+            let (item, _) = item_start.split_at(item_start.len()-doc.len());
+
+            // Record which `items` this value meets, and whether it could be an extra item instead. 
+            // These are only probes, so any documents they would need checked are left off `list`.
+            if any_order {
+                let mask = self.items.iter()
+                    .enumerate()
+                    .filter(|(_, v_index)| types[**v_index]
+                        .validate(field, &mut &item[..], types, **v_index, &mut list.remaining()).is_ok())
+                    .fold(0u16, |mask, (j, _)| mask | (1 << j));
+                let extra_ok = match self.extra_items {
+                    Some(v_index) => types[v_index]
+                        .validate(field, &mut &item[..], types, v_index, &mut list.remaining()).is_ok(),
+                    None => true,
+                };
+                any_order_set.push((mask, extra_ok));
+                any_order_values.push(item);
+            }
+
+            // Check for uniqueness
+            if self.unique {
+                if !unique_set.insert(item) {
+                    return Err(Error::new(InvalidData,
+                        format!("Field {} contains a repeated item at index {}", field, i)));
+                }
+            }
+            // Check to see if any `contains` requirements are met
+            contain_set.iter_mut()
+                .zip(self.contains.iter())
+                .filter(|(checked,_)| !**checked)
+                .for_each(|(checked,contains_item)| {
+                    if let Ok(()) = types[*contains_item].validate(field, &mut item.clone(), types, *contains_item, list) {
+                        *checked = true;
+                    }
+                });
+        }
+
+        // Failed `contains` and `any_order` checks shouldn't be reported as the reason this array 
+        // failed
+        clear_failure();
+        if any_order {
+            let assigned = match any_order_match(&any_order_set, self.items.len()) {
+                Some(assigned) => assigned,
+                None => return Err(Error::new(InvalidData,
+                    format!("Field {} contains array that doesn't meet `items` in any order", field))),
+            };
+            // Validate again against the chosen validators, so only their document checks are added
+            for (i, (item, assigned)) in any_order_values.iter().zip(assigned.iter()).enumerate() {
+                let v_index = match assigned {
+                    Some(j) => Some(self.items[*j]),
+                    None => self.extra_items,
+                };
+                let value = match v_index {
+                    Some(v_index) => match types[v_index].validate_value(field, &mut &item[..], types, v_index, list, decode) {
+                        Ok(value) => value,
+                        Err(e) => {
+                            track_path(|| format!("[{}]", i));
+                            return Err(e);
+                        },
+                    },
+                    None if decode => Some(read_value_ref(&mut &item[..])?),
+                    None => None,
+                };
+                decoded.extend(value);
+            }
+        }
+        let (array, _) = array_start.split_at(array_start.len()-doc.len());
+        if contain_set.contains(&false) {
+            let unmet: Vec<String> = contain_set.iter()
+                .enumerate()
+                .filter(|(_, checked)| !**checked)
+                .map(|(i, _)| match self.contains_labels.get(i) {
+                    Some(Some(label)) => format!("'{}' (index {})", label, i),
+                    _ => format!("index {}", i),
+                })
+                .collect();
+            Err(Error::new(InvalidData,
+                format!("Field {} does not satisfy `contains` requirement{} {}",
+                    field, if unmet.len() > 1 { "s" } else { "" }, unmet.join(", "))))
+        }
+        else if self.nin_vec.binary_search_by(|probe| (**probe).cmp(array)).is_ok() {
+            Err(Error::new(InvalidData,
+                format!("Field \"{}\" contains array on `nin` list", field)))
+        }
+        else if (self.in_vec.len() > 0) && self.in_vec.binary_search_by(|probe| (**probe).cmp(array)).is_err() {
+            Err(Error::new(InvalidData,
+                format!("Field \"{}\" contains array not on `in` list", field)))
+        }
+        else {
+            Ok(if decode { Some(ValueRef::Array(decoded)) } else { None })
+        }
+    }
+
+    /// Checks if `other` only uses query operations this Array validator allows. If not, 
+    /// `intersect` fails when `query` is true.
+    pub fn query_ok(&self, other: &Validator) -> bool {
+        if !self.query && !self.array && !self.contains_ok { return false; }
+        match other {
+            Validator::Array(other) => !(
+                (!self.query &&
+                 ((other.max_len < usize::max_value()) || (other.min_len > usize::min_value())
+                  || other.unique || !other.in_vec.is_empty() || !other.nin_vec.is_empty()))
+                || (!self.array && 
+                    (!other.items.is_empty() || other.extra_items.is_some() || other.strict_tuple
+                     || other.any_order))
+                || (!self.contains_ok && !other.contains.is_empty())),
+            _ => true,
+        }
+    }
+
+    /// Intersection of Array with other Validators. Returns Err only if `query` is true and the 
+    /// other validator contains non-allowed query parameters.
+    ///
+    /// Items are intersected position by position, and the whole intersection is Invalid as soon 
+    /// as any position is:
+    ///
+    /// - Two strict tuples must have the same number of items. `[Str, Int]` and `[Str, Int]` give 
+    ///     `[Str, Int]`, but `[Str, Int]` and `[Int, Int]` are Invalid after checking only the 
+    ///     first position.
+    /// - Otherwise, positions past the end of the shorter `items` list are intersected with that 
+    ///     validator's `extra_items`, if it has one. `[Str, Int, Int]` and `[Str]` with 
+    ///     `extra_items: Str` are Invalid, because the tail `Int`s meet `extra_items`.
+    ///
+    /// An `any_order` validator's items can only be intersected with another validator's 
+    /// `extra_items`, so if both validators have `items` and either sets `any_order`, the 
+    /// intersection is Invalid.
+    pub fn intersect(&self,
+                 other: &Validator,
+                 query: bool,
+                 builder: &mut ValidBuilder
+                 )
+        -> Result<Validator, ()>
+    {
+        let builder_len = builder.len();
+        if query && !self.query_ok(other) { return Err(()); }
+        match other {
+            Validator::Array(other) => {
+                // Get intersection of `in` vectors
+                let in_vec = if (self.in_vec.len() > 0) && (other.in_vec.len() > 0) {
+                    sorted_intersection(&self.in_vec[..], &other.in_vec[..], |a,b| a.cmp(b))
+                }
+                else if self.in_vec.len() > 0 {
+                    self.in_vec.clone()
+                }
+                else {
+                    other.in_vec.clone()
+                };
+
+                // Only the side with `items` decides whether they're matched in any order
+                let any_order = (self.any_order && !self.items.is_empty())
+                    || (other.any_order && !other.items.is_empty());
+                if any_order && !self.items.is_empty() && !other.items.is_empty() {
+                    return Ok(Validator::Invalid);
+                }
+
+                // Strict tuples fix the number of items, so they must agree on it
+                let tuple_len = match (self.strict_tuple, other.strict_tuple) {
+                    (true, true) => {
+                        if self.items.len() != other.items.len() {
+                            return Ok(Validator::Invalid);
+                        }
+                        Some(self.items.len())
+                    },
+                    (true, false) => Some(self.items.len()),
+                    (false, true) => Some(other.items.len()),
+                    (false, false) => None,
+                };
+
+                // Get intersection of items
+                let items_len = tuple_len.unwrap_or(self.items.len().max(other.items.len()));
+                let mut items = Vec::with_capacity(items_len);
+                for i in 0..items_len {
+                    let self_index = if let Some(index) = self.items.get(i) {
+                        *index
+                    }
+                    else if let Some(index) = self.extra_items {
+                        index
+                    }
+                    else {
+                        1
+                    };
+                    let other_index = if let Some(index) = other.items.get(i) {
+                        *index
+                    }
+                    else if let Some(index) = other.extra_items {
+                        index
+                    }
+                    else {
+                        1
+                    };
+
+                    let item = builder.intersect(query, self_index, other_index)?;
+                    if item == 0 {
+                        builder.undo_to(builder_len);
+                        return Ok(Validator::Invalid);
+                    }
+                    items.push(item);
+                }
+
+                // Get extra items
+                let extra_items = if let (Some(self_extra), Some(other_extra)) = (self.extra_items,other.extra_items) {
+                    Some(builder.intersect(query, self_extra, other_extra)?)
+                }
+                else if let Some(extra_items) = self.extra_items {
+                    Some(builder.intersect(query, extra_items, 1)?)
+                }
+                else if let Some(extra_items) = other.extra_items {
+                    Some(builder.intersect(query, 1, extra_items)?)
+                }
+                else {
+                    None
+                };
+
+                let mut contains: Vec<usize> = Vec::with_capacity(self.contains.len() + other.contains.len());
+                contains.extend(self.contains.iter()
+                    .map(|x| builder.intersect(query, *x, 1).unwrap()));
+                contains.extend(other.contains.iter()
+                    .map(|x| builder.intersect(query, 1, *x).unwrap()));
+                let mut contains_labels: Vec<Option<String>> = Vec::with_capacity(contains.len());
+                contains_labels.extend((0..self.contains.len())
+                    .map(|i| self.contains_labels.get(i).cloned().unwrap_or(None)));
+                contains_labels.extend(other.contains_labels.iter().cloned());
+
+                // Create new Validator
+                let mut new_validator = ValidArray {
+                    in_vec: in_vec,
+                    nin_vec: sorted_union(&self.nin_vec[..], &other.nin_vec[..], |a,b| a.cmp(b)),
+                    min_len: self.min_len.max(other.min_len),
+                    max_len: self.max_len.min(other.max_len),
+                    items: items,
+                    extra_items: extra_items,
+                    contains: contains,
+                    contains_labels: contains_labels,
+                    unique: self.unique || other.unique,
+                    strict_tuple: tuple_len.is_some(),
+                    any_order: any_order,
+                    query: self.query && other.query,
+                    array: self.array && other.array,
+                    contains_ok: self.contains_ok && other.contains_ok,
+                };
+                if new_validator.in_vec.len() == 0 && (self.in_vec.len()+other.in_vec.len() > 0) {
+                    builder.undo_to(builder_len);
+                    return Ok(Validator::Invalid);
+                }
+                let valid = new_validator.finalize();
+                if !valid {
+                    builder.undo_to(builder_len);
+                    Ok(Validator::Invalid)
+                }
+                else {
+                    Ok(Validator::Array(new_validator))
+                }
+            },
+            Validator::Valid => {
+                // Get intersection of items
+                let mut v = self.clone();
+                let mut items = Vec::with_capacity(self.items.len());
+                items.extend(self.items.iter()
+                    .map(|x| builder.intersect(query, *x, 1).unwrap()));
+                v.items = items;
+
+                if let Some(extra) = self.extra_items {
+                    v.extra_items = Some(builder.intersect(query, extra, 1).unwrap());
+                }
+
+                let mut contains: Vec<usize> = Vec::with_capacity(self.contains.len());
+                contains.extend(self.contains.iter()
+                    .map(|x| builder.intersect(query, *x, 1).unwrap()));
+                v.contains = contains;
+                Ok(Validator::Array(v))
+            }
+            _ => Ok(Validator::Invalid),
+        }
+    }
+}
+
+/// Matches array values up with distinct `items` validators. Each entry in `values` holds a 
+/// bitmask of the `items` that value meets, and whether it meets `extra_items`. Values that don't 
+/// meet `extra_items` must be matched, and as many `items` must be used as there are values to 
+/// match them. Values are matched by finding augmenting paths, so a value already matched can be 
+/// moved to another of its `items` to make room. Returns the index into `items` for each value, or 
+/// `None` for values left to `extra_items`, if a match was found.
+fn any_order_match(values: &[(u16, bool)], num_items: usize) -> Option<Vec<Option<usize>>> {
+    let mut owners: Vec<Option<usize>> = vec![None; num_items];
+    let required = values.iter().enumerate().filter(|(_, v)| !v.1);
+    let optional = values.iter().enumerate().filter(|(_, v)| v.1);
+    let mut matched = 0;
+    for (i, (_, extra_ok)) in required.chain(optional) {
+        if any_order_augment(i, values, &mut owners, &mut 0) {
+            matched += 1;
+        }
+        else if !extra_ok {
+            return None;
+        }
+    }
+    if matched != num_items.min(values.len()) { return None; }
+    let mut assigned = vec![None; values.len()];
+    for (j, owner) in owners.iter().enumerate() {
+        if let Some(i) = owner { assigned[*i] = Some(j); }
+    }
+    Some(assigned)
+}
+
+/// Try to match a value to one of its `items`, moving along any value already holding it.
+fn any_order_augment(value: usize, values: &[(u16, bool)], owners: &mut [Option<usize>], seen: &mut u16) -> bool {
+    for j in 0..owners.len() {
+        let bit = 1u16 << j;
+        if ((values[value].0 & bit) == 0) || ((*seen & bit) != 0) { continue; }
+        *seen |= bit;
+        let free = match owners[j] {
+            Some(owner) => any_order_augment(owner, values, owners, seen),
+            None => true,
+        };
+        if free {
+            owners[j] = Some(value);
+            return true;
+        }
+    }
+    false
+}
+
+pub fn get_raw_array(raw: &mut &[u8], len: usize) -> io::Result<Box<[u8]>> {
+    let start = raw.clone();
+    for _ in 0..len {
+        verify_value(raw)?;
+    }
+    let (array, _) = start.split_at(start.len()-raw.len());
+    Ok(array.to_vec().into_boxed_slice())
+}
+
+#[cfg(test)]
+mod tests {
+    use encode;
+    use crypto;
+    use value::Value;
+    use super::*;
+
+    fn read_it(v: Value, types: &mut Vec<Validator>) -> usize {
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &v);
+        let mut type_names = HashMap::new();
+        Validator::read_validator(&mut &raw[..], false, types, &mut type_names).unwrap()
+    }
+
+    fn passes(types: &[Validator], index: usize, v: Value) -> bool {
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &v);
+        types[index].validate("", &mut &raw[..], types, index, &mut Checklist::new()).is_ok()
+    }
+
+    #[test]
+    fn strict_tuple() {
+        let mut types = vec![Validator::Invalid, Validator::Valid];
+        let index = read_it(msgpack!({
+            "type": "Array",
+            "items": [ { "type": "Str" }, { "type": "Int" } ],
+            "strict_tuple": true
+        }), &mut types);
+        assert!(passes(&types, index, msgpack!(["a", 1])));
+        assert!(!passes(&types, index, msgpack!(["a"])));
+        assert!(!passes(&types, index, msgpack!(["a", 1, 2])));
+        assert!(!passes(&types, index, msgpack!([1, "a"])));
+
+        // Without strict_tuple, shorter and longer arrays are fine
+        let index_loose = read_it(msgpack!({
+            "type": "Array",
+            "items": [ { "type": "Str" }, { "type": "Int" } ]
+        }), &mut types);
+        assert!(passes(&types, index_loose, msgpack!(["a"])));
+        assert!(passes(&types, index_loose, msgpack!(["a", 1, 2])));
+
+        // Length limits that exclude the tuple length make it unsatisfiable
+        let index_bad = read_it(msgpack!({
+            "type": "Array",
+            "items": [ { "type": "Str" }, { "type": "Int" } ],
+            "max_len": 1,
+            "strict_tuple": true
+        }), &mut types);
+        assert_eq!(index_bad, 0);
+    }
+
+    #[test]
+    fn strict_tuple_intersect() {
+        let mut types1 = vec![Validator::Invalid, Validator::Valid];
+        let mut types2 = vec![Validator::Invalid, Validator::Valid];
+        let tuple2 = read_it(msgpack!({
+            "type": "Array",
+            "items": [ { "type": "Str" }, { "type": "Int" } ],
+            "strict_tuple": true
+        }), &mut types1);
+        let tuple3 = read_it(msgpack!({
+            "type": "Array",
+            "items": [ { "type": "Str" }, { "type": "Int" }, { "type": "Int" } ],
+            "strict_tuple": true
+        }), &mut types2);
+        let loose = read_it(msgpack!({
+            "type": "Array",
+            "items": [ { "type": "Str", "max_len": 1 } ],
+            "extra_items": { "type": "Int", "min": 0 }
+        }), &mut types2);
+
+        let mut builder = ValidBuilder::init(&types1, &types2);
+        assert_eq!(builder.intersect(false, tuple2, tuple3).unwrap(), 0);
+
+        let mut builder = ValidBuilder::init(&types1, &types2);
+        let index = builder.intersect(false, tuple2, loose).unwrap();
+        let types = builder.build();
+        assert!(passes(&types, index, msgpack!(["a", 1])));
+        assert!(!passes(&types, index, msgpack!(["ab", 1])));
+        assert!(!passes(&types, index, msgpack!(["a", -1])));
+        assert!(!passes(&types, index, msgpack!(["a", 1, 2])));
+    }
+
+    #[test]
+    fn items_intersect_short_circuit() {
+        let mut types1 = vec![Validator::Invalid, Validator::Valid];
+        let mut types2 = vec![Validator::Invalid, Validator::Valid];
+        let tuple_with = |types: &mut Vec<Validator>, str_at: usize| {
+            let items: Vec<Value> = (0..8)
+                .map(|i| if i == str_at { msgpack!({ "type": "Str" }) } else { msgpack!({ "type": "Int" }) })
+                .collect();
+            read_it(msgpack!({ "type": "Array", "items": items, "strict_tuple": true }), types)
+        };
+        let all_int = tuple_with(&mut types1, 8);
+        let str_first = tuple_with(&mut types2, 0);
+        let str_fifth = tuple_with(&mut types2, 5);
+
+        // A conflict at the first position fails right away, one at position 5 after checking the 
+        // positions before it. Either way, nothing is left behind in the builder.
+        for other in [str_first, str_fifth].iter() {
+            let mut builder = ValidBuilder::init(&types1, &types2);
+            assert_eq!(builder.intersect(false, all_int, *other).unwrap(), 0);
+            assert_eq!(builder.len(), 2);
+        }
+
+        // Without strict_tuple, the tail of the longer list meets the other's extra_items
+        let loose = read_it(msgpack!({
+            "type": "Array",
+            "items": [ { "type": "Int" } ],
+            "extra_items": { "type": "Str" }
+        }), &mut types2);
+        let mut builder = ValidBuilder::init(&types1, &types2);
+        assert_eq!(builder.intersect(false, all_int, loose).unwrap(), 0);
+        let loose_int = read_it(msgpack!({
+            "type": "Array",
+            "items": [ { "type": "Int" } ],
+            "extra_items": { "type": "Int", "min": 0 }
+        }), &mut types2);
+        let mut builder = ValidBuilder::init(&types1, &types2);
+        let index = builder.intersect(false, all_int, loose_int).unwrap();
+        let types = builder.build();
+        assert!(passes(&types, index, msgpack!([-1, 1, 2, 3, 4, 5, 6, 7])));
+        assert!(!passes(&types, index, msgpack!([-1, -1, 2, 3, 4, 5, 6, 7])));
+    }
+
+    #[test]
+    fn any_order() {
+        let mut types = vec![Validator::Invalid, Validator::Valid];
+        let index = read_it(msgpack!({
+            "type": "Array",
+            "any_order": true,
+            "items": [ { "type": "Str" }, { "type": "Int" }, { "type": "Bool" } ]
+        }), &mut types);
+        assert!(passes(&types, index, msgpack!(["a", 1, true])));
+        assert!(passes(&types, index, msgpack!([1, true, "a"])));
+        assert!(passes(&types, index, msgpack!([true, "a", 1])));
+        assert!(passes(&types, index, msgpack!([false, 1, "a"])));
+        assert!(passes(&types, index, msgpack!([true, "a"])));
+        assert!(passes(&types, index, msgpack!(["a", 1, true, 2.0])));
+        assert!(!passes(&types, index, msgpack!(["a", "b", true])));
+        assert!(!passes(&types, index, msgpack!([1, 2])));
+
+        // Extra values must meet `extra_items`, and every item must be used before them
+        let index = read_it(msgpack!({
+            "type": "Array",
+            "any_order": true,
+            "items": [ { "type": "Str" }, { "type": "Int" }, { "type": "Bool" } ],
+            "extra_items": { "type": "Int" }
+        }), &mut types);
+        assert!(passes(&types, index, msgpack!([2, "a", 1, true])));
+        assert!(!passes(&types, index, msgpack!([2, "a", 1, 3])));
+        assert!(!passes(&types, index, msgpack!(["a", 1, true, "b"])));
+
+        // A value taken by the first item it meets gets moved when a later value needs that item
+        let index = read_it(msgpack!({
+            "type": "Array",
+            "any_order": true,
+            "items": [ { "type": "Int" }, { "type": "Int", "min": 5 }, { "type": "Str" } ],
+            "strict_tuple": true
+        }), &mut types);
+        assert!(passes(&types, index, msgpack!([7, "a", 1])));
+        assert!(passes(&types, index, msgpack!(["a", 1, 7])));
+        assert!(!passes(&types, index, msgpack!([1, "a", 2])));
+        assert!(!passes(&types, index, msgpack!([7, 1])));
+
+        // Too many items to match up
+        let items: Vec<Value> = (0..17).map(|_| msgpack!({ "type": "Int" })).collect();
+        let index = read_it(msgpack!({ "type": "Array", "any_order": true, "items": items }), &mut types);
+        assert_eq!(index, 0);
+    }
+
+    #[test]
+    fn any_order_intersect() {
+        let mut types1 = vec![Validator::Invalid, Validator::Valid];
+        let mut types2 = vec![Validator::Invalid, Validator::Valid];
+        let unordered = read_it(msgpack!({
+            "type": "Array",
+            "any_order": true,
+            "items": [ { "type": "Int", "max": 10 }, { "type": "Int" } ]
+        }), &mut types1);
+        let extra = read_it(msgpack!({
+            "type": "Array",
+            "extra_items": { "type": "Int", "min": 0 }
+        }), &mut types2);
+        let ordered = read_it(msgpack!({
+            "type": "Array",
+            "items": [ { "type": "Str" }, { "type": "Int" } ]
+        }), &mut types2);
+
+        let mut builder = ValidBuilder::init(&types1, &types2);
+        let index = builder.intersect(false, unordered, extra).unwrap();
+        let types = builder.build();
+        assert!(passes(&types, index, msgpack!([20, 3])));
+        assert!(passes(&types, index, msgpack!([20, 3, 4])));
+        assert!(!passes(&types, index, msgpack!([20, 30])));
+        assert!(!passes(&types, index, msgpack!([20, -3])));
+        assert!(!passes(&types, index, msgpack!([20, 3, -4])));
+
+        let mut builder = ValidBuilder::init(&types1, &types2);
+        assert_eq!(builder.intersect(false, unordered, ordered).unwrap(), 0);
+
+        // `any_order` without `items` doesn't change how the other side's `items` are matched
+        let unordered_empty = read_it(msgpack!({ "type": "Array", "any_order": true }), &mut types1);
+        let tuple = read_it(msgpack!({
+            "type": "Array",
+            "items": [ { "type": "Str" }, { "type": "Int" } ]
+        }), &mut types2);
+        let mut builder = ValidBuilder::init(&types1, &types2);
+        let index = builder.intersect(false, unordered_empty, tuple).unwrap();
+        let types = builder.build();
+        assert!(passes(&types, index, msgpack!(["a", 1])));
+        assert!(!passes(&types, index, msgpack!([1, "a"])));
+    }
+
+    #[test]
+    fn any_order_checklist() {
+        crypto::init().unwrap();
+        let mut types = vec![Validator::Invalid, Validator::Valid];
+        let index = read_it(msgpack!({
+            "type": "Array",
+            "any_order": true,
+            "items": [
+                { "type": "Hash", "link": { "type": "Obj", "req": { "a": { "type": "Int" } } } },
+                { "type": "Hash", "link": { "type": "Obj", "req": { "b": { "type": "Int" } } } }
+            ],
+            "extra_items": { "type": "Hash", "link": { "type": "Obj", "req": { "c": { "type": "Int" } } } }
+        }), &mut types);
+        let hashes: Vec<Hash> = (0u8..3).map(|i| Hash::new(1, &[i]).unwrap()).collect();
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &Value::from(hashes.iter().cloned().map(Value::from).collect::<Vec<Value>>()));
+        let mut list = Checklist::new();
+        types[index].validate("", &mut &raw[..], &types, index, &mut list).unwrap();
+
+        // Each hash is only checked against the validator it was matched with
+        for hash in hashes.iter() {
+            assert_eq!(list.get_list(hash).map(|l| l.len()), Some(1));
+        }
+    }
+
+    #[test]
+    fn contains_labels() {
+        let mut types = vec![Validator::Invalid, Validator::Valid];
+        let index = read_it(msgpack!({
+            "type": "Array",
+            "contains": [ { "type": "Str" }, { "type": "Int", "min": 0 }, { "type": "F64", "min": 0.0 } ],
+            "contains_labels": [ "must have a name", null, "must have positive price" ]
+        }), &mut types);
+        assert!(passes(&types, index, msgpack!(["a", 1, 2.0])));
+
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &msgpack!([-1, "a"]));
+        let err = types[index].validate("", &mut &raw[..], &types, index, &mut Checklist::new())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("index 1,"));
+        assert!(err.contains("'must have positive price' (index 2)"));
+        assert!(!err.contains("must have a name"));
+
+        // More labels than validators is an error
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &msgpack!({
+            "type": "Array",
+            "contains": [ { "type": "Str" } ],
+            "contains_labels": [ "a", "b" ]
+        }));
+        assert!(Validator::read_validator(&mut &raw[..], false, &mut types, &mut HashMap::new()).is_err());
+    }
+}
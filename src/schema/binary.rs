@@ -4,8 +4,9 @@ use std::io::Error;
 use std::io::ErrorKind::InvalidData;
 use std::iter::repeat;
 use decode::*;
-use super::{MAX_VEC_RESERVE, sorted_union, sorted_intersection, Validator};
+use super::{MAX_VEC_RESERVE, sorted_union, sorted_intersection, lcm, Validator};
 use marker::MarkerType;
+use value::Value;
 
 /// Binary type validator
 #[derive(Clone, Debug)]
@@ -14,10 +15,15 @@ pub struct ValidBin {
     nin_vec: Vec<Box<[u8]>>,
     min_len: usize,
     max_len: usize,
+    /// Lengths must be a multiple of this. 1 allows any length.
+    len_multiple: usize,
     min: Box<[u8]>,
     max: Option<Box<[u8]>>,
     bits_set: Vec<u8>,
     bits_clr: Vec<u8>,
+    pattern: Option<Vec<Option<u8>>>,
+    prefix: Option<Vec<u8>>,
+    suffix: Option<Vec<u8>>,
     query: bool,
     ord: bool,
     bit: bool,
@@ -26,30 +32,31 @@ pub struct ValidBin {
 }
 
 impl ValidBin {
-    pub fn new(is_query: bool) -> ValidBin {
-        ValidBin {
-            in_vec: Vec::with_capacity(0),
-            nin_vec: Vec::with_capacity(0),
-            min_len: usize::min_value(),
-            max_len: usize::max_value(),
-            min: Vec::new().into_boxed_slice(),
-            max: None,
-            bits_set: Vec::with_capacity(0),
-            bits_clr: Vec::with_capacity(0),
-            query: is_query,
-            ord: is_query,
-            bit: is_query,
-            ex_min: false,
-            ex_max: false,
-        }
-    }
+    impl_validator_new!(ValidBin, |is_query| {
+        in_vec: Vec::with_capacity(0),
+        nin_vec: Vec::with_capacity(0),
+        min_len: usize::min_value(),
+        max_len: usize::max_value(),
+        len_multiple: 1,
+        min: Vec::new().into_boxed_slice(),
+        max: None,
+        bits_set: Vec::with_capacity(0),
+        bits_clr: Vec::with_capacity(0),
+        pattern: None,
+        prefix: None,
+        suffix: None,
+        query: is_query,
+        ord: is_query,
+        bit: is_query,
+        ex_min: false,
+        ex_max: false,
+    });
+
+    impl_validator_from_const!(ValidBin, &[u8], |constant| constant.to_vec().into_boxed_slice());
 
-    pub fn from_const(constant: &[u8], is_query: bool) -> ValidBin {
-        let mut v = ValidBin::new(is_query);
-        let mut in_vec = Vec::with_capacity(1);
-        in_vec.push(constant.to_vec().into_boxed_slice());
-        v.in_vec = in_vec;
-        v
+    /// Human-readable name of this validator type, for use in error messages.
+    pub fn type_name() -> &'static str {
+        "Binary"
     }
 
     /// Update the validator. Returns `Ok(true)` if everything is read out Ok, `Ok(false)` if we 
@@ -103,11 +110,20 @@ impl ValidBin {
                         self.in_vec.dedup();
                     },
                     _ => {
-                        return Err(Error::new(InvalidData, "Binary validator expected array or constant for `in` field"));
+                        return Err(Error::new(InvalidData, format!("{} validator expected array or constant for `in` field", Self::type_name())));
                     },
                 }
                 Ok(true)
             },
+            "len_multiple" => {
+                match read_integer(raw)?.as_u64() {
+                    Some(m) if (m > 0) && (m <= (usize::max_value() as u64)) => {
+                        self.len_multiple = m as usize;
+                        Ok(true)
+                    },
+                    _ => Ok(false),
+                }
+            },
             "max" => {
                 let mut max = read_vec(raw)?;
                 if !self.ex_max {
@@ -170,7 +186,7 @@ impl ValidBin {
                         self.nin_vec.dedup();
                     },
                     _ => {
-                        return Err(Error::new(InvalidData, "Binary validator expected array or constant for `nin` field"));
+                        return Err(Error::new(InvalidData, format!("{} validator expected array or constant for `nin` field", Self::type_name())));
                     },
                 }
                 Ok(true)
@@ -179,18 +195,51 @@ impl ValidBin {
                 self.ord = read_bool(raw)?;
                 Ok(true)
             }
+            "pattern" => {
+                self.pattern = Some(read_pattern(raw)?);
+                Ok(true)
+            }
+            "prefix" => {
+                self.prefix = Some(read_vec(raw)?);
+                Ok(true)
+            }
             "query" => {
                 self.query = read_bool(raw)?;
                 Ok(true)
             }
+            "suffix" => {
+                self.suffix = Some(read_vec(raw)?);
+                Ok(true)
+            }
             "type" => if "Bin" == read_str(raw)? { Ok(true) } else { Err(Error::new(InvalidData, "Type doesn't match Bin")) },
-            _ => Err(Error::new(InvalidData, "Unknown fields not allowed in binary validator")),
+            _ => Err(Error::new(InvalidData, format!("Unknown fields not allowed in {} validator", Self::type_name()))),
         }
     }
 
     /// Final check on the validator. Returns true if at least one value can still pass the 
     /// validator.
     pub fn finalize(&mut self) -> bool {
+        // Narrow the length range to the allowed multiples
+        let m = self.len_multiple;
+        self.max_len -= self.max_len % m;
+        self.min_len = match round_up(self.min_len, m) {
+            Some(len) => len,
+            None => return false,
+        };
+        if self.min_len > self.max_len {
+            return false;
+        }
+        let affix_len = self.prefix.as_ref().map_or(0, |x| x.len())
+            + self.suffix.as_ref().map_or(0, |x| x.len());
+        if affix_len > self.max_len || self.pattern.as_ref().map_or(0, |x| x.len()) > self.max_len {
+            return false;
+        }
+        // The prefix must agree with the pattern wherever both require a specific byte
+        if let (Some(pattern), Some(prefix)) = (&self.pattern, &self.prefix) {
+            if !pattern.iter().zip(prefix.iter()).all(|(p, b)| p.is_none() || *p == Some(*b)) {
+                return false;
+            }
+        }
         if self.in_vec.len() > 0 {
             let mut in_vec: Vec<Box<[u8]>> = Vec::with_capacity(self.in_vec.len());
             let mut nin_index = 0;
@@ -202,12 +251,16 @@ impl ValidBin {
                     if nin == val { continue; }
                 }
                 if (val.len() >= self.min_len) && (val.len() <= self.max_len) 
+                    && (val.len() % self.len_multiple == 0)
                     && self.bits_set.iter()
                         .zip(val.iter().chain(repeat(&0u8)))
                         .all(|(bit, val)| (bit & val) == *bit)
                     && self.bits_clr.iter()
                         .zip(val.iter().chain(repeat(&0u8)))
                         .all(|(bit, val)| (bit & val) == 0)
+                    && self.pattern.as_ref().map_or(true, |x| pattern_match(x, val))
+                    && self.prefix.as_ref().map_or(true, |x| val.starts_with(x))
+                    && self.suffix.as_ref().map_or(true, |x| val.ends_with(x))
                 {
                     in_vec.push(val.clone());
                 }
@@ -220,23 +273,53 @@ impl ValidBin {
         else {
             let min_len = self.min_len;
             let max_len = self.max_len;
+            let len_multiple = self.len_multiple;
             let bits_set = self.bits_set.clone();
             let bits_clr = self.bits_clr.clone();
+            let pattern = self.pattern.clone();
+            let prefix = self.prefix.clone();
+            let suffix = self.suffix.clone();
             // Only keep `nin` values that would otherwise pass
             self.nin_vec.retain(|val| {
                 (val.len() >= min_len) && (val.len() <= max_len) 
+                    && (val.len() % len_multiple == 0)
                     && bits_set.iter()
                         .zip(val.iter().chain(repeat(&0u8)))
                         .all(|(bit, val)| (bit & val) == *bit)
                     && bits_clr.iter()
                         .zip(val.iter().chain(repeat(&0u8)))
                         .all(|(bit, val)| (bit & val) == 0)
+                    && pattern.as_ref().map_or(true, |x| pattern_match(x, val))
+                    && prefix.as_ref().map_or(true, |x| val.starts_with(x))
+                    && suffix.as_ref().map_or(true, |x| val.ends_with(x))
             });
             self.nin_vec.shrink_to_fit();
             true
         }
     }
 
+    /// Generate a minimal example value that passes this validator: the first allowed value, or 
+    /// the required prefix and suffix padded out with zeros to `min_len`, with any required bits 
+    /// set.
+    pub fn example_value(&self) -> Option<Value> {
+        if let Some(v) = self.in_vec.first() {
+            return Some(Value::from(&v[..]));
+        }
+        let prefix = self.prefix.as_ref().map_or(&[][..], |x| &x[..]);
+        let suffix = self.suffix.as_ref().map_or(&[][..], |x| &x[..]);
+        let mut value = prefix.to_vec();
+        if let Some(ref pattern) = self.pattern {
+            value.extend(pattern.iter().skip(prefix.len()).map(|p| p.unwrap_or(0)));
+        }
+        let len = round_up(self.min_len.max(value.len() + suffix.len()), self.len_multiple)?;
+        let pad = len - value.len() - suffix.len();
+        value.extend(repeat(0u8).take(pad));
+        value.extend_from_slice(suffix);
+        if value.len() < self.bits_set.len() { value.resize(self.bits_set.len(), 0); }
+        value.iter_mut().zip(self.bits_set.iter()).for_each(|(v, bit)| *v |= bit);
+        Some(Value::from(value))
+    }
+
     pub fn validate(&self, field: &str, doc: &mut &[u8]) -> io::Result<()> {
         let value = read_bin(doc)?;
         if (self.in_vec.len() > 0) && self.in_vec.binary_search_by(|probe| (**probe).cmp(value)).is_err() {
@@ -254,6 +337,10 @@ impl ValidBin {
             Err(Error::new(InvalidData,
                 format!("Field \"{}\" contains binary longer than max length of {}", field, self.min_len)))
         }
+        else if value.len() % self.len_multiple != 0 {
+            Err(Error::new(InvalidData,
+                format!("Field \"{}\" contains binary with length not a multiple of {}", field, self.len_multiple)))
+        }
         else if self.min.iter()
             .zip(value.iter().chain(repeat(&0u8)))
             .fold(false, |carry, (min, val)| {
@@ -284,6 +371,18 @@ impl ValidBin {
             Err(Error::new(InvalidData,
                 format!("Field \"{}\" does not have all required bits cleared", field)))
         }
+        else if self.pattern.as_ref().map_or(false, |x| !pattern_match(x, value)) {
+            Err(Error::new(InvalidData,
+                format!("Field \"{}\" does not match the required pattern", field)))
+        }
+        else if self.prefix.as_ref().map_or(false, |x| !value.starts_with(x)) {
+            Err(Error::new(InvalidData,
+                format!("Field \"{}\" does not start with the required prefix", field)))
+        }
+        else if self.suffix.as_ref().map_or(false, |x| !value.ends_with(x)) {
+            Err(Error::new(InvalidData,
+                format!("Field \"{}\" does not end with the required suffix", field)))
+        }
         else if self.nin_vec.binary_search_by(|probe| (**probe).cmp(value)).is_ok() {
             Err(Error::new(InvalidData,
                 format!("Field \"{}\" contains binary on the `nin` list", field)))
@@ -293,20 +392,28 @@ impl ValidBin {
         }
     }
 
+    /// Checks if `other` only uses query operations this Binary validator allows. If not, 
+    /// `intersect` fails when `query` is true.
+    pub fn query_ok(&self, other: &Validator) -> bool {
+        if !self.query && !self.ord && !self.bit { return false; }
+        match other {
+            Validator::Binary(other) => !(
+                (!self.query && (!other.in_vec.is_empty() || !other.nin_vec.is_empty()
+                    || other.pattern.is_some() || other.prefix.is_some() || other.suffix.is_some()))
+                || (!self.ord && ((other.min_len > usize::min_value()) || (other.max_len < usize::max_value())
+                    || (other.len_multiple > 1)))
+                || (!self.bit && ((other.bits_set.len() > 0) || (other.bits_clr.len() > 0)))),
+            _ => true,
+        }
+    }
+
     /// Intersection of Binary with other Validators. Returns Err only if `query` is true and the 
     /// other validator contains non-allowed query parameters.
     pub fn intersect(&self, other: &Validator, query: bool) -> Result<Validator, ()> {
-        if query && !self.query && !self.ord && !self.bit { return Err(()); }
+        if query && !self.query_ok(other) { return Err(()); }
         match other {
             Validator::Binary(other) => {
-                if query && (
-                    (!self.query && (!other.in_vec.is_empty() || !other.nin_vec.is_empty()))
-                    || (!self.ord && ((other.min_len > usize::min_value()) || (other.max_len < usize::max_value())))
-                    || (!self.bit && ((other.bits_set.len() > 0) || (other.bits_clr.len() > 0))))
-                {
-                    Err(())
-                }
-                else if (self.min_len > other.max_len) || (self.max_len < other.min_len) 
+                if (self.min_len > other.max_len) || (self.max_len < other.min_len) 
                     || self.bits_set.iter().zip(other.bits_clr.iter()).any(|(a,b)| (a&b) != 0)
                     || self.bits_clr.iter().zip(other.bits_set.iter()).any(|(a,b)| (a&b) != 0)
                 {
@@ -335,16 +442,61 @@ impl ValidBin {
                     else {
                         other.max.clone()
                     };
+                    // Prefixes & suffixes must agree, in which case the longer one is used
+                    let prefix = match (&self.prefix, &other.prefix) {
+                        (Some(s), Some(o)) => {
+                            if s.starts_with(o) { Some(s.clone()) }
+                            else if o.starts_with(s) { Some(o.clone()) }
+                            else { return Ok(Validator::Invalid); }
+                        },
+                        (Some(s), None) => Some(s.clone()),
+                        (None, o) => o.clone(),
+                    };
+                    // Patterns must agree wherever both require a specific byte
+                    let pattern = match (&self.pattern, &other.pattern) {
+                        (Some(s), Some(o)) => {
+                            let (long, short) = if s.len() >= o.len() { (s, o) } else { (o, s) };
+                            let mut pattern = long.clone();
+                            for (p, b) in pattern.iter_mut().zip(short.iter()) {
+                                match (*p, *b) {
+                                    (Some(p), Some(b)) if p != b => return Ok(Validator::Invalid),
+                                    (None, b) => *p = b,
+                                    _ => (),
+                                }
+                            }
+                            Some(pattern)
+                        },
+                        (Some(s), None) => Some(s.clone()),
+                        (None, o) => o.clone(),
+                    };
+                    let suffix = match (&self.suffix, &other.suffix) {
+                        (Some(s), Some(o)) => {
+                            if s.ends_with(o) { Some(s.clone()) }
+                            else if o.ends_with(s) { Some(o.clone()) }
+                            else { return Ok(Validator::Invalid); }
+                        },
+                        (Some(s), None) => Some(s.clone()),
+                        (None, o) => o.clone(),
+                    };
+
+                    let len_multiple = match lcm(self.len_multiple as u64, other.len_multiple as u64) {
+                        Some(m) if m <= (usize::max_value() as u64) => m as usize,
+                        _ => return Ok(Validator::Invalid),
+                    };
 
                     let mut new_validator = ValidBin {
                         in_vec: in_vec,
                         nin_vec: sorted_union(&self.nin_vec[..], &other.nin_vec[..], |a,b| a.cmp(b)),
                         min_len: self.min_len.max(other.min_len),
                         max_len: self.max_len.min(other.max_len),
+                        len_multiple,
                         min: min,
                         max: max,
                         bits_set: self.bits_set.iter().zip(other.bits_set.iter()).map(|(a,b)| a | b).collect(),
                         bits_clr: self.bits_clr.iter().zip(other.bits_clr.iter()).map(|(a,b)| a | b).collect(),
+                        pattern: pattern,
+                        prefix: prefix,
+                        suffix: suffix,
                         query: self.query && other.query,
                         ord: self.ord && other.ord,
                         bit: self.bit && other.bit,
@@ -369,6 +521,41 @@ impl ValidBin {
     }
 }
 
+/// Read a byte pattern. The pattern is binary data where `0xFF` matches any byte, `0xFE` is 
+/// followed by a byte to be matched literally, and all other bytes are matched literally.
+fn read_pattern(raw: &mut &[u8]) -> io::Result<Vec<Option<u8>>> {
+    let encoded = read_vec(raw)?;
+    let mut pattern = Vec::with_capacity(encoded.len());
+    let mut iter = encoded.iter();
+    while let Some(b) = iter.next() {
+        match *b {
+            0xFF => pattern.push(None),
+            0xFE => {
+                let b = iter.next()
+                    .ok_or_else(|| Error::new(InvalidData,
+                        format!("{} validator `pattern` ended with an unfinished literal", ValidBin::type_name())))?;
+                pattern.push(Some(*b));
+            },
+            b => pattern.push(Some(b)),
+        }
+    }
+    Ok(pattern)
+}
+
+/// Check if a binary sequence matches a pattern. The sequence may be longer than the pattern.
+fn pattern_match(pattern: &[Option<u8>], val: &[u8]) -> bool {
+    (val.len() >= pattern.len())
+        && pattern.iter().zip(val.iter()).all(|(p, v)| p.map_or(true, |p| p == *v))
+}
+
+/// Round a length up to the next multiple of `m`, if it can be represented.
+fn round_up(len: usize, m: usize) -> Option<usize> {
+    match len % m {
+        0 => Some(len),
+        rem => len.checked_add(m - rem),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use encode;
@@ -553,4 +740,217 @@ mod tests {
         assert!(validate_bin(Vec::new(), &validi).is_err());
     }
 
+    #[test]
+    fn prefix_suffix() {
+        let mut test1 = Vec::new();
+
+        let png: Vec<u8> = vec![137, 80, 78, 71];
+        let iend: Vec<u8> = vec![0xAE, 0x42, 0x60, 0x82];
+        encode::write_value(&mut test1, &msgpack!({
+            "prefix": png.clone(),
+            "suffix": iend.clone()
+        }));
+        let validator = read_it(&mut &test1[..], false).unwrap();
+        assert!(validate_bin(vec![137, 80, 78, 71, 0xAE, 0x42, 0x60, 0x82], &validator).is_ok());
+        assert!(validate_bin(vec![137, 80, 78, 71, 1, 2, 3, 0xAE, 0x42, 0x60, 0x82], &validator).is_ok());
+        assert!(validate_bin(vec![137, 80, 78, 71], &validator).is_err());
+        assert!(validate_bin(vec![137, 80, 78, 72, 0xAE, 0x42, 0x60, 0x82], &validator).is_err());
+        assert!(validate_bin(vec![137, 80, 78, 71, 0xAE, 0x42, 0x60, 0x83], &validator).is_err());
+        assert!(validate_bin(Vec::new(), &validator).is_err());
+
+        // Prefix and suffix can't fit in the maximum length
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({
+            "max_len": 6,
+            "prefix": png.clone(),
+            "suffix": iend.clone()
+        }));
+        let mut raw = &test1[..];
+        let mut validator = ValidBin::new(false);
+        if let MarkerType::Object(len) = read_marker(&mut raw).unwrap() {
+            object_iterate(&mut raw, len, |field, raw| { validator.update(field, raw)?; Ok(()) }).unwrap();
+        }
+        assert!(!validator.finalize());
+    }
+
+    #[test]
+    fn prefix_intersect() {
+        let mut test1 = Vec::new();
+
+        encode::write_value(&mut test1, &msgpack!({
+            "prefix": vec![137u8, 80u8]
+        }));
+        let valid1 = read_it(&mut &test1[..], false).unwrap();
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({
+            "prefix": vec![137u8, 80u8, 78u8, 71u8]
+        }));
+        let valid2 = read_it(&mut &test1[..], false).unwrap();
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({
+            "prefix": vec![0xFFu8, 0xD8u8]
+        }));
+        let valid3 = read_it(&mut &test1[..], false).unwrap();
+
+        let validi = valid1.intersect(&Validator::Binary(valid2.clone()), false).unwrap();
+        let validi = if let Validator::Binary(v) = validi {
+            v
+        }
+        else {
+            panic!("Intersection invalid");
+        };
+        assert!(validate_bin(vec![137, 80, 78, 71, 13, 10], &validi).is_ok());
+        assert!(validate_bin(vec![137, 80, 0, 0], &validi).is_err());
+
+        match valid2.intersect(&Validator::Binary(valid3), false).unwrap() {
+            Validator::Invalid => (),
+            _ => panic!("Intersection of conflicting prefixes should be invalid"),
+        }
+    }
+
+    #[test]
+    fn pattern() {
+        let mut test1 = Vec::new();
+
+        // PNG signature & IHDR chunk, with any chunk length in bytes 8-11
+        let png_ihdr: Vec<u8> = vec![137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82];
+        encode::write_value(&mut test1, &msgpack!({
+            "pattern": vec![137u8, 80, 78, 71, 13, 10, 26, 10, 0xFF, 0xFF, 0xFF, 0xFF, 73, 72, 68, 82]
+        }));
+        let validator = read_it(&mut &test1[..], false).unwrap();
+        assert!(validate_bin(png_ihdr.clone(), &validator).is_ok());
+        let mut png_long = png_ihdr.clone();
+        png_long[8..12].copy_from_slice(&[0xFF, 0xFE, 1, 2]);
+        png_long.extend_from_slice(&[1, 2, 3]);
+        assert!(validate_bin(png_long, &validator).is_ok());
+        let mut png_bad = png_ihdr.clone();
+        png_bad[12] = 0;
+        assert!(validate_bin(png_bad, &validator).is_err());
+        assert!(validate_bin(png_ihdr[..15].to_vec(), &validator).is_err());
+
+        // Literal escapes for the wildcard & escape bytes
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({
+            "pattern": vec![0xFEu8, 0xFF, 0xFF, 0xFE, 0xFE]
+        }));
+        let validator = read_it(&mut &test1[..], false).unwrap();
+        assert!(validate_bin(vec![0xFF, 0, 0xFE], &validator).is_ok());
+        assert!(validate_bin(vec![0, 0, 0xFE], &validator).is_err());
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({ "pattern": vec![0u8, 0xFE] }));
+        assert!(read_it(&mut &test1[..], false).is_err());
+
+        // Intersections fill in each other's wildcards, and fail if specific bytes disagree
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({ "pattern": vec![1u8, 0xFF, 3] }));
+        let valid1 = read_it(&mut &test1[..], false).unwrap();
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({ "pattern": vec![0xFFu8, 2] }));
+        let valid2 = read_it(&mut &test1[..], false).unwrap();
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({ "pattern": vec![0xFFu8, 0xFF, 4] }));
+        let valid3 = read_it(&mut &test1[..], false).unwrap();
+        let validi = match valid1.intersect(&Validator::Binary(valid2), false).unwrap() {
+            Validator::Binary(v) => v,
+            _ => panic!("Intersection invalid"),
+        };
+        assert!(validate_bin(vec![1, 2, 3], &validi).is_ok());
+        assert!(validate_bin(vec![1, 0, 3], &validi).is_err());
+        match valid1.intersect(&Validator::Binary(valid3), false).unwrap() {
+            Validator::Invalid => (),
+            _ => panic!("Intersection of conflicting patterns should be invalid"),
+        }
+
+        // Prefixes must agree with the pattern's specific bytes
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({
+            "pattern": vec![1u8, 0xFF, 3],
+            "prefix": vec![1u8, 2, 3, 4]
+        }));
+        let mut validator = read_it(&mut &test1[..], false).unwrap();
+        assert!(validator.finalize());
+        assert!(validate_bin(vec![1, 2, 3, 4], &validator).is_ok());
+        assert_eq!(validator.example_value().unwrap().as_slice(), Some(&[1u8, 2, 3, 4][..]));
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({
+            "pattern": vec![1u8, 0xFF, 3],
+            "prefix": vec![1u8, 2, 4]
+        }));
+        let mut validator = read_it(&mut &test1[..], false).unwrap();
+        assert!(!validator.finalize());
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({ "prefix": vec![2u8] }));
+        let valid4 = read_it(&mut &test1[..], false).unwrap();
+        match valid1.intersect(&Validator::Binary(valid4), false).unwrap() {
+            Validator::Invalid => (),
+            _ => panic!("Intersection of a pattern with a conflicting prefix should be invalid"),
+        }
+    }
+
+    #[test]
+    fn len_multiple() {
+        let mut test1 = Vec::new();
+
+        // AES-256-CBC ciphertext: IV plus at least one block, always whole blocks
+        encode::write_value(&mut test1, &msgpack!({
+            "len_multiple": 16,
+            "min_len": 32
+        }));
+        let validator = read_it(&mut &test1[..], false).unwrap();
+        assert!(validate_bin(vec![0; 32], &validator).is_ok());
+        assert!(validate_bin(vec![0; 48], &validator).is_ok());
+        assert!(validate_bin(vec![0; 160], &validator).is_ok());
+        assert!(validate_bin(vec![0; 16], &validator).is_err());
+        assert!(validate_bin(vec![0; 33], &validator).is_err());
+        assert!(validate_bin(vec![0; 47], &validator).is_err());
+        assert_eq!(validator.example_value().unwrap().as_slice().map(|v| v.len()), Some(32));
+
+        // Length limits are narrowed to multiples, and fail if no multiple is left
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({ "len_multiple": 16, "max_len": 40, "min_len": 17 }));
+        let validator = read_it(&mut &test1[..], false).unwrap();
+        assert_eq!((validator.min_len, validator.max_len), (32, 32));
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({ "len_multiple": 16, "max_len": 31, "min_len": 17 }));
+        let mut validator = read_it(&mut &test1[..], false).unwrap();
+        assert!(!validator.finalize());
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({ "len_multiple": 0 }));
+        assert!(read_it(&mut &test1[..], false).is_err());
+
+        // Examples are padded out to a multiple
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({ "len_multiple": 4, "prefix": vec![1u8, 2, 3, 4, 5] }));
+        let validator = read_it(&mut &test1[..], false).unwrap();
+        assert_eq!(validator.example_value().unwrap().as_slice(), Some(&[1u8, 2, 3, 4, 5, 0, 0, 0][..]));
+
+        // Intersection uses the least common multiple
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({ "len_multiple": 6 }));
+        let valid1 = read_it(&mut &test1[..], false).unwrap();
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({ "len_multiple": 4 }));
+        let valid2 = read_it(&mut &test1[..], false).unwrap();
+        let validi = match valid1.intersect(&Validator::Binary(valid2.clone()), false).unwrap() {
+            Validator::Binary(v) => v,
+            _ => panic!("Intersection invalid"),
+        };
+        assert!(validate_bin(vec![0; 24], &validi).is_ok());
+        assert!(validate_bin(vec![0; 12], &validi).is_ok());
+        assert!(validate_bin(vec![0; 6], &validi).is_err());
+        assert!(validate_bin(vec![0; 8], &validi).is_err());
+        let mut huge = valid2.clone();
+        huge.len_multiple = usize::max_value();
+        match valid1.intersect(&Validator::Binary(huge), false).unwrap() {
+            Validator::Invalid => (),
+            _ => panic!("Intersection with an unrepresentable multiple should be invalid"),
+        }
+
+        // Queries need `ord` to use it
+        let mut query = ValidBin::new(false);
+        query.query = true;
+        assert!(query.intersect(&Validator::Binary(valid1.clone()), true).is_err());
+        query.ord = true;
+        assert!(query.intersect(&Validator::Binary(valid1), true).is_ok());
+    }
 }
@@ -3,21 +3,22 @@ use std::io::Error;
 use std::io::ErrorKind::InvalidData;
 use decode::*;
 use super::Validator;
+use value::Value;
 
 /// Boolean type validator
 #[derive(Clone, Debug)]
 pub struct ValidBool {
     constant: Option<bool>,
+    default: Option<bool>,
     query: bool,
 }
 
 impl ValidBool {
-    pub fn new(is_query: bool) -> ValidBool {
-        ValidBool {
-            constant: None,
-            query: is_query
-        }
-    }
+    impl_validator_new!(ValidBool, |is_query| {
+        constant: None,
+        default: None,
+        query: is_query,
+    });
 
     pub fn from_const(constant: bool, is_query: bool) -> ValidBool {
         let mut v = ValidBool::new(is_query);
@@ -26,6 +27,16 @@ impl ValidBool {
         v
     }
 
+    /// The default value set by the schema, if any.
+    pub fn default_value(&self) -> Option<bool> {
+        self.default
+    }
+
+    /// Human-readable name of this validator type, for use in error messages.
+    pub fn type_name() -> &'static str {
+        "Boolean"
+    }
+
     /// Update the validator. Returns `Ok(true)` if everything is read out Ok, `Ok(false)` if we 
     /// don't recognize the field type or value, and `Err` if we recognize the field but fail to 
     /// parse the expected contents. The updated `raw` slice reference is only accurate if 
@@ -33,6 +44,10 @@ impl ValidBool {
     pub fn update(&mut self, field: &str, raw: &mut &[u8]) -> io::Result<bool> {
         match field {
             "type" => if "Bool" == read_str(raw)? { Ok(true) } else { Err(Error::new(InvalidData, "Type doesn't match Bool")) },
+            "default" => {
+                self.default = Some(read_bool(raw)?);
+                Ok(true)
+            },
             "in" => {
                 self.constant = Some(read_bool(raw)?);
                 Ok(true)
@@ -45,7 +60,7 @@ impl ValidBool {
                 self.query = read_bool(raw)?;
                 Ok(true)
             }
-            _ => Err(Error::new(InvalidData, "Unknown fields not allowed in boolean validator")),
+            _ => Err(Error::new(InvalidData, format!("Unknown fields not allowed in {} validator", Self::type_name()))),
         }
     }
 
@@ -53,6 +68,12 @@ impl ValidBool {
         true
     }
 
+    /// Generate a minimal example value that passes this validator, preferring the default value 
+    /// if one was set.
+    pub fn example_value(&self) -> Option<Value> {
+        Some(Value::from(self.constant.or(self.default).unwrap_or(false)))
+    }
+
     pub fn validate(&self, field: &str, doc: &mut &[u8]) -> io::Result<()> {
         let value = read_bool(doc)?;
         match self.constant {
@@ -68,35 +89,25 @@ impl ValidBool {
         }
     }
 
+    /// Checks if `other` only uses query operations this Bool validator allows. If not, 
+    /// `intersect` fails when `query` is true.
+    pub fn query_ok(&self, _other: &Validator) -> bool {
+        self.query
+    }
+
     pub fn intersect(&self, other: &Validator, query: bool) -> Result<Validator, ()> {
-        if !self.query && query { return Err(()); }
+        if query && !self.query_ok(other) { return Err(()); }
         match other {
             Validator::Boolean(other) => {
-                if let Some(o) = other.constant {
-                    if let Some(s) = self.constant {
-                        if s == o {
-                            Ok(Validator::Boolean(ValidBool {
-                                constant: self.constant,
-                                query: self.query && other.query,
-                            }))
-                        }
-                        else {
-                            Ok(Validator::Invalid)
-                        }
-                    }
-                    else {
-                        Ok(Validator::Boolean(ValidBool {
-                            constant: other.constant,
-                            query: self.query && other.query,
-                        }))
-                    }
-                }
-                else {
-                    Ok(Validator::Boolean(ValidBool {
-                        constant: None,
-                        query: self.query && other.query,
-                    }))
-                }
+                let constant = match (self.constant, other.constant) {
+                    (Some(s), Some(o)) => if s == o { Some(s) } else { return Ok(Validator::Invalid); },
+                    (s, o) => s.or(o),
+                };
+                Ok(Validator::Boolean(ValidBool {
+                    constant,
+                    default: self.default.or(other.default),
+                    query: self.query && other.query,
+                }))
             },
             Validator::Valid => Ok(Validator::Boolean(self.clone())),
             _ => Ok(Validator::Invalid),
@@ -104,3 +115,61 @@ impl ValidBool {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encode;
+    use marker::MarkerType;
+
+    fn read_it(raw: &mut &[u8]) -> ValidBool {
+        let mut validator = ValidBool::new(false);
+        if let MarkerType::Object(len) = read_marker(raw).unwrap() {
+            object_iterate(raw, len, |field, raw| {
+                assert!(validator.update(field, raw).unwrap());
+                Ok(())
+            }).unwrap();
+        }
+        assert!(validator.finalize());
+        validator
+    }
+
+    #[test]
+    fn default() {
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &msgpack!({ "default": true, "type": "Bool" }));
+        let with_default = read_it(&mut &raw[..]);
+        assert_eq!(with_default.default_value(), Some(true));
+        assert_eq!(with_default.example_value().unwrap().as_bool(), Some(true));
+        assert_eq!(ValidBool::new(false).default_value(), None);
+
+        // The default survives intersection from either side
+        let plain = Validator::Boolean(ValidBool::new(false));
+        match with_default.intersect(&plain, false).unwrap() {
+            Validator::Boolean(v) => assert_eq!(v.default_value(), Some(true)),
+            _ => panic!("Intersection should be a Bool validator"),
+        }
+        match ValidBool::new(false).intersect(&Validator::Boolean(with_default.clone()), false).unwrap() {
+            Validator::Boolean(v) => assert_eq!(v.default_value(), Some(true)),
+            _ => panic!("Intersection should be a Bool validator"),
+        }
+        match with_default.intersect(&Validator::Valid, false).unwrap() {
+            Validator::Boolean(v) => assert_eq!(v.default_value(), Some(true)),
+            _ => panic!("Intersection should be a Bool validator"),
+        }
+
+        // A constant takes precedence over the default when making an example
+        let constant = Validator::Boolean(ValidBool::from_const(false, false));
+        match with_default.intersect(&constant, false).unwrap() {
+            Validator::Boolean(v) => {
+                assert_eq!(v.default_value(), Some(true));
+                assert_eq!(v.example_value().unwrap().as_bool(), Some(false));
+            },
+            _ => panic!("Intersection should be a Bool validator"),
+        }
+        let other_constant = Validator::Boolean(ValidBool::from_const(true, false));
+        assert!(match ValidBool::from_const(false, false).intersect(&other_constant, false).unwrap() {
+            Validator::Invalid => true,
+            _ => false,
+        });
+    }
+}
@@ -10,6 +10,7 @@ use ieee754::Ieee754;
 use decode::*;
 use super::{MAX_VEC_RESERVE, sorted_union, sorted_intersection, Validator};
 use marker::MarkerType;
+use value::Value;
 
 /// F64 type validator
 #[derive(Clone,Debug)]
@@ -19,6 +20,7 @@ pub struct ValidF64 {
     min: f64,
     max: f64,
     nan_ok: bool,
+    finite_only: bool,
     query: bool,
     ord: bool,
     ex_min: bool, // setup only
@@ -26,26 +28,24 @@ pub struct ValidF64 {
 }
 
 impl ValidF64 {
-    pub fn new(is_query: bool) -> ValidF64 {
-        ValidF64 {
-            in_vec: Vec::with_capacity(0),
-            nin_vec: Vec::with_capacity(0),
-            min: f64::NEG_INFINITY,
-            max: f64::INFINITY,
-            nan_ok: true,
-            query: is_query,
-            ord: is_query,
-            ex_min: false,
-            ex_max: false,
-        }
-    }
+    impl_validator_new!(ValidF64, |is_query| {
+        in_vec: Vec::with_capacity(0),
+        nin_vec: Vec::with_capacity(0),
+        min: f64::NEG_INFINITY,
+        max: f64::INFINITY,
+        nan_ok: true,
+        finite_only: false,
+        query: is_query,
+        ord: is_query,
+        ex_min: false,
+        ex_max: false,
+    });
+
+    impl_validator_from_const!(ValidF64, f64);
 
-    pub fn from_const(constant: f64, is_query: bool) -> ValidF64 {
-        let mut v = ValidF64::new(is_query);
-        let mut in_vec = Vec::with_capacity(1);
-        in_vec.push(constant);
-        v.in_vec = in_vec;
-        v
+    /// Human-readable name of this validator type, for use in error messages.
+    pub fn type_name() -> &'static str {
+        "F64"
     }
 
     /// Update the validator. Returns `Ok(true)` if everything is read out Ok, `Ok(false)` if we 
@@ -72,6 +72,10 @@ impl ValidF64 {
                 self.nan_ok = false;
                 Ok(true)
             },
+            "finite" => {
+                self.finite_only = read_bool(raw)?;
+                Ok(true)
+            },
             "in" => {
                 match read_marker(raw)? {
                     MarkerType::F64 => {
@@ -88,7 +92,7 @@ impl ValidF64 {
                         self.in_vec.dedup();
                     },
                     _ => {
-                        return Err(Error::new(InvalidData, "F64 validator expected array or constant for `in` field"));
+                        return Err(Error::new(InvalidData, format!("{} validator expected array or constant for `in` field", Self::type_name())));
                     },
                 }
                 Ok(true)
@@ -96,7 +100,7 @@ impl ValidF64 {
             "max" => {
                 let max = read_f64(raw)?;
                 if max.is_nan() {
-                    Err(Error::new(InvalidData, "F64 validator does not accept NaN for `max` field"))
+                    Err(Error::new(InvalidData, format!("{} validator does not accept NaN for `max` field", Self::type_name())))
                 }
                 else if self.ex_max && (max == f64::NEG_INFINITY) {
                     Ok(false)
@@ -110,7 +114,7 @@ impl ValidF64 {
             "min" => {
                 let min = read_f64(raw)?;
                 if min.is_nan() {
-                    Err(Error::new(InvalidData, "F64 validator does not accept NaN for `min` field"))
+                    Err(Error::new(InvalidData, format!("{} validator does not accept NaN for `min` field", Self::type_name())))
                 }
                 else if self.ex_min && (min == f64::INFINITY) {
                     Ok(false)
@@ -137,7 +141,7 @@ impl ValidF64 {
                         self.nin_vec.dedup();
                     },
                     _ => {
-                        return Err(Error::new(InvalidData, "F64 validator expected array or constant for `nin` field"));
+                        return Err(Error::new(InvalidData, format!("{} validator expected array or constant for `nin` field", Self::type_name())));
                     },
                 }
                 Ok(true)
@@ -151,13 +155,18 @@ impl ValidF64 {
                 Ok(true)
             }
             "type" => if "F64" == read_str(raw)? { Ok(true) } else { Err(Error::new(InvalidData, "Type doesn't match F64")) },
-            _ => Err(Error::new(InvalidData, "Unknown fields not allowed in f64 validator")),
+            _ => Err(Error::new(InvalidData, format!("Unknown fields not allowed in {} validator", Self::type_name()))),
         }
     }
 
     /// Final check on the validator. Returns true if at least one value can still pass the 
     /// validator.
     pub fn finalize(&mut self) -> bool {
+        if self.finite_only {
+            self.nan_ok = false;
+            self.min = self.min.max(-f64::MAX);
+            self.max = self.max.min(f64::MAX);
+        }
         if self.in_vec.len() > 0 {
             let mut in_vec: Vec<f64> = Vec::with_capacity(self.in_vec.len());
             let mut nin_index = 0;
@@ -189,10 +198,25 @@ impl ValidF64 {
                 nan_ok || (!val.is_nan() && (*val >= min) && (*val <= max))
             });
             self.nin_vec.shrink_to_fit();
-            true
+            nan_ok || (min <= max)
         }
     }
 
+    /// Generate a minimal example value that passes this validator: the first allowed value, or 
+    /// the smallest allowed value if there's a lower bound, or zero if there isn't.
+    pub fn example_value(&self) -> Option<Value> {
+        if let Some(v) = self.in_vec.first() {
+            return Some(Value::from(*v));
+        }
+        let value = if self.min > f64::NEG_INFINITY {
+            self.min
+        }
+        else {
+            0.0f64.min(self.max)
+        };
+        Some(Value::from(value))
+    }
+
     pub fn validate(&self, field: &str, doc: &mut &[u8]) -> io::Result<()> {
         let value = read_f64(doc)?;
         if (self.in_vec.len() > 0) && self.in_vec.binary_search_by(|probe| probe.total_cmp(&value)).is_err() {
@@ -226,19 +250,25 @@ impl ValidF64 {
 
     }
 
+    /// Checks if `other` only uses query operations this F64 validator allows. If not, 
+    /// `intersect` fails when `query` is true.
+    pub fn query_ok(&self, other: &Validator) -> bool {
+        if !self.query && !self.ord { return false; }
+        match other {
+            Validator::F64(other) => !(
+                (!self.query && (!other.in_vec.is_empty() || !other.nin_vec.is_empty()))
+                || (!self.ord && !other.nan_ok)),
+            _ => true,
+        }
+    }
+
     /// Intersection of F64 with other Validators. Returns Err only if `query` is true and the 
     /// other validator contains non-allowed query parameters.
     pub fn intersect(&self, other: &Validator, query: bool) -> Result<Validator, ()> {
-        if query && !self.query && !self.ord { return Err(()); }
+        if query && !self.query_ok(other) { return Err(()); }
         match other {
             Validator::F64(other) => {
-                if query && (
-                    (!self.query && (!other.in_vec.is_empty() || !other.nin_vec.is_empty()))
-                    || (!self.ord && !other.nan_ok))
-                {
-                    Err(())
-                }
-                else if !self.nan_ok && !other.nan_ok && ((self.min > other.max) || (self.max < other.min)) {
+                if !self.nan_ok && !other.nan_ok && ((self.min > other.max) || (self.max < other.min)) {
                     Ok(Validator::Invalid)
                 }
                 else {
@@ -260,6 +290,7 @@ impl ValidF64 {
                         min: self.min.max(other.min),
                         max: self.max.min(other.max),
                         nan_ok: self.nan_ok && other.nan_ok,
+                        finite_only: self.finite_only || other.finite_only,
                         query: self.query && other.query,
                         ord: self.ord && other.ord,
                         ex_min: false, // Doesn't get used by this point - for setup of validator only.
@@ -562,4 +593,56 @@ mod tests {
                 "Set intersection for F64 validators fails with {}", test_val);
         }
     }
+
+    #[test]
+    fn finite() {
+        let mut rng = rand::thread_rng();
+        let mut test1 = Vec::new();
+        let mut val = Vec::with_capacity(9);
+        encode::write_value(&mut test1, &msgpack!({
+            "finite": true,
+            "type": "F64"
+        }));
+        let validator = read_it(&mut &test1[..], false).unwrap();
+        for v in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY].iter() {
+            val.clear();
+            encode::write_value(&mut val, &Value::from(*v));
+            assert!(validator.validate("", &mut &val[..]).is_err(), "{} should fail", v);
+        }
+        for v in [0.0, -0.0, f64::MAX, f64::MIN, f64::MIN_POSITIVE, -f64::MIN_POSITIVE].iter() {
+            val.clear();
+            encode::write_value(&mut val, &Value::from(*v));
+            validator.validate("", &mut &val[..]).unwrap();
+        }
+        for _ in 0..100 {
+            val.clear();
+            encode::write_value(&mut val, &Value::from(rand_float(&mut rng)));
+            validator.validate("", &mut &val[..]).unwrap();
+        }
+
+        // Finite-only carries through intersection, and bounds still apply
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({
+            "ex_max": true,
+            "max": f64::INFINITY,
+            "type": "F64"
+        }));
+        let bounded = read_it(&mut &test1[..], false).unwrap();
+        let validi = match bounded.intersect(&Validator::F64(validator.clone()), false).unwrap() {
+            Validator::F64(v) => v,
+            _ => panic!("Intersection should be a F64 validator"),
+        };
+        for (v, ok) in [(f64::NEG_INFINITY, false), (f64::MAX, true), (1.0, true)].iter() {
+            val.clear();
+            encode::write_value(&mut val, &Value::from(*v));
+            assert_eq!(validi.validate("", &mut &val[..]).is_ok(), *ok, "{} failed", v);
+        }
+
+        // An `in` list only keeps finite values
+        let mut in_valid = ValidF64::new(false);
+        in_valid.in_vec = vec![f64::NEG_INFINITY, 1.0];
+        in_valid.finite_only = true;
+        assert!(in_valid.finalize());
+        assert_eq!(in_valid.in_vec, vec![1.0]);
+    }
 }
@@ -7,6 +7,7 @@ use decode::*;
 use super::{MAX_VEC_RESERVE, sorted_union, sorted_intersection, Validator, ValidBuilder};
 use marker::MarkerType;
 use crypto::Hash;
+use value::Value;
 
 /// Hash type validator
 #[derive(Clone, Debug)]
@@ -42,6 +43,11 @@ impl ValidHash {
         v
     }
 
+    /// Human-readable name of this validator type, for use in error messages.
+    pub fn type_name() -> &'static str {
+        "Hash"
+    }
+
     /// Update the validator. Returns `Ok(true)` if everything is read out Ok, `Ok(false)` if we 
     /// don't recognize the field type or value, and `Err` if we recognize the field but fail to 
     /// parse the expected contents. The updated `raw` slice reference is only accurate if 
@@ -72,7 +78,7 @@ impl ValidHash {
                         self.in_vec.dedup();
                     },
                     _ => {
-                        return Err(Error::new(InvalidData, "Hash validator expected array or constant for `in` field"));
+                        return Err(Error::new(InvalidData, format!("{} validator expected array or constant for `in` field", Self::type_name())));
                     },
                 }
                 Ok(true)
@@ -101,7 +107,7 @@ impl ValidHash {
                         self.nin_vec.dedup();
                     },
                     _ => {
-                        return Err(Error::new(InvalidData, "Hash validator expected array or constant for `nin` field"));
+                        return Err(Error::new(InvalidData, format!("{} validator expected array or constant for `nin` field", Self::type_name())));
                     },
                 }
                 Ok(true)
@@ -126,7 +132,7 @@ impl ValidHash {
                         self.schema.dedup();
                     },
                     _ => {
-                        return Err(Error::new(InvalidData, "Hash validator expected array or constant for `schema` field"));
+                        return Err(Error::new(InvalidData, format!("{} validator expected array or constant for `schema` field", Self::type_name())));
                     },
                 }
                 Ok(true)
@@ -136,7 +142,7 @@ impl ValidHash {
                 Ok(true)
             }
             "type" => if "Hash" == read_str(raw)? { Ok(true) } else { Err(Error::new(InvalidData, "Type doesn't match Hash")) },
-            _ => Err(Error::new(InvalidData, "Unknown fields not allowed in Hash validator")),
+            _ => Err(Error::new(InvalidData, format!("Unknown fields not allowed in {} validator", Self::type_name()))),
         }
     }
 
@@ -162,18 +168,31 @@ impl ValidHash {
         }
     }
 
-    pub fn schema_in_set(&self, hash: &Hash) -> bool {
-        self.schema.contains(hash)
-    }
-
-    pub fn schema_required(&self) -> bool {
+    /// Whether a document referenced by a passing Hash must use one of the `allowed_schemas`.
+    pub fn requires_schema(&self) -> bool {
         self.schema.len() > 0
     }
 
-    pub fn link(&self) -> Option<usize> {
+    /// Index of the validator that a document referenced by a passing Hash must pass, if any.
+    pub fn link_validator_index(&self) -> Option<usize> {
         self.link
     }
 
+    /// Schemas that a document referenced by a passing Hash may use. Empty if the document may use 
+    /// any schema, or none at all.
+    pub fn allowed_schemas(&self) -> &[Hash] {
+        &self.schema[..]
+    }
+
+    /// Generate an example value that passes this validator: the first allowed hash, or the empty 
+    /// hash. Linked documents aren't checked.
+    pub fn example_value(&self) -> Option<Value> {
+        if let Some(v) = self.in_vec.first() {
+            return Some(Value::from(v.clone()));
+        }
+        Some(Value::from(Hash::new_empty()))
+    }
+
     /// Validates that the next value is a Hash that meets the validator requirements. Fails if the 
     /// requirements are not met. If it passes, the optional returned Hash indicates that an 
     /// additional document (referenced by the Hash) needs to be checked.
@@ -198,6 +217,19 @@ impl ValidHash {
         }
     }
 
+    /// Checks if `other` only uses query operations this Hash validator allows. If not, 
+    /// `intersect` fails when `query` is true.
+    pub fn query_ok(&self, other: &Validator) -> bool {
+        if !self.query && !self.link_ok && !self.schema_ok { return false; }
+        match other {
+            Validator::Hash(other) => !(
+                (!self.query && (!other.in_vec.is_empty() || !other.nin_vec.is_empty()))
+                || (!self.link_ok && other.link.is_some())
+                || (!self.schema_ok && (other.schema.len() > 0))),
+            _ => true,
+        }
+    }
+
     /// Intersection of Hash with other Validators. Returns Err only if `query` is true and the 
     /// other validator contains non-allowed query parameters.
     pub fn intersect(&self,
@@ -207,77 +239,68 @@ impl ValidHash {
                  )
         -> Result<Validator, ()>
     {
-        if query && !self.query && !self.link_ok && !self.schema_ok{ return Err(()); }
+        if query && !self.query_ok(other) { return Err(()); }
         match other {
             Validator::Hash(other) => {
                 let builder_len = builder.len();
-                if query && (
-                    (!self.query && (!other.in_vec.is_empty() || !other.nin_vec.is_empty()))
-                    || (!self.link_ok && other.link.is_some())
-                    || (!self.schema_ok && (other.schema.len() > 0)))
-                {
-                    Err(())
+                // Get instersection of `in` vectors
+                let in_vec = if (self.in_vec.len() > 0) && (other.in_vec.len() > 0) {
+                    sorted_intersection(&self.in_vec[..], &other.in_vec[..], |a,b| a.cmp(b))
+                }
+                else if self.in_vec.len() > 0 {
+                    self.in_vec.clone()
+                }
+                else {
+                    other.in_vec.clone()
+                };
+                // Get instersection of schema
+                let schema = if (self.schema.len() > 0) && (other.schema.len() > 0) {
+                    sorted_intersection(&self.schema[..], &other.schema[..], |a,b| a.cmp(b))
+                }
+                else if self.schema.len() > 0 {
+                    self.schema.clone()
                 }
                 else {
-                    // Get instersection of `in` vectors
-                    let in_vec = if (self.in_vec.len() > 0) && (other.in_vec.len() > 0) {
-                        sorted_intersection(&self.in_vec[..], &other.in_vec[..], |a,b| a.cmp(b))
-                    }
-                    else if self.in_vec.len() > 0 {
-                        self.in_vec.clone()
-                    }
-                    else {
-                        other.in_vec.clone()
-                    };
-                    // Get instersection of schema
-                    let schema = if (self.schema.len() > 0) && (other.schema.len() > 0) {
-                        sorted_intersection(&self.schema[..], &other.schema[..], |a,b| a.cmp(b))
-                    }
-                    else if self.schema.len() > 0 {
-                        self.schema.clone()
-                    }
-                    else {
-                        other.schema.clone()
-                    };
-                    // Get link
-                    let link = if let (Some(self_link), Some(other_link)) = (self.link,other.link) {
-                        Some(builder.intersect(query, self_link, other_link)?)
-                    }
-                    else if let Some(link) = self.link {
-                        Some(builder.intersect(query, link, 1)?)
-                    }
-                    else if let Some(link) = other.link {
-                        Some(builder.intersect(query, 1, link)?)
-                    }
-                    else {
-                        None
-                    };
-                    // Create new Validator
-                    let mut new_validator = ValidHash {
-                        in_vec: in_vec,
-                        nin_vec: sorted_union(&self.nin_vec[..], &other.nin_vec[..], |a,b| a.cmp(b)),
-                        schema: schema,
-                        link: link,
-                        query: self.query && other.query,
-                        link_ok: self.link_ok && other.link_ok,
-                        schema_ok: self.schema_ok && other.schema_ok,
-                    };
-                    if new_validator.in_vec.len() == 0 && (self.in_vec.len()+other.in_vec.len() > 0) {
-                        builder.undo_to(builder_len);
-                        return Ok(Validator::Invalid);
-                    }
-                    if new_validator.schema.len() == 0 && (self.schema.len()+other.schema.len() > 0) {
-                        builder.undo_to(builder_len);
-                        return Ok(Validator::Invalid);
-                    }
-                    let valid = new_validator.finalize();
-                    if !valid {
-                        builder.undo_to(builder_len);
-                        Ok(Validator::Invalid)
-                    }
-                    else {
-                        Ok(Validator::Hash(new_validator))
-                    }
+                    other.schema.clone()
+                };
+                // Get link
+                let link = if let (Some(self_link), Some(other_link)) = (self.link,other.link) {
+                    Some(builder.intersect(query, self_link, other_link)?)
+                }
+                else if let Some(link) = self.link {
+                    Some(builder.intersect(query, link, 1)?)
+                }
+                else if let Some(link) = other.link {
+                    Some(builder.intersect(query, 1, link)?)
+                }
+                else {
+                    None
+                };
+                // Create new Validator
+                let mut new_validator = ValidHash {
+                    in_vec: in_vec,
+                    nin_vec: sorted_union(&self.nin_vec[..], &other.nin_vec[..], |a,b| a.cmp(b)),
+                    schema: schema,
+                    link: link,
+                    query: self.query && other.query,
+                    link_ok: self.link_ok && other.link_ok,
+                    schema_ok: self.schema_ok && other.schema_ok,
+                };
+                if new_validator.in_vec.len() == 0 && (self.in_vec.len()+other.in_vec.len() > 0) {
+                    builder.undo_to(builder_len);
+                    return Ok(Validator::Invalid);
+                }
+                if new_validator.schema.len() == 0 && (self.schema.len()+other.schema.len() > 0) {
+                    builder.undo_to(builder_len);
+                    return Ok(Validator::Invalid);
+                }
+                let valid = new_validator.finalize();
+                if !valid {
+                    builder.undo_to(builder_len);
+                    Ok(Validator::Invalid)
+                }
+                else {
+                    Ok(Validator::Hash(new_validator))
                 }
             },
             Validator::Valid => {
@@ -293,3 +316,106 @@ impl ValidHash {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use encode;
+    use crypto;
+    use schema::INVALID;
+    use super::*;
+
+    fn read_it(value: Value, types: &mut Vec<Validator>) -> ValidHash {
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &value);
+        let index = Validator::read_validator(&mut &raw[..], false, types, &mut HashMap::new()).unwrap();
+        match types[index] {
+            Validator::Hash(ref v) => v.clone(),
+            ref v => panic!("Expected a hash validator, got {:?}", v),
+        }
+    }
+
+    #[test]
+    fn accessors() {
+        crypto::init().unwrap();
+        let schema1 = Hash::new(1, b"schema1").unwrap();
+        let schema2 = Hash::new(1, b"schema2").unwrap();
+        let mut types = vec![Validator::Invalid, Validator::Valid];
+
+        let plain = read_it(msgpack!({ "type": "Hash" }), &mut types);
+        assert!(!plain.requires_schema());
+        assert!(plain.allowed_schemas().is_empty());
+        assert_eq!(plain.link_validator_index(), None);
+
+        let schemas = read_it(msgpack!({ "type": "Hash", "schema": [schema1.clone(), schema2.clone()] }), &mut types);
+        assert!(schemas.requires_schema());
+        assert_eq!(schemas.allowed_schemas().len(), 2);
+        assert!(schemas.allowed_schemas().contains(&schema1));
+        assert!(schemas.allowed_schemas().contains(&schema2));
+
+        let linked = read_it(msgpack!({
+            "type": "Hash",
+            "schema": schema1.clone(),
+            "link": { "type": "Obj", "req": { "title": { "type": "Str" } } }
+        }), &mut types);
+        assert_eq!(linked.allowed_schemas(), &[schema1][..]);
+        let link = linked.link_validator_index().unwrap();
+        match types[link] {
+            Validator::Object(_) => (),
+            ref v => panic!("Link should be an object validator, got {:?}", v),
+        }
+    }
+
+    #[test]
+    fn in_nin() {
+        crypto::init().unwrap();
+        let hashes: Vec<Hash> = (0u8..4).map(|i| Hash::new(1, &[i]).unwrap()).collect();
+        let check = |v: &ValidHash, h: &Hash| {
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &Value::from(h.clone()));
+            v.validate("", &mut &raw[..]).is_ok()
+        };
+        let mut types = vec![Validator::Invalid, Validator::Valid];
+
+        // A single hash or an array of them
+        let single = read_it(msgpack!({ "type": "Hash", "in": hashes[0].clone() }), &mut types);
+        assert!(check(&single, &hashes[0]));
+        assert!(!check(&single, &hashes[1]));
+        let list = read_it(msgpack!({ "type": "Hash", "in": [hashes[2].clone(), hashes[0].clone(), hashes[1].clone()] }), &mut types);
+        assert!(check(&list, &hashes[0]));
+        assert!(check(&list, &hashes[1]));
+        assert!(check(&list, &hashes[2]));
+        assert!(!check(&list, &hashes[3]));
+
+        // `nin` removes hashes from the `in` list, and can empty it
+        let both = read_it(msgpack!({
+            "type": "Hash",
+            "in": [hashes[0].clone(), hashes[1].clone()],
+            "nin": hashes[1].clone()
+        }), &mut types);
+        assert!(check(&both, &hashes[0]));
+        assert!(!check(&both, &hashes[1]));
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &msgpack!({ "type": "Hash", "in": hashes[1].clone(), "nin": [hashes[1].clone()] }));
+        assert_eq!(INVALID, Validator::read_validator(&mut &raw[..], false, &mut types, &mut HashMap::new()).unwrap());
+
+        let constant = ValidHash::from_const(hashes[3].clone(), false);
+        assert!(check(&constant, &hashes[3]));
+        assert!(!check(&constant, &hashes[0]));
+
+        // Intersections keep only hashes on both `in` lists
+        let types2 = vec![Validator::Invalid, Validator::Valid, Validator::Hash(list.clone()), Validator::Hash(both.clone())];
+        let mut builder = ValidBuilder::init(&types2, &types2);
+        let index = builder.intersect(false, 2, 3).unwrap();
+        let types3 = builder.build();
+        match types3[index] {
+            Validator::Hash(ref v) => {
+                assert!(check(v, &hashes[0]));
+                assert!(!check(v, &hashes[1]));
+                assert!(!check(v, &hashes[2]));
+            },
+            ref v => panic!("Expected a hash validator, got {:?}", v),
+        }
+        let types2 = vec![Validator::Invalid, Validator::Valid, Validator::Hash(single), Validator::Hash(constant)];
+        let mut builder = ValidBuilder::init(&types2, &types2);
+        assert_eq!(INVALID, builder.intersect(false, 2, 3).unwrap());
+    }
+}
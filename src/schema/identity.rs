@@ -6,6 +6,7 @@ use decode::*;
 use super::{MAX_VEC_RESERVE, Validator};
 use marker::MarkerType;
 use crypto::Identity;
+use value::Value;
 
 /// Identity type validator
 #[derive(Clone, Debug)]
@@ -16,20 +17,17 @@ pub struct ValidIdentity {
 }
 
 impl ValidIdentity {
-    pub fn new(is_query: bool) -> ValidIdentity {
-        ValidIdentity {
-            in_vec: Vec::with_capacity(0),
-            nin_vec: Vec::with_capacity(0),
-            query: is_query,
-        }
-    }
+    impl_validator_new!(ValidIdentity, |is_query| {
+        in_vec: Vec::with_capacity(0),
+        nin_vec: Vec::with_capacity(0),
+        query: is_query,
+    });
+
+    impl_validator_from_const!(ValidIdentity, Identity);
 
-    pub fn from_const(constant: Identity, is_query: bool) -> ValidIdentity {
-        let mut v = ValidIdentity::new(is_query);
-        let mut in_vec = Vec::with_capacity(1);
-        in_vec.push(constant);
-        v.in_vec = in_vec;
-        v
+    /// Human-readable name of this validator type, for use in error messages.
+    pub fn type_name() -> &'static str {
+        "Identity"
     }
 
     /// Update the validator. Returns `Ok(true)` if everything is read out Ok, `Ok(false)` if we 
@@ -58,7 +56,7 @@ impl ValidIdentity {
                         };
                     },
                     _ => {
-                        return Err(Error::new(InvalidData, "Identity validator expected array or constant for `in` field"));
+                        return Err(Error::new(InvalidData, format!("{} validator expected array or constant for `in` field", Self::type_name())));
                     },
                 }
                 Ok(true)
@@ -77,7 +75,7 @@ impl ValidIdentity {
                         };
                     },
                     _ => {
-                        return Err(Error::new(InvalidData, "Identity validator expected array or constant for `nin` field"));
+                        return Err(Error::new(InvalidData, format!("{} validator expected array or constant for `nin` field", Self::type_name())));
                     },
                 }
                 Ok(true)
@@ -87,7 +85,7 @@ impl ValidIdentity {
                 Ok(true)
             }
             "type" => if "Ident" == read_str(raw)? { Ok(true) } else { Err(Error::new(InvalidData, "Type doesn't match Ident")) },
-            _ => Err(Error::new(InvalidData, "Unknown fields not allowed in Identity validator")),
+            _ => Err(Error::new(InvalidData, format!("Unknown fields not allowed in {} validator", Self::type_name()))),
         }
     }
 
@@ -112,6 +110,12 @@ impl ValidIdentity {
         }
     }
 
+    /// Generate an example value that passes this validator. Only possible if there is an `in` 
+    /// list to take it from.
+    pub fn example_value(&self) -> Option<Value> {
+        self.in_vec.first().map(|v| Value::from(v.clone()))
+    }
+
     pub fn validate(&self, field: &str, doc: &mut &[u8]) -> io::Result<()> {
         let value = read_id(doc)?;
         if self.nin_vec.contains(&value) {
@@ -127,50 +131,49 @@ impl ValidIdentity {
         }
     }
 
+    /// Checks if `other` only uses query operations this Identity validator allows. If not, 
+    /// `intersect` fails when `query` is true.
+    pub fn query_ok(&self, _other: &Validator) -> bool {
+        self.query
+    }
+
     /// Intersection of Identity with other Validators. Returns Err only if `query` is true and the 
     /// other validator contains non-allowed query parameters.
     pub fn intersect(&self, other: &Validator, query: bool) -> Result<Validator, ()> {
-        if query && !self.query { return Err(()); }
+        if query && !self.query_ok(other) { return Err(()); }
         match other {
             Validator::Identity(other) => {
-                if query && (
-                    (!self.query && (!other.in_vec.is_empty() || !other.nin_vec.is_empty())))
-                {
-                    Err(())
-                }
-                else {
-                    let in_vec = if (self.in_vec.len() > 0) && (other.in_vec.len() > 0) {
-                        let mut vec: Vec<Identity> = Vec::new();
-                        for val in other.in_vec.iter() {
-                            if self.in_vec.contains(&val) {
-                                vec.push(val.clone());
-                            }
+                let in_vec = if (self.in_vec.len() > 0) && (other.in_vec.len() > 0) {
+                    let mut vec: Vec<Identity> = Vec::new();
+                    for val in other.in_vec.iter() {
+                        if self.in_vec.contains(&val) {
+                            vec.push(val.clone());
                         }
-                        vec
-                    }
-                    else if self.in_vec.len() > 0 {
-                        self.in_vec.clone()
-                    }
-                    else {
-                        other.in_vec.clone()
-                    };
-                    let mut nin_vec = self.nin_vec.clone();
-                    nin_vec.extend_from_slice(&other.nin_vec[..]);
-                    let mut new_validator = ValidIdentity {
-                        in_vec: in_vec,
-                        nin_vec: nin_vec,
-                        query: self.query && other.query,
-                    };
-                    if new_validator.in_vec.len() == 0 && (self.in_vec.len()+other.in_vec.len() > 0) {
-                        return Ok(Validator::Invalid);
-                    }
-                    let valid = new_validator.finalize();
-                    if !valid {
-                        Ok(Validator::Invalid)
-                    }
-                    else {
-                        Ok(Validator::Identity(new_validator))
                     }
+                    vec
+                }
+                else if self.in_vec.len() > 0 {
+                    self.in_vec.clone()
+                }
+                else {
+                    other.in_vec.clone()
+                };
+                let mut nin_vec = self.nin_vec.clone();
+                nin_vec.extend_from_slice(&other.nin_vec[..]);
+                let mut new_validator = ValidIdentity {
+                    in_vec: in_vec,
+                    nin_vec: nin_vec,
+                    query: self.query && other.query,
+                };
+                if new_validator.in_vec.len() == 0 && (self.in_vec.len()+other.in_vec.len() > 0) {
+                    return Ok(Validator::Invalid);
+                }
+                let valid = new_validator.finalize();
+                if !valid {
+                    Ok(Validator::Invalid)
+                }
+                else {
+                    Ok(Validator::Identity(new_validator))
                 }
             },
             Validator::Valid => Ok(Validator::Identity(self.clone())),
@@ -2,8 +2,9 @@ use std::io;
 use std::io::Error;
 use std::io::ErrorKind::InvalidData;
 use decode::*;
-use super::{MAX_VEC_RESERVE, sorted_union, sorted_intersection, Validator};
+use super::{MAX_VEC_RESERVE, sorted_union, sorted_intersection, lcm, Validator};
 use integer::Integer;
+use value::Value;
 use marker::MarkerType;
 
 /// Integer type validator
@@ -15,6 +16,11 @@ pub struct ValidInt {
     max: Integer,
     bit_set: u64,
     bit_clear: u64,
+    /// Bounds on the number of bits set in a value's 64-bit representation.
+    count_ones_min: u32,
+    count_ones_max: u32,
+    /// Values must be a multiple of this. 1 allows any value.
+    multiple_of: u64,
     query: bool,
     ord: bool,
     bit: bool,
@@ -23,28 +29,28 @@ pub struct ValidInt {
 }
 
 impl ValidInt {
-    pub fn new(is_query: bool) -> ValidInt {
-        ValidInt {
-            in_vec: Vec::with_capacity(0),
-            nin_vec: Vec::with_capacity(0),
-            min: Integer::min_value(),
-            max: Integer::max_value(),
-            bit_set: 0,
-            bit_clear: 0,
-            query: is_query,
-            ord: is_query,
-            bit: is_query,
-            ex_min: false,
-            ex_max: false,
-        }
-    }
+    impl_validator_new!(ValidInt, |is_query| {
+        in_vec: Vec::with_capacity(0),
+        nin_vec: Vec::with_capacity(0),
+        min: Integer::min_value(),
+        max: Integer::max_value(),
+        bit_set: 0,
+        bit_clear: 0,
+        count_ones_min: 0,
+        count_ones_max: 64,
+        multiple_of: 1,
+        query: is_query,
+        ord: is_query,
+        bit: is_query,
+        ex_min: false,
+        ex_max: false,
+    });
+
+    impl_validator_from_const!(ValidInt, Integer);
 
-    pub fn from_const(constant: Integer, is_query: bool) -> ValidInt {
-        let mut v = ValidInt::new(is_query);
-        let mut in_vec = Vec::with_capacity(1);
-        in_vec.push(constant);
-        v.in_vec = in_vec;
-        v
+    /// Human-readable name of this validator type, for use in error messages.
+    pub fn type_name() -> &'static str {
+        "Integer"
     }
 
     /// Update the validator. Returns `Ok(true)` if everything is read out Ok, `Ok(false)` if we 
@@ -102,7 +108,7 @@ impl ValidInt {
                         self.in_vec.dedup();
                     },
                     _ => {
-                        return Err(Error::new(InvalidData, "Integer validator expected array or constant for `in` field"));
+                        return Err(Error::new(InvalidData, format!("{} validator expected array or constant for `in` field", Self::type_name())));
                     },
                 }
                 Ok(true)
@@ -117,6 +123,15 @@ impl ValidInt {
                     Ok(true)
                 }
             }
+            "max_ones" => {
+                match read_integer(raw)?.as_u64() {
+                    Some(max) => {
+                        self.count_ones_max = max.min(64) as u32;
+                        Ok(true)
+                    },
+                    None => Ok(false),
+                }
+            }
             "min" => {
                 let min = read_integer(raw)?;
                 if self.ex_min && min == Integer::max_value() {
@@ -128,6 +143,24 @@ impl ValidInt {
                     Ok(self.min <= self.max)
                 }
             }
+            "min_ones" => {
+                match read_integer(raw)?.as_u64() {
+                    Some(min) if min <= 64 => {
+                        self.count_ones_min = min as u32;
+                        Ok(self.count_ones_min <= self.count_ones_max)
+                    },
+                    _ => Ok(false),
+                }
+            }
+            "multiple_of" => {
+                match read_integer(raw)?.as_u64() {
+                    Some(0) | None => Ok(false),
+                    Some(m) => {
+                        self.multiple_of = m;
+                        Ok(true)
+                    }
+                }
+            }
             "nin" => {
                 match read_marker(raw)? {
                     MarkerType::PosInt((len, v)) => {
@@ -149,7 +182,7 @@ impl ValidInt {
                         self.nin_vec.dedup();
                     },
                     _ => {
-                        return Err(Error::new(InvalidData, "Integer validator expected array or constant for `nin` field"));
+                        return Err(Error::new(InvalidData, format!("{} validator expected array or constant for `nin` field", Self::type_name())));
                     },
                 }
                 Ok(true)
@@ -163,7 +196,7 @@ impl ValidInt {
                 Ok(true)
             }
             "type" => if "Int" == read_str(raw)? { Ok(true) } else { Err(Error::new(InvalidData, "Type doesn't match Int")) },
-            _ => Err(Error::new(InvalidData, "Unknown fields not allowed in Integer validator")),
+            _ => Err(Error::new(InvalidData, format!("Unknown fields not allowed in {} validator", Self::type_name()))),
         }
     }
 
@@ -183,6 +216,8 @@ impl ValidInt {
                 if (*val >= self.min) && (*val <= self.max) 
                     && ((val.as_bits() & self.bit_set) == self.bit_set)
                     && ((val.as_bits() & self.bit_clear) == 0)
+                    && self.ones_ok(val.as_bits())
+                    && is_multiple(val, self.multiple_of)
                 {
                     in_vec.push(*val);
                 }
@@ -197,17 +232,67 @@ impl ValidInt {
             let max = self.max;
             let bit_set = self.bit_set;
             let bit_clear = self.bit_clear;
+            let (ones_min, ones_max) = (self.count_ones_min, self.count_ones_max);
+            let multiple_of = self.multiple_of;
             // Only keep `nin` values that would otherwise pass
             self.nin_vec.retain(|val| {
+                let ones = val.as_bits().count_ones();
                 (*val >= min) && (*val <= max)
                     && ((val.as_bits() & bit_set) == bit_set)
                     && ((val.as_bits() & bit_clear) == 0)
+                    && (ones >= ones_min) && (ones <= ones_max)
+                    && is_multiple(val, multiple_of)
             });
             self.nin_vec.shrink_to_fit();
-            true
+            // The required set & clear bits must leave room for the allowed number of set bits
+            let free = 64 - (bit_set | bit_clear).count_ones();
+            (ones_min <= ones_max) && (bit_set.count_ones() <= ones_max)
+                && (bit_set.count_ones() + free >= ones_min)
         }
     }
 
+    /// Checks if the number of set bits is within the `min_ones` and `max_ones` bounds.
+    fn ones_ok(&self, bits: u64) -> bool {
+        let ones = bits.count_ones();
+        (ones >= self.count_ones_min) && (ones <= self.count_ones_max)
+    }
+
+    /// Generate a minimal example value that passes this validator: the first allowed value, or 
+    /// the smallest allowed value if there's a lower bound, or zero if there isn't.
+    pub fn example_value(&self) -> Option<Value> {
+        if let Some(v) = self.in_vec.first() {
+            return Some(Value::from(*v));
+        }
+        let mut value = if self.min > Integer::min_value() {
+            self.min
+        }
+        else {
+            Integer::from(0).max(self.min).min(self.max)
+        };
+        if (self.bit_set != 0) && (self.bit_set & self.bit_clear == 0) && (value.as_bits() & self.bit_set != self.bit_set) {
+            value = Integer::from(self.bit_set);
+        }
+        if !self.ones_ok(value.as_bits()) {
+            // Set the lowest bits that aren't required to be clear until there are enough
+            let mut bits = self.bit_set;
+            let mut free = !(self.bit_set | self.bit_clear);
+            while (bits.count_ones() < self.count_ones_min) && (free != 0) {
+                let lowest = free & free.wrapping_neg();
+                bits |= lowest;
+                free &= !lowest;
+            }
+            value = Integer::from(bits);
+        }
+        if !is_multiple(&value, self.multiple_of) {
+            value = next_multiple(&value, self.multiple_of)?;
+        }
+        // Step past anything on the `nin` list. `nin` is sorted, so a single pass is enough.
+        for v in self.nin_vec.iter() {
+            if *v == value && value < self.max { value = value + 1; }
+        }
+        Some(Value::from(value))
+    }
+
     pub fn validate(&self, field: &str, doc: &mut &[u8]) -> io::Result<()> {
         let value = read_integer(doc)?;
         let value_raw = value.as_bits();
@@ -238,31 +323,52 @@ impl ValidInt {
             Err(Error::new(InvalidData,
                 format!("Field \"{}\" is 0x{:X}, but must have cleared bits 0x{:X}", field, value_raw, self.bit_clear)))
         }
+        else if !self.ones_ok(value_raw) {
+            Err(Error::new(InvalidData,
+                format!("Field \"{}\" is 0x{:X}, which has {} bits set, but must have between {} and {}",
+                    field, value_raw, value_raw.count_ones(), self.count_ones_min, self.count_ones_max)))
+        }
+        else if !is_multiple(&value, self.multiple_of) {
+            Err(Error::new(InvalidData,
+                format!("Field \"{}\" is {}, which is not a multiple of {}", field, value, self.multiple_of)))
+        }
         else {
             Ok(())
         }
 
     }
 
+    /// Checks if `other` only uses query operations this Integer validator allows. If not, 
+    /// `intersect` fails when `query` is true.
+    pub fn query_ok(&self, other: &Validator) -> bool {
+        if !self.query && !self.ord && !self.bit { return false; }
+        match other {
+            Validator::Integer(other) => !(
+                (!self.query && (!other.in_vec.is_empty() || !other.nin_vec.is_empty()))
+                || (!self.ord && ((other.min > Integer::min_value()) || (other.max < Integer::max_value())
+                    || (other.multiple_of > 1)))
+                || (!self.bit && ((other.bit_set > 0) || (other.bit_clear > 0)
+                    || (other.count_ones_min > 0) || (other.count_ones_max < 64)))),
+            _ => true,
+        }
+    }
+
     /// Intersection of Integer with other Validators. Returns Err only if `query` is true and the 
     /// other validator contains non-allowed query parameters.
     pub fn intersect(&self, other: &Validator, query: bool) -> Result<Validator, ()> {
-        if query && !self.query && !self.ord && !self.bit { return Err(()); }
+        if query && !self.query_ok(other) { return Err(()); }
         match other {
             Validator::Integer(other) => {
-                if query && (
-                    (!self.query && (!other.in_vec.is_empty() || !other.nin_vec.is_empty()))
-                    || (!self.ord && ((other.min > Integer::min_value()) || (other.max < Integer::max_value())))
-                    || (!self.bit && ((other.bit_set > 0) || (other.bit_clear > 0))))
-                {
-                    Err(())
-                }
-                else if (self.min > other.max) || (self.max < other.min) 
+                if (self.min > other.max) || (self.max < other.min) 
                     || ((self.bit_set & other.bit_clear) != 0) || ((self.bit_clear & other.bit_set) != 0)
                 {
                     Ok(Validator::Invalid)
                 }
                 else {
+                    let multiple_of = match lcm(self.multiple_of, other.multiple_of) {
+                        Some(m) => m,
+                        None => return Ok(Validator::Invalid),
+                    };
                     let in_vec = if (self.in_vec.len() > 0) && (other.in_vec.len() > 0) {
                         sorted_intersection(&self.in_vec[..], &other.in_vec[..], |a,b| a.cmp(b))
                     }
@@ -279,6 +385,9 @@ impl ValidInt {
                         max: self.max.min(other.max),
                         bit_set: self.bit_set | other.bit_set,
                         bit_clear: self.bit_clear | other.bit_clear,
+                        count_ones_min: self.count_ones_min.max(other.count_ones_min),
+                        count_ones_max: self.count_ones_max.min(other.count_ones_max),
+                        multiple_of,
                         query: self.query && other.query,
                         ord: self.ord && other.ord,
                         bit: self.bit && other.bit,
@@ -303,6 +412,33 @@ impl ValidInt {
     }
 }
 
+/// Checks if an integer is a multiple of `m`. Negative integers are handled by value, not by their 
+/// bit pattern.
+fn is_multiple(val: &Integer, m: u64) -> bool {
+    match val.as_u64() {
+        Some(v) => v % m == 0,
+        None => val.as_i64().map_or(false, |v| (v as i128) % (m as i128) == 0),
+    }
+}
+
+/// The smallest multiple of `m` that is greater than `val`, if it can be represented.
+fn next_multiple(val: &Integer, m: u64) -> Option<Integer> {
+    let v = match val.as_u64() {
+        Some(v) => v as i128,
+        None => val.as_i64()? as i128,
+    };
+    let next = v - v.rem_euclid(m as i128) + (m as i128);
+    if next < 0 {
+        Some(Integer::from(next as i64))
+    }
+    else if next <= (u64::max_value() as i128) {
+        Some(Integer::from(next as u64))
+    }
+    else {
+        None
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use encode;
@@ -607,4 +743,117 @@ mod tests {
                 "Set intersection for Integer validators fails with {}", test_val);
         }
     }
+
+    #[test]
+    fn multiple_of() {
+        let passes = |v: &ValidInt, x: Integer| {
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &Value::from(x));
+            v.validate("", &mut &raw[..]).is_ok()
+        };
+        let read = |v: Value| {
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &v);
+            read_it(&mut &raw[..], false).unwrap()
+        };
+
+        let even = read(msgpack!({ "multiple_of": 2 }));
+        for x in -6i64..7 {
+            assert_eq!(passes(&even, Integer::from(x)), x % 2 == 0, "Wrong result for {}", x);
+        }
+        assert!(passes(&even, Integer::max_value() - 1));
+        assert!(!passes(&even, Integer::max_value()));
+        assert!(passes(&even, Integer::min_value()));
+
+        let hundreds = read(msgpack!({ "multiple_of": 100 }));
+        assert!(passes(&hundreds, Integer::from(0)));
+        assert!(passes(&hundreds, Integer::from(300)));
+        assert!(passes(&hundreds, Integer::from(-100)));
+        assert!(!passes(&hundreds, Integer::from(150)));
+        assert!(!passes(&hundreds, Integer::from(-1)));
+
+        // `in` values that aren't multiples are dropped, and the validator fails if none are left
+        let mut in_list = read(msgpack!({ "in": [5, 10, 15], "multiple_of": 10 }));
+        assert_eq!(in_list.in_vec, vec![Integer::from(10)]);
+        in_list.multiple_of = 3;
+        assert!(!in_list.finalize());
+
+        // Intersection uses the least common multiple
+        let six = read(msgpack!({ "multiple_of": 6 }));
+        let four = read(msgpack!({ "multiple_of": 4 }));
+        match six.intersect(&Validator::Integer(four), false).unwrap() {
+            Validator::Integer(v) => {
+                assert_eq!(v.multiple_of, 12);
+                assert!(passes(&v, Integer::from(24)));
+                assert!(!passes(&v, Integer::from(18)));
+                assert_eq!(v.example_value().unwrap().as_i64(), Some(0));
+            },
+            _ => panic!("Intersection should be valid"),
+        }
+        let big1 = read(msgpack!({ "multiple_of": u64::max_value() }));
+        let big2 = read(msgpack!({ "multiple_of": u64::max_value() - 1 }));
+        assert!(match big1.intersect(&Validator::Integer(big2), false).unwrap() {
+            Validator::Invalid => true,
+            _ => false,
+        });
+
+        let odd_min = read(msgpack!({ "min": 3, "multiple_of": 4 }));
+        assert_eq!(odd_min.example_value().unwrap().as_i64(), Some(4));
+    }
+
+    #[test]
+    fn count_ones() {
+        let passes = |v: &ValidInt, x: Integer| {
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &Value::from(x));
+            v.validate("", &mut &raw[..]).is_ok()
+        };
+        let read = |v: Value| {
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &v);
+            let mut validator = ValidInt::new(false);
+            let mut valid = true;
+            if let MarkerType::Object(len) = read_marker(&mut &raw[..1]).unwrap() {
+                object_iterate(&mut &raw[1..], len, |field, raw| {
+                    valid = valid && validator.update(field, raw)?;
+                    Ok(())
+                }).unwrap();
+            }
+            (validator.finalize() && valid, validator)
+        };
+
+        // Exactly 2 bits set in a u8
+        let (valid, two_bits) = read(msgpack!({ "max": 255, "max_ones": 2, "min": 0, "min_ones": 2 }));
+        assert!(valid);
+        for x in 0u64..256 {
+            assert_eq!(passes(&two_bits, Integer::from(x)), x.count_ones() == 2, "Wrong result for {:#b}", x);
+        }
+        assert!(!passes(&two_bits, Integer::from(-1)));
+        let example = Integer::from(two_bits.example_value().unwrap().as_u64().unwrap());
+        assert!(passes(&two_bits, example));
+
+        // Required set & cleared bits can rule out every value
+        assert!(!read(msgpack!({ "bits_set": 7, "max_ones": 2 })).0);
+        assert!(!read(msgpack!({ "bits_clr": (u64::max_value() - 1), "min_ones": 2 })).0);
+        assert!(!read(msgpack!({ "max_ones": 2, "min_ones": 3 })).0);
+        assert!(!read(msgpack!({ "min_ones": 65 })).0);
+        assert!(read(msgpack!({ "bits_set": 1, "bits_clr": 6, "max_ones": 2, "min_ones": 2 })).0);
+
+        // Intersection keeps the tighter bounds
+        let (_, at_least_one) = read(msgpack!({ "min_ones": 1 }));
+        let (_, at_most_three) = read(msgpack!({ "max_ones": 3 }));
+        match at_least_one.intersect(&Validator::Integer(at_most_three), false).unwrap() {
+            Validator::Integer(v) => {
+                assert!(!passes(&v, Integer::from(0)));
+                assert!(passes(&v, Integer::from(0b111)));
+                assert!(!passes(&v, Integer::from(0b1111)));
+            },
+            _ => panic!("Intersection should be valid"),
+        }
+        let (_, none) = read(msgpack!({ "max_ones": 0 }));
+        assert!(match at_least_one.intersect(&Validator::Integer(none), false).unwrap() {
+            Validator::Invalid => true,
+            _ => false,
+        });
+    }
 }
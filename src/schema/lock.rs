@@ -13,11 +13,14 @@ pub struct ValidLock {
 }
 
 impl ValidLock {
-    pub fn new(is_query: bool) -> ValidLock {
-        ValidLock {
-            max_len: usize::max_value(),
-            query: is_query
-        }
+    impl_validator_new!(ValidLock, |is_query| {
+        max_len: usize::max_value(),
+        query: is_query,
+    });
+
+    /// Human-readable name of this validator type, for use in error messages.
+    pub fn type_name() -> &'static str {
+        "Lockbox"
     }
 
     /// Update the validator. Returns `Ok(true)` if everything is read out Ok, `Ok(false)` if we 
@@ -42,7 +45,7 @@ impl ValidLock {
                 Ok(true)
             }
             "type" => if "Lock" == read_str(raw)? { Ok(true) } else { Err(Error::new(InvalidData, "Type doesn't match Lock")) },
-            _ => Err(Error::new(InvalidData, "Unknown fields not allowed in Lockbox validator")),
+            _ => Err(Error::new(InvalidData, format!("Unknown fields not allowed in {} validator", Self::type_name()))),
         }
     }
 
@@ -63,23 +66,23 @@ impl ValidLock {
         }
     }
 
+    /// Checks if `other` only uses query operations this Lockbox validator allows. If not, 
+    /// `intersect` fails when `query` is true.
+    pub fn query_ok(&self, _other: &Validator) -> bool {
+        self.query
+    }
+
     /// Intersection of Lockbox with other Validators. Returns Err only if `query` is true and the 
     /// other validator contains non-allowed query parameters.
     pub fn intersect(&self, other: &Validator, query: bool) -> Result<Validator, ()> {
-        if query && !self.query { return Err(()); }
+        if query && !self.query_ok(other) { return Err(()); }
         match other {
             Validator::Lockbox(other) => {
-                if query && !self.query && (other.max_len < usize::max_value())
-                {
-                    Err(())
-                }
-                else {
-                    let new_validator = ValidLock {
-                        max_len: self.max_len.min(other.max_len),
-                        query: self.query && other.query,
-                    };
-                    Ok(Validator::Lockbox(new_validator))
-                }
+                let new_validator = ValidLock {
+                    max_len: self.max_len.min(other.max_len),
+                    query: self.query && other.query,
+                };
+                Ok(Validator::Lockbox(new_validator))
             },
             Validator::Valid => Ok(Validator::Lockbox(self.clone())),
             _ => Ok(Validator::Invalid),
@@ -17,18 +17,53 @@
 // place. If it isn't at the very end, then it is referencing another type and is ignored.
 use std::io;
 use std::io::Error;
-use std::io::ErrorKind::{InvalidData,Other};
-use std::collections::HashMap;
+use std::io::ErrorKind::{InvalidData,InvalidInput,Other};
+use std::collections::{HashMap, BTreeMap};
 use std::cmp::Ordering;
 use std::mem;
+use std::cell::RefCell;
+use std::ptr;
+use std::sync::Once;
 
 use byteorder::{ReadBytesExt, BigEndian};
 
 use MarkerType;
 use decode::*;
+use encode;
+use value::{Value, ValueRef};
 use crypto::Hash;
 use document::extract_schema_hash;
 
+/// Generates a validator's `new(is_query: bool) -> Self` constructor from the default value of 
+/// each field. The name given in `|...|` is bound to `is_query` for use in the defaults.
+macro_rules! impl_validator_new {
+    ($name:ident, |$is_query:ident| { $($field:ident: $default:expr),* $(,)* }) => {
+        pub fn new($is_query: bool) -> $name {
+            $name {
+                $($field: $default,)*
+            }
+        }
+    };
+}
+
+/// Generates a validator's `from_const(constant, is_query: bool) -> Self` constructor, for 
+/// validators that only allow the values in their `in_vec`. An optional conversion turns the 
+/// constant into the type stored in `in_vec`.
+macro_rules! impl_validator_from_const {
+    ($name:ident, $const_type:ty) => {
+        impl_validator_from_const!($name, $const_type, |constant| constant);
+    };
+    ($name:ident, $const_type:ty, |$constant:ident| $convert:expr) => {
+        pub fn from_const($constant: $const_type, is_query: bool) -> $name {
+            let mut v = $name::new(is_query);
+            let mut in_vec = Vec::with_capacity(1);
+            in_vec.push($convert);
+            v.in_vec = in_vec;
+            v
+        }
+    };
+}
+
 mod bool;
 mod integer;
 mod float32;
@@ -58,32 +93,173 @@ use self::object::ValidObj;
 use self::multi::ValidMulti;
 
 const MAX_VEC_RESERVE: usize = 2048;
+
+/// Maximum number of fields in any object within a schema, and of validators in any list within a 
+/// schema. Documents aren't limited by this.
+const MAX_SCHEMA_FIELDS: usize = 1024;
+
+/// How deeply nested an example value from `Validator::example_value` may be. Keeps recursive 
+/// types with required self-references from generating forever.
+const MAX_EXAMPLE_DEPTH: usize = 32;
+
+/// Encoded meta-schema that every schema document is checked against after it is parsed. It 
+/// describes the top-level fields of a schema, and is itself a valid schema document. See the 
+/// `meta_schema` test for the `Value` it was encoded from.
+pub const SCHEMA_META_SCHEMA: &[u8] = &[
+    0x83, 0xA4, 0x6E, 0x61, 0x6D, 0x65, 0xB7, 0x43, 0x6F, 0x6E, 0x64, 0x65, 0x6E, 0x73, 0x65, 0x2D,
+    0x64, 0x62, 0x20, 0x4D, 0x65, 0x74, 0x61, 0x2D, 0x53, 0x63, 0x68, 0x65, 0x6D, 0x61, 0xA3, 0x6F,
+    0x70, 0x74, 0xDE, 0x00, 0x13, 0xA0, 0x81, 0xA4, 0x74, 0x79, 0x70, 0x65, 0xA4, 0x48, 0x61, 0x73,
+    0x68, 0xAB, 0x64, 0x65, 0x73, 0x63, 0x72, 0x69, 0x70, 0x74, 0x69, 0x6F, 0x6E, 0x81, 0xA4, 0x74,
+    0x79, 0x70, 0x65, 0xA3, 0x53, 0x74, 0x72, 0xA7, 0x65, 0x6E, 0x74, 0x72, 0x69, 0x65, 0x73, 0x81,
+    0xA4, 0x74, 0x79, 0x70, 0x65, 0xAC, 0x56, 0x61, 0x6C, 0x69, 0x64, 0x61, 0x74, 0x6F, 0x72, 0x4D,
+    0x61, 0x70, 0xAA, 0x66, 0x69, 0x65, 0x6C, 0x64, 0x5F, 0x64, 0x65, 0x70, 0x73, 0x82, 0xAB, 0x65,
+    0x78, 0x74, 0x72, 0x61, 0x5F, 0x69, 0x74, 0x65, 0x6D, 0x73, 0x84, 0xAB, 0x65, 0x78, 0x74, 0x72,
+    0x61, 0x5F, 0x69, 0x74, 0x65, 0x6D, 0x73, 0x81, 0xA4, 0x74, 0x79, 0x70, 0x65, 0xA3, 0x53, 0x74,
+    0x72, 0xA7, 0x6D, 0x61, 0x78, 0x5F, 0x6C, 0x65, 0x6E, 0x02, 0xA7, 0x6D, 0x69, 0x6E, 0x5F, 0x6C,
+    0x65, 0x6E, 0x02, 0xA4, 0x74, 0x79, 0x70, 0x65, 0xA5, 0x41, 0x72, 0x72, 0x61, 0x79, 0xA4, 0x74,
+    0x79, 0x70, 0x65, 0xA5, 0x41, 0x72, 0x72, 0x61, 0x79, 0xAA, 0x66, 0x69, 0x65, 0x6C, 0x64, 0x5F,
+    0x74, 0x79, 0x70, 0x65, 0xC0, 0xAC, 0x69, 0x66, 0x5F, 0x74, 0x68, 0x65, 0x6E, 0x5F, 0x65, 0x6C,
+    0x73, 0x65, 0x82, 0xAB, 0x65, 0x78, 0x74, 0x72, 0x61, 0x5F, 0x69, 0x74, 0x65, 0x6D, 0x73, 0x83,
+    0xA7, 0x6D, 0x61, 0x78, 0x5F, 0x6C, 0x65, 0x6E, 0x03, 0xA7, 0x6D, 0x69, 0x6E, 0x5F, 0x6C, 0x65,
+    0x6E, 0x03, 0xA4, 0x74, 0x79, 0x70, 0x65, 0xA5, 0x41, 0x72, 0x72, 0x61, 0x79, 0xA4, 0x74, 0x79,
+    0x70, 0x65, 0xA5, 0x41, 0x72, 0x72, 0x61, 0x79, 0xA9, 0x6D, 0x61, 0x78, 0x5F, 0x64, 0x65, 0x70,
+    0x74, 0x68, 0x81, 0xA4, 0x74, 0x79, 0x70, 0x65, 0xA5, 0x43, 0x6F, 0x75, 0x6E, 0x74, 0xA9, 0x6D,
+    0x61, 0x78, 0x5F, 0x65, 0x78, 0x74, 0x72, 0x61, 0x81, 0xA4, 0x74, 0x79, 0x70, 0x65, 0xA5, 0x43,
+    0x6F, 0x75, 0x6E, 0x74, 0xAA, 0x6D, 0x61, 0x78, 0x5F, 0x66, 0x69, 0x65, 0x6C, 0x64, 0x73, 0x81,
+    0xA4, 0x74, 0x79, 0x70, 0x65, 0xA5, 0x43, 0x6F, 0x75, 0x6E, 0x74, 0xA9, 0x6D, 0x69, 0x6E, 0x5F,
+    0x65, 0x78, 0x74, 0x72, 0x61, 0x81, 0xA4, 0x74, 0x79, 0x70, 0x65, 0xA5, 0x43, 0x6F, 0x75, 0x6E,
+    0x74, 0xAA, 0x6D, 0x69, 0x6E, 0x5F, 0x66, 0x69, 0x65, 0x6C, 0x64, 0x73, 0x81, 0xA4, 0x74, 0x79,
+    0x70, 0x65, 0xA5, 0x43, 0x6F, 0x75, 0x6E, 0x74, 0xA4, 0x6E, 0x61, 0x6D, 0x65, 0x81, 0xA4, 0x74,
+    0x79, 0x70, 0x65, 0xA3, 0x53, 0x74, 0x72, 0xA3, 0x6F, 0x70, 0x74, 0x81, 0xA4, 0x74, 0x79, 0x70,
+    0x65, 0xAC, 0x56, 0x61, 0x6C, 0x69, 0x64, 0x61, 0x74, 0x6F, 0x72, 0x4D, 0x61, 0x70, 0xA3, 0x72,
+    0x65, 0x71, 0x81, 0xA4, 0x74, 0x79, 0x70, 0x65, 0xAC, 0x56, 0x61, 0x6C, 0x69, 0x64, 0x61, 0x74,
+    0x6F, 0x72, 0x4D, 0x61, 0x70, 0xA6, 0x73, 0x74, 0x72, 0x69, 0x63, 0x74, 0x81, 0xA4, 0x74, 0x79,
+    0x70, 0x65, 0xA4, 0x42, 0x6F, 0x6F, 0x6C, 0xAA, 0x74, 0x72, 0x61, 0x6E, 0x73, 0x66, 0x6F, 0x72,
+    0x6D, 0x73, 0x83, 0xAA, 0x66, 0x69, 0x65, 0x6C, 0x64, 0x5F, 0x74, 0x79, 0x70, 0x65, 0x82, 0xA2,
+    0x69, 0x6E, 0x94, 0xA4, 0x74, 0x72, 0x69, 0x6D, 0xA5, 0x6C, 0x6F, 0x77, 0x65, 0x72, 0xA5, 0x75,
+    0x70, 0x70, 0x65, 0x72, 0xA4, 0x6E, 0x6F, 0x6E, 0x65, 0xA4, 0x74, 0x79, 0x70, 0x65, 0xA3, 0x53,
+    0x74, 0x72, 0xA4, 0x74, 0x79, 0x70, 0x65, 0xA3, 0x4F, 0x62, 0x6A, 0xAA, 0x75, 0x6E, 0x6B, 0x6E,
+    0x6F, 0x77, 0x6E, 0x5F, 0x6F, 0x6B, 0xC3, 0xA5, 0x74, 0x79, 0x70, 0x65, 0x73, 0x81, 0xA4, 0x74,
+    0x79, 0x70, 0x65, 0xAC, 0x56, 0x61, 0x6C, 0x69, 0x64, 0x61, 0x74, 0x6F, 0x72, 0x4D, 0x61, 0x70,
+    0xAA, 0x75, 0x6E, 0x6B, 0x6E, 0x6F, 0x77, 0x6E, 0x5F, 0x6F, 0x6B, 0x81, 0xA4, 0x74, 0x79, 0x70,
+    0x65, 0xA4, 0x42, 0x6F, 0x6F, 0x6C, 0xA7, 0x76, 0x65, 0x72, 0x73, 0x69, 0x6F, 0x6E, 0x83, 0xA3,
+    0x6D, 0x61, 0x78, 0xCE, 0xFF, 0xFF, 0xFF, 0xFF, 0xA3, 0x6D, 0x69, 0x6E, 0x00, 0xA4, 0x74, 0x79,
+    0x70, 0x65, 0xA3, 0x49, 0x6E, 0x74, 0xA5, 0x74, 0x79, 0x70, 0x65, 0x73, 0x82, 0xA5, 0x43, 0x6F,
+    0x75, 0x6E, 0x74, 0x82, 0xA3, 0x6D, 0x69, 0x6E, 0x00, 0xA4, 0x74, 0x79, 0x70, 0x65, 0xA3, 0x49,
+    0x6E, 0x74, 0xAC, 0x56, 0x61, 0x6C, 0x69, 0x64, 0x61, 0x74, 0x6F, 0x72, 0x4D, 0x61, 0x70, 0x82,
+    0xA4, 0x74, 0x79, 0x70, 0x65, 0xA3, 0x4F, 0x62, 0x6A, 0xAA, 0x75, 0x6E, 0x6B, 0x6E, 0x6F, 0x77,
+    0x6E, 0x5F, 0x6F, 0x6B, 0xC3,
+];
 const INVALID: usize = 0;
 const VALID: usize = 1;
 
+/// Where a document failed validation, as reported by `Schema::validate_doc_with_context`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationContext {
+    /// Path to the failing value. Object fields are separated by `.`, and array items are given as 
+    /// `[index]`. Empty if the document as a whole failed.
+    pub field_path: String,
+    /// Offset of the failing value from the start of the document.
+    pub byte_offset: usize,
+    /// The validation error.
+    pub error: String,
+}
+
+/// Tracks where the most recent validation failure occurred, when enabled.
+struct FailTracker {
+    active: bool,
+    /// Address of the failing value, and the path leading to it, innermost first.
+    location: Option<(usize, Vec<String>)>,
+}
+
+thread_local! {
+    static FAIL_TRACKER: RefCell<FailTracker> = RefCell::new(FailTracker { active: false, location: None });
+}
+
+/// Record the result of validating the value starting at `start`. A failure is only recorded if a 
+/// more specific one wasn't already, and a success clears any failure that was recovered from.
+fn track_result<T>(start: &[u8], result: &io::Result<T>) {
+    FAIL_TRACKER.with(|tracker| {
+        let mut tracker = tracker.borrow_mut();
+        if !tracker.active { return; }
+        if result.is_ok() {
+            tracker.location = None;
+        }
+        else if tracker.location.is_none() {
+            tracker.location = Some((start.as_ptr() as usize, Vec::new()));
+        }
+    });
+}
+
+/// Add a path component to the recorded failure as it is passed up through an object or array.
+fn track_path<F: FnOnce() -> String>(component: F) {
+    FAIL_TRACKER.with(|tracker| {
+        if let Some((_, ref mut path)) = tracker.borrow_mut().location {
+            path.push(component());
+        }
+    });
+}
+
+/// Forget the recorded failure, so that the containing validator's own failure is recorded 
+/// instead. Used when failures of contained values were expected and ignored.
+fn clear_failure() {
+    FAIL_TRACKER.with(|tracker| tracker.borrow_mut().location = None);
+}
+
 pub struct Checklist {
-    list: HashMap<Hash, Vec<usize>>
+    list: HashMap<Hash, Vec<usize>>,
+    /// Total number of document checks added so far.
+    count: usize,
+    limit: Option<usize>,
 }
 
 impl Checklist {
     pub fn new() -> Checklist {
-        Checklist { list: HashMap::new() }
+        Checklist { list: HashMap::new(), count: 0, limit: None }
+    }
+
+    /// Create a checklist that fails once more than `max` document checks are added to it. Each 
+    /// reference to a document counts, even if the same document is referenced several times.
+    pub fn with_limit(max: usize) -> Checklist {
+        Checklist { list: HashMap::new(), count: 0, limit: Some(max) }
+    }
+
+    /// Create an empty checklist with whatever room is left under this checklist's limit, for 
+    /// checks that may later be merged into this one.
+    fn remaining(&self) -> Checklist {
+        Checklist { list: HashMap::new(), count: 0, limit: self.limit.map(|l| l.saturating_sub(self.count)) }
     }
 
-    pub fn add(&mut self, hash: Hash, index: usize) {
+    /// Make sure `more` document checks can be added without going over the limit.
+    fn reserve(&mut self, more: usize) -> io::Result<()> {
+        let count = self.count.saturating_add(more);
+        if self.limit.map_or(false, |limit| count > limit) {
+            Err(Error::new(InvalidData, "Too many document references to check"))
+        }
+        else {
+            self.count = count;
+            Ok(())
+        }
+    }
+
+    pub fn add(&mut self, hash: Hash, index: usize) -> io::Result<()> {
+        self.reserve(1)?;
         self.list
             .entry(hash)
             .or_insert(Vec::with_capacity(1))
-            .push(index)
+            .push(index);
+        Ok(())
     }
 
-    pub fn merge(&mut self, mut other: Checklist) {
+    pub fn merge(&mut self, mut other: Checklist) -> io::Result<()> {
+        self.reserve(other.count)?;
         for (hash, mut items) in other.list.drain() {
             self.list
                 .entry(hash)
                 .and_modify(|i| i.append(&mut items))
                 .or_insert(items);
         }
+        Ok(())
     }
 
     pub fn iter(&self) -> ::std::collections::hash_map::Iter<Hash, Vec<usize>> {
@@ -101,6 +277,49 @@ impl Checklist {
     pub fn len(&self) -> usize {
         self.list.len()
     }
+
+    /// Encode the checklist as a msgpack object, mapping each document hash, as a hex string, to 
+    /// an array of the validator indices it must be checked against. Lets a peer with access to 
+    /// the referenced documents finish the validation.
+    pub fn to_msgpack(&self) -> Vec<u8> {
+        let list: BTreeMap<String, Value> = self.list.iter()
+            .map(|(hash, items)| {
+                let items = items.iter().map(|i| Value::from(*i as u64)).collect();
+                (hash.to_hex_string(), Value::Array(items))
+            })
+            .collect();
+        let mut buf = Vec::new();
+        encode::write_value(&mut buf, &Value::Object(list));
+        buf
+    }
+
+    /// Decode a checklist written by `to_msgpack`. The decoded checklist has no limit.
+    pub fn from_msgpack(data: &[u8]) -> io::Result<Checklist> {
+        let mut buf = data;
+        let mut checklist = Checklist::new();
+        let len = match read_marker(&mut buf)? {
+            MarkerType::Object(len) => len,
+            _ => return Err(Error::new(InvalidData, "Checklist isn't an object")),
+        };
+        object_iterate(&mut buf, len, |field, buf| {
+            let hash = Hash::from_hex_string(field)
+                .map_err(|_e| Error::new(InvalidData, "Checklist field isn't a hex-encoded Hash"))?;
+            let items = match read_marker(buf)? {
+                MarkerType::Array(len) => len,
+                _ => return Err(Error::new(InvalidData, "Checklist values must be arrays")),
+            };
+            for _ in 0..items {
+                let index = read_integer(buf)?.as_u64()
+                    .ok_or_else(|| Error::new(InvalidData, "Checklist validator indices must be non-negative"))?;
+                checklist.add(hash.clone(), index as usize)?;
+            }
+            Ok(())
+        })?;
+        if !buf.is_empty() {
+            return Err(Error::new(InvalidData, "Checklist has data after the object"));
+        }
+        Ok(checklist)
+    }
 }
 
 pub struct ValidBuilder<'a> {
@@ -116,7 +335,7 @@ impl <'a> ValidBuilder<'a> {
         ValidBuilder {
             types1,
             types2,
-            dest: Vec::new(),
+            dest: vec![Validator::Invalid, Validator::Valid],
             map1: vec![0; types1.len()],
             map2: vec![0; types2.len()],
         }
@@ -135,10 +354,11 @@ impl <'a> ValidBuilder<'a> {
         else if type1 == 1 {
             // Clone type2 into the new validator list
             if self.map2[type2] == 0 {
-                let v = self.types2[type2].intersect(&Validator::Valid, query, self)?;
-                self.dest.push(v);
-                let new_index = self.dest.len() - 1;
+                // Reserve the slot first, so recursive types can refer back to it
+                let new_index = self.push(Validator::Invalid);
                 self.map2[type2] = new_index;
+                let v = self.types2[type2].intersect(&Validator::Valid, query, self)?;
+                self.dest[new_index] = v;
                 new_index
             }
             else {
@@ -148,10 +368,11 @@ impl <'a> ValidBuilder<'a> {
         else if type2 == 1 {
             // Clone type1 into the new validator list
             if self.map1[type1] == 0 {
-                let v = self.types1[type1].intersect(&Validator::Valid, query, self)?;
-                self.dest.push(v);
-                let new_index = self.dest.len() - 1;
+                // Reserve the slot first, so recursive types can refer back to it
+                let new_index = self.push(Validator::Invalid);
                 self.map1[type1] = new_index;
+                let v = self.types1[type1].intersect(&Validator::Valid, query, self)?;
+                self.dest[new_index] = v;
                 new_index
             }
             else {
@@ -195,13 +416,96 @@ impl <'a> ValidBuilder<'a> {
 /// entry.
 #[derive(Clone, Debug)]
 pub struct Schema {
+    name: String,
+    version: u32,
+    max_depth: Option<usize>,
     object: ValidObj,
     entries: Vec<(String, usize)>,
     types: Vec<Validator>,
+    type_names: HashMap<String, usize>,
+}
+
+/// Get the meta-schema that every schema is checked against, parsing it the first time it's needed. 
+/// Returns `None` if it couldn't be parsed.
+fn meta_schema() -> Option<&'static Schema> {
+    static PARSE: Once = Once::new();
+    static mut META: *const Schema = ptr::null();
+    unsafe {
+        PARSE.call_once(|| {
+            // The meta-schema is parsed without checking it against itself. It's never freed.
+            if let Ok(meta) = Schema::parse(&mut &SCHEMA_META_SCHEMA[..]) {
+                META = Box::into_raw(Box::new(meta));
+            }
+        });
+        META.as_ref()
+    }
+}
+
+/// Checks if a named type's validator was left undefined by the schema.
+fn is_undefined(v: &Validator) -> bool {
+    match v {
+        Validator::Invalid => true,
+        _ => false,
+    }
+}
+
+/// Checks if a type name is one of the built-in validator types.
+fn is_builtin_type(name: &str) -> bool {
+    match name {
+        "Null" | "Bool" | "Int" | "Str" | "F32" | "F64" | "Bin" |
+        "Array" | "Obj" | "Hash" | "Ident" | "Lock" | "Time" | "Multi" => true,
+        _ => false,
+    }
 }
 
 impl Schema {
+    /// Read a schema document. Fails if the document can't be parsed as a schema, or if it 
+    /// doesn't pass the meta-schema check (`SCHEMA_META_SCHEMA`).
     pub fn from_raw(raw: &mut &[u8]) -> io::Result<Schema> {
+        let start = *raw;
+        let schema = Schema::parse(raw)?;
+        let meta = meta_schema().ok_or_else(|| Error::new(Other, "Meta-schema couldn't be read"))?;
+        meta.validate_doc(&mut &start[..(start.len() - raw.len())])
+            .map_err(|e| Error::new(InvalidData, format!("Schema failed meta-schema check: {}", e)))?;
+        Ok(schema)
+    }
+
+    /// Read a schema like `from_raw`, also returning the names of any types the schema refers to 
+    /// without defining, in sorted order. Each can be supplied later with `resolve_type`; until 
+    /// then, nothing passes a validator using it.
+    pub fn from_raw_partial(raw: &mut &[u8]) -> io::Result<(Schema, Vec<String>)> {
+        let schema = Schema::from_raw(raw)?;
+        let unresolved = schema.unresolved_types();
+        Ok((schema, unresolved))
+    }
+
+    /// Names of the types the schema refers to without defining, in sorted order.
+    pub fn unresolved_types(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.type_names.iter()
+            .filter(|(_, index)| (**index > VALID) && is_undefined(&self.types[**index]))
+            .map(|(name, _)| name.clone())
+            .collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Supply the validator for a type the schema refers to without defining. Fails if the name 
+    /// isn't one of the schema's unresolved types. The validator can't refer to other types in 
+    /// the schema.
+    pub fn resolve_type(&mut self, name: &str, validator: Validator) -> io::Result<()> {
+        match self.type_names.get(name) {
+            Some(index) if (*index > VALID) && is_undefined(&self.types[*index]) => {
+                self.register_type(name.to_string(), validator)?;
+                Ok(())
+            },
+            _ => Err(Error::new(InvalidInput, format!("Type `{}` isn't an unresolved type in the schema", name))),
+        }
+    }
+
+    /// Parse a schema document without checking it against the meta-schema.
+    fn parse(raw: &mut &[u8]) -> io::Result<Schema> {
+        let mut name = String::new();
+        let mut version = 0;
         let mut entries = Vec::new();
         let mut types = Vec::with_capacity(2);
         let mut type_names = HashMap::new();
@@ -213,7 +517,7 @@ impl Schema {
             MarkerType::Object(len) => len,
             _ => return Err(Error::new(InvalidData, "Schema wasn't an object")),
         };
-        object_iterate(raw, num_fields, |field, raw| {
+        object_iterate_with_limit(raw, num_fields, MAX_SCHEMA_FIELDS, |field, raw| {
             match field {
                 "" => {
                     read_hash(raw).map_err(|_e| Error::new(InvalidData, "Schema's empty field didn't contain root Schema Hash"))?;
@@ -222,14 +526,17 @@ impl Schema {
                     read_str(raw).map_err(|_e| Error::new(InvalidData, "`description` field didn't contain string"))?;
                 },
                 "name" => {
-                    read_str(raw).map_err(|_e| Error::new(InvalidData, "`name` field didn't contain string"))?;
+                    name = read_str(raw).map_err(|_e| Error::new(InvalidData, "`name` field didn't contain string"))?.to_string();
                 },
                 "version" => {
-                    read_integer(raw).map_err(|_e| Error::new(InvalidData, "`name` field didn't contain integer"))?;
+                    let v = read_integer(raw).map_err(|_e| Error::new(InvalidData, "`version` field didn't contain integer"))?;
+                    version = v.as_u64()
+                        .filter(|v| *v <= (u32::max_value() as u64))
+                        .ok_or(Error::new(InvalidData, "`version` field must be a 32-bit unsigned integer"))? as u32;
                 },
                 "entries" => {
                     if let MarkerType::Object(len) = read_marker(raw)? {
-                        object_iterate(raw, len, |field, raw| {
+                        object_iterate_with_limit(raw, len, MAX_SCHEMA_FIELDS, |field, raw| {
                             let v = Validator::read_validator(raw, false, &mut types, &mut type_names)?;
                             entries.push((field.to_string(), v));
                             Ok(())
@@ -239,27 +546,51 @@ impl Schema {
                         return Err(Error::new(InvalidData, "`entries` field doesn't contain an Object"));
                     }
                 }
-               "field_type" | "max_fields" | "min_fields" | "req" | "opt" | "unknown_ok" => {
-                   object.update(field, raw, false, &mut types, &mut type_names)?;
+               "field_deps" | "field_type" | "if_then_else" | "max_depth" | "max_extra" | "max_fields" | "min_extra"
+                   | "min_fields" | "req" | "opt" | "strict" | "transforms" | "unknown_ok" => {
+                   if !object.update(field, raw, false, &mut types, &mut type_names)? {
+                       return Err(Error::new(InvalidData, format!("Schema's `{}` field means no document can pass", field)));
+                   }
                 },
                 "types" => {
                     if let MarkerType::Object(len) = read_marker(raw)? {
-                        object_iterate(raw, len, |field, raw| {
+                        let mut aliases = Vec::new();
+                        object_iterate_with_limit(raw, len, MAX_SCHEMA_FIELDS, |field, raw| {
                             let v = Validator::read_validator(raw, false, &mut types, &mut type_names)?;
-                            if v == (types.len() - 1) {
-                                let v = types.pop();
-                                match field {
-                                    "Null" | "Bool" | "Int" | "Str" | "F32" | "F64" | "Bin" |
-                                    "Array" | "Obj" | "Hash" | "Ident" | "Lock" | "Time" | "Multi" => (),
-                                    _ => {
-                                        if let Some(index) = type_names.get(field) {
-                                            types[*index] = v.unwrap();
+                            let is_alias = (v <= VALID)
+                                || type_names.iter().any(|(name, index)| (*index == v) && (name != field));
+                            match field {
+                                field if is_builtin_type(field) => {
+                                    if !is_alias && (v == (types.len() - 1)) {
+                                        types.pop();
+                                    }
+                                },
+                                _ => {
+                                    if let Some(index) = type_names.get(field).cloned() {
+                                        if is_alias {
+                                            aliases.push((index, v));
+                                        }
+                                        else if v == (types.len() - 1) {
+                                            types[index] = types.pop().unwrap();
                                         }
                                     }
+                                    else {
+                                        // Not referenced yet, so leave it in place for any later 
+                                        // references to use.
+                                        type_names.insert(field.to_string(), v);
+                                    }
                                 }
                             }
                             Ok(())
                         })?;
+                        // Second pass: types that are aliases of other named types can only be 
+                        // copied once all types have been read. Repeat to follow chains of 
+                        // aliases.
+                        for _ in 0..aliases.len() {
+                            for (index, v) in aliases.iter() {
+                                types[*index] = types[*v].clone();
+                            }
+                        }
                     }
                     else {
                         return Err(Error::new(InvalidData, "`entries` field doesn't contain an Object"));
@@ -273,28 +604,199 @@ impl Schema {
         })?;
 
         Ok(Schema {
+            name,
+            version,
+            max_depth: None,
             object,
             entries,
             types,
+            type_names,
+        })
+    }
+
+    /// Add a named type to the schema after it has been read, returning the new type's validator 
+    /// index. If the schema refers to the name without defining it in `types`, those references 
+    /// now use the new type. Fails if the name is a built-in type or is already defined by the 
+    /// schema. The validator can't refer to other types in the schema.
+    pub fn register_type(&mut self, name: String, validator: Validator) -> io::Result<usize> {
+        if is_builtin_type(&name) {
+            return Err(Error::new(InvalidInput, format!("`{}` is a built-in type name", name)));
+        }
+        match self.type_names.get(&name).cloned() {
+            // References to an undefined type are left as an Invalid validator
+            Some(index) if (index > VALID) && is_undefined(&self.types[index]) => {
+                self.types[index] = validator;
+                Ok(index)
+            },
+            Some(_) => Err(Error::new(InvalidInput, format!("Type `{}` is already defined", name))),
+            None => {
+                self.types.push(validator);
+                self.type_names.insert(name, self.types.len() - 1);
+                Ok(self.types.len() - 1)
+            },
+        }
+    }
+
+    /// Create a schema that only passes documents and entries that pass both `a` and `b`. Entries 
+    /// are kept for names present in both schemas, or all of one schema's entries if the other 
+    /// defines none. The name and version are kept only if both schemas share them. Fails if no 
+    /// document could pass both schemas.
+    pub fn intersect(a: &Schema, b: &Schema) -> Result<Schema, ()> {
+        let mut builder = ValidBuilder::init(&a.types, &b.types);
+        let object = match a.object.intersect(&Validator::Object(b.object.clone()), false, &mut builder)? {
+            Validator::Object(object) => object,
+            _ => return Err(()),
+        };
+        let mut entries = Vec::with_capacity(a.entries.len().max(b.entries.len()));
+        if b.entries.is_empty() {
+            for (name, index) in a.entries.iter() {
+                entries.push((name.clone(), builder.intersect(false, *index, VALID)?));
+            }
+        }
+        else if a.entries.is_empty() {
+            for (name, index) in b.entries.iter() {
+                entries.push((name.clone(), builder.intersect(false, VALID, *index)?));
+            }
+        }
+        else {
+            for (name, index_a) in a.entries.iter() {
+                if let Some((_, index_b)) = b.entries.iter().find(|(b_name, _)| b_name == name) {
+                    entries.push((name.clone(), builder.intersect(false, *index_a, *index_b)?));
+                }
+            }
+        }
+        let same_schema = (a.name == b.name) && (a.version == b.version);
+        let max_depth = match (a.max_depth, b.max_depth) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        Ok(Schema {
+            name: if same_schema { a.name.clone() } else { String::new() },
+            version: if same_schema { a.version } else { 0 },
+            max_depth,
+            object,
+            entries,
+            types: builder.build(),
+            type_names: HashMap::new(),
         })
     }
 
+    /// Name of the schema, or an empty string if it has none.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Version of the schema. Defaults to 0 if the schema doesn't have a `version` field.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Limit how deeply objects may be nested in anything validated by this schema. Any 
+    /// `max_depth` set within the schema still applies if it is lower.
+    pub fn set_max_depth(&mut self, depth: usize) {
+        self.max_depth = Some(depth);
+    }
+
     /// Validates a document against this schema. Does not check the schema field itself.
     pub fn validate_doc(&self, doc: &mut &[u8]) -> io::Result<()> {
         let mut checklist = Checklist::new();
-        self.object.validate("", doc, &self.types, &mut checklist, true).and(Ok(()))
+        object::with_depth_limit(self.max_depth, || {
+            self.object.validate("", doc, &self.types, &mut checklist, true).and(Ok(()))
+        })
+    }
+
+    /// Validates every document in `docs`, without stopping at the first failure. Returns the 
+    /// index and error of each document that failed, in order. An empty result means every 
+    /// document passed.
+    pub fn validate_batch(&self, docs: &[&[u8]]) -> Vec<(usize, io::Error)> {
+        docs.iter()
+            .enumerate()
+            .filter_map(|(i, doc)| self.validate_doc(&mut &doc[..]).err().map(|e| (i, e)))
+            .collect()
+    }
+
+    /// Validates a document against this schema, like `validate_doc`. On failure, also reports 
+    /// the path to the failing value and its offset within `doc`.
+    pub fn validate_doc_with_context(&self, doc: &[u8]) -> Result<(), ValidationContext> {
+        FAIL_TRACKER.with(|tracker| *tracker.borrow_mut() = FailTracker { active: true, location: None });
+        let result = self.validate_doc(&mut &doc[..]);
+        let location = FAIL_TRACKER.with(|tracker| {
+            let mut tracker = tracker.borrow_mut();
+            tracker.active = false;
+            tracker.location.take()
+        });
+        result.map_err(|e| {
+            let (byte_offset, field_path) = match location {
+                Some((addr, path)) => {
+                    let mut field_path = String::new();
+                    for component in path.iter().rev() {
+                        if !field_path.is_empty() && !component.starts_with('[') {
+                            field_path.push('.');
+                        }
+                        field_path.push_str(component);
+                    }
+                    (addr - (doc.as_ptr() as usize), field_path)
+                },
+                None => (0, String::new()),
+            };
+            ValidationContext {
+                field_path,
+                byte_offset,
+                error: e.to_string(),
+            }
+        })
     }
 
     /// Validates a given entry against this schema.
     pub fn validate_entry(&self, entry: &str, doc: &mut &[u8]) -> io::Result<Checklist> {
-        let mut checklist = Checklist::new();
+        self.validate_entry_with_checklist(entry, doc, Checklist::new())
+    }
+
+    /// Validates a given entry against this schema, adding any documents that need to be checked 
+    /// to the provided checklist. Fails if the checklist's limit is exceeded.
+    pub fn validate_entry_with_checklist(&self, entry: &str, doc: &mut &[u8], mut checklist: Checklist)
+        -> io::Result<Checklist>
+    {
         let v = self.entries.binary_search_by(|x| x.0.as_str().cmp(entry));
         if v.is_err() { return Err(Error::new(InvalidData, "Entry field type doesn't exist in schema")); }
         let v = self.entries[v.unwrap()].1;
-        self.types[v].validate("", doc, &self.types, 0, &mut checklist)?;
+        object::with_depth_limit(self.max_depth, || {
+            self.types[v].validate("", doc, &self.types, v, &mut checklist)
+        })?;
         Ok(checklist)
     }
 
+    /// Iterates over the names of all entry types this schema has validators for.
+    pub fn entry_names(&self) -> impl Iterator<Item=&str> {
+        self.entries.iter().map(|x| x.0.as_str())
+    }
+
+    /// Checks if the schema has a validator for the given entry type.
+    pub fn has_entry(&self, name: &str) -> bool {
+        self.entry_validator_index(name).is_some()
+    }
+
+    /// Returns the index of the validator used for the given entry type, if the schema has one.
+    pub fn entry_validator_index(&self, name: &str) -> Option<usize> {
+        self.entries.binary_search_by(|x| x.0.as_str().cmp(name))
+            .ok()
+            .map(|i| self.entries[i].1)
+    }
+
+    /// Iterates over the fields a document is required to have, in order, along with their 
+    /// validators.
+    pub fn required_fields(&self) -> impl Iterator<Item=(&str, &Validator)> {
+        let types = &self.types;
+        self.object.required_fields().map(move |(name, index)| (name, &types[index]))
+    }
+
+    /// Iterates over the fields a document may optionally have, in order, along with their 
+    /// validators.
+    pub fn optional_fields(&self) -> impl Iterator<Item=(&str, &Validator)> {
+        let types = &self.types;
+        self.object.optional_fields().map(move |(name, index)| (name, &types[index]))
+    }
+
     /// Validates a document against a specific Hash Validator. Should be used in conjunction with 
     /// a Checklist returned from `validate_entry` to confirm that all documents referenced in an 
     /// entry meet the schema's criteria.
@@ -303,9 +805,9 @@ impl Schema {
             // Extract schema. Also verifies we are dealing with an Object (an actual document)
             let doc_schema = extract_schema_hash(&doc.clone())?;
             // Check against acceptable schemas
-            if v.schema_required() {
+            if v.requires_schema() {
                 if let Some(hash) = doc_schema {
-                    if !v.schema_in_set(&hash) {
+                    if !v.allowed_schemas().contains(&hash) {
                         return Err(Error::new(InvalidData, "Document uses unrecognized schema"));
                     }
                 }
@@ -313,10 +815,12 @@ impl Schema {
                     return Err(Error::new(InvalidData, "Document doesn't have schema, but needs one"));
                 }
             }
-            if let Some(link) = v.link() {
+            if let Some(link) = v.link_validator_index() {
                 let mut checklist = Checklist::new();
                 if let Validator::Object(ref v) = self.types[link] {
-                    v.validate("", doc, &self.types, &mut checklist, true).and(Ok(()))
+                    object::with_depth_limit(self.max_depth, || {
+                        v.validate("", doc, &self.types, &mut checklist, true).and(Ok(()))
+                    })
                 }
                 else {
                     Err(Error::new(Other, "Can't validate a document against a non-object validator"))
@@ -355,6 +859,29 @@ pub enum Validator {
 }
 
 impl Validator {
+    /// Human-readable name of the validator's type, for use in error messages.
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Validator::Invalid => "Invalid",
+            Validator::Valid => "Valid",
+            Validator::Null => "Null",
+            Validator::Type(_) => "Type",
+            Validator::Boolean(_) => ValidBool::type_name(),
+            Validator::Integer(_) => ValidInt::type_name(),
+            Validator::String(_) => ValidStr::type_name(),
+            Validator::F32(_) => ValidF32::type_name(),
+            Validator::F64(_) => ValidF64::type_name(),
+            Validator::Binary(_) => ValidBin::type_name(),
+            Validator::Array(_) => ValidArray::type_name(),
+            Validator::Object(_) => ValidObj::type_name(),
+            Validator::Hash(_) => ValidHash::type_name(),
+            Validator::Identity(_) => ValidIdentity::type_name(),
+            Validator::Lockbox(_) => ValidLock::type_name(),
+            Validator::Timestamp(_) => ValidTime::type_name(),
+            Validator::Multi(_) => ValidMulti::type_name(),
+        }
+    }
+
     pub fn read_validator(raw: &mut &[u8], is_query: bool, types: &mut Vec<Validator>, type_names: &mut HashMap<String, usize>)
         -> io::Result<usize>
     {
@@ -429,7 +956,7 @@ impl Validator {
                 let mut type_seen = false;
 
                 // Try all of the possible validators on each field
-                object_iterate(raw, len, |field, raw| {
+                object_iterate_with_limit(raw, len, MAX_SCHEMA_FIELDS, |field, raw| {
                     match field {
                         "comment" => {
                             read_str(raw).map_err(|_e| Error::new(InvalidData, "`comment` field didn't contain string"))?;
@@ -553,13 +1080,13 @@ impl Validator {
                         v.push_str(read_str(raw)?);
                         Ok(true)
                     },
-                    _ => Err(Error::new(InvalidData, "Unknown fields not allowed in Type validator")),
+                    _ => Err(Error::new(InvalidData, format!("Unknown fields not allowed in {} validator", self.display_name()))),
                 }
             },
             Validator::Null => {
                 match field {
                     "type" => if "Null" == read_str(raw)? { Ok(true) } else { Err(Error::new(InvalidData, "Type doesn't match Null")) },
-                    _ => Err(Error::new(InvalidData, "Unknown fields not allowed in Null validator")),
+                    _ => Err(Error::new(InvalidData, format!("Unknown fields not allowed in {} validator", self.display_name()))),
                 }
             },
             Validator::Boolean(v) => v.update(field, raw),
@@ -605,10 +1132,50 @@ impl Validator {
     pub fn validate(&self,
                     field: &str,
                     doc: &mut &[u8],
-                    types: &Vec<Validator>,
+                    types: &[Validator],
                     index: usize,
                     list: &mut Checklist,
                     ) -> io::Result<()>
+    {
+        self.validate_value(field, doc, types, index, list, false).and(Ok(()))
+    }
+
+    /// Validates the next value, like `validate`. If `decode` is set, the value is also decoded as 
+    /// it is validated and returned. Objects and arrays are built up from their contents as each 
+    /// one passes, so a failing value is never fully decoded.
+    pub fn validate_value<'a>(&self,
+                              field: &str,
+                              doc: &mut &'a [u8],
+                              types: &[Validator],
+                              index: usize,
+                              list: &mut Checklist,
+                              decode: bool,
+                              ) -> io::Result<Option<ValueRef<'a>>>
+    {
+        let start = *doc;
+        let result = match self {
+            Validator::Valid if decode => read_value_ref(doc).map(Some),
+            Validator::Array(v) => v.validate_value(field, doc, types, list, decode),
+            Validator::Object(v) => v.validate_value(field, doc, types, list, false, decode),
+            Validator::Multi(v) => v.validate_value(field, doc, types, list, decode),
+            _ => self.validate_scalar(field, doc, index, list).and_then(|()| {
+                if decode {
+                    // Only the bytes the validator just read are decoded
+                    let (value, _) = start.split_at(start.len()-doc.len());
+                    read_value_ref(&mut &value[..]).map(Some)
+                }
+                else {
+                    Ok(None)
+                }
+            }),
+        };
+        track_result(start, &result);
+        result
+    }
+
+    /// Validates any value that isn't an array, object, or multi-type.
+    fn validate_scalar(&self, field: &str, doc: &mut &[u8], index: usize, list: &mut Checklist)
+        -> io::Result<()>
     {
         match self {
             Validator::Invalid => Err(Error::new(InvalidData, format!("Field \"{}\" is always invalid", field))),
@@ -627,18 +1194,17 @@ impl Validator {
             Validator::F32(v) => v.validate(field, doc),
             Validator::F64(v) => v.validate(field, doc),
             Validator::Binary(v) => v.validate(field, doc),
-            Validator::Array(v) => v.validate(field, doc, types, list),
-            Validator::Object(v) => v.validate(field, doc, types, list, false),
             Validator::Hash(v) => {
                 if let Some(hash) = v.validate(field, doc)? {
-                    list.add(hash, index);
+                    list.add(hash, index)?;
                 }
                 Ok(())
             },
             Validator::Identity(v) => v.validate(field, doc),
             Validator::Lockbox(v) => v.validate(field, doc),
             Validator::Timestamp(v) => v.validate(field, doc),
-            Validator::Multi(v) => v.validate(field, doc, types, list),
+            Validator::Array(_) | Validator::Object(_) | Validator::Multi(_) =>
+                Err(Error::new(Other, "Should never be validating a container as a scalar")),
         }
     }
 
@@ -687,6 +1253,86 @@ impl Validator {
             Validator::Multi(v) => v.intersect(other, query, builder),
         }
     }
+
+    /// Checks if `query` only uses query operations this validator allows, without computing the 
+    /// intersection. Only the top-level settings of both validators are compared, so validators 
+    /// nested within arrays, objects, and Multi validators may still fail the full intersection 
+    /// with `intersect`.
+    pub fn is_query_compatible(&self, query: &Validator) -> bool {
+        match self {
+            Validator::Invalid => true,
+            Validator::Valid => false,
+            Validator::Null => true,
+            Validator::Type(_) => false,
+            Validator::Boolean(v) => v.query_ok(query),
+            Validator::Integer(v) => v.query_ok(query),
+            Validator::String(v) => v.query_ok(query),
+            Validator::F32(v) => v.query_ok(query),
+            Validator::F64(v) => v.query_ok(query),
+            Validator::Binary(v) => v.query_ok(query),
+            Validator::Array(v) => v.query_ok(query),
+            Validator::Object(v) => v.query_ok(query),
+            Validator::Hash(v) => v.query_ok(query),
+            Validator::Identity(v) => v.query_ok(query),
+            Validator::Lockbox(v) => v.query_ok(query),
+            Validator::Timestamp(v) => v.query_ok(query),
+            Validator::Multi(_) => true,
+        }
+    }
+
+    /// Generate a minimal example value that passes this validator, for documentation, testing, 
+    /// or building forms from a schema. `types` is the list of validators this one was read 
+    /// alongside. Returns `None` if no example could be found, which is always the case for 
+    /// `Invalid` and `Lockbox` validators.
+    pub fn example_value(&self, types: &[Validator]) -> Option<Value> {
+        self.example(types, MAX_EXAMPLE_DEPTH)
+    }
+
+    fn example(&self, types: &[Validator], depth: usize) -> Option<Value> {
+        if depth == 0 { return None; }
+        let depth = depth - 1;
+        let example = match self {
+            Validator::Invalid => None,
+            Validator::Valid => Some(Value::Null),
+            Validator::Null => Some(Value::Null),
+            Validator::Type(_) => None,
+            Validator::Boolean(v) => v.example_value(),
+            Validator::Integer(v) => v.example_value(),
+            Validator::String(v) => v.example_value(),
+            Validator::F32(v) => v.example_value(),
+            Validator::F64(v) => v.example_value(),
+            Validator::Binary(v) => v.example_value(),
+            Validator::Array(v) => v.example_value(types, depth),
+            Validator::Object(v) => v.example_value(types, depth),
+            Validator::Hash(v) => v.example_value(),
+            Validator::Identity(v) => v.example_value(),
+            Validator::Lockbox(_) => None, // Can't be made without a key
+            Validator::Timestamp(v) => v.example_value(),
+            Validator::Multi(v) => v.example_value(types, depth),
+        }?;
+        // The examples don't account for every combination of constraints, so only return ones 
+        // that actually pass.
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &example);
+        match self.validate("", &mut &raw[..], types, 0, &mut Checklist::new()) {
+            Ok(()) => Some(example),
+            Err(_) => None,
+        }
+    }
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        let t = a % b;
+        a = b;
+        b = t;
+    }
+    a
+}
+
+/// Least common multiple of two non-zero values, or `None` if it doesn't fit in a u64.
+fn lcm(a: u64, b: u64) -> Option<u64> {
+    (a / gcd(a, b)).checked_mul(b)
 }
 
 /// Returns the union of two slices that have been sorted and deduplicated. The union is also 
@@ -761,6 +1407,8 @@ fn sorted_intersection<T,F>(in1: &[T], in2: &[T], compare: F) -> Vec<T>
 #[cfg(test)]
 mod tests {
     use super::*;
+    use encode;
+    use value::Value;
     use rand::prelude::*;
 
     fn comp(in1: &i8, in2: &i8) -> Ordering {
@@ -850,4 +1498,735 @@ mod tests {
 
         assert!(success, "sorted_intersection did not work for all random vectors");
     }
+
+    #[test]
+    fn intersect_schemas() {
+        let read = |v: Value| {
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &v);
+            Schema::from_raw(&mut &raw[..]).unwrap()
+        };
+        let passes = |schema: &Schema, v: Value| {
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &v);
+            schema.validate_doc(&mut &raw[..]).is_ok()
+        };
+        let entry_passes = |schema: &Schema, entry: &str, v: Value| {
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &v);
+            schema.validate_entry(entry, &mut &raw[..]).is_ok()
+        };
+        let a = read(msgpack!({
+            "name": "post",
+            "req": {
+                "title": { "type": "Str", "max_len": 10 },
+                "score": { "type": "Int", "min": 0 }
+            },
+            "opt": { "body": { "type": "Str" } },
+            "entries": {
+                "comment": { "type": "Str", "max_len": 20 },
+                "vote": { "type": "Int", "min": -1, "max": 1 }
+            }
+        }));
+        let b = read(msgpack!({
+            "name": "post",
+            "req": {
+                "title": { "type": "Str", "min_len": 2 },
+                "score": { "type": "Int", "max": 10 }
+            },
+            "unknown_ok": true,
+            "entries": {
+                "comment": { "type": "Str", "min_len": 1 }
+            }
+        }));
+        let both = Schema::intersect(&a, &b).unwrap();
+        assert_eq!(both.name(), "post");
+        assert!(passes(&both, msgpack!({ "score": 5, "title": "Hello" })));
+        assert!(passes(&both, msgpack!({ "body": "Text", "score": 0, "title": "Hi" })));
+        // Fails `a`
+        assert!(!passes(&both, msgpack!({ "score": 5, "title": "Hello there, world" })));
+        assert!(!passes(&both, msgpack!({ "score": -1, "title": "Hello" })));
+        assert!(!passes(&both, msgpack!({ "body": 1, "score": 5, "title": "Hello" })));
+        // Fails `b`
+        assert!(!passes(&both, msgpack!({ "score": 5, "title": "H" })));
+        assert!(!passes(&both, msgpack!({ "score": 11, "title": "Hello" })));
+
+        // Only entries in both schemas are kept
+        assert_eq!(both.entry_names().collect::<Vec<&str>>(), vec!["comment"]);
+        assert!(entry_passes(&both, "comment", msgpack!("Nice")));
+        assert!(!entry_passes(&both, "comment", msgpack!("")));
+        assert!(!entry_passes(&both, "comment", msgpack!("This comment is far too long")));
+        assert!(!entry_passes(&both, "vote", msgpack!(1)));
+
+        // Entries come from one schema if the other has none
+        let c = read(msgpack!({ "req": { "title": { "type": "Str" } }, "unknown_ok": true }));
+        let with_c = Schema::intersect(&c, &a).unwrap();
+        assert_eq!(with_c.name(), "");
+        assert_eq!(with_c.entry_names().collect::<Vec<&str>>(), vec!["comment", "vote"]);
+        assert!(entry_passes(&with_c, "vote", msgpack!(1)));
+        assert!(!entry_passes(&with_c, "vote", msgpack!(2)));
+
+        // Conflicting requirements can't be intersected
+        let d = read(msgpack!({ "req": { "title": { "type": "Int" } } }));
+        assert!(Schema::intersect(&a, &d).is_err());
+    }
+
+    #[test]
+    fn entry_names() {
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &msgpack!({
+            "entries": {
+                "comment": { "type": "Str", "max_len": 255 },
+                "vote": { "type": "Int", "min": -1, "max": 1 }
+            },
+            "name": "post"
+        }));
+        let schema = Schema::from_raw(&mut &raw[..]).unwrap();
+        let names: Vec<&str> = schema.entry_names().collect();
+        assert_eq!(names, vec!["comment", "vote"]);
+        assert!(schema.has_entry("comment"));
+        assert!(schema.has_entry("vote"));
+        assert!(!schema.has_entry("title"));
+        assert!(schema.entry_validator_index("title").is_none());
+        let index = schema.entry_validator_index("vote").unwrap();
+        assert!(index > VALID);
+        assert!(schema.entry_validator_index("comment").unwrap() != index);
+    }
+
+    #[test]
+    fn field_limit() {
+        use std::collections::BTreeMap;
+        let schema_with_req = |count: usize| {
+            let mut req = BTreeMap::new();
+            for i in 0..count {
+                req.insert(format!("f{}", i), msgpack!({ "type": "Int" }));
+            }
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &msgpack!({ "name": "wide", "req": Value::Object(req) }));
+            Schema::from_raw(&mut &raw[..])
+        };
+        schema_with_req(MAX_SCHEMA_FIELDS).unwrap();
+        assert!(schema_with_req(MAX_SCHEMA_FIELDS + 1).is_err());
+    }
+
+    #[test]
+    fn root_fields() {
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &msgpack!({
+            "name": "person",
+            "opt": {
+                "nickname": { "type": "Str" },
+                "age": { "type": "Int", "min": 0 }
+            },
+            "req": {
+                "name": { "type": "Str" },
+                "id": { "type": "Int" },
+                "email": { "type": "Str", "max_len": 255 }
+            }
+        }));
+        let schema = Schema::from_raw(&mut &raw[..]).unwrap();
+        let required: Vec<&str> = schema.required_fields().map(|(name, _)| name).collect();
+        assert_eq!(required, vec!["email", "id", "name"]);
+        let optional: Vec<&str> = schema.optional_fields().map(|(name, _)| name).collect();
+        assert_eq!(optional, vec!["age", "nickname"]);
+        for (name, v) in schema.required_fields().chain(schema.optional_fields()) {
+            match (name, v) {
+                ("email", Validator::String(_)) | ("name", Validator::String(_))
+                    | ("nickname", Validator::String(_)) => (),
+                ("id", Validator::Integer(_)) | ("age", Validator::Integer(_)) => (),
+                (name, v) => panic!("Unexpected validator for {}: {:?}", name, v),
+            }
+        }
+    }
+
+    #[test]
+    fn display_name() {
+        let validators = vec![
+            (Validator::Invalid, "Invalid"),
+            (Validator::Valid, "Valid"),
+            (Validator::Null, "Null"),
+            (Validator::Type(String::from("thing")), "Type"),
+            (Validator::Boolean(ValidBool::new(false)), "Boolean"),
+            (Validator::Integer(ValidInt::new(false)), "Integer"),
+            (Validator::String(ValidStr::new(false)), "String"),
+            (Validator::F32(ValidF32::new(false)), "F32"),
+            (Validator::F64(ValidF64::new(false)), "F64"),
+            (Validator::Binary(ValidBin::new(false)), "Binary"),
+            (Validator::Array(ValidArray::new(false)), "Array"),
+            (Validator::Object(ValidObj::new(false)), "Object"),
+            (Validator::Hash(ValidHash::new(false)), "Hash"),
+            (Validator::Identity(ValidIdentity::new(false)), "Identity"),
+            (Validator::Lockbox(ValidLock::new(false)), "Lockbox"),
+            (Validator::Timestamp(ValidTime::new(false)), "Timestamp"),
+            (Validator::Multi(ValidMulti::new(false)), "Multi"),
+        ];
+        for (validator, name) in validators.iter() {
+            assert_eq!(validator.display_name(), *name);
+        }
+
+        // Unknown field errors use the same names
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &msgpack!(true));
+        let err = ValidStr::new(false).update("bogus", &mut &raw[..]).unwrap_err();
+        assert_eq!(err.to_string(), "Unknown fields not allowed in String validator");
+    }
+
+    #[test]
+    fn resolve_type() {
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &msgpack!({
+            "name": "sensor reading",
+            "req": {
+                "device": { "type": "DeviceId" },
+                "readings": { "type": "Array", "extra_items": { "type": "Reading" } }
+            },
+            "types": {
+                "Reading": { "type": "F64" }
+            }
+        }));
+        let (mut schema, unresolved) = Schema::from_raw_partial(&mut &raw[..]).unwrap();
+        assert_eq!(unresolved, vec![String::from("DeviceId")]);
+        let doc = |device: Value| {
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &msgpack!({ "device": device, "readings": [1.5f64, 2.5f64] }));
+            raw
+        };
+        assert!(schema.validate_doc(&mut &doc(Value::from("abc"))[..]).is_err());
+
+        // Resolve the external type in a second call
+        let mut types = vec![Validator::Invalid, Validator::Valid];
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &msgpack!({ "type": "Str", "max_len": 8 }));
+        let index = Validator::read_validator(&mut &raw[..], false, &mut types, &mut HashMap::new()).unwrap();
+        assert!(schema.resolve_type("Reading", types[index].clone()).is_err());
+        assert!(schema.resolve_type("Missing", types[index].clone()).is_err());
+        schema.resolve_type("DeviceId", types[index].clone()).unwrap();
+        assert!(schema.unresolved_types().is_empty());
+        assert!(schema.resolve_type("DeviceId", types[index].clone()).is_err());
+        schema.validate_doc(&mut &doc(Value::from("abc"))[..]).unwrap();
+        assert!(schema.validate_doc(&mut &doc(Value::from("too long name"))[..]).is_err());
+    }
+
+    #[test]
+    fn register_type() {
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &msgpack!({
+            "name": "device",
+            "req": {
+                "id": { "type": "UUID" },
+                "name": { "type": "Str" }
+            },
+            "types": {
+                "Serial": { "type": "Int", "min": 0 }
+            }
+        }));
+        let mut schema = Schema::from_raw(&mut &raw[..]).unwrap();
+        let doc = |id: Value| {
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &msgpack!({ "id": id, "name": "sensor" }));
+            raw
+        };
+        // Undefined types can't be satisfied
+        assert!(schema.validate_doc(&mut &doc(Value::from(vec![7u8; 16]))[..]).is_err());
+
+        let mut types = vec![Validator::Invalid, Validator::Valid];
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &msgpack!({ "type": "Bin", "min_len": 16, "max_len": 16 }));
+        let index = Validator::read_validator(&mut &raw[..], false, &mut types, &mut HashMap::new()).unwrap();
+        let uuid_type = types[index].clone();
+
+        let uuid_index = schema.register_type(String::from("UUID"), uuid_type.clone()).unwrap();
+        assert!(uuid_index > VALID);
+        match schema.required_fields().find(|(name, _)| *name == "id") {
+            Some((_, Validator::Binary(_))) => (),
+            _ => panic!("`id` field should use the registered UUID type"),
+        }
+        schema.validate_doc(&mut &doc(Value::from(vec![7u8; 16]))[..]).unwrap();
+        assert!(schema.validate_doc(&mut &doc(Value::from(vec![7u8; 15]))[..]).is_err());
+        assert!(schema.validate_doc(&mut &doc(Value::from("uuid"))[..]).is_err());
+
+        // Names can't be reused, and new names are added at the end
+        assert!(schema.register_type(String::from("UUID"), uuid_type.clone()).is_err());
+        assert!(schema.register_type(String::from("Serial"), uuid_type.clone()).is_err());
+        assert!(schema.register_type(String::from("Bin"), uuid_type.clone()).is_err());
+        let len = schema.types.len();
+        assert_eq!(schema.register_type(String::from("Key"), uuid_type).unwrap(), len);
+    }
+
+    #[test]
+    fn query_compatible() {
+        let mut types = vec![Validator::Invalid, Validator::Valid];
+        let mut type_names = HashMap::new();
+        let mut read = |v: Value, is_query: bool| {
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &v);
+            Validator::read_validator(&mut &raw[..], is_query, &mut types, &mut type_names).unwrap()
+        };
+        let no_ord = read(msgpack!({ "type": "Int", "query": true }), false);
+        let ord = read(msgpack!({ "type": "Int", "ord": true }), false);
+        let range = read(msgpack!({ "type": "Int", "min": 3 }), true);
+        let equal = read(msgpack!({ "type": "Int", "in": [3] }), true);
+        let string = read(msgpack!({ "type": "Str", "max_len": 3 }), true);
+
+        for (schema, query, compatible) in [
+            (no_ord, range, false),
+            (no_ord, equal, true),
+            (ord, range, true),
+            (ord, equal, false),
+            (ord, string, true), // Type mismatches still pass, and intersect to Invalid
+            (VALID, equal, false),
+        ].iter() {
+            assert_eq!(types[*schema].is_query_compatible(&types[*query]), *compatible);
+            let mut builder = ValidBuilder::init(&types, &types);
+            assert_eq!(types[*schema].intersect(&types[*query], true, &mut builder).is_ok(), *compatible);
+        }
+    }
+
+    #[test]
+    fn read_with_schema() {
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &msgpack!({
+            "type": "Obj",
+            "req": {
+                "id": { "type": "Int", "min": 0 },
+                "nested": {
+                    "type": "Array",
+                    "items": [{ "type": "Str" }]
+                }
+            }
+        }));
+        let mut types = vec![Validator::Invalid, Validator::Valid];
+        let mut type_names = HashMap::new();
+        let index = Validator::read_validator(&mut &raw[..], false, &mut types, &mut type_names).unwrap();
+
+        let mut good = Vec::new();
+        encode::write_value(&mut good, &msgpack!({ "id": 1, "nested": ["a"] }));
+        let mut buf = &good[..];
+        let value = read_value_with_schema(&mut buf, &types[index], &types).unwrap();
+        assert_eq!(buf.len(), 0);
+        assert_eq!(value.as_obj().unwrap().get("id").unwrap().as_u64(), Some(1));
+
+        let mut bad = Vec::new();
+        encode::write_value(&mut bad, &msgpack!({ "id": -1, "nested": ["a"] }));
+        assert!(read_value_with_schema(&mut &bad[..], &types[index], &types).is_err());
+        let mut bad = Vec::new();
+        encode::write_value(&mut bad, &msgpack!({ "id": 1, "nested": [1] }));
+        assert!(read_value_with_schema(&mut &bad[..], &types[index], &types).is_err());
+    }
+
+    #[test]
+    fn read_with_schema_matches_decode() {
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &msgpack!({
+            "type": "Obj",
+            "req": {
+                "name": { "type": "Str", "in": ["bob"] },
+                "pair": { "type": "Array", "items": [{ "type": "Str" }, { "type": "Int" }], "any_order": true },
+                "tag": { "type": "Multi", "any_of": [ { "type": "Str" }, { "type": "Int" } ] }
+            },
+            "opt": {
+                "list": { "type": "Array", "extra_items": { "type": "Int" } },
+                "loose": { "type": "Array" }
+            },
+            "transforms": { "name": "trim" },
+            "unknown_ok": true
+        }));
+        let mut types = vec![Validator::Invalid, Validator::Valid];
+        let mut type_names = HashMap::new();
+        let index = Validator::read_validator(&mut &raw[..], false, &mut types, &mut type_names).unwrap();
+
+        let mut good = Vec::new();
+        encode::write_value(&mut good, &msgpack!({
+            "extra": { "a": [1, "b"] },
+            "list": [1, 2, 3],
+            "loose": [true, null],
+            "name": " bob ",
+            "pair": [5, "five"],
+            "tag": 3
+        }));
+        let mut buf = &good[..];
+        let value = read_value_with_schema(&mut buf, &types[index], &types).unwrap();
+        assert_eq!(buf.len(), 0);
+        // The decoded value is the original, not the transformed one
+        let mut encoded = Vec::new();
+        encode::write_value(&mut encoded, &value.to_owned());
+        assert_eq!(encoded, good);
+    }
+
+    #[test]
+    fn type_references() {
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &msgpack!({
+            "name": "Type references",
+            "req": {
+                "start": { "type": "C" }
+            },
+            "types": {
+                "A": { "type": "Obj", "opt": { "c": { "type": "C" } }, "req": { "a": { "type": "Int" } } },
+                "B": { "type": "Obj", "opt": { "a": { "type": "A" } }, "req": { "b": { "type": "Str" } } },
+                "C": { "type": "Obj", "opt": { "b": { "type": "B" }, "d": { "type": "D" } } },
+                "D": { "type": "E" },
+                "E": { "type": "Bool" }
+            }
+        }));
+        let schema = Schema::from_raw(&mut &raw[..]).unwrap();
+
+        let mut doc = Vec::new();
+        encode::write_value(&mut doc, &msgpack!({
+            "start": {
+                "b": {
+                    "a": {
+                        "a": 1,
+                        "c": { "d": true }
+                    },
+                    "b": "B"
+                }
+            }
+        }));
+        schema.validate_doc(&mut &doc[..]).unwrap();
+
+        let mut doc = Vec::new();
+        encode::write_value(&mut doc, &msgpack!({
+            "start": { "b": { "a": { "a": "not an int" }, "b": "B" } }
+        }));
+        assert!(schema.validate_doc(&mut &doc[..]).is_err());
+        let mut doc = Vec::new();
+        encode::write_value(&mut doc, &msgpack!({
+            "start": { "d": 1 }
+        }));
+        assert!(schema.validate_doc(&mut &doc[..]).is_err());
+    }
+
+    #[test]
+    fn example_values() {
+        use timestamp::Timestamp;
+        ::crypto::init().unwrap();
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &msgpack!({
+            "name": "Example test",
+            "req": {
+                "id": { "type": "Int", "min": 1 },
+                "tags": { "type": "Array", "extra_items": { "type": "Str" } }
+            },
+            "entries": {
+                "array": { "type": "Array", "items": [ { "type": "Int", "min": 3 } ], "contains": [ { "type": "Int", "min": 4 } ] },
+                "bin": { "type": "Bin", "min_len": 4, "bits_set": vec![0u8, 0x80], "prefix": vec![1u8] },
+                "bool": true,
+                "f32": { "type": "F32", "min": 1.5f32 },
+                "f64": { "type": "F64", "max": -2.0 },
+                "hash": { "type": "Hash" },
+                "int": { "type": "Int", "min": -5, "nin": [-5, -4] },
+                "multi": { "type": "Multi", "any_of": [ { "type": "Null" }, { "type": "Str", "in": ["b", "a"] } ] },
+                "obj": { "type": "Obj", "req": { "a": { "type": "Null" } },
+                    "opt": { "b": { "type": "Int" }, "c": { "type": "Str" } }, "min_fields": 2 },
+                "str": { "type": "Str", "min_len": 3 },
+                "time": { "type": "Time", "min": Timestamp::from_sec(5) },
+                "bits": { "type": "Int", "bits_set": 6, "bits_clr": 1 },
+                "enum": { "type": "Str", "in": ["z", "y"] },
+                "regex": { "type": "Str", "matches": "^a+$" },
+                "never": { "type": "Int", "min": 2, "max": 1 },
+                "lock": { "type": "Lock" },
+                "sparse": { "type": "Obj", "req": { "a": { "type": "Null" } }, "min_fields": 2 },
+            }
+        }));
+        let schema = Schema::from_raw(&mut &raw[..]).unwrap();
+        let ex = |v: &Validator| {
+            let example = v.example_value(&schema.types)?;
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &example);
+            assert!(v.validate("", &mut &raw[..], &schema.types, 0, &mut Checklist::new()).is_ok());
+            Some(raw)
+        };
+        let encoded = |v: Value| {
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &v);
+            raw
+        };
+        let field = |name: &str| &schema.types[schema.entry_validator_index(name).unwrap()];
+
+        assert_eq!(ex(field("array")).unwrap(), encoded(msgpack!([3, 4])));
+        assert_eq!(ex(field("bin")).unwrap(), encoded(Value::from(vec![1u8, 0x80, 0, 0])));
+        assert_eq!(ex(field("bool")).unwrap(), encoded(Value::from(true)));
+        assert_eq!(ex(field("f32")).unwrap(), encoded(Value::from(1.5f32)));
+        assert_eq!(ex(field("f64")).unwrap(), encoded(Value::from(-2.0f64)));
+        assert_eq!(ex(field("hash")).unwrap(), encoded(Value::from(Hash::new_empty())));
+        assert_eq!(ex(field("int")).unwrap(), encoded(Value::from(-3)));
+        assert_eq!(ex(field("multi")).unwrap(), encoded(Value::Null));
+        assert_eq!(ex(field("obj")).unwrap(), encoded(msgpack!({ "a": null, "b": 0 })));
+        assert_eq!(ex(field("str")).unwrap(), encoded(Value::from("   ")));
+        assert_eq!(ex(field("time")).unwrap(), encoded(Value::from(Timestamp::from_sec(5))));
+        assert_eq!(ex(field("bits")).unwrap(), encoded(Value::from(6)));
+        assert_eq!(ex(field("enum")).unwrap(), encoded(Value::from("y")));
+        assert!(ex(field("regex")).is_none());
+        assert!(ex(field("never")).is_none());
+        assert!(ex(field("lock")).is_none());
+        assert!(ex(field("sparse")).is_none());
+        assert!(ex(&Validator::Invalid).is_none());
+        assert_eq!(ex(&Validator::Valid).unwrap(), encoded(Value::Null));
+
+        // The whole document can be built from the top-level object's example
+        let doc = schema.object.example_value(&schema.types, MAX_EXAMPLE_DEPTH).unwrap();
+        let doc = encoded(doc);
+        assert_eq!(doc, encoded(msgpack!({ "id": 1, "tags": [] })));
+        assert!(schema.validate_doc(&mut &doc[..]).is_ok());
+    }
+
+    #[test]
+    fn validation_context() {
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &msgpack!({
+            "name": "Context test",
+            "req": {
+                "id": { "type": "Int", "min": 0 },
+                "list": {
+                    "type": "Array",
+                    "extra_items": {
+                        "type": "Obj",
+                        "req": { "name": { "type": "Str" } },
+                        "opt": { "tag": { "type": "Multi", "any_of": [ { "type": "Str" }, { "type": "Int" } ] } }
+                    }
+                }
+            }
+        }));
+        let schema = Schema::from_raw(&mut &raw[..]).unwrap();
+        let encode_doc = |v: Value| {
+            let mut doc = Vec::new();
+            encode::write_value(&mut doc, &v);
+            doc
+        };
+        // Find where a byte sequence starts in the document
+        let find = |doc: &[u8], needle: &[u8]| {
+            doc.windows(needle.len()).position(|w| w == needle).unwrap()
+        };
+
+        let doc = encode_doc(msgpack!({ "id": 1, "list": [ { "name": "a", "tag": 1 } ] }));
+        assert!(schema.validate_doc_with_context(&doc).is_ok());
+
+        let doc = encode_doc(msgpack!({ "id": -3, "list": [] }));
+        let context = schema.validate_doc_with_context(&doc).unwrap_err();
+        assert_eq!(context.field_path, "id");
+        assert_eq!(context.byte_offset, find(&doc, b"\xa2id") + 3);
+        assert_eq!(doc[context.byte_offset], 0xfd); // -3 as a negative fixint
+
+        let doc = encode_doc(msgpack!({ "id": 1, "list": [ { "name": "a", "tag": "b" }, { "name": 5 } ] }));
+        let context = schema.validate_doc_with_context(&doc).unwrap_err();
+        assert_eq!(context.field_path, "list[1].name");
+        assert_eq!(context.byte_offset, find(&doc, b"\xa4name\x05") + 5);
+        assert!(schema.validate_doc(&mut &doc[..]).unwrap_err().to_string() == context.error);
+
+        let doc = encode_doc(msgpack!({ "id": 1, "list": [ { "name": "a", "tag": true } ] }));
+        let context = schema.validate_doc_with_context(&doc).unwrap_err();
+        assert_eq!(context.field_path, "list[0].tag");
+        assert_eq!(context.byte_offset, find(&doc, b"\xa3tag\xc3") + 4);
+
+        let doc = encode_doc(msgpack!({ "id": 1 }));
+        let context = schema.validate_doc_with_context(&doc).unwrap_err();
+        assert_eq!(context.field_path, "");
+        assert_eq!(context.byte_offset, 0);
+    }
+
+    #[test]
+    fn validate_batch() {
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &msgpack!({
+            "name": "Batch test",
+            "req": { "id": { "type": "Int", "min": 0 } }
+        }));
+        let schema = Schema::from_raw(&mut &raw[..]).unwrap();
+        let docs: Vec<Vec<u8>> = (0..10).map(|i| {
+            let mut doc = Vec::new();
+            match i {
+                2 => encode::write_value(&mut doc, &msgpack!({ "id": -1 })),
+                5 => encode::write_value(&mut doc, &msgpack!({ "id": "5" })),
+                9 => encode::write_value(&mut doc, &msgpack!({ "name": 9 })),
+                _ => encode::write_value(&mut doc, &msgpack!({ "id": i })),
+            };
+            doc
+        }).collect();
+        let batch: Vec<&[u8]> = docs.iter().map(|doc| &doc[..]).collect();
+
+        let errors = schema.validate_batch(&batch);
+        assert_eq!(errors.iter().map(|e| e.0).collect::<Vec<usize>>(), vec![2, 5, 9]);
+        for (i, e) in errors.iter() {
+            assert_eq!(e.to_string(), schema.validate_doc(&mut &docs[*i][..]).unwrap_err().to_string());
+        }
+        assert!(schema.validate_batch(&batch[..2]).is_empty());
+        assert!(schema.validate_batch(&[]).is_empty());
+    }
+
+    #[test]
+    fn checklist_limit() {
+        ::crypto::init().unwrap();
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &msgpack!({
+            "name": "Checklist limit",
+            "entries": {
+                "refs": {
+                    "type": "Array",
+                    "extra_items": {
+                        "type": "Multi",
+                        "any_of": [ { "type": "Hash", "link": { "type": "Obj", "unknown_ok": true } } ]
+                    }
+                }
+            }
+        }));
+        let schema = Schema::from_raw(&mut &raw[..]).unwrap();
+        let entry = |n: usize| {
+            let refs: Vec<Value> = (0..n).map(|i| Value::from(Hash::new(1, &[i as u8]).unwrap())).collect();
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &Value::from(refs));
+            raw
+        };
+        let list = schema.validate_entry_with_checklist("refs", &mut &entry(3)[..], Checklist::with_limit(3)).unwrap();
+        assert_eq!(list.len(), 3);
+        assert!(schema.validate_entry_with_checklist("refs", &mut &entry(4)[..], Checklist::with_limit(3)).is_err());
+        assert_eq!(schema.validate_entry("refs", &mut &entry(4)[..]).unwrap().len(), 4);
+
+        let mut list = Checklist::with_limit(2);
+        let hash = Hash::new_empty();
+        list.add(hash.clone(), 2).unwrap();
+        list.add(hash.clone(), 3).unwrap();
+        assert!(list.add(hash.clone(), 4).is_err());
+        assert_eq!(list.get_list(&hash).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn checklist_msgpack() {
+        ::crypto::init().unwrap();
+        let mut list = Checklist::new();
+        for i in 0..5u8 {
+            let hash = Hash::new(1, &[i]).unwrap();
+            list.add(hash.clone(), 2 + i as usize).unwrap();
+            if i % 2 == 0 { list.add(hash, 100).unwrap(); }
+        }
+        let raw = list.to_msgpack();
+        let decoded = Checklist::from_msgpack(&raw[..]).unwrap();
+        assert_eq!(decoded.len(), 5);
+        for (hash, items) in list.iter() {
+            assert_eq!(decoded.get_list(hash), Some(items));
+        }
+        assert_eq!(decoded.to_msgpack(), raw);
+
+        let encode_value = |value: Value| {
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &value);
+            raw
+        };
+        let hex = Hash::new_empty().to_hex_string();
+        assert!(Checklist::from_msgpack(&encode_value(msgpack!([1, 2]))[..]).is_err());
+        assert!(Checklist::from_msgpack(&encode_value(msgpack!({ "not a hash": [1] }))[..]).is_err());
+        let mut bad = BTreeMap::new();
+        bad.insert(hex.clone(), msgpack!([-1]));
+        assert!(Checklist::from_msgpack(&encode_value(Value::Object(bad))[..]).is_err());
+        let mut bad = BTreeMap::new();
+        bad.insert(hex, msgpack!(1));
+        assert!(Checklist::from_msgpack(&encode_value(Value::Object(bad))[..]).is_err());
+        let mut trailing = Checklist::new().to_msgpack();
+        trailing.push(0);
+        assert!(Checklist::from_msgpack(&trailing[..]).is_err());
+    }
+
+    fn meta_schema_value() -> Value {
+        msgpack!({
+            "name": "Condense-db Meta-Schema",
+            "opt": {
+                "": { "type": "Hash" },
+                "description": { "type": "Str" },
+                "entries": { "type": "ValidatorMap" },
+                "field_deps": { "type": "Array", "extra_items": { "type": "Array", "min_len": 2, "max_len": 2, "extra_items": { "type": "Str" } } },
+                "field_type": null,
+                "if_then_else": { "type": "Array", "extra_items": { "type": "Array", "min_len": 3, "max_len": 3 } },
+                "max_depth": { "type": "Count" },
+                "max_extra": { "type": "Count" },
+                "max_fields": { "type": "Count" },
+                "min_extra": { "type": "Count" },
+                "min_fields": { "type": "Count" },
+                "name": { "type": "Str" },
+                "opt": { "type": "ValidatorMap" },
+                "req": { "type": "ValidatorMap" },
+                "strict": { "type": "Bool" },
+                "transforms": { "type": "Obj", "unknown_ok": true, "field_type": { "type": "Str", "in": ["trim", "lower", "upper", "none"] } },
+                "types": { "type": "ValidatorMap" },
+                "unknown_ok": { "type": "Bool" },
+                "version": { "type": "Int", "min": 0, "max": 4294967295u32 }
+            },
+            "types": {
+                "Count": { "type": "Int", "min": 0 },
+                "ValidatorMap": { "type": "Obj", "unknown_ok": true }
+            }
+        })
+    }
+
+    #[test]
+    fn meta_schema() {
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &meta_schema_value());
+        assert_eq!(&raw[..], SCHEMA_META_SCHEMA);
+        // The meta-schema passes its own check
+        Schema::from_raw(&mut &SCHEMA_META_SCHEMA[..]).unwrap();
+
+        let check = |v: Value| {
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &v);
+            Schema::from_raw(&mut &raw[..])
+        };
+        check(msgpack!({
+            "name": "Good schema",
+            "min_fields": 1,
+            "max_fields": 2,
+            "req": { "title": { "type": "Str" } }
+        })).unwrap();
+        // Fields that parse but aren't what a schema should contain
+        assert!(check(msgpack!({ "name": "Bad count", "min_fields": -1 })).is_err());
+        assert!(check(msgpack!({ "name": "Bad req", "req": "title" })).is_err());
+        assert!(check(msgpack!({ "name": "Bad version", "version": -1 })).is_err());
+        assert!(check(msgpack!({ "name": "Bad flag", "unknown_ok": 1 })).is_err());
+        // Fields that can't be satisfied together
+        assert!(check(msgpack!({ "name": "Bad range", "min_fields": 3, "max_fields": 2 })).is_err());
+    }
+
+    #[test]
+    fn constructors() {
+        use timestamp::Timestamp;
+        use integer::Integer;
+        use crypto;
+        crypto::init().unwrap();
+        let passes = |validator: Validator, value: Value| {
+            let types = vec![Validator::Invalid, Validator::Valid, validator];
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &value);
+            types[2].validate("", &mut &raw[..], &types, 2, &mut Checklist::new()).is_ok()
+        };
+        let mut vault = crypto::Vault::new_from_password(crypto::PasswordLevel::Interactive, String::from("test")).unwrap();
+        let id1 = vault.new_key().unwrap().get_identity();
+        let id2 = vault.new_key().unwrap().get_identity();
+
+        // Validators made with `new` accept any value of their type
+        assert!(passes(Validator::Boolean(ValidBool::new(false)), msgpack!(true)));
+        assert!(passes(Validator::Integer(ValidInt::new(false)), msgpack!(-5)));
+        assert!(passes(Validator::F32(ValidF32::new(false)), msgpack!(1.5f32)));
+        assert!(passes(Validator::F64(ValidF64::new(false)), msgpack!(1.5f64)));
+        assert!(passes(Validator::Binary(ValidBin::new(false)), Value::Binary(vec![1, 2])));
+        assert!(passes(Validator::String(ValidStr::new(false)), msgpack!("any")));
+        assert!(passes(Validator::Timestamp(ValidTime::new(false)), msgpack!(Timestamp::from_sec(5))));
+        assert!(passes(Validator::Identity(ValidIdentity::new(false)), msgpack!(id1.clone())));
+        assert!(!passes(Validator::Lockbox(ValidLock::new(false)), msgpack!(1)));
+        assert!(!passes(Validator::Integer(ValidInt::new(false)), msgpack!("1")));
+
+        // Validators made with `from_const` only accept the constant
+        let check_const = |validator: Validator, good: Value, bad: Value| {
+            assert!(passes(validator.clone(), good));
+            assert!(!passes(validator, bad));
+        };
+        check_const(Validator::Integer(ValidInt::from_const(Integer::from(3), false)), msgpack!(3), msgpack!(4));
+        check_const(Validator::F32(ValidF32::from_const(1.5, false)), msgpack!(1.5f32), msgpack!(2.5f32));
+        check_const(Validator::F64(ValidF64::from_const(1.5, false)), msgpack!(1.5f64), msgpack!(2.5f64));
+        check_const(Validator::Binary(ValidBin::from_const(&[1, 2], false)),
+            Value::Binary(vec![1, 2]), Value::Binary(vec![1, 3]));
+        check_const(Validator::String(ValidStr::from_const("a", false)), msgpack!("a"), msgpack!("b"));
+        check_const(Validator::Timestamp(ValidTime::from_const(Timestamp::from_sec(5), false)),
+            msgpack!(Timestamp::from_sec(5)), msgpack!(Timestamp::from_sec(6)));
+        check_const(Validator::Identity(ValidIdentity::from_const(id1.clone(), false)),
+            msgpack!(id1), msgpack!(id2));
+    }
 }
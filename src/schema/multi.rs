@@ -1,4 +1,6 @@
 use super::*;
+use encode;
+use value::{Value, ValueRef};
 
 /// Container for multiple accepted Validators
 #[derive(Clone, Debug)]
@@ -14,6 +16,11 @@ impl ValidMulti {
         }
     }
 
+    /// Human-readable name of this validator type, for use in error messages.
+    pub fn type_name() -> &'static str {
+        "Multi"
+    }
+
     /// Update the validator. Returns `Ok(true)` if everything is read out Ok, `Ok(false)` if we 
     /// don't recognize the field type or value, and `Err` if we recognize the field but fail to 
     /// parse the expected contents. The updated `raw` slice reference is only accurate if 
@@ -38,7 +45,7 @@ impl ValidMulti {
                 }
             }
             "type" => if "Multi" == read_str(raw)? { Ok(true) } else { Err(Error::new(InvalidData, "Type doesn't match Multi")) },
-            _ => Err(Error::new(InvalidData, "Unknown fields not allowed in Multi validator")),
+            _ => Err(Error::new(InvalidData, format!("Unknown fields not allowed in {} validator", Self::type_name()))),
         }
     }
 
@@ -50,29 +57,67 @@ impl ValidMulti {
         true
     }
 
+    /// Generate an example value that passes this validator, by trying examples of each of the 
+    /// first set of allowed types until one passes all of them.
+    pub fn example_value(&self, types: &[Validator], depth: usize) -> Option<Value> {
+        let first = match self.any_of.first() {
+            Some(first) => first,
+            None => return Some(Value::Null),
+        };
+        first.iter()
+            .filter_map(|v_index| types[*v_index].example(types, depth))
+            .find(|example| {
+                let mut raw = Vec::new();
+                encode::write_value(&mut raw, example);
+                self.validate("", &mut &raw[..], types, &mut Checklist::new()).is_ok()
+            })
+    }
+
     pub fn validate(&self,
                     field: &str,
                     doc: &mut &[u8],
-                    types: &Vec<Validator>,
+                    types: &[Validator],
                     list: &mut Checklist,
                     ) -> io::Result<()>
     {
+        self.validate_value(field, doc, types, list, false).and(Ok(()))
+    }
+
+    /// Validates the next value like `validate`, and also decodes it if `decode` is set. The value 
+    /// is decoded by the first validator it passes.
+    pub fn validate_value<'a>(&self,
+                              field: &str,
+                              doc: &mut &'a [u8],
+                              types: &[Validator],
+                              list: &mut Checklist,
+                              decode: bool,
+                              ) -> io::Result<Option<ValueRef<'a>>>
+    {
+        // Each validator attempt starts from the beginning of the value
+        let start = *doc;
+        let mut merged = Ok(());
+        let mut decoded = None;
         if self.any_of.iter().all(|any_list| {
             any_list.iter().any(|v_index| {
-                let mut temp_list = Checklist::new();
-                if let Err(_) = types[*v_index].validate(field, doc, types, *v_index, &mut temp_list) {
-                    false
-                }
-                else {
-                    list.merge(temp_list);
-                    true
+                let mut temp_list = list.remaining();
+                let mut doc_local = start;
+                let decode = decode && decoded.is_none();
+                match types[*v_index].validate_value(field, &mut doc_local, types, *v_index, &mut temp_list, decode) {
+                    Err(_) => false,
+                    Ok(value) => {
+                        if merged.is_ok() { merged = list.merge(temp_list); }
+                        if value.is_some() { decoded = value; }
+                        *doc = doc_local;
+                        true
+                    },
                 }
             })
         })
         {
-            Ok(())
+            merged.and(Ok(decoded))
         }
         else {
+            clear_failure();
             Err(Error::new(InvalidData,
                 format!("Field \"{}\" failed against all allowed types.", field)))
         }
@@ -101,26 +146,7 @@ impl ValidMulti {
                 }
                 Ok(Validator::Multi(ValidMulti { any_of }))
             },
-            Validator::Multi(other) => {
-                let mut any_of = Vec::with_capacity(self.any_of.len() + other.any_of.len());
-                for list in self.any_of.iter() {
-                    let mut new_list = Vec::with_capacity(list.len());
-                    for item in list.iter() {
-                        let v = builder.intersect(query, *item, VALID).unwrap();
-                        new_list.push(v);
-                    }
-                    any_of.push(new_list);
-                }
-                for list in other.any_of.iter() {
-                    let mut new_list = Vec::with_capacity(list.len());
-                    for item in list.iter() {
-                        let v = builder.intersect(query, VALID, *item).unwrap();
-                        new_list.push(v);
-                    }
-                    any_of.push(new_list);
-                }
-                Ok(Validator::Multi(ValidMulti { any_of }))
-            }
+            Validator::Multi(other) => self.intersect_multi(other, query, builder),
             _ => {
                 let v_new = vec![builder.push(other.clone())];
                 let mut v = self.clone();
@@ -129,5 +155,155 @@ impl ValidMulti {
             }
         }
     }
+
+    /// Intersection of two Multi validators. If both are a single list of alternatives, the result 
+    /// is the list of intersections of every pair of alternatives, with the `Invalid` ones removed.
+    /// An empty list becomes `Invalid`, and a list with one validator becomes that validator.
+    /// Otherwise, the lists of alternatives from both are all required to be met.
+    pub fn intersect_multi(&self,
+                 other: &ValidMulti,
+                 query: bool,
+                 builder: &mut ValidBuilder
+                 )
+        -> Result<Validator, ()>
+    {
+        if (self.any_of.len() == 1) && (other.any_of.len() == 1) {
+            let builder_len = builder.len();
+            let mut list = Vec::with_capacity(self.any_of[0].len() * other.any_of[0].len());
+            for self_item in self.any_of[0].iter() {
+                for other_item in other.any_of[0].iter() {
+                    let v = match builder.intersect(query, *self_item, *other_item) {
+                        Ok(v) => v,
+                        Err(()) => {
+                            builder.undo_to(builder_len);
+                            return Err(());
+                        }
+                    };
+                    if v != INVALID { list.push(v); }
+                }
+            }
+            list.sort_unstable();
+            list.dedup();
+            match list.len() {
+                0 => {
+                    builder.undo_to(builder_len);
+                    Ok(Validator::Invalid)
+                },
+                1 if list[0] == VALID => Ok(Validator::Valid),
+                1 => Ok(builder.dest[list[0]].clone()),
+                _ => Ok(Validator::Multi(ValidMulti { any_of: vec![list] })),
+            }
+        }
+        else {
+            let mut any_of = Vec::with_capacity(self.any_of.len() + other.any_of.len());
+            for list in self.any_of.iter() {
+                let mut new_list = Vec::with_capacity(list.len());
+                for item in list.iter() {
+                    let v = builder.intersect(query, *item, VALID).unwrap();
+                    new_list.push(v);
+                }
+                any_of.push(new_list);
+            }
+            for list in other.any_of.iter() {
+                let mut new_list = Vec::with_capacity(list.len());
+                for item in list.iter() {
+                    let v = builder.intersect(query, VALID, *item).unwrap();
+                    new_list.push(v);
+                }
+                any_of.push(new_list);
+            }
+            Ok(Validator::Multi(ValidMulti { any_of }))
+        }
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use encode;
+    use value::Value;
+    use super::*;
+    use rand::prelude::*;
+
+    fn read_multi(ranges: &[(i64, i64)], types: &mut Vec<Validator>) -> usize {
+        let any_of: Vec<Value> = ranges.iter()
+            .map(|(min, max)| msgpack!({ "type": "Int", "min": *min, "max": *max }))
+            .collect();
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &msgpack!({
+            "any_of": any_of,
+            "type": "Multi"
+        }));
+        let mut type_names = HashMap::new();
+        Validator::read_validator(&mut &raw[..], false, types, &mut type_names).unwrap()
+    }
+
+    fn passes(types: &[Validator], index: usize, val: i64) -> bool {
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &Value::from(val));
+        types[index].validate("", &mut &raw[..], types, index, &mut Checklist::new()).is_ok()
+    }
+
+    fn in_ranges(ranges: &[(i64, i64)], val: i64) -> bool {
+        ranges.iter().any(|(min, max)| (*min <= val) && (val <= *max))
+    }
+
+    #[test]
+    fn intersect_cross_product() {
+        let num_iter = 500;
+        let mut rng = rand::thread_rng();
+        let range = rand::distributions::Uniform::new(-20,20);
+
+        for _ in 0..num_iter {
+            let mut random_ranges = || -> Vec<(i64, i64)> {
+                (0..2).map(|_| {
+                    let (a, b) = (rng.sample(range), rng.sample(range));
+                    (a.min(b), a.max(b))
+                }).collect()
+            };
+            let ranges1 = random_ranges();
+            let ranges2 = random_ranges();
+            let mut types1 = vec![Validator::Invalid, Validator::Valid];
+            let mut types2 = vec![Validator::Invalid, Validator::Valid];
+            let index1 = read_multi(&ranges1, &mut types1);
+            let index2 = read_multi(&ranges2, &mut types2);
+
+            let mut builder = ValidBuilder::init(&types1, &types2);
+            let index = builder.intersect(false, index1, index2).unwrap();
+            let types = builder.build();
+
+            // Check the result is flattened
+            let overlaps = ranges1.iter()
+                .flat_map(|r1| ranges2.iter().map(move |r2| (r1.0.max(r2.0), r1.1.min(r2.1))))
+                .filter(|(min, max)| min <= max)
+                .count();
+            match types[index] {
+                Validator::Invalid => assert_eq!(overlaps, 0),
+                Validator::Integer(_) => assert!(overlaps >= 1),
+                Validator::Multi(ref v) => {
+                    assert_eq!(v.any_of.len(), 1);
+                    assert!(v.any_of[0].len() > 1);
+                    assert!(v.any_of[0].iter().all(|x| *x != INVALID));
+                },
+                _ => panic!("Unexpected validator type from intersection: {:?}", types[index]),
+            }
+
+            for val in -21..21 {
+                assert_eq!(
+                    passes(&types, index, val),
+                    in_ranges(&ranges1, val) && in_ranges(&ranges2, val),
+                    "Value {} failed for ranges {:?} and {:?}", val, ranges1, ranges2
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn intersect_all_invalid() {
+        let mut types1 = vec![Validator::Invalid, Validator::Valid];
+        let mut types2 = vec![Validator::Invalid, Validator::Valid];
+        let index1 = read_multi(&[(0, 1), (2, 3)], &mut types1);
+        let index2 = read_multi(&[(4, 5), (6, 7)], &mut types2);
+        let mut builder = ValidBuilder::init(&types1, &types2);
+        assert_eq!(builder.intersect(false, index1, index2).unwrap(), INVALID);
+    }
+}
@@ -1,13 +1,74 @@
 use std::io;
 use std::io::Error;
 use std::io::ErrorKind::InvalidData;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::cmp::Ordering;
 use std::mem;
+use std::cell::Cell;
 
 use decode::*;
+use encode;
 use super::*;
 use marker::MarkerType;
+use value::{Value, ValueRef};
+
+thread_local! {
+    /// Number of levels of nested objects that may still be validated, if limited.
+    static DEPTH_LEFT: Cell<Option<usize>> = Cell::new(None);
+}
+
+/// Returns the tighter of two optional depth limits.
+fn min_depth(a: Option<usize>, b: Option<usize>) -> Option<usize> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (Some(a), None) => Some(a),
+        (None, b) => b,
+    }
+}
+
+/// Run a validation with object nesting limited to `depth` levels, in addition to any limit 
+/// already in place.
+pub fn with_depth_limit<T, F: FnOnce() -> T>(depth: Option<usize>, f: F) -> T {
+    let prev = DEPTH_LEFT.with(|left| left.replace(min_depth(left.get(), depth)));
+    let result = f();
+    DEPTH_LEFT.with(|left| left.set(prev));
+    result
+}
+
+/// Normalization applied to a field's string value before it is validated. The document itself 
+/// is never changed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transform {
+    /// Remove leading and trailing whitespace.
+    TrimWhitespace,
+    /// Convert to lowercase.
+    LowerCase,
+    /// Convert to uppercase.
+    UpperCase,
+    /// Validate the value as-is.
+    None,
+}
+
+impl Transform {
+    fn from_str(s: &str) -> Option<Transform> {
+        match s {
+            "trim" => Some(Transform::TrimWhitespace),
+            "lower" => Some(Transform::LowerCase),
+            "upper" => Some(Transform::UpperCase),
+            "none" => Some(Transform::None),
+            _ => None,
+        }
+    }
+
+    fn apply(&self, s: &str) -> String {
+        match self {
+            Transform::TrimWhitespace => s.trim().to_string(),
+            Transform::LowerCase => s.to_lowercase(),
+            Transform::UpperCase => s.to_uppercase(),
+            Transform::None => s.to_string(),
+        }
+    }
+}
 
 /// Object type validator
 #[derive(Clone, Debug)]
@@ -16,10 +77,25 @@ pub struct ValidObj {
     nin_vec: Vec<Box<[u8]>>,
     required: Vec<(String, usize)>,
     optional: Vec<(String, usize)>,
+    query_required: Vec<(String, usize)>,
+    /// Validators applied to the whole object: (condition, then, else). If the object passes the 
+    /// condition, it must also pass `then`, otherwise it must pass `else`.
+    conditional: Vec<(usize, usize, usize)>,
+    /// Field dependencies: (field, dependent). If the first field is present, the second must be 
+    /// too.
+    field_deps: Vec<(String, String)>,
     min_fields: usize,
     max_fields: usize,
+    /// Bounds on the number of fields that are neither required nor optional.
+    min_extra: usize,
+    max_extra: usize,
     field_type: Option<usize>,
     unknown_ok: bool,
+    /// When an unknown field is rejected, list every allowed field in the error.
+    strict: bool,
+    /// Transforms applied to string fields before validating them, sorted by field name.
+    field_transforms: Vec<(String, Transform)>,
+    max_depth: Option<usize>,
     query: bool,
 }
 
@@ -32,14 +108,27 @@ impl ValidObj {
             nin_vec: Vec::with_capacity(0),
             required: Vec::with_capacity(0),
             optional: Vec::with_capacity(0),
+            query_required: Vec::with_capacity(0),
+            conditional: Vec::with_capacity(0),
+            field_deps: Vec::with_capacity(0),
             min_fields: usize::min_value(),
             max_fields: usize::max_value(),
+            min_extra: usize::min_value(),
+            max_extra: usize::max_value(),
             field_type: None,
             unknown_ok: is_query,
+            strict: false,
+            field_transforms: Vec::with_capacity(0),
+            max_depth: None,
             query: is_query,
         }
     }
 
+    /// Human-readable name of this validator type, for use in error messages.
+    pub fn type_name() -> &'static str {
+        "Object"
+    }
+
     /// Update the validator. Returns `Ok(true)` if everything is read out Ok, `Ok(false)` if we 
     /// don't recognize the field type or value, and `Err` if we recognize the field but fail to 
     /// parse the expected contents. The updated `raw` slice reference is only accurate if 
@@ -56,13 +145,54 @@ impl ValidObj {
                     Ok(true)
                 }
                 else {
-                    Err(Error::new(InvalidData, "Object `default` isn't a valid object"))
+                    Err(Error::new(InvalidData, format!("{} `default` isn't a valid object", Self::type_name())))
+                }
+            },
+            "field_deps" => {
+                if let MarkerType::Array(len) = read_marker(raw)? {
+                    self.field_deps.reserve_exact(len.min(MAX_VEC_RESERVE));
+                    for _ in 0..len {
+                        if let MarkerType::Array(2) = read_marker(raw)? {
+                            let field = read_str(raw)?.to_string();
+                            let dependent = read_str(raw)?.to_string();
+                            self.field_deps.push((field, dependent));
+                        }
+                        else {
+                            return Err(Error::new(InvalidData, "`field_deps` items must be arrays of 2 field names"));
+                        }
+                    }
+                    self.field_deps.sort_unstable();
+                    self.field_deps.dedup();
+                    Ok(true)
+                }
+                else {
+                    Err(Error::new(InvalidData, "`field_deps` field must contain an array."))
                 }
             },
             "field_type" => {
                 self.field_type = Some(Validator::read_validator(raw, is_query, types, type_names)?);
                 Ok(true)
             }
+            "if_then_else" => {
+                if let MarkerType::Array(len) = read_marker(raw)? {
+                    self.conditional.reserve_exact(len.min(MAX_VEC_RESERVE));
+                    for _ in 0..len {
+                        if let MarkerType::Array(3) = read_marker(raw)? {
+                            let cond = Validator::read_validator(raw, is_query, types, type_names)?;
+                            let then = Validator::read_validator(raw, is_query, types, type_names)?;
+                            let otherwise = Validator::read_validator(raw, is_query, types, type_names)?;
+                            self.conditional.push((cond, then, otherwise));
+                        }
+                        else {
+                            return Err(Error::new(InvalidData, "`if_then_else` items must be arrays of 3 validators"));
+                        }
+                    }
+                    Ok(true)
+                }
+                else {
+                    Err(Error::new(InvalidData, "`if_then_else` field must contain an array."))
+                }
+            },
             "in" => {
                 match read_marker(&mut raw.clone())? {
                     MarkerType::Object(_) => {
@@ -80,11 +210,29 @@ impl ValidObj {
                         self.in_vec.dedup();
                     },
                     _ => {
-                        return Err(Error::new(InvalidData, "Object validator expected array or constant for `in` field"));
+                        return Err(Error::new(InvalidData, format!("{} validator expected array or constant for `in` field", Self::type_name())));
                     }
                 }
                 Ok(true)
             },
+            "max_depth" => {
+                if let Some(depth) = read_integer(raw)?.as_u64() {
+                    self.max_depth = Some(depth as usize);
+                    Ok(true)
+                }
+                else {
+                    Ok(false)
+                }
+            },
+            "max_extra" => {
+                if let Some(len) = read_integer(raw)?.as_u64() {
+                    self.max_extra = len as usize;
+                    Ok(true)
+                }
+                else {
+                    Ok(false)
+                }
+            },
             "max_fields" => {
                 if let Some(len) = read_integer(raw)?.as_u64() {
                     self.max_fields = len as usize;
@@ -94,6 +242,15 @@ impl ValidObj {
                     Ok(false)
                 }
             },
+            "min_extra" => {
+                if let Some(len) = read_integer(raw)?.as_u64() {
+                    self.min_extra = len as usize;
+                    Ok(self.max_extra >= self.min_extra)
+                }
+                else {
+                    Ok(false)
+                }
+            },
             "min_fields" => {
                 if let Some(len) = read_integer(raw)?.as_u64() {
                     self.min_fields = len as usize;
@@ -120,7 +277,7 @@ impl ValidObj {
                         self.nin_vec.dedup();
                     },
                     _ => {
-                        return Err(Error::new(InvalidData, "Object validator expected array or constant for `nin` field"));
+                        return Err(Error::new(InvalidData, format!("{} validator expected array or constant for `nin` field", Self::type_name())));
                     }
                 }
                 Ok(true)
@@ -128,7 +285,7 @@ impl ValidObj {
             "opt" => {
                 let mut valid = true;
                 if let MarkerType::Object(len) = read_marker(raw)? {
-                    object_iterate(raw, len, |field, raw| {
+                    object_iterate_with_limit(raw, len, MAX_SCHEMA_FIELDS, |field, raw| {
                         let v = Validator::read_validator(raw, is_query, types, type_names)?;
                         if v == 0 { valid = false; }
                         self.optional.push((field.to_string(), v));
@@ -144,10 +301,25 @@ impl ValidObj {
                 self.query = read_bool(raw)?;
                 Ok(true)
             },
+            "query_req" => {
+                let mut valid = true;
+                if let MarkerType::Object(len) = read_marker(raw)? {
+                    object_iterate_with_limit(raw, len, MAX_SCHEMA_FIELDS, |field, raw| {
+                        let v = Validator::read_validator(raw, is_query, types, type_names)?;
+                        if v == 0 { valid = false; }
+                        self.query_required.push((field.to_string(), v));
+                        Ok(())
+                    })?;
+                    Ok(valid)
+                }
+                else {
+                    Err(Error::new(InvalidData, "`query_req` field must contain an object."))
+                }
+            }
             "req" => {
                 let mut valid = true;
                 if let MarkerType::Object(len) = read_marker(raw)? {
-                    object_iterate(raw, len, |field, raw| {
+                    object_iterate_with_limit(raw, len, MAX_SCHEMA_FIELDS, |field, raw| {
                         let v = Validator::read_validator(raw, is_query, types, type_names)?;
                         if v == 0 { valid = false; }
                         self.required.push((field.to_string(), v));
@@ -159,12 +331,31 @@ impl ValidObj {
                     Err(Error::new(InvalidData, "`req` field must contain an object."))
                 }
             }
+            "strict" => {
+                self.strict = read_bool(raw)?;
+                Ok(true)
+            },
+            "transforms" => {
+                if let MarkerType::Object(len) = read_marker(raw)? {
+                    self.field_transforms.reserve_exact(len.min(MAX_VEC_RESERVE));
+                    object_iterate_with_limit(raw, len, MAX_SCHEMA_FIELDS, |field, raw| {
+                        let transform = Transform::from_str(read_str(raw)?).ok_or_else(|| Error::new(InvalidData,
+                            "`transforms` values must be \"trim\", \"lower\", \"upper\", or \"none\""))?;
+                        self.field_transforms.push((field.to_string(), transform));
+                        Ok(())
+                    })?;
+                    Ok(true)
+                }
+                else {
+                    Err(Error::new(InvalidData, "`transforms` field must contain an object."))
+                }
+            },
             "type" => if "Obj" == read_str(raw)? { Ok(true) } else { Err(Error::new(InvalidData, "Type doesn't match Obj")) },
             "unknown_ok" => {
                 self.unknown_ok = read_bool(raw)?;
                 Ok(true)
             },
-            _ => Err(Error::new(InvalidData, "Unknown fields not allowed in Array validator")),
+            _ => Err(Error::new(InvalidData, format!("Unknown fields not allowed in {} validator", Self::type_name()))),
         }
     }
 
@@ -178,6 +369,101 @@ impl ValidObj {
         optional.retain(|x| self.required.binary_search_by(|y| y.0.cmp(&x.0)).is_err());
         mem::swap(&mut self.optional, &mut optional);
         (self.min_fields <= self.max_fields) && !self.required.iter().any(|x| x.1 == 0)
+            && (self.min_extra <= self.max_extra) && (self.unknown_ok || self.min_extra == 0)
+            && !self.conditional.iter().any(|x| (x.1 == INVALID) || (x.2 == INVALID))
+    }
+
+    /// Iterates over the required fields, in order, along with the index of each one's validator.
+    pub fn required_fields(&self) -> impl Iterator<Item=(&str, usize)> {
+        self.required.iter().map(|x| (x.0.as_str(), x.1))
+    }
+
+    /// Iterates over the optional fields, in order, along with the index of each one's validator.
+    pub fn optional_fields(&self) -> impl Iterator<Item=(&str, usize)> {
+        self.optional.iter().map(|x| (x.0.as_str(), x.1))
+    }
+
+    /// Iterates over the names of the required fields, in order.
+    pub fn field_names_required(&self) -> impl Iterator<Item=&str> {
+        self.required.iter().map(|x| x.0.as_str())
+    }
+
+    /// Iterates over the names of the optional fields, in order.
+    pub fn field_names_optional(&self) -> impl Iterator<Item=&str> {
+        self.optional.iter().map(|x| x.0.as_str())
+    }
+
+    /// Look up the validator for a required field. `types` must be the validator list this one 
+    /// was read alongside. Returns `None` if `field` isn't a required field.
+    pub fn get_required<'a>(&'a self, field: &str, types: &'a [Validator]) -> Option<&'a Validator> {
+        self.required.binary_search_by(|x| x.0.as_str().cmp(field))
+            .ok()
+            .and_then(|i| types.get(self.required[i].1))
+    }
+
+    /// Look up the validator for an optional field. `types` must be the validator list this one 
+    /// was read alongside. Returns `None` if `field` isn't an optional field.
+    pub fn get_optional<'a>(&'a self, field: &str, types: &'a [Validator]) -> Option<&'a Validator> {
+        self.optional.binary_search_by(|x| x.0.as_str().cmp(field))
+            .ok()
+            .and_then(|i| types.get(self.optional[i].1))
+    }
+
+    /// Returns a copy of this validator with every optional field promoted to a required field.
+    pub fn with_all_required(&self) -> ValidObj {
+        let mut v = self.clone();
+        v.required.extend(v.optional.drain(..));
+        v.required.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        v
+    }
+
+    /// Returns a copy of this validator with the named optional fields promoted to required 
+    /// fields. Fails if any of the named fields isn't in the optional field list.
+    pub fn with_required_subset(&self, fields: &[&str]) -> Result<ValidObj, ()> {
+        let mut v = self.clone();
+        for field in fields.iter() {
+            let index = v.optional.binary_search_by(|x| x.0.as_str().cmp(field)).map_err(|_| ())?;
+            let item = v.optional.remove(index);
+            v.required.push(item);
+        }
+        v.required.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        Ok(v)
+    }
+
+    /// Returns a copy of this validator as it applies to queries: only the `query_required` fields 
+    /// are required, and any other required fields become optional.
+    fn query_form(&self) -> ValidObj {
+        let mut v = self.clone();
+        let required = mem::replace(&mut v.required, Vec::with_capacity(0));
+        v.required = mem::replace(&mut v.query_required, Vec::with_capacity(0));
+        for item in required.into_iter() {
+            if v.required.binary_search_by(|x| x.0.cmp(&item.0)).is_err() {
+                v.optional.push(item);
+            }
+        }
+        v.optional.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        v.finalize();
+        v
+    }
+
+    /// Generate a minimal example value that passes this validator: the first allowed object, or 
+    /// an object with examples for all required fields, and for as many optional fields as are 
+    /// needed to reach `min_fields`.
+    pub fn example_value(&self, types: &[Validator], depth: usize) -> Option<Value> {
+        if let Some(v) = self.in_vec.first() {
+            return read_value(&mut &v[..]).ok();
+        }
+        let mut value = BTreeMap::new();
+        for (field, v_index) in self.required.iter() {
+            value.insert(field.clone(), types[*v_index].example(types, depth)?);
+        }
+        for (field, v_index) in self.optional.iter() {
+            if value.len() >= self.min_fields { break; }
+            if let Some(example) = types[*v_index].example(types, depth) {
+                value.insert(field.clone(), example);
+            }
+        }
+        Some(Value::from(value))
     }
 
     /// Validates that the next value is a Hash that meets the validator requirements. Fails if the 
@@ -186,28 +472,51 @@ impl ValidObj {
     pub fn validate(&self,
                     field: &str,
                     doc: &mut &[u8],
-                    types: &Vec<Validator>,
+                    types: &[Validator],
                     list: &mut Checklist,
                     top_schema: bool
                     ) -> io::Result<()>
+    {
+        self.validate_value(field, doc, types, list, top_schema, false).and(Ok(()))
+    }
+
+    /// Validates the next value like `validate`, and also decodes it if `decode` is set. Each 
+    /// field is decoded as it is validated.
+    pub fn validate_value<'a>(&self,
+                              field: &str,
+                              doc: &mut &'a [u8],
+                              types: &[Validator],
+                              list: &mut Checklist,
+                              top_schema: bool,
+                              decode: bool
+                              ) -> io::Result<Option<ValueRef<'a>>>
     {
         let obj_start = doc.clone();
         let mut num_fields = match read_marker(doc)? {
             MarkerType::Object(len) => len,
             _ => return Err(Error::new(InvalidData, "Object not found")),
         };
+        let mut decoded = BTreeMap::new();
+
+        // Limit how much deeper objects may nest within this one
+        let depth = min_depth(DEPTH_LEFT.with(|left| left.get()), self.max_depth);
+        if depth == Some(0) {
+            return Err(Error::new(InvalidData,
+                format!("Field \"{}\" contains objects nested too deeply", field)));
+        }
 
         // Read out the schema field if this is a Document, and don't count it towards the field 
         // limit
         if top_schema {
             let mut schema = &doc[..];
             if read_str(&mut schema)?.len() == 0 {
-                if read_hash(&mut schema).is_err() {
-                    return Err(Error::new(InvalidData, "Document schema field doesn't contain a Hash"));
-                }
-                else {
-                    *doc = schema;
-                    num_fields -= 1;
+                match read_hash(&mut schema) {
+                    Ok(hash) => {
+                        if decode { decoded.insert("", ValueRef::Hash(hash)); }
+                        *doc = schema;
+                        num_fields -= 1;
+                    },
+                    Err(_) => return Err(Error::new(InvalidData, "Document schema field doesn't contain a Hash")),
                 }
             }
         }
@@ -217,42 +526,73 @@ impl ValidObj {
                 format!("Field \"{}\" contains object with {} fields, less than the {} required",
                     field, num_fields, self.min_fields)));
         }
-        if num_fields == 0 && self.required.len() == 0 { return Ok(()); }
+        if num_fields == 0 && self.required.len() == 0 && self.conditional.len() == 0 && self.min_extra == 0 {
+            return Ok(if decode { Some(ValueRef::Object(decoded)) } else { None });
+        }
         if num_fields > self.max_fields {
             return Err(Error::new(InvalidData,
                 format!("Field \"{}\" contains object with {} fields, more than the {} required",
                     field, num_fields, self.max_fields)));
         }
 
+        let prev_depth = DEPTH_LEFT.with(|left| left.replace(depth.map(|d| d - 1)));
+
         // Setup for loop
         let parent_field = field;
         let mut req_index = 0;
         let mut opt_index = 0;
-        object_iterate(doc, num_fields, |field, doc| {
-            // Check against required/optional/unknown types
-            if Some(field) == self.required.get(req_index).map(|x| x.0.as_str()) {
-                let v_index = self.required[req_index].1;
+        let mut present: Vec<String> = Vec::new();
+        let mut num_extra = 0;
+        let result = object_iterate(doc, num_fields, |field, doc| {
+            if !self.field_deps.is_empty() { present.push(field.to_string()); }
+            // Skip past optional fields that weren't present
+            while self.optional.get(opt_index).map_or(false, |x| x.0.as_str() < field) {
+                opt_index += 1;
+            }
+            // Pick the validator for this field. Unknown fields without a validator for them only 
+            // need to be valid values.
+            let v_index = if Some(field) == self.required.get(req_index).map(|x| x.0.as_str()) {
                 req_index += 1;
-                types[v_index].validate(field, doc, types, v_index, list)
+                Ok(Some(self.required[req_index-1].1))
             }
             else if Some(field) == self.optional.get(opt_index).map(|x| x.0.as_str()) {
-                let v_index = self.optional[opt_index].1;
                 opt_index += 1;
-                types[v_index].validate(field, doc, types, v_index, list)
+                Ok(Some(self.optional[opt_index-1].1))
             }
             else if self.unknown_ok {
-                if let Some(v_index) = self.field_type {
-                    types[v_index].validate(field, doc, types, v_index, list)
-                }
-                else {
-                    verify_value(doc)?;
-                    Ok(())
-                }
+                num_extra += 1;
+                Ok(self.field_type)
+            }
+            else if self.strict {
+                Err(Error::new(InvalidData,
+                    format!("Field \"{}\" not allowed. Valid fields are: {}", field, self.allowed_fields())))
             }
             else {
                 Err(Error::new(InvalidData, format!("Unknown, invalid field: \"{}\"", field)))
+            };
+            // A transformed field is validated in its transformed form, but the original is what 
+            // gets decoded
+            let mut original = *doc;
+            let transformed = self.transform_value(field, doc)?;
+            let result = v_index.and_then(|v_index| {
+                let (validator, v_index) = match v_index {
+                    Some(v_index) => (&types[v_index], v_index),
+                    None => (&Validator::Valid, VALID),
+                };
+                match transformed {
+                    Some(transformed) => validator.validate(field, &mut &transformed[..], types, v_index, list)
+                        .and_then(|()| if decode { read_value_ref(&mut original).map(Some) } else { Ok(None) }),
+                    None => validator.validate_value(field, doc, types, v_index, list, decode),
+                }
+            });
+            match result {
+                Ok(Some(value)) => { decoded.insert(field, value); Ok(()) },
+                Ok(None) => Ok(()),
+                Err(e) => { track_path(|| field.to_string()); Err(e) },
             }
-        })?;
+        });
+        DEPTH_LEFT.with(|left| left.set(prev_depth));
+        result?;
 
         let (obj_start, _) = obj_start.split_at(obj_start.len()-doc.len());
         if self.nin_vec.iter().any(|x| obj_start == &x[..]) {
@@ -267,22 +607,129 @@ impl ValidObj {
             Err(Error::new(InvalidData,
                 format!("Missing required fields, starting with {}", self.required[req_index].0.as_str())))
         }
+        else if num_extra < self.min_extra {
+            Err(Error::new(InvalidData,
+                format!("Field \"{}\" contains object with {} extra fields, less than the {} required",
+                    parent_field, num_extra, self.min_extra)))
+        }
+        else if num_extra > self.max_extra {
+            Err(Error::new(InvalidData,
+                format!("Field \"{}\" contains object with {} extra fields, more than the {} allowed",
+                    parent_field, num_extra, self.max_extra)))
+        }
+        else if let Some((dep_field, dependent)) = self.field_deps.iter().find(|(dep_field, dependent)| {
+            present.binary_search(dep_field).is_ok() && present.binary_search(dependent).is_err()
+        }) {
+            Err(Error::new(InvalidData,
+                format!("Field \"{}\" is missing \"{}\", which is required when \"{}\" is present",
+                    parent_field, dependent, dep_field)))
+        }
+        else {
+            self.validate_conditional(parent_field, obj_start, types, list)?;
+            Ok(if decode { Some(ValueRef::Object(decoded)) } else { None })
+        }
+    }
+
+    /// The transform for a field, or `Transform::None` if it doesn't have one.
+    fn transform_of(&self, field: &str) -> Transform {
+        match self.field_transforms.binary_search_by(|x| x.0.as_str().cmp(field)) {
+            Ok(index) => self.field_transforms[index].1,
+            Err(_) => Transform::None,
+        }
+    }
+
+    /// Returns true if a field would be checked against a validator: it is required, optional, or 
+    /// there is a validator for unknown fields.
+    fn checks_field(&self, field: &str) -> bool {
+        self.required.binary_search_by(|x| x.0.as_str().cmp(field)).is_ok()
+            || self.optional.binary_search_by(|x| x.0.as_str().cmp(field)).is_ok()
+            || self.field_type.is_some()
+    }
+
+    /// If the field has a transform and holds a string, read the string out and return an encoded 
+    /// copy of it with the transform applied, for validating in its place.
+    fn transform_value(&self, field: &str, doc: &mut &[u8]) -> io::Result<Option<Vec<u8>>> {
+        let transform = self.transform_of(field);
+        if transform == Transform::None { return Ok(None); }
+        if let MarkerType::String(_) = read_marker(&mut doc.clone())? {
+            let value = transform.apply(read_str(doc)?);
+            let mut transformed = Vec::new();
+            encode::write_value(&mut transformed, &Value::from(value));
+            Ok(Some(transformed))
+        }
         else {
-            Ok(())
+            Ok(None)
+        }
+    }
+
+    /// List the allowed fields in alphabetical order, for error messages. Required fields are 
+    /// marked with `*`.
+    fn allowed_fields(&self) -> String {
+        let mut fields: Vec<String> = self.required.iter().map(|x| format!("{}*", x.0))
+            .chain(self.optional.iter().map(|x| x.0.clone()))
+            .collect();
+        fields.sort_unstable();
+        fields.join(", ")
+    }
+
+    /// Run the `if_then_else` validators against the complete object.
+    fn validate_conditional(&self, field: &str, obj: &[u8], types: &[Validator], list: &mut Checklist)
+        -> io::Result<()>
+    {
+        for (cond, then, otherwise) in self.conditional.iter() {
+            // Documents the condition would have needed checked don't matter, only whether it 
+            // passed
+            let mut cond_list = list.remaining();
+            let branch = if types[*cond].validate(field, &mut &obj[..], types, *cond, &mut cond_list).is_ok() {
+                *then
+            }
+            else {
+                *otherwise
+            };
+            // A failed condition shouldn't be reported as the reason the object failed
+            clear_failure();
+            types[branch].validate(field, &mut &obj[..], types, branch, list)?;
+        }
+        Ok(())
+    }
+
+    /// Checks if `other` only uses query operations this Obj validator allows. Only the objects 
+    /// themselves are checked, not the validators for their fields. If this fails, `intersect` 
+    /// fails when `query` is true.
+    pub fn query_ok(&self, other: &Validator) -> bool {
+        self.query && match other {
+            Validator::Object(other) => other.conditional.is_empty(),
+            _ => true,
         }
     }
 
     /// Intersection of Object with other Validators. Returns Err only if `query` is true and the 
-    /// other validator contains non-allowed query parameters.
+    /// other validator contains non-allowed query parameters. When `query` is true, only the 
+    /// `query_required` fields are treated as required.
     pub fn intersect(&self,
                  other: &Validator,
                  query: bool,
                  builder: &mut ValidBuilder
                  )
         -> Result<Validator, ()>
+    {
+        if query && !self.query_ok(other) { return Err(()); }
+        if query {
+            self.query_form().intersect_fields(other, query, builder)
+        }
+        else {
+            self.intersect_fields(other, query, builder)
+        }
+    }
+
+    fn intersect_fields(&self,
+                 other: &Validator,
+                 query: bool,
+                 builder: &mut ValidBuilder
+                 )
+        -> Result<Validator, ()>
     {
         let builder_len = builder.len();
-        if query && !self.query { return Err(()); }
         match other {
             Validator::Object(other) => {
                 // Get intersection of `in` vectors
@@ -314,7 +761,7 @@ impl ValidObj {
                 else {
                     INVALID
                 });
-                let other_type = ("".to_string(), if self.unknown_ok {
+                let other_type = ("".to_string(), if other.unknown_ok {
                     if let Some(other_type) = other.field_type {
                         other_type
                     }
@@ -363,31 +810,31 @@ impl ValidObj {
                     //   opt, but only if unknown_ok true in other
                     if (s_is_req || s_is_opt) && (o_is_req || o_is_opt) {
                         // Detemine intersection & increment pointers
-                        let v = match s.0.cmp(&o.0) {
+                        let (name, v, is_req) = match s.0.cmp(&o.0) {
                             Ordering::Less => {
                                 if s_is_req { self_req_i += 1; }
                                 if s_is_opt { self_opt_i += 1; }
-                                builder.intersect(query, s.1, other_type.1)?
+                                (&s.0, builder.intersect(query, s.1, other_type.1)?, s_is_req)
                             },
                             Ordering::Equal => {
                                 if s_is_req { self_req_i += 1; }
                                 if s_is_opt { self_opt_i += 1; }
                                 if o_is_req { other_req_i += 1; }
                                 if o_is_opt { other_opt_i += 1; }
-                                builder.intersect(query, s.1, o.1)?
+                                (&s.0, builder.intersect(query, s.1, o.1)?, s_is_req || o_is_req)
                             },
                             Ordering::Greater => {
                                 if o_is_req { other_req_i += 1; }
                                 if o_is_opt { other_opt_i += 1; }
-                                builder.intersect(query, self_type.1, o.1)?
+                                (&o.0, builder.intersect(query, self_type.1, o.1)?, o_is_req)
                             },
                         };
                         // Add to appropriate list
-                        if s_is_req || o_is_req {
-                            required.push((s.0.clone(), v));
+                        if is_req {
+                            required.push((name.clone(), v));
                         }
                         else {
-                            optional.push((s.0.clone(), v));
+                            optional.push((name.clone(), v));
                         }
                     }
                     else if s_is_req || s_is_opt {
@@ -428,6 +875,47 @@ impl ValidObj {
                     None
                 };
 
+                // Both sets of conditionals must hold. Stored documents have already passed them, 
+                // so they're left out of query intersections.
+                let mut conditional = Vec::new();
+                if !query {
+                    for (cond, then, otherwise) in self.conditional.iter() {
+                        conditional.push((
+                            builder.intersect(query, *cond, VALID)?,
+                            builder.intersect(query, *then, VALID)?,
+                            builder.intersect(query, *otherwise, VALID)?,
+                        ));
+                    }
+                    for (cond, then, otherwise) in other.conditional.iter() {
+                        conditional.push((
+                            builder.intersect(query, VALID, *cond)?,
+                            builder.intersect(query, VALID, *then)?,
+                            builder.intersect(query, VALID, *otherwise)?,
+                        ));
+                    }
+                }
+
+                // Both sets of transforms apply. A field can't be given two different transforms, 
+                // and a transform can only be added to a field the other validator doesn't check, 
+                // as its validators would otherwise see transformed values.
+                let mut field_transforms = self.field_transforms.clone();
+                for (field, transform) in other.field_transforms.iter() {
+                    if let Err(index) = field_transforms.binary_search_by(|x| x.0.cmp(field)) {
+                        field_transforms.insert(index, (field.clone(), *transform));
+                    }
+                }
+                let conflict = field_transforms.iter().any(|(field, _)| {
+                    let (self_transform, other_transform) = (self.transform_of(field), other.transform_of(field));
+                    (self_transform != other_transform) && (
+                        ((self_transform != Transform::None) && (other_transform != Transform::None))
+                        || ((self_transform == Transform::None) && self.checks_field(field))
+                        || ((other_transform == Transform::None) && other.checks_field(field)))
+                });
+                if conflict {
+                    builder.undo_to(builder_len);
+                    return Ok(Validator::Invalid);
+                }
+
                 // Check that this isn't an invalid validator before proceeding
                 /*
                 if items.contains(&0) {
@@ -442,10 +930,18 @@ impl ValidObj {
                     nin_vec: sorted_union(&self.nin_vec[..], &other.nin_vec[..], |a,b| a.cmp(b)),
                     required: required,
                     optional: optional,
+                    query_required: Vec::with_capacity(0),
+                    conditional: conditional,
+                    field_deps: sorted_union(&self.field_deps[..], &other.field_deps[..], |a,b| a.cmp(b)),
                     min_fields: self.min_fields.max(other.min_fields),
                     max_fields: self.max_fields.min(other.max_fields),
+                    min_extra: self.min_extra.max(other.min_extra),
+                    max_extra: self.max_extra.min(other.max_extra),
                     field_type: field_type,
                     unknown_ok: self.unknown_ok && other.unknown_ok,
+                    strict: self.strict || other.strict,
+                    field_transforms: field_transforms,
+                    max_depth: min_depth(self.max_depth, other.max_depth),
                     query: self.query && other.query,
                 };
                 if new_validator.in_vec.len() == 0 && (self.in_vec.len()+other.in_vec.len() > 0) {
@@ -473,6 +969,22 @@ impl ValidObj {
                     .map(|x| (x.0.clone(), builder.intersect(query, x.1, 1).unwrap())));
                 v.optional = optional;
 
+                let mut query_required = Vec::with_capacity(self.query_required.len());
+                query_required.extend(self.query_required.iter()
+                    .map(|x| (x.0.clone(), builder.intersect(query, x.1, 1).unwrap())));
+                v.query_required = query_required;
+
+                let mut conditional = Vec::with_capacity(0);
+                if !query {
+                    conditional.extend(self.conditional.iter()
+                        .map(|x| (
+                            builder.intersect(query, x.0, 1).unwrap(),
+                            builder.intersect(query, x.1, 1).unwrap(),
+                            builder.intersect(query, x.2, 1).unwrap(),
+                        )));
+                }
+                v.conditional = conditional;
+
                 if let Some(field_type) = self.field_type {
                     v.field_type = Some(builder.intersect(query, field_type, 1).unwrap());
                 }
@@ -589,4 +1101,499 @@ mod tests {
         let mut list = Checklist::new();
         assert!(types[validator].validate("", &mut &raw_test[..], &types, validator, &mut list).is_err());
     }
+
+    #[test]
+    fn promote_required() {
+        let mut raw_schema = Vec::new();
+        let schema: Value = msgpack!({
+            "type": "Obj",
+            "req": {
+                "test": true
+            },
+            "opt": {
+                "a": 1,
+                "b": "string",
+                "c": true
+            }
+        });
+        encode::write_value(&mut raw_schema, &schema);
+        let mut types = Vec::new();
+        types.push(Validator::Invalid);
+        types.push(Validator::Valid);
+        let mut type_names = HashMap::new();
+        let validator = Validator::read_validator(&mut &raw_schema[..], false, &mut types, &mut type_names).unwrap();
+        let obj = match types[validator] {
+            Validator::Object(ref v) => v.clone(),
+            _ => panic!("Parsing an object validator didn't yield an object validator!"),
+        };
+
+        let mut raw_partial = Vec::new();
+        encode::write_value(&mut raw_partial, &msgpack!({ "a": 1, "test": true }));
+        let mut raw_full = Vec::new();
+        encode::write_value(&mut raw_full, &msgpack!({ "a": 1, "b": "string", "c": true, "test": true }));
+        let mut list = Checklist::new();
+
+        // Promote all optional fields
+        let all = obj.with_all_required();
+        assert!(obj.validate("", &mut &raw_partial[..], &types, &mut list, false).is_ok());
+        assert!(all.validate("", &mut &raw_partial[..], &types, &mut list, false).is_err());
+        assert!(all.validate("", &mut &raw_full[..], &types, &mut list, false).is_ok());
+
+        // Promote only some optional fields
+        let subset = obj.with_required_subset(&["a"]).unwrap();
+        assert!(subset.validate("", &mut &raw_partial[..], &types, &mut list, false).is_ok());
+        let subset = obj.with_required_subset(&["c", "a"]).unwrap();
+        assert!(subset.validate("", &mut &raw_partial[..], &types, &mut list, false).is_err());
+        assert!(subset.validate("", &mut &raw_full[..], &types, &mut list, false).is_ok());
+
+        // Fail on fields that aren't optional
+        assert!(obj.with_required_subset(&["test"]).is_err());
+        assert!(obj.with_required_subset(&["d"]).is_err());
+    }
+
+    #[test]
+    fn field_lookup() {
+        let mut raw_schema = Vec::new();
+        let schema: Value = msgpack!({
+            "type": "Obj",
+            "req": {
+                "a": { "type": "Int" },
+                "b": { "type": "Str" },
+                "c": { "type": "Bool" },
+                "d": { "type": "F64" },
+                "e": { "type": "Bin" }
+            },
+            "opt": {
+                "f": { "type": "Hash" }
+            }
+        });
+        encode::write_value(&mut raw_schema, &schema);
+        let mut types = Vec::new();
+        types.push(Validator::Invalid);
+        types.push(Validator::Valid);
+        let mut type_names = HashMap::new();
+        let validator = Validator::read_validator(&mut &raw_schema[..], false, &mut types, &mut type_names).unwrap();
+        let obj = match types[validator] {
+            Validator::Object(ref v) => v.clone(),
+            _ => panic!("Parsing an object validator didn't yield an object validator!"),
+        };
+
+        assert_eq!(obj.field_names_required().collect::<Vec<&str>>(), vec!["a", "b", "c", "d", "e"]);
+        assert_eq!(obj.field_names_optional().collect::<Vec<&str>>(), vec!["f"]);
+        let kinds = [("a", "Integer"), ("b", "String"), ("c", "Boolean"), ("d", "F64"), ("e", "Binary")];
+        for (field, kind) in kinds.iter() {
+            let found = match obj.get_required(field, &types) {
+                Some(Validator::Integer(_)) => "Integer",
+                Some(Validator::String(_)) => "String",
+                Some(Validator::Boolean(_)) => "Boolean",
+                Some(Validator::F64(_)) => "F64",
+                Some(Validator::Binary(_)) => "Binary",
+                _ => panic!("Field {} has the wrong validator", field),
+            };
+            assert_eq!(found, *kind);
+            assert!(obj.get_optional(field, &types).is_none());
+        }
+        match obj.get_optional("f", &types) {
+            Some(Validator::Hash(_)) => (),
+            _ => panic!("Optional field f has the wrong validator"),
+        }
+        assert!(obj.get_required("f", &types).is_none());
+        assert!(obj.get_required("g", &types).is_none());
+        assert!(obj.get_optional("g", &types).is_none());
+    }
+
+    fn nested(levels: usize) -> Vec<u8> {
+        let mut value = msgpack!({});
+        for _ in 1..levels {
+            value = msgpack!({ "a": value });
+        }
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &value);
+        raw
+    }
+
+    #[test]
+    fn max_depth() {
+        let mut raw_schema = Vec::new();
+        encode::write_value(&mut raw_schema, &msgpack!({
+            "type": "Obj",
+            "max_depth": 4,
+            "opt": { "a": { "type": "Inner" } }
+        }));
+        let mut types = vec![Validator::Invalid, Validator::Valid];
+        let mut type_names = HashMap::new();
+        let validator = Validator::read_validator(&mut &raw_schema[..], false, &mut types, &mut type_names).unwrap();
+        // Make the inner type recursive, so it would accept any level of nesting
+        let mut raw_inner = Vec::new();
+        encode::write_value(&mut raw_inner, &msgpack!({
+            "type": "Obj",
+            "opt": { "a": { "type": "Inner" } }
+        }));
+        let inner = Validator::read_validator(&mut &raw_inner[..], false, &mut types, &mut type_names).unwrap();
+        let inner_index = type_names["Inner"];
+        types[inner_index] = types[inner].clone();
+
+        let check = |levels: usize, max: Option<usize>| {
+            let raw = nested(levels);
+            with_depth_limit(max, || {
+                types[validator].validate("", &mut &raw[..], &types, validator, &mut Checklist::new())
+            })
+        };
+        assert!(check(4, None).is_ok());
+        assert!(check(5, None).is_err());
+        assert!(check(3, Some(3)).is_ok());
+        assert!(check(4, Some(3)).is_err());
+        assert!(check(5, Some(64)).is_err());
+        // The limit is cleared once validation is done
+        assert!(types[inner_index].validate("", &mut &nested(10)[..], &types, inner_index, &mut Checklist::new()).is_ok());
+
+        // Intersections keep the smaller limit
+        let mut raw_other = Vec::new();
+        encode::write_value(&mut raw_other, &msgpack!({
+            "type": "Obj",
+            "max_depth": 2,
+            "unknown_ok": true
+        }));
+        let mut types2 = vec![Validator::Invalid, Validator::Valid];
+        let other = Validator::read_validator(&mut &raw_other[..], false, &mut types2, &mut HashMap::new()).unwrap();
+        let mut builder = ValidBuilder::init(&types, &types2);
+        match types[validator].intersect(&types2[other], false, &mut builder).unwrap() {
+            Validator::Object(v) => assert_eq!(v.max_depth, Some(2)),
+            v => panic!("Intersection should be an object validator, got {:?}", v),
+        }
+    }
+
+    #[test]
+    fn query_required() {
+        let read = |value: Value, is_query: bool, types: &mut Vec<Validator>| {
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &value);
+            Validator::read_validator(&mut &raw[..], is_query, types, &mut HashMap::new()).unwrap()
+        };
+        let passes = |types: &[Validator], index: usize, value: Value| {
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &value);
+            types[index].validate("", &mut &raw[..], types, index, &mut Checklist::new()).is_ok()
+        };
+        let mut types1 = vec![Validator::Invalid, Validator::Valid];
+        let schema = read(msgpack!({
+            "type": "Obj",
+            "query": true,
+            "req": {
+                "age": { "type": "Int", "query": true },
+                "name": { "type": "Str", "query": true }
+            },
+            "query_req": {
+                "name": { "type": "Str", "query": true }
+            }
+        }), false, &mut types1);
+        let no_query_req = read(msgpack!({
+            "type": "Obj",
+            "query": true,
+            "req": {
+                "age": { "type": "Int", "query": true },
+                "name": { "type": "Str", "query": true }
+            }
+        }), false, &mut types1);
+
+        // Documents still need every required field
+        assert!(passes(&types1, schema, msgpack!({ "age": 30, "name": "Alice" })));
+        assert!(!passes(&types1, schema, msgpack!({ "name": "Alice" })));
+
+        let mut types2 = vec![Validator::Invalid, Validator::Valid];
+        let query = read(msgpack!({
+            "type": "Obj",
+            "req": { "name": { "type": "Str", "in": ["Alice"] } }
+        }), true, &mut types2);
+        let empty_query = read(msgpack!({ "type": "Obj" }), true, &mut types2);
+
+        // Queries only need the `query_req` fields
+        let mut builder = ValidBuilder::init(&types1, &types2);
+        let index = builder.intersect(true, schema, query).unwrap();
+        let types = builder.build();
+        assert!(passes(&types, index, msgpack!({ "name": "Alice" })));
+        assert!(passes(&types, index, msgpack!({ "age": 30, "name": "Alice" })));
+        assert!(!passes(&types, index, msgpack!({ "age": 30, "name": "Bob" })));
+        assert!(!passes(&types, index, msgpack!({ "age": 30 })));
+
+        let mut builder = ValidBuilder::init(&types1, &types2);
+        let index = builder.intersect(true, schema, empty_query).unwrap();
+        let types = builder.build();
+        assert!(passes(&types, index, msgpack!({ "name": "Alice" })));
+        assert!(!passes(&types, index, msgpack!({ "age": 30 })));
+
+        // Without `query_req`, nothing is required in query mode
+        let mut builder = ValidBuilder::init(&types1, &types2);
+        let index = builder.intersect(true, no_query_req, empty_query).unwrap();
+        let types = builder.build();
+        assert!(passes(&types, index, msgpack!({ "age": 30 })));
+        assert!(passes(&types, index, msgpack!({})));
+
+        // Outside of query mode, all required fields are still enforced
+        let mut builder = ValidBuilder::init(&types1, &types2);
+        let index = builder.intersect(false, schema, empty_query).unwrap();
+        let types = builder.build();
+        assert!(!passes(&types, index, msgpack!({ "name": "Alice" })));
+    }
+
+    #[test]
+    fn conditional() {
+        let read = |value: Value, types: &mut Vec<Validator>| {
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &value);
+            Validator::read_validator(&mut &raw[..], false, types, &mut HashMap::new())
+        };
+        let passes = |types: &[Validator], index: usize, value: Value| {
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &value);
+            types[index].validate("", &mut &raw[..], types, index, &mut Checklist::new()).is_ok()
+        };
+        let mut types = vec![Validator::Invalid, Validator::Valid];
+        let shape = read(msgpack!({
+            "type": "Obj",
+            "req": { "kind": { "type": "Str", "in": ["circle", "rect"] } },
+            "opt": {
+                "height": { "type": "Int" },
+                "radius": { "type": "Int" },
+                "width": { "type": "Int" }
+            },
+            "if_then_else": [[
+                { "type": "Obj", "req": { "kind": "circle" }, "unknown_ok": true },
+                { "type": "Obj", "req": { "radius": { "type": "Int" } }, "unknown_ok": true },
+                { "type": "Obj", "req": { "height": { "type": "Int" }, "width": { "type": "Int" } }, "unknown_ok": true }
+            ]]
+        }), &mut types).unwrap();
+
+        assert!(passes(&types, shape, msgpack!({ "kind": "circle", "radius": 2 })));
+        assert!(!passes(&types, shape, msgpack!({ "kind": "circle" })));
+        assert!(!passes(&types, shape, msgpack!({ "kind": "circle", "width": 2, "height": 3 })));
+        assert!(passes(&types, shape, msgpack!({ "kind": "rect", "width": 2, "height": 3 })));
+        assert!(!passes(&types, shape, msgpack!({ "kind": "rect", "radius": 2 })));
+        // The regular checks still apply
+        assert!(!passes(&types, shape, msgpack!({ "kind": "triangle", "width": 2, "height": 3 })));
+        assert!(!passes(&types, shape, msgpack!({ "kind": "circle", "radius": "big" })));
+
+        // Conditionals are kept when intersecting outside of query mode
+        let mut types2 = vec![Validator::Invalid, Validator::Valid];
+        let other = read(msgpack!({ "type": "Obj", "unknown_ok": true }), &mut types2).unwrap();
+        let mut builder = ValidBuilder::init(&types, &types2);
+        let index = builder.intersect(false, shape, other).unwrap();
+        let types3 = builder.build();
+        assert!(passes(&types3, index, msgpack!({ "kind": "circle", "radius": 2 })));
+        assert!(!passes(&types3, index, msgpack!({ "kind": "circle" })));
+
+        // A branch that can never pass makes the whole validator invalid
+        let mut types = vec![Validator::Invalid, Validator::Valid];
+        assert_eq!(INVALID, read(msgpack!({
+            "type": "Obj",
+            "opt": { "a": { "type": "Int" } },
+            "if_then_else": [[
+                { "type": "Obj", "unknown_ok": true },
+                { "type": "Int", "in": [1], "nin": [1] },
+                { "type": "Obj", "unknown_ok": true }
+            ]]
+        }), &mut types).unwrap());
+    }
+
+    #[test]
+    fn strict() {
+        let read = |value: Value, types: &mut Vec<Validator>| {
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &value);
+            Validator::read_validator(&mut &raw[..], false, types, &mut HashMap::new()).unwrap()
+        };
+        let check = |types: &[Validator], index: usize, value: Value| {
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &value);
+            types[index].validate("", &mut &raw[..], types, index, &mut Checklist::new())
+        };
+        let mut types = vec![Validator::Invalid, Validator::Valid];
+        let strict = read(msgpack!({
+            "type": "Obj",
+            "strict": true,
+            "req": { "name": { "type": "Str" } },
+            "opt": {
+                "age": { "type": "Int" },
+                "email": { "type": "Str" }
+            }
+        }), &mut types);
+        let loose = read(msgpack!({
+            "type": "Obj",
+            "req": { "name": { "type": "Str" } },
+            "opt": { "age": { "type": "Int" } }
+        }), &mut types);
+
+        assert!(check(&types, strict, msgpack!({ "name": "Alice", "age": 30 })).is_ok());
+        let err = check(&types, strict, msgpack!({ "name": "Alice", "extra": 1 })).unwrap_err();
+        assert_eq!(err.to_string(), "Field \"extra\" not allowed. Valid fields are: age, email, name*");
+        let err = check(&types, loose, msgpack!({ "name": "Alice", "extra": 1 })).unwrap_err();
+        assert_eq!(err.to_string(), "Unknown, invalid field: \"extra\"");
+
+        // Strict mode carries through intersections
+        let mut builder = ValidBuilder::init(&types, &types);
+        let index = builder.intersect(false, loose, strict).unwrap();
+        let types2 = builder.build();
+        let err = check(&types2, index, msgpack!({ "name": "Alice", "extra": 1 })).unwrap_err();
+        assert!(err.to_string().starts_with("Field \"extra\" not allowed."));
+    }
+
+    #[test]
+    fn field_deps() {
+        let read = |value: Value, types: &mut Vec<Validator>| {
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &value);
+            Validator::read_validator(&mut &raw[..], false, types, &mut HashMap::new()).unwrap()
+        };
+        let check = |types: &[Validator], index: usize, value: Value| {
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &value);
+            types[index].validate("", &mut &raw[..], types, index, &mut Checklist::new())
+        };
+        let mut types = vec![Validator::Invalid, Validator::Valid];
+        let dates = read(msgpack!({
+            "type": "Obj",
+            "field_deps": [["end_date", "start_date"]],
+            "opt": {
+                "end_date": { "type": "Int" },
+                "start_date": { "type": "Int" },
+                "title": { "type": "Str" }
+            }
+        }), &mut types);
+        let titled = read(msgpack!({
+            "type": "Obj",
+            "field_deps": [["start_date", "title"]],
+            "unknown_ok": true
+        }), &mut types);
+
+        assert!(check(&types, dates, msgpack!({ "title": "None" })).is_ok());
+        assert!(check(&types, dates, msgpack!({ "start_date": 1 })).is_ok());
+        assert!(check(&types, dates, msgpack!({ "end_date": 2, "start_date": 1 })).is_ok());
+        let err = check(&types, dates, msgpack!({ "end_date": 2, "title": "No start" })).unwrap_err();
+        assert_eq!(err.to_string(),
+            "Field \"\" is missing \"start_date\", which is required when \"end_date\" is present");
+
+        // Both sets of dependencies must hold after intersection
+        let mut builder = ValidBuilder::init(&types, &types);
+        let index = builder.intersect(false, dates, titled).unwrap();
+        let types2 = builder.build();
+        assert!(check(&types2, index, msgpack!({ "title": "None" })).is_ok());
+        assert!(check(&types2, index, msgpack!({ "end_date": 2, "start_date": 1, "title": "Both" })).is_ok());
+        assert!(check(&types2, index, msgpack!({ "end_date": 2, "title": "No start" })).is_err());
+        assert!(check(&types2, index, msgpack!({ "end_date": 2, "start_date": 1 })).is_err());
+    }
+
+    #[test]
+    fn extra_fields() {
+        let read = |value: Value, types: &mut Vec<Validator>| {
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &value);
+            Validator::read_validator(&mut &raw[..], false, types, &mut HashMap::new()).unwrap()
+        };
+        let check = |types: &[Validator], index: usize, value: Value| {
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &value);
+            types[index].validate("", &mut &raw[..], types, index, &mut Checklist::new())
+        };
+        let mut types = vec![Validator::Invalid, Validator::Valid];
+        // An open-ended record that must carry at least one extension
+        let extended = read(msgpack!({
+            "type": "Obj",
+            "req": { "id": { "type": "Int" } },
+            "opt": { "name": { "type": "Str" } },
+            "unknown_ok": true,
+            "min_extra": 1
+        }), &mut types);
+        let limited = read(msgpack!({
+            "type": "Obj",
+            "opt": { "name": { "type": "Str" } },
+            "unknown_ok": true,
+            "max_extra": 3
+        }), &mut types);
+
+        assert!(check(&types, extended, msgpack!({ "id": 1, "ext": true })).is_ok());
+        assert!(check(&types, extended, msgpack!({ "id": 1, "name": "a", "ext": true, "ext2": 1 })).is_ok());
+        let err = check(&types, extended, msgpack!({ "id": 1, "name": "a" })).unwrap_err();
+        assert_eq!(err.to_string(), "Field \"\" contains object with 0 extra fields, less than the 1 required");
+
+        assert!(check(&types, limited, msgpack!({})).is_ok());
+        assert!(check(&types, limited, msgpack!({ "name": "a", "a": 1, "b": 2, "c": 3 })).is_ok());
+        let err = check(&types, limited, msgpack!({ "a": 1, "b": 2, "c": 3, "d": 4 })).unwrap_err();
+        assert_eq!(err.to_string(), "Field \"\" contains object with 4 extra fields, more than the 3 allowed");
+
+        // Requiring extra fields when none are allowed can never pass
+        assert_eq!(INVALID, read(msgpack!({ "type": "Obj", "min_extra": 1 }), &mut types));
+        assert_eq!(INVALID, read(msgpack!({ "type": "Obj", "unknown_ok": true, "max_extra": 1, "min_extra": 2 }), &mut types));
+
+        // Intersecting keeps the tighter of both bounds
+        let mut builder = ValidBuilder::init(&types, &types);
+        let index = builder.intersect(false, extended, limited).unwrap();
+        let types2 = builder.build();
+        assert!(check(&types2, index, msgpack!({ "id": 1, "a": 1 })).is_ok());
+        assert!(check(&types2, index, msgpack!({ "id": 1 })).is_err());
+        assert!(check(&types2, index, msgpack!({ "id": 1, "a": 1, "b": 2, "c": 3, "d": 4 })).is_err());
+    }
+
+    #[test]
+    fn transforms() {
+        let read = |value: Value, types: &mut Vec<Validator>| {
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &value);
+            Validator::read_validator(&mut &raw[..], false, types, &mut HashMap::new()).unwrap()
+        };
+        let check = |types: &[Validator], index: usize, value: Value| {
+            let mut raw = Vec::new();
+            encode::write_value(&mut raw, &value);
+            types[index].validate("", &mut &raw[..], types, index, &mut Checklist::new())
+        };
+        let mut types = vec![Validator::Invalid, Validator::Valid];
+        let contact = read(msgpack!({
+            "type": "Obj",
+            "req": {
+                "email": { "type": "Str", "matches": "^[a-z]+@[a-z]+\\.com$" },
+                "name": { "type": "Str", "in": ["Alice", "Bob"] }
+            },
+            "opt": {
+                "code": { "type": "Str", "in": ["ABC"] },
+                "id": { "type": "Str", "in": ["x1"] }
+            },
+            "transforms": { "code": "upper", "email": "lower", "id": "none", "name": "trim" }
+        }), &mut types);
+
+        assert!(check(&types, contact, msgpack!({ "email": "alice@example.com", "name": "Alice" })).is_ok());
+        assert!(check(&types, contact, msgpack!({ "email": "Alice@Example.COM", "name": "  Alice\t" })).is_ok());
+        assert!(check(&types, contact, msgpack!({ "code": "aBc", "email": "BOB@EXAMPLE.COM", "name": "Bob" })).is_ok());
+        assert!(check(&types, contact, msgpack!({ "email": "Alice@Example.COM", "name": "alice" })).is_err());
+        assert!(check(&types, contact, msgpack!({ "email": "alice@example.com", "id": "X1", "name": "Alice" })).is_err());
+        // Non-string values are validated unchanged
+        assert!(check(&types, contact, msgpack!({ "email": "alice@example.com", "name": 1 })).is_err());
+
+        // Unknown transforms are rejected
+        let mut raw = Vec::new();
+        encode::write_value(&mut raw, &msgpack!({ "type": "Obj", "transforms": { "name": "reverse" } }));
+        assert!(Validator::read_validator(&mut &raw[..], false, &mut types, &mut HashMap::new()).is_err());
+
+        // Transforms carry through intersections, but can't conflict
+        let upper = read(msgpack!({ "type": "Obj", "unknown_ok": true, "transforms": { "email": "upper" } }), &mut types);
+        let trim = read(msgpack!({ "type": "Obj", "unknown_ok": true, "transforms": { "name": "trim" } }), &mut types);
+        let mut builder = ValidBuilder::init(&types, &types);
+        let index = builder.intersect(false, contact, trim).unwrap();
+        let conflict = builder.intersect(false, contact, upper).unwrap();
+        let types2 = builder.build();
+        assert!(check(&types2, index, msgpack!({ "email": "Alice@Example.COM", "name": " Bob " })).is_ok());
+        assert_eq!(conflict, INVALID);
+
+        // A transform can't be applied to a field the other validator checks without it
+        let lower = read(msgpack!({ "type": "Obj", "req": { "email": { "type": "Str" } }, "transforms": { "email": "lower" } }), &mut types);
+        let plain = read(msgpack!({ "type": "Obj", "req": { "email": { "type": "Str", "matches": "^[a-z]+$" } } }), &mut types);
+        let any_field = read(msgpack!({ "type": "Obj", "unknown_ok": true, "field_type": { "type": "Str" } }), &mut types);
+        let same = read(msgpack!({ "type": "Obj", "req": { "email": { "type": "Str", "matches": "^[a-z]+$" } }, "transforms": { "email": "lower" } }), &mut types);
+        let mut builder = ValidBuilder::init(&types, &types);
+        assert_eq!(builder.intersect(false, lower, plain).unwrap(), INVALID);
+        assert_eq!(builder.intersect(false, plain, lower).unwrap(), INVALID);
+        assert_eq!(builder.intersect(false, lower, any_field).unwrap(), INVALID);
+        let index = builder.intersect(false, lower, same).unwrap();
+        let types2 = builder.build();
+        assert!(check(&types2, index, msgpack!({ "email": "ABC" })).is_ok());
+        assert!(check(&types2, index, msgpack!({ "email": "A1" })).is_err());
+    }
 }
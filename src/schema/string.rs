@@ -3,10 +3,26 @@ use std::io::Error;
 use std::io::ErrorKind::InvalidData;
 
 use regex::Regex;
+use unicode_segmentation::UnicodeSegmentation;
 
 use decode::*;
 use super::{MAX_VEC_RESERVE, sorted_union, sorted_intersection, Validator};
 use marker::MarkerType;
+use value::Value;
+
+/// Unicode general categories that can be allowed with the `categories` field, as (name, regex 
+/// class, example character). A category's bit in the allowed mask is its index in this list.
+const CATEGORIES: [(&str, &str, char); 7] = [
+    ("Letter", "\\p{L}", 'a'),
+    ("Mark", "\\p{M}", '\u{301}'),
+    ("Number", "\\p{N}", '0'),
+    ("Punctuation", "\\p{P}", '.'),
+    ("Symbol", "\\p{S}", '+'),
+    ("Separator", "\\p{Z}", ' '),
+    ("Other", "\\p{C}", '\u{0}'),
+];
+/// Bit for the "Separator" category, which contains the space character.
+const SEPARATOR_BIT: u64 = 1 << 5;
 
 /// String type validator
 #[derive(Clone, Debug)]
@@ -15,32 +31,43 @@ pub struct ValidStr {
     nin_vec: Vec<String>,
     min_len: usize,
     max_len: usize,
+    max_codepoints: usize,
+    max_graphemes: usize,
     matches: Vec<Regex>,
+    /// String may only contain ASCII characters
+    ascii_only: bool,
+    /// Bitmask of the Unicode general categories characters may be in, indexed by `CATEGORIES`
+    allowed_categories: Option<u64>,
+    /// Anchored character class matching only strings in the allowed categories. Built by 
+    /// `finalize`.
+    category_regex: Option<Regex>,
     query: bool,
     ord: bool,
     regex: bool,
 }
 
 impl ValidStr {
-    pub fn new(is_query: bool) -> ValidStr {
-        ValidStr {
-            in_vec: Vec::with_capacity(0),
-            nin_vec: Vec::with_capacity(0),
-            min_len: usize::min_value(),
-            max_len: usize::max_value(),
-            matches: Vec::with_capacity(0),
-            query: is_query,
-            ord: is_query,
-            regex: is_query,
-        }
-    }
+    impl_validator_new!(ValidStr, |is_query| {
+        in_vec: Vec::with_capacity(0),
+        nin_vec: Vec::with_capacity(0),
+        min_len: usize::min_value(),
+        max_len: usize::max_value(),
+        max_codepoints: usize::max_value(),
+        max_graphemes: usize::max_value(),
+        matches: Vec::with_capacity(0),
+        ascii_only: false,
+        allowed_categories: None,
+        category_regex: None,
+        query: is_query,
+        ord: is_query,
+        regex: is_query,
+    });
 
-    pub fn from_const(constant: &str, is_query: bool) -> ValidStr {
-        let mut v = ValidStr::new(is_query);
-        let mut in_vec = Vec::with_capacity(1);
-        in_vec.push(constant.to_string());
-        v.in_vec = in_vec;
-        v
+    impl_validator_from_const!(ValidStr, &str, |constant| constant.to_string());
+
+    /// Human-readable name of this validator type, for use in error messages.
+    pub fn type_name() -> &'static str {
+        "String"
     }
 
     /// Update the validator. Returns `Ok(true)` if everything is read out Ok, `Ok(false)` if we 
@@ -51,11 +78,40 @@ impl ValidStr {
         // Note about this match: because fields are lexicographically ordered, the items in this 
         // match statement are either executed sequentially or are skipped.
         match field {
+            "ascii" => {
+                self.ascii_only = read_bool(raw)?;
+                Ok(true)
+            },
+            "categories" => {
+                let mut mask = 0u64;
+                let mut add = |name: &str| {
+                    match CATEGORIES.iter().position(|c| c.0 == name) {
+                        Some(i) => { mask |= 1 << i; Ok(()) },
+                        None => Err(Error::new(InvalidData, format!("Unknown Unicode category \"{}\"", name))),
+                    }
+                };
+                match read_marker(raw)? {
+                    MarkerType::String(len) => add(read_raw_str(raw, len)?)?,
+                    MarkerType::Array(len) => {
+                        for _i in 0..len {
+                            add(read_str(raw)?)?;
+                        }
+                    },
+                    _ => {
+                        return Err(Error::new(InvalidData, format!("{} validator expected array or string for `categories` field", Self::type_name())));
+                    },
+                }
+                self.allowed_categories = Some(mask);
+                Ok(true)
+            },
             "default" => {
                 read_string(raw)?;
                 Ok(true)
             }
-            "in" => {
+            "enum" | "in" => {
+                if self.in_vec.len() > 0 {
+                    return Err(Error::new(InvalidData, format!("{} validator can't have both `enum` and `in` fields", Self::type_name())));
+                }
                 match read_marker(raw)? {
                     MarkerType::String(len) => {
                         let v = read_raw_str(raw, len)?;
@@ -71,7 +127,7 @@ impl ValidStr {
                         self.in_vec.dedup();
                     },
                     _ => {
-                        return Err(Error::new(InvalidData, "String validator expected array or constant for `in` field"));
+                        return Err(Error::new(InvalidData, format!("{} validator expected array or constant for `{}` field", Self::type_name(), field)));
                     },
                 }
                 Ok(true)
@@ -109,10 +165,28 @@ impl ValidStr {
                         Ok(regexes_ok)
                     },
                     _ => {
-                        return Err(Error::new(InvalidData, "String validator expected array or string for `matches` field"));
+                        return Err(Error::new(InvalidData, format!("{} validator expected array or string for `matches` field", Self::type_name())));
                     },
                 }
             },
+            "max_codepoints" => {
+                if let Some(len) = read_integer(raw)?.as_u64() {
+                    self.max_codepoints = len as usize;
+                    Ok(true)
+                }
+                else {
+                    Ok(false)
+                }
+            }
+            "max_graphemes" => {
+                if let Some(len) = read_integer(raw)?.as_u64() {
+                    self.max_graphemes = len as usize;
+                    Ok(true)
+                }
+                else {
+                    Ok(false)
+                }
+            }
             "max_len" => {
                 if let Some(len) = read_integer(raw)?.as_u64() {
                     self.max_len = len as usize;
@@ -147,7 +221,7 @@ impl ValidStr {
                         self.nin_vec.dedup();
                     },
                     _ => {
-                        return Err(Error::new(InvalidData, "String validator expected array or constant for `nin` field"));
+                        return Err(Error::new(InvalidData, format!("{} validator expected array or constant for `nin` field", Self::type_name())));
                     },
                 }
                 Ok(true)
@@ -165,13 +239,29 @@ impl ValidStr {
                 Ok(true)
             },
             "type" => if "Str" == read_str(raw)? { Ok(true) } else { Err(Error::new(InvalidData, "Type doesn't match Str")) },
-            _ => Err(Error::new(InvalidData, "Unknown fields not allowed in string validator")),
+            _ => Err(Error::new(InvalidData, format!("Unknown fields not allowed in {} validator", Self::type_name()))),
         }
     }
 
+    /// Returns true if the validator is only a list of allowed strings, with no other 
+    /// constraints.
+    pub fn is_enum(&self) -> bool {
+        (self.in_vec.len() > 0)
+            && (self.nin_vec.len() == 0)
+            && (self.min_len == usize::min_value())
+            && (self.max_len == usize::max_value())
+            && (self.max_codepoints == usize::max_value())
+            && (self.max_graphemes == usize::max_value())
+            && (self.matches.len() == 0)
+            && !self.ascii_only
+            && self.allowed_categories.is_none()
+    }
+
     /// Final check on the validator. Returns true if at least one value can still pass the 
     /// validator.
     pub fn finalize(&mut self) -> bool {
+        deduplicate_matches(&mut self.matches);
+        self.category_regex = self.allowed_categories.map(category_regex);
         if self.in_vec.len() > 0 {
             let mut in_vec: Vec<String> = Vec::with_capacity(self.in_vec.len());
             let mut nin_index = 0;
@@ -183,6 +273,10 @@ impl ValidStr {
                     if nin == val { continue; }
                 }
                 if (val.len() >= self.min_len) && (val.len() <= self.max_len) 
+                    && (val.chars().count() <= self.max_codepoints)
+                    && (grapheme_count(val) <= self.max_graphemes)
+                    && (!self.ascii_only || val.is_ascii())
+                    && self.categories_ok(val)
                     && self.matches.iter().all(|reg| reg.is_match(val))
                 {
                     in_vec.push(val.clone());
@@ -196,10 +290,17 @@ impl ValidStr {
         else {
             let min_len = self.min_len;
             let max_len = self.max_len;
+            let max_codepoints = self.max_codepoints;
+            let max_graphemes = self.max_graphemes;
+            let ascii_only = self.ascii_only;
             // Only keep `nin` values that would otherwise pass
             let mut nin_vec = self.nin_vec.clone();
             nin_vec.retain(|val| {
                 (val.len() >= min_len) && (val.len() <= max_len) 
+                    && (val.chars().count() <= max_codepoints)
+                    && (grapheme_count(val) <= max_graphemes)
+                    && (!ascii_only || val.is_ascii())
+                    && self.categories_ok(val)
                     && self.matches.iter().all(|reg| reg.is_match(val))
             });
             nin_vec.shrink_to_fit();
@@ -208,6 +309,27 @@ impl ValidStr {
         }
     }
 
+    /// Checks if every character in the string is in one of the allowed Unicode categories.
+    fn categories_ok(&self, val: &str) -> bool {
+        self.category_regex.as_ref().map_or(true, |reg| reg.is_match(val))
+    }
+
+    /// Generate a minimal example value that passes this validator: the first allowed string, or a 
+    /// string of `min_len` spaces (or of another character if spaces aren't in an allowed 
+    /// category).
+    pub fn example_value(&self) -> Option<Value> {
+        if let Some(v) = self.in_vec.first() {
+            return Some(Value::from(v.as_str()));
+        }
+        let c = match self.allowed_categories {
+            Some(mask) if (mask & SEPARATOR_BIT) == 0 => {
+                CATEGORIES.iter().enumerate().find(|(i, _)| (mask & (1 << i)) != 0).map_or(' ', |(_, c)| c.2)
+            },
+            _ => ' ',
+        };
+        Some(Value::from(c.to_string().repeat(self.min_len)))
+    }
+
     pub fn validate(&self, field: &str, doc: &mut &[u8]) -> io::Result<()> {
         let value = read_str(doc)?;
         if (self.in_vec.len() > 0) && self.in_vec.binary_search_by(|probe| (**probe).cmp(value)).is_err() {
@@ -225,10 +347,26 @@ impl ValidStr {
             Err(Error::new(InvalidData,
                 format!("Field \"{}\" contains string longer than max length of {}", field, self.min_len)))
         }
+        else if (self.max_codepoints < usize::max_value()) && (value.chars().count() > self.max_codepoints) {
+            Err(Error::new(InvalidData,
+                format!("Field \"{}\" contains string with more than {} code points", field, self.max_codepoints)))
+        }
+        else if (self.max_graphemes < usize::max_value()) && (grapheme_count(value) > self.max_graphemes) {
+            Err(Error::new(InvalidData,
+                format!("Field \"{}\" contains string with more than {} grapheme clusters", field, self.max_graphemes)))
+        }
         else if self.nin_vec.binary_search_by(|probe| (**probe).cmp(value)).is_ok() {
             Err(Error::new(InvalidData,
                 format!("Field \"{}\" contains string on the `nin` list", field)))
         }
+        else if self.ascii_only && !value.is_ascii() {
+            Err(Error::new(InvalidData,
+                format!("Field \"{}\" contains string with non-ASCII characters", field)))
+        }
+        else if !self.categories_ok(value) {
+            Err(Error::new(InvalidData,
+                format!("Field \"{}\" contains characters outside the allowed Unicode categories", field)))
+        }
         else if self.matches.iter().any(|reg| !reg.is_match(value)) {
             Err(Error::new(InvalidData,
                 format!("Field \"{}\" fails regex check", field)))
@@ -238,20 +376,29 @@ impl ValidStr {
         }
     }
 
+    /// Checks if `other` only uses query operations this String validator allows. If not, 
+    /// `intersect` fails when `query` is true.
+    pub fn query_ok(&self, other: &Validator) -> bool {
+        if !self.query && !self.ord && !self.regex { return false; }
+        match other {
+            Validator::String(other) => !(
+                (!self.query && (!other.in_vec.is_empty() || !other.nin_vec.is_empty()))
+                || (!self.ord && ((other.min_len > usize::min_value()) || (other.max_len < usize::max_value())
+                    || (other.max_codepoints < usize::max_value())
+                    || (other.max_graphemes < usize::max_value())))
+                || (!self.regex && ((other.matches.len() > 0) || other.ascii_only
+                    || other.allowed_categories.is_some()))),
+            _ => true,
+        }
+    }
+
     /// Intersection of String with other Validators. Returns Err only if `query` is true and the 
     /// other validator contains non-allowed query parameters.
     pub fn intersect(&self, other: &Validator, query: bool) -> Result<Validator, ()> {
-        if query && !self.query && !self.ord && !self.regex { return Err(()); }
+        if query && !self.query_ok(other) { return Err(()); }
         match other {
             Validator::String(other) => {
-                if query && (
-                    (!self.query && (!other.in_vec.is_empty() || !other.nin_vec.is_empty()))
-                    || (!self.ord && ((other.min_len > usize::min_value()) || (other.max_len < usize::max_value())))
-                    || (!self.regex && (other.matches.len() > 0)))
-                {
-                    Err(())
-                }
-                else if (self.min_len > other.max_len) || (self.max_len < other.min_len) 
+                if (self.min_len > other.max_len) || (self.max_len < other.min_len) 
                 {
                     Ok(Validator::Invalid)
                 }
@@ -267,12 +414,21 @@ impl ValidStr {
                     };
                     let mut matches = self.matches.clone();
                     matches.extend_from_slice(&other.matches);
+                    deduplicate_matches(&mut matches);
                     let mut new_validator = ValidStr {
                         in_vec: in_vec,
                         nin_vec: sorted_union(&self.nin_vec[..], &other.nin_vec[..], |a,b| a.cmp(b)),
                         min_len: self.min_len.max(other.min_len),
                         max_len: self.max_len.min(other.max_len),
+                        max_codepoints: self.max_codepoints.min(other.max_codepoints),
+                        max_graphemes: self.max_graphemes.min(other.max_graphemes),
                         matches: matches,
+                        ascii_only: self.ascii_only || other.ascii_only,
+                        allowed_categories: match (self.allowed_categories, other.allowed_categories) {
+                            (Some(s), Some(o)) => Some(s & o),
+                            (s, o) => s.or(o),
+                        },
+                        category_regex: None,
                         query: self.query && other.query,
                         ord: self.ord && other.ord,
                         regex: self.regex && other.regex,
@@ -295,6 +451,30 @@ impl ValidStr {
     }
 }
 
+/// Build a regex that only matches strings whose characters are all in the categories set in 
+/// `mask`. The regex crate's Unicode tables already cover the general categories, and a single 
+/// anchored character class runs much faster than an equivalent user-written `matches` regex.
+fn category_regex(mask: u64) -> Regex {
+    let classes: String = CATEGORIES.iter().enumerate()
+        .filter(|(i, _)| (mask & (1 << i)) != 0)
+        .map(|(_, c)| c.1)
+        .collect();
+    let pattern = if classes.is_empty() { String::from("^$") } else { format!("^[{}]*$", classes) };
+    // Only built from the fixed classes above, so this can't fail
+    Regex::new(&pattern).unwrap()
+}
+
+/// Count the extended grapheme clusters in a string.
+fn grapheme_count(s: &str) -> usize {
+    s.graphemes(true).count()
+}
+
+/// Sort a list of regexes by their pattern strings and remove any repeated patterns.
+fn deduplicate_matches(matches: &mut Vec<Regex>) {
+    matches.sort_unstable_by(|a, b| a.as_str().cmp(b.as_str()));
+    matches.dedup_by(|a, b| a.as_str() == b.as_str());
+}
+
 #[cfg(test)]
 mod tests {
     use encode;
@@ -452,4 +632,258 @@ mod tests {
         assert!(validate_str("string", &validi).is_err());
     }
 
+    #[test]
+    fn regex_dedup() {
+        let mut test1 = Vec::new();
+        encode::write_value(&mut test1, &msgpack!({
+            "matches": ["^[a-z]+", "test"],
+        }));
+        let valid1 = read_it(&mut &test1[..], false).unwrap();
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({
+            "matches": "^[a-z]+",
+        }));
+        let valid2 = read_it(&mut &test1[..], false).unwrap();
+        let validi = valid1.intersect(&Validator::String(valid2), false).unwrap();
+        let validi = if let Validator::String(v) = validi {
+            v
+        }
+        else {
+            panic!("Intersection invalid");
+        };
+        let patterns: Vec<&str> = validi.matches.iter().map(|r| r.as_str()).collect();
+        assert_eq!(patterns, vec!["^[a-z]+", "test"]);
+        assert!(validate_str("attest", &validi).is_ok());
+        assert!(validate_str("Test", &validi).is_err());
+    }
+
+
+    #[test]
+    fn enum_str() {
+        let mut test1 = Vec::new();
+        let mut test2 = Vec::new();
+        encode::write_value(&mut test1, &msgpack!({
+            "enum": ["pending", "active", "archived"],
+            "type": "Str"
+        }));
+        encode::write_value(&mut test2, &msgpack!({
+            "in": ["pending", "active", "archived"],
+            "type": "Str"
+        }));
+        let validator1 = read_it(&mut &test1[..], false).unwrap();
+        let validator2 = read_it(&mut &test2[..], false).unwrap();
+        assert_eq!(format!("{:?}", validator1), format!("{:?}", validator2));
+        assert!(validator1.is_enum());
+        assert!(validate_str("active", &validator1).is_ok());
+        assert!(validate_str("deleted", &validator1).is_err());
+
+        let mut test1 = Vec::new();
+        encode::write_value(&mut test1, &msgpack!({
+            "enum": ["pending", "active", "archived"],
+            "max_len": 7,
+            "type": "Str"
+        }));
+        let validator = read_it(&mut &test1[..], false).unwrap();
+        assert!(!validator.is_enum());
+        assert!(!ValidStr::new(false).is_enum());
+
+        let mut test1 = Vec::new();
+        encode::write_value(&mut test1, &msgpack!({
+            "enum": ["pending"],
+            "in": ["active"],
+            "type": "Str"
+        }));
+        assert!(read_it(&mut &test1[..], false).is_err());
+    }
+
+    #[test]
+    fn max_codepoints() {
+        let mut test1 = Vec::new();
+        encode::write_value(&mut test1, &msgpack!({
+            "max_codepoints": 4,
+            "max_len": 16,
+            "type": "Str"
+        }));
+        let validator = read_it(&mut &test1[..], false).unwrap();
+        assert!(validate_str("Test", &validator).is_ok());
+        assert!(validate_str("Tests", &validator).is_err());
+        // 4 code points, 16 bytes
+        assert!(validate_str("\u{1F600}\u{1F601}\u{1F602}\u{1F603}", &validator).is_ok());
+        // 5 code points, 17 bytes
+        assert!(validate_str("a\u{1F600}\u{1F601}\u{1F602}\u{1F603}", &validator).is_err());
+        // Family emoji: 1 grapheme cluster, but 5 code points and 18 bytes
+        assert!(validate_str("\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}", &validator).is_err());
+        // Family emoji: 3 code points, 11 bytes
+        assert!(validate_str("\u{1F468}\u{200D}\u{1F469}", &validator).is_ok());
+
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({
+            "max_codepoints": 2,
+            "type": "Str"
+        }));
+        let valid2 = read_it(&mut &test1[..], false).unwrap();
+        let validi = validator.intersect(&Validator::String(valid2), false).unwrap();
+        let validi = if let Validator::String(v) = validi {
+            v
+        }
+        else {
+            panic!("Intersection invalid");
+        };
+        assert!(validate_str("\u{1F600}\u{1F601}", &validi).is_ok());
+        assert!(validate_str("\u{1F600}\u{1F601}\u{1F602}", &validi).is_err());
+
+        // Enum values with too many code points are dropped
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({
+            "in": ["ab", "abc"],
+            "max_codepoints": 2,
+            "type": "Str"
+        }));
+        let validator = read_it(&mut &test1[..], false).unwrap();
+        assert!(validate_str("ab", &validator).is_ok());
+        assert!(validate_str("abc", &validator).is_err());
+    }
+
+    #[test]
+    fn max_graphemes() {
+        let mut test1 = Vec::new();
+        encode::write_value(&mut test1, &msgpack!({
+            "max_graphemes": 2,
+            "type": "Str"
+        }));
+        let validator = read_it(&mut &test1[..], false).unwrap();
+        assert!(validate_str("ab", &validator).is_ok());
+        assert!(validate_str("abc", &validator).is_err());
+
+        // Thumbs up with a skin tone modifier, and a family joined with zero-width joiners
+        let thumbs = "\u{1F44D}\u{1F3FD}";
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        let combining = "e\u{301}";
+        assert!(validate_str(thumbs, &validator).is_ok());
+        assert!(validate_str(&format!("{}{}", thumbs, family), &validator).is_ok());
+        assert!(validate_str(&format!("{}{}", combining, combining), &validator).is_ok());
+        assert!(validate_str(&format!("{}{}{}", thumbs, family, combining), &validator).is_err());
+
+        // Intersection takes the smaller limit
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({
+            "max_graphemes": 1,
+            "type": "Str"
+        }));
+        let valid2 = read_it(&mut &test1[..], false).unwrap();
+        let validi = match validator.intersect(&Validator::String(valid2), false).unwrap() {
+            Validator::String(v) => v,
+            _ => panic!("Intersection invalid"),
+        };
+        assert!(validate_str("a", &validi).is_ok());
+        assert!(validate_str("ab", &validi).is_err());
+        assert!(validate_str(family, &validi).is_ok());
+    }
+
+    #[test]
+    fn categories() {
+        let mut test1 = Vec::new();
+        encode::write_value(&mut test1, &msgpack!({
+            "categories": ["Letter"],
+            "type": "Str"
+        }));
+        let letters = read_it(&mut &test1[..], false).unwrap();
+        assert!(validate_str("", &letters).is_ok());
+        assert!(validate_str("abcXYZ", &letters).is_ok());
+        assert!(validate_str("\u{e9}t\u{e9}\u{3b1}\u{3b2}\u{65e5}", &letters).is_ok());
+        assert!(validate_str("abc1", &letters).is_err());
+        assert!(validate_str("\u{663}", &letters).is_err()); // Arabic-Indic digit three
+        assert!(validate_str("abc.", &letters).is_err());
+        assert!(validate_str("\u{bf}abc", &letters).is_err()); // Inverted question mark
+        assert!(validate_str("a b", &letters).is_err());
+        assert!(validate_str("e\u{301}", &letters).is_err());
+
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({
+            "categories": ["Letter", "Number"],
+            "min_len": 2,
+            "type": "Str"
+        }));
+        let alnum = read_it(&mut &test1[..], false).unwrap();
+        assert!(validate_str("a1", &alnum).is_ok());
+        assert!(validate_str("\u{2167}", &alnum).is_ok()); // Roman numeral eight
+        assert!(validate_str("a+1", &alnum).is_err());
+        let example = alnum.example_value().unwrap();
+        assert!(validate_str(example.as_str().unwrap(), &alnum).is_ok());
+
+        // Unknown categories are rejected
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({
+            "categories": ["Letters"],
+            "type": "Str"
+        }));
+        assert!(read_it(&mut &test1[..], false).is_err());
+
+        // Intersection only keeps categories allowed by both
+        let validi = match alnum.intersect(&Validator::String(letters.clone()), false).unwrap() {
+            Validator::String(v) => v,
+            _ => panic!("Intersection invalid"),
+        };
+        assert!(validate_str("ab", &validi).is_ok());
+        assert!(validate_str("a1", &validi).is_err());
+        let validi = match ValidStr::new(false).intersect(&Validator::String(letters.clone()), false).unwrap() {
+            Validator::String(v) => v,
+            _ => panic!("Intersection invalid"),
+        };
+        assert!(validate_str("ab", &validi).is_ok());
+        assert!(validate_str("a1", &validi).is_err());
+
+        // Enum values outside the categories are dropped
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({
+            "categories": "Number",
+            "in": ["123", "abc"],
+            "type": "Str"
+        }));
+        let validator = read_it(&mut &test1[..], false).unwrap();
+        assert!(validate_str("123", &validator).is_ok());
+        assert!(validate_str("abc", &validator).is_err());
+    }
+
+    #[test]
+    fn ascii() {
+        let mut test1 = Vec::new();
+        encode::write_value(&mut test1, &msgpack!({
+            "ascii": true,
+            "type": "Str"
+        }));
+        let validator = read_it(&mut &test1[..], false).unwrap();
+        assert!(validate_str("", &validator).is_ok());
+        assert!(validate_str("Plain ASCII\t~", &validator).is_ok());
+        assert!(validate_str("caf\u{E9}", &validator).is_err());
+        assert!(validate_str("\u{1F600}", &validator).is_err());
+
+        // Regexes still apply alongside the ASCII check
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({
+            "ascii": true,
+            "matches": "^[a-z\u{E9}]+$",
+            "type": "Str"
+        }));
+        let validator = read_it(&mut &test1[..], false).unwrap();
+        assert!(validate_str("cafe", &validator).is_ok());
+        assert!(validate_str("caf\u{E9}", &validator).is_err());
+        assert!(validate_str("Cafe", &validator).is_err());
+
+        // Intersection keeps the flag, and non-ASCII enum values are dropped
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({
+            "in": ["cafe", "caf\u{E9}"],
+            "type": "Str"
+        }));
+        let valid2 = read_it(&mut &test1[..], false).unwrap();
+        assert!(validate_str("caf\u{E9}", &valid2).is_ok());
+        let validi = match validator.intersect(&Validator::String(valid2), false).unwrap() {
+            Validator::String(v) => v,
+            _ => panic!("Intersection invalid"),
+        };
+        assert!(validate_str("cafe", &validi).is_ok());
+        assert!(validate_str("caf\u{E9}", &validi).is_err());
+    }
+
 }
@@ -5,6 +5,7 @@ use std::io::ErrorKind::InvalidData;
 use decode::*;
 use super::{MAX_VEC_RESERVE, sorted_union, sorted_intersection, Validator};
 use timestamp::Timestamp;
+use value::Value;
 use marker::MarkerType;
 
 /// Timestamp type validator
@@ -14,6 +15,8 @@ pub struct ValidTime {
     nin_vec: Vec<Timestamp>,
     min: Timestamp,
     max: Timestamp,
+    max_age_seconds: Option<u64>,
+    max_future_seconds: Option<u64>,
     query: bool,
     ord: bool,
     ex_min: bool, // setup only
@@ -21,25 +24,24 @@ pub struct ValidTime {
 }
 
 impl ValidTime {
-    pub fn new(is_query: bool) -> ValidTime {
-        ValidTime {
-            in_vec: Vec::with_capacity(0),
-            nin_vec: Vec::with_capacity(0),
-            min: Timestamp::min_value(),
-            max: Timestamp::max_value(),
-            query: is_query,
-            ord: is_query,
-            ex_min: false,
-            ex_max: false,
-        }
-    }
+    impl_validator_new!(ValidTime, |is_query| {
+        in_vec: Vec::with_capacity(0),
+        nin_vec: Vec::with_capacity(0),
+        min: Timestamp::min_value(),
+        max: Timestamp::max_value(),
+        max_age_seconds: None,
+        max_future_seconds: None,
+        query: is_query,
+        ord: is_query,
+        ex_min: false,
+        ex_max: false,
+    });
+
+    impl_validator_from_const!(ValidTime, Timestamp);
 
-    pub fn from_const(constant: Timestamp, is_query: bool) -> ValidTime {
-        let mut v = ValidTime::new(is_query);
-        let mut in_vec = Vec::with_capacity(1);
-        in_vec.push(constant);
-        v.in_vec = in_vec;
-        v
+    /// Human-readable name of this validator type, for use in error messages.
+    pub fn type_name() -> &'static str {
+        "Timestamp"
     }
 
     /// Update the validator. Returns `Ok(true)` if everything is read out Ok, `Ok(false)` if we 
@@ -80,7 +82,7 @@ impl ValidTime {
                         self.in_vec.dedup();
                     },
                     _ => {
-                        return Err(Error::new(InvalidData, "Timestamp validator expected array or constant for `in` field"));
+                        return Err(Error::new(InvalidData, format!("{} validator expected array or constant for `in` field", Self::type_name())));
                     },
                 }
                 Ok(true)
@@ -95,6 +97,24 @@ impl ValidTime {
                     Ok(true)
                 }
             }
+            "max_age_seconds" => {
+                if let Some(age) = read_integer(raw)?.as_u64() {
+                    self.max_age_seconds = Some(age);
+                    Ok(true)
+                }
+                else {
+                    Ok(false)
+                }
+            }
+            "max_future_seconds" => {
+                if let Some(future) = read_integer(raw)?.as_u64() {
+                    self.max_future_seconds = Some(future);
+                    Ok(true)
+                }
+                else {
+                    Ok(false)
+                }
+            }
             "min" => {
                 let min = read_time(raw)?;
                 if self.ex_min && min == Timestamp::max_value() {
@@ -122,7 +142,7 @@ impl ValidTime {
                         self.nin_vec.dedup();
                     },
                     _ => {
-                        return Err(Error::new(InvalidData, "Timestamp validator expected array or constant for `nin` field"));
+                        return Err(Error::new(InvalidData, format!("{} validator expected array or constant for `nin` field", Self::type_name())));
                     },
                 }
                 Ok(true)
@@ -136,12 +156,13 @@ impl ValidTime {
                 Ok(true)
             }
             "type" => if "Time" == read_str(raw)? { Ok(true) } else { Err(Error::new(InvalidData, "Type doesn't match Time")) },
-            _ => Err(Error::new(InvalidData, "Unknown fields not allowed in Timestamp validator")),
+            _ => Err(Error::new(InvalidData, format!("Unknown fields not allowed in {} validator", Self::type_name()))),
         }
     }
 
     /// Final check on the validator. Returns true if at least one value can still pass the 
-    /// validator.
+    /// validator. The `max_age_seconds` and `max_future_seconds` limits depend on the time of 
+    /// validation, so they aren't considered here.
     pub fn finalize(&mut self) -> bool {
         if self.in_vec.len() > 0 {
             let mut in_vec: Vec<Timestamp> = Vec::with_capacity(self.in_vec.len());
@@ -175,6 +196,24 @@ impl ValidTime {
         }
     }
 
+    /// Generate a minimal example value that passes this validator: the first allowed value, the 
+    /// current time if the age is limited, the minimum if there is one, or the Unix epoch.
+    pub fn example_value(&self) -> Option<Value> {
+        if let Some(v) = self.in_vec.first() {
+            return Some(Value::from(*v));
+        }
+        let value = if self.max_age_seconds.is_some() || self.max_future_seconds.is_some() {
+            Timestamp::now()?
+        }
+        else if self.min > Timestamp::min_value() {
+            self.min
+        }
+        else {
+            Timestamp::from_sec(0).max(self.min).min(self.max)
+        };
+        Some(Value::from(value))
+    }
+
     pub fn validate(&self, field: &str, doc: &mut &[u8]) -> io::Result<()> {
         let value = read_time(doc)?;
         if (self.in_vec.len() > 0) && self.in_vec.binary_search(&value).is_err() {
@@ -196,25 +235,47 @@ impl ValidTime {
             Err(Error::new(InvalidData,
                 format!("Field \"{}\" is {}, which is on the `nin` list", field, value)))
         }
-        else {
+        else if self.max_age_seconds.is_none() && self.max_future_seconds.is_none() {
             Ok(())
         }
+        else {
+            let now = Timestamp::now()
+                .ok_or(Error::new(InvalidData, "System time can't be used to check timestamp age"))?;
+            if self.max_age_seconds.map_or(false, |age| more_than_seconds(now, value, age)) {
+                Err(Error::new(InvalidData,
+                    format!("Field \"{}\" is {}, more than {} seconds in the past", field, value, self.max_age_seconds.unwrap())))
+            }
+            else if self.max_future_seconds.map_or(false, |future| more_than_seconds(value, now, future)) {
+                Err(Error::new(InvalidData,
+                    format!("Field \"{}\" is {}, more than {} seconds in the future", field, value, self.max_future_seconds.unwrap())))
+            }
+            else {
+                Ok(())
+            }
+        }
 
     }
 
+    /// Checks if `other` only uses query operations this Timestamp validator allows. If not, 
+    /// `intersect` fails when `query` is true.
+    pub fn query_ok(&self, other: &Validator) -> bool {
+        if !self.query && !self.ord { return false; }
+        match other {
+            Validator::Timestamp(other) => !(
+                (!self.query && (!other.in_vec.is_empty() || !other.nin_vec.is_empty()))
+                || (!self.ord && ((other.min > Timestamp::min_value()) || (other.max < Timestamp::max_value())
+                    || other.max_age_seconds.is_some() || other.max_future_seconds.is_some()))),
+            _ => true,
+        }
+    }
+
     /// Intersection of Timestamp with other Validators. Returns Err only if `query` is true and the 
     /// other validator contains non-allowed query parameters.
     pub fn intersect(&self, other: &Validator, query: bool) -> Result<Validator, ()> {
-        if query && !self.query && !self.ord { return Err(()); }
+        if query && !self.query_ok(other) { return Err(()); }
         match other {
             Validator::Timestamp(other) => {
-                if query && (
-                    (!self.query && (!other.in_vec.is_empty() || !other.nin_vec.is_empty()))
-                    || (!self.ord && ((other.min > Timestamp::min_value()) || (other.max < Timestamp::max_value()))))
-                {
-                    Err(())
-                }
-                else if (self.min > other.max) || (self.max < other.min) 
+                if (self.min > other.max) || (self.max < other.min) 
                 {
                     Ok(Validator::Invalid)
                 }
@@ -236,6 +297,8 @@ impl ValidTime {
                         nin_vec: sorted_union(&self.nin_vec[..], &other.nin_vec[..], |a,b| a.cmp(b)),
                         min: self.min.max(other.min),
                         max: self.max.min(other.max),
+                        max_age_seconds: min_limit(self.max_age_seconds, other.max_age_seconds),
+                        max_future_seconds: min_limit(self.max_future_seconds, other.max_future_seconds),
                         query: self.query && other.query,
                         ord: self.ord && other.ord,
                         ex_min: false, // Doesn't get used by this point - for setup of validator only.
@@ -256,6 +319,22 @@ impl ValidTime {
     }
 }
 
+/// Check if `later` is more than `limit` seconds after `earlier`.
+fn more_than_seconds(later: Timestamp, earlier: Timestamp, limit: u64) -> bool {
+    let diff = (later.sec as i128 - earlier.sec as i128) * 1_000_000_000
+        + (later.nano as i128 - earlier.nano as i128);
+    diff > (limit as i128) * 1_000_000_000
+}
+
+/// Combine two optional upper limits, keeping the stricter one.
+fn min_limit(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use encode;
@@ -471,4 +550,107 @@ mod tests {
                 "Set intersection for Timestamp validators fails with {}", test_val);
         }
     }
+
+    #[test]
+    fn relative_time() {
+        let mut test1 = Vec::new();
+        let mut val = Vec::with_capacity(9);
+        let now = Timestamp::now().unwrap();
+
+        encode::write_value(&mut test1, &msgpack!({
+            "max_age_seconds": 300,
+            "max_future_seconds": 60
+        }));
+        let valid1 = read_it(&mut &test1[..], false).unwrap();
+        let check = |validator: &ValidTime, time: Timestamp, val: &mut Vec<u8>| {
+            val.clear();
+            encode::write_value(val, &Value::from(time));
+            validator.validate("", &mut &val[..]).is_ok()
+        };
+        assert!(check(&valid1, now, &mut val));
+        assert!(check(&valid1, now - 200, &mut val));
+        assert!(!check(&valid1, now - 400, &mut val));
+        assert!(check(&valid1, now + 30, &mut val));
+        assert!(!check(&valid1, now + 120, &mut val));
+        assert!(!check(&valid1, Timestamp::min_value(), &mut val));
+        assert!(!check(&valid1, Timestamp::max_value(), &mut val));
+
+        // Intersection keeps the stricter limits
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({
+            "max_age_seconds": 100
+        }));
+        let valid2 = read_it(&mut &test1[..], false).unwrap();
+        assert!(check(&valid2, now - 50, &mut val));
+        assert!(check(&valid2, now + 120, &mut val));
+        let validi = valid1.intersect(&Validator::Timestamp(valid2), false).unwrap();
+        let check_i = |time: Timestamp, val: &mut Vec<u8>| {
+            val.clear();
+            encode::write_value(val, &Value::from(time));
+            validi.validate("", &mut &val[..], &Vec::new(), 0, &mut Checklist::new()).is_ok()
+        };
+        assert!(check_i(now - 50, &mut val));
+        assert!(!check_i(now - 200, &mut val));
+        assert!(check_i(now + 30, &mut val));
+        assert!(!check_i(now + 120, &mut val));
+    }
+
+    #[test]
+    fn in_nin() {
+        let t = |sec: i64| Timestamp::from_raw(sec, 0).unwrap();
+        let mut val = Vec::with_capacity(9);
+        let check = |validator: &ValidTime, time: Timestamp, val: &mut Vec<u8>| {
+            val.clear();
+            encode::write_value(val, &Value::from(time));
+            validator.validate("", &mut &val[..]).is_ok()
+        };
+
+        let mut test1 = Vec::new();
+        encode::write_value(&mut test1, &msgpack!({
+            "in": [t(30), t(10), t(20), t(10)],
+            "nin": t(20)
+        }));
+        let valid1 = read_it(&mut &test1[..], false).unwrap();
+        assert!(check(&valid1, t(10), &mut val));
+        assert!(check(&valid1, t(30), &mut val));
+        assert!(!check(&valid1, t(20), &mut val));
+        assert!(!check(&valid1, t(15), &mut val));
+
+        // A single timestamp works for `in`, and matches a constant validator
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({ "in": t(30) }));
+        let valid2 = read_it(&mut &test1[..], false).unwrap();
+        let constant = ValidTime::from_const(t(30), false);
+        for v in [&valid2, &constant].iter() {
+            assert!(check(v, t(30), &mut val));
+            assert!(!check(v, t(10), &mut val));
+        }
+
+        // Intersection keeps only timestamps on both `in` lists
+        let validi = valid1.intersect(&Validator::Timestamp(valid2), false).unwrap();
+        let check_i = |time: Timestamp, val: &mut Vec<u8>| {
+            val.clear();
+            encode::write_value(val, &Value::from(time));
+            validi.validate("", &mut &val[..], &Vec::new(), 0, &mut Checklist::new()).is_ok()
+        };
+        assert!(check_i(t(30), &mut val));
+        assert!(!check_i(t(10), &mut val));
+
+        // Intersection combines `nin` lists
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({ "nin": [t(1), t(2)] }));
+        let valid3 = read_it(&mut &test1[..], false).unwrap();
+        test1.clear();
+        encode::write_value(&mut test1, &msgpack!({ "nin": t(3) }));
+        let valid4 = read_it(&mut &test1[..], false).unwrap();
+        let validi = valid3.intersect(&Validator::Timestamp(valid4), false).unwrap();
+        let check_i = |time: Timestamp, val: &mut Vec<u8>| {
+            val.clear();
+            encode::write_value(val, &Value::from(time));
+            validi.validate("", &mut &val[..], &Vec::new(), 0, &mut Checklist::new()).is_ok()
+        };
+        assert!(!check_i(t(1), &mut val));
+        assert!(!check_i(t(3), &mut val));
+        assert!(check_i(t(4), &mut val));
+    }
 }
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+
+use crypto::Hash;
+use decode;
+
+/// A flat secondary index on string fields of documents. Each indexed field maps a string value
+/// to the hashes of every document that has the field set to that value. Documents where the
+/// field is missing or isn't a string aren't indexed.
+pub struct StringFieldIndex {
+    fields: Vec<String>,
+    entries: HashMap<(String, String), Vec<Hash>>,
+}
+
+impl StringFieldIndex {
+    pub fn new() -> StringFieldIndex {
+        StringFieldIndex {
+            fields: Vec::new(),
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Start indexing a field. Returns false if the field was already indexed. Existing documents
+    /// must be added with `add` to be included.
+    pub fn add_field(&mut self, field: &str) -> bool {
+        if self.is_indexed(field) {
+            false
+        }
+        else {
+            self.fields.push(field.to_string());
+            true
+        }
+    }
+
+    /// Checks if the given field is being indexed.
+    pub fn is_indexed(&self, field: &str) -> bool {
+        self.fields.iter().any(|f| f == field)
+    }
+
+    /// Iterates over all fields being indexed.
+    pub fn fields(&self) -> impl Iterator<Item=&str> {
+        self.fields.iter().map(|f| f.as_str())
+    }
+
+    /// Add a document to the index for a field, if it has a string value for that field.
+    pub fn add(&mut self, doc_hash: &Hash, raw_doc: &[u8], field: &str) {
+        if let Some(value) = string_field(raw_doc, field) {
+            let hashes = self.entries.entry((field.to_string(), value)).or_insert_with(Vec::new);
+            if !hashes.contains(doc_hash) {
+                hashes.push(doc_hash.clone());
+            }
+        }
+    }
+
+    /// Remove a document from the index for a field.
+    pub fn remove(&mut self, doc_hash: &Hash, raw_doc: &[u8], field: &str) {
+        if let Some(value) = string_field(raw_doc, field) {
+            let key = (field.to_string(), value);
+            let now_empty = match self.entries.get_mut(&key) {
+                Some(hashes) => {
+                    hashes.retain(|h| h != doc_hash);
+                    hashes.is_empty()
+                },
+                None => false,
+            };
+            if now_empty {
+                self.entries.remove(&key);
+            }
+        }
+    }
+
+    /// Add a document to the index for every indexed field.
+    pub fn add_doc(&mut self, doc_hash: &Hash, raw_doc: &[u8]) {
+        for field in self.fields.clone().iter() {
+            self.add(doc_hash, raw_doc, field);
+        }
+    }
+
+    /// Remove a document from the index for every indexed field.
+    pub fn remove_doc(&mut self, doc_hash: &Hash, raw_doc: &[u8]) {
+        for field in self.fields.clone().iter() {
+            self.remove(doc_hash, raw_doc, field);
+        }
+    }
+
+    /// Iterates over the hashes of all documents with `field` set to `value`.
+    pub fn query<'a>(&'a self, field: &str, value: &str) -> impl Iterator<Item=Hash> + 'a {
+        self.entries.get(&(field.to_string(), value.to_string()))
+            .into_iter()
+            .flat_map(|hashes| hashes.iter().cloned())
+    }
+}
+
+/// Get the string value of a top-level field in a raw document, if it has one.
+fn string_field(raw_doc: &[u8], field: &str) -> Option<String> {
+    match decode::try_extract_field(raw_doc, field) {
+        Ok(Some(mut raw)) => decode::read_string(&mut raw).ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto;
+    use document::Document;
+
+    #[test]
+    fn string_field_index() {
+        crypto::init().unwrap();
+        let mut index = StringFieldIndex::new();
+        assert!(index.add_field("name"));
+        assert!(!index.add_field("name"));
+        assert!(index.is_indexed("name"));
+        assert!(!index.is_indexed("age"));
+
+        let names = ["alice", "bob", "carol"];
+        let mut docs = Vec::new();
+        for i in 0..10 {
+            let doc = Document::new(msgpack!({ "age": i, "name": names[i % 3] })).unwrap();
+            let (hash, raw) = (doc.hash(), doc.to_vec());
+            index.add(&hash, &raw[..], "name");
+            docs.push((hash, raw));
+        }
+        let non_string = Document::new(msgpack!({ "name": 1 })).unwrap();
+        index.add(&non_string.hash(), &non_string.to_vec()[..], "name");
+
+        let mut found: Vec<Hash> = index.query("name", "alice").collect();
+        found.sort();
+        let mut expected: Vec<Hash> = docs.iter().step_by(3).map(|d| d.0.clone()).collect();
+        expected.sort();
+        assert_eq!(found, expected);
+        assert_eq!(index.query("name", "bob").count(), 3);
+        assert_eq!(index.query("name", "dave").count(), 0);
+        assert_eq!(index.query("age", "alice").count(), 0);
+
+        index.remove(&docs[0].0, &docs[0].1[..], "name");
+        assert_eq!(index.query("name", "alice").count(), 3);
+        assert!(index.query("name", "alice").all(|h| h != docs[0].0));
+    }
+}
@@ -26,6 +26,25 @@ pub enum Value {
 
 impl Value {
 
+    /// Name of the value's type, for use in error messages and logs.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            &Value::Null => "Null",
+            &Value::Boolean(_) => "Boolean",
+            &Value::Integer(_) => "Integer",
+            &Value::String(_) => "String",
+            &Value::F32(_) => "F32",
+            &Value::F64(_) => "F64",
+            &Value::Binary(_) => "Binary",
+            &Value::Array(_) => "Array",
+            &Value::Object(_) => "Object",
+            &Value::Hash(_) => "Hash",
+            &Value::Identity(_) => "Identity",
+            &Value::Lockbox(_) => "Lockbox",
+            &Value::Timestamp(_) => "Timestamp",
+        }
+    }
+
     pub fn as_ref(&self) -> ValueRef {
         match self {
             &Value::Null => ValueRef::Null,
@@ -547,6 +566,25 @@ pub enum ValueRef<'a> {
 
 impl<'a> ValueRef<'a> {
 
+    /// Name of the value's type, for use in error messages and logs.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            &ValueRef::Null => "Null",
+            &ValueRef::Boolean(_) => "Boolean",
+            &ValueRef::Integer(_) => "Integer",
+            &ValueRef::String(_) => "String",
+            &ValueRef::F32(_) => "F32",
+            &ValueRef::F64(_) => "F64",
+            &ValueRef::Binary(_) => "Binary",
+            &ValueRef::Array(_) => "Array",
+            &ValueRef::Object(_) => "Object",
+            &ValueRef::Hash(_) => "Hash",
+            &ValueRef::Identity(_) => "Identity",
+            &ValueRef::Lockbox(_) => "Lockbox",
+            &ValueRef::Timestamp(_) => "Timestamp",
+        }
+    }
+
     pub fn to_owned(&self) -> Value {
         match self {
             &ValueRef::Null => Value::Null,
@@ -853,7 +891,36 @@ impl<'a> Display for ValueRef<'a> {
     }
 }
 
-
-
-
-
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::{self, Vault, PasswordLevel, LockboxContent};
+
+    #[test]
+    fn type_name() {
+        crypto::init().unwrap();
+        let mut vault = Vault::new_from_password(PasswordLevel::Interactive, String::from("password")).unwrap();
+        let key = vault.new_key().unwrap();
+        let stream = vault.new_stream().unwrap();
+        let lock = vault.encrypt_using_stream(LockboxContent::Data(vec![1, 2, 3]), &stream).unwrap();
+        let values = vec![
+            (Value::Null, "Null"),
+            (Value::from(true), "Boolean"),
+            (Value::from(1), "Integer"),
+            (Value::from("test"), "String"),
+            (Value::from(1.0f32), "F32"),
+            (Value::from(1.0f64), "F64"),
+            (Value::from(vec![0u8, 1u8]), "Binary"),
+            (Value::Array(vec![Value::Null]), "Array"),
+            (Value::Object(BTreeMap::new()), "Object"),
+            (Value::from(Hash::new_empty()), "Hash"),
+            (Value::from(key.get_identity()), "Identity"),
+            (Value::from(lock), "Lockbox"),
+            (Value::from(Timestamp::from_sec(0)), "Timestamp"),
+        ];
+        for (value, name) in values.iter() {
+            assert_eq!(value.type_name(), *name);
+            assert_eq!(value.as_ref().type_name(), *name);
+        }
+    }
+}
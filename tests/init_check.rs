@@ -0,0 +1,22 @@
+extern crate condense_db;
+
+use condense_db::*;
+use condense_db::crypto::{PasswordLevel, Vault};
+
+// Kept in its own test binary so no other test can initialize the crypto library first.
+#[test]
+fn uninitialized_crypto() {
+    match crypto::ensure_init() {
+        Err(CryptoError::NotInitialized) => (),
+        _ => panic!("ensure_init should fail before init is called"),
+    };
+    match Hash::new(1, &[1,2,3]) {
+        Err(CryptoError::NotInitialized) => (),
+        _ => panic!("Hash::new should fail before init is called"),
+    };
+    assert!(Vault::new_from_password(PasswordLevel::Interactive, String::from("password")).is_err());
+
+    crypto::init().unwrap();
+    assert!(crypto::ensure_init().is_ok());
+    assert!(Hash::new(1, &[1,2,3]).is_ok());
+}
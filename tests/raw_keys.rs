@@ -0,0 +1,18 @@
+#![cfg(feature = "raw-keys")]
+extern crate condense_db;
+
+use condense_db::crypto::{self, FullKey, FullStreamKey};
+
+// Stream keys can be exchanged between raw keys without a Vault on either side.
+#[test]
+fn wrap_stream_key() {
+    crypto::init().unwrap();
+    let (key, id) = FullKey::new_pair().unwrap();
+    let (other_key, _) = FullKey::new_pair().unwrap();
+    let stream = FullStreamKey::new();
+
+    let lock = stream.wrap(&id.get_identity_ref()).unwrap();
+    let unwrapped = key.unwrap_stream_key(&lock).unwrap();
+    assert_eq!(unwrapped.get_stream_ref(), stream.get_stream_ref());
+    assert!(other_key.unwrap_stream_key(&lock).is_err());
+}